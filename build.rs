@@ -0,0 +1,40 @@
+//! Captures the compiler version and git commit of the build, for
+//! `provenance::SoftwareAttestation`. Builds from a source tarball have no
+//! git metadata; set `PSBTCOORD_GIT_COMMIT` to record the release commit.
+
+use std::process::Command;
+
+fn main() {
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| String::from("rustc"));
+    let rustc_version = output(&rustc, &["--version"]).unwrap_or_else(|| String::from("unknown"));
+    let git_commit = match std::env::var("PSBTCOORD_GIT_COMMIT") {
+        Ok(commit) => commit,
+        Err(_) => git_commit().unwrap_or_else(|| String::from("unknown")),
+    };
+    println!("cargo:rustc-env=PSBTCOORD_RUSTC_VERSION={}", rustc_version);
+    println!("cargo:rustc-env=PSBTCOORD_GIT_COMMIT={}", git_commit);
+    println!("cargo:rerun-if-env-changed=PSBTCOORD_GIT_COMMIT");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/index");
+    println!("cargo:rerun-if-changed=src");
+}
+
+/// `HEAD`'s hash, with `-dirty` when tracked files have uncommitted
+/// changes, so a patched build cannot pass for the commit it started from.
+fn git_commit() -> Option<String> {
+    let hash = output("git", &["rev-parse", "HEAD"])?;
+    let status = output("git", &["status", "--porcelain", "--untracked-files=no"])?;
+    if status.is_empty() {
+        Some(hash)
+    } else {
+        Some(format!("{}-dirty", hash))
+    }
+}
+
+fn output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8(output.stdout).ok()?.trim().to_string())
+}
@@ -0,0 +1,39 @@
+# Signer review screen. Amounts, addresses and IDs arrive already
+# formatted; do not reformat them.
+
+signer-key = Signer: { $name } [{ $fingerprint }]
+signer-account = Account: { $account } ({ $path })
+training-psbt = Warning: this PSBT was made in training mode and spends no real funds
+
+review-transaction = Transaction: { $inputs ->
+        [one] 1 input
+       *[other] { $inputs } inputs
+    }, { $outputs ->
+        [one] 1 output
+       *[other] { $outputs } outputs
+    }
+review-policy-id = Policy ID: { $id }
+review-policy-id-missing = Policy ID: (not recorded)
+review-total-in = Total in:  { $amount }
+review-total-out = Total out: { $amount }
+review-fee = Fee:       { $amount }
+review-fetched-input = Input { $input }: amount and script from { $source }, NOT from the PSBT
+review-signer-policy = Signer policy { $sequence } (signed by { $signers })
+review-valid-until = Request valid until { $expiry }
+review-output-descriptor = Output { $output } pays index { $index } of { $descriptor } (verified)
+review-output-label = Output { $output } is labelled "{ $label }"
+review-internal = Internal transfer: output { $output } to { $label }, { $amount }
+review-external = External payment: output { $output }, { $amount }
+review-approval-tier = Approval tier: external payments over { $amount } need { $signatures } signatures
+review-tier-required = Required cosigners: { $cosigners }
+review-tier-delay = Must stay time-locked { $hours }h beyond signing
+
+sign-input-signed = Input { $input }: signed
+sign-input-mismatch = Input { $input }: key mismatch, skipping
+sign-summary = Signed { $inputs ->
+        [one] 1 input
+       *[other] { $inputs } inputs
+    }, signatures: { $signatures }/{ $threshold }
+sign-output = Output: { $file }
+sign-receipt = Receipt: { $file }
+sign-threshold-met = Threshold met. Run: cargo run --bin finalizer -- { $file }
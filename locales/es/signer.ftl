@@ -0,0 +1,39 @@
+# Pantalla de revisión del firmante. Los importes, direcciones e IDs
+# llegan ya formateados; no los cambie (p. ej. 0.5 BTC, no 0,5 BTC).
+
+signer-key = Firmante: { $name } [{ $fingerprint }]
+signer-account = Cuenta: { $account } ({ $path })
+training-psbt = Aviso: este PSBT se creó en modo de práctica y no gasta fondos reales
+
+review-transaction = Transacción: { $inputs ->
+        [one] 1 entrada
+       *[other] { $inputs } entradas
+    }, { $outputs ->
+        [one] 1 salida
+       *[other] { $outputs } salidas
+    }
+review-policy-id = ID de política: { $id }
+review-policy-id-missing = ID de política: (no registrado)
+review-total-in = Total de entradas: { $amount }
+review-total-out = Total de salidas: { $amount }
+review-fee = Comisión:          { $amount }
+review-fetched-input = Entrada { $input }: importe y script obtenidos de { $source }, NO del PSBT
+review-signer-policy = Política de firmantes { $sequence } (firmada por { $signers })
+review-valid-until = Solicitud válida hasta { $expiry }
+review-output-descriptor = La salida { $output } paga al índice { $index } de { $descriptor } (verificado)
+review-output-label = La salida { $output } lleva la etiqueta "{ $label }"
+review-internal = Transferencia interna: salida { $output } a { $label }, { $amount }
+review-external = Pago externo: salida { $output }, { $amount }
+review-approval-tier = Nivel de aprobación: los pagos externos de más de { $amount } necesitan { $signatures } firmas
+review-tier-required = Cofirmantes obligatorios: { $cosigners }
+review-tier-delay = Debe seguir bloqueada por tiempo { $hours } h después de firmar
+
+sign-input-signed = Entrada { $input }: firmada
+sign-input-mismatch = Entrada { $input }: la clave no coincide, se omite
+sign-summary = { $inputs ->
+        [one] 1 entrada firmada
+       *[other] { $inputs } entradas firmadas
+    }, firmas: { $signatures }/{ $threshold }
+sign-output = Salida: { $file }
+sign-receipt = Recibo: { $file }
+sign-threshold-met = Umbral alcanzado. Ejecute: cargo run --bin finalizer -- { $file }
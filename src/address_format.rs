@@ -0,0 +1,104 @@
+//! Address parsing and display options.
+
+use crate::CoordinatorError;
+use bitcoin::{Address, Amount, Denomination, Network};
+use std::str::FromStr;
+
+/// How addresses are rendered for people and QR codes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AddressFormat {
+    /// Uppercase bech32 addresses, which encode more densely in QR codes
+    /// (alphanumeric mode). Base58 addresses are case-sensitive and unchanged.
+    pub uppercase: bool,
+    /// Wrap in a BIP 21 `bitcoin:` URI.
+    pub uri: bool,
+}
+
+/// Bech32 human-readable part used for `network`.
+pub fn expected_hrp(network: Network) -> &'static str {
+    match network {
+        Network::Bitcoin => "bc",
+        Network::Regtest => "bcrt",
+        _ => "tb",
+    }
+}
+
+/// Rejects bech32 addresses whose HRP belongs to another network, with an
+/// error that names both, before any other validation.
+pub fn check_hrp(address: &str, network: Network) -> Result<(), CoordinatorError> {
+    let lower = address.trim().to_ascii_lowercase();
+    let Some((hrp, _)) = lower.rsplit_once('1') else {
+        return Ok(());
+    };
+    if !["bc", "tb", "bcrt"].contains(&hrp) {
+        return Ok(());
+    }
+    let expected = expected_hrp(network);
+    if hrp != expected {
+        return Err(CoordinatorError::Invalid(format!(
+            "address {} has HRP '{}' but {:?} uses '{}'",
+            address, hrp, network, expected
+        )));
+    }
+    Ok(())
+}
+
+/// Parses an address and requires it to belong to `network`.
+pub fn parse_address(address: &str, network: Network) -> Result<Address, CoordinatorError> {
+    check_hrp(address, network)?;
+    Address::from_str(address.trim())
+        .map_err(CoordinatorError::invalid)?
+        .require_network(network)
+        .map_err(CoordinatorError::invalid)
+}
+
+pub fn format_address(address: &Address, format: AddressFormat) -> String {
+    if format.uri {
+        bip21_uri(address, None, None, format.uppercase)
+    } else if format.uppercase {
+        format!("{:#}", address)
+    } else {
+        address.to_string()
+    }
+}
+
+/// Builds a BIP 21 URI. With `uppercase`, the scheme and address are
+/// uppercased for QR density; query parameters keep their case.
+pub fn bip21_uri(
+    address: &Address,
+    amount: Option<Amount>,
+    label: Option<&str>,
+    uppercase: bool,
+) -> String {
+    let mut uri = if uppercase {
+        format!("BITCOIN:{:#}", address)
+    } else {
+        format!("bitcoin:{}", address)
+    };
+    let mut params = Vec::new();
+    if let Some(amount) = amount {
+        params.push(format!(
+            "amount={}",
+            amount.to_string_in(Denomination::Bitcoin)
+        ));
+    }
+    if let Some(label) = label {
+        params.push(format!("label={}", percent_encode(label)));
+    }
+    if !params.is_empty() {
+        uri.push('?');
+        uri.push_str(&params.join("&"));
+    }
+    uri
+}
+
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
@@ -0,0 +1,295 @@
+//! ASCII-armored PSBT text format.
+//!
+//! Long single-line base64 gets wrapped, re-flowed or truncated by email
+//! clients and ticketing systems. The armored form wraps the payload at a
+//! fixed width between BEGIN/END lines, carries header lines, and ends with a
+//! checksum line so a mangled transfer is detected instead of silently
+//! producing a different PSBT.
+
+use crate::CoordinatorError;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use bitcoin::hashes::{Hash, sha256d};
+use std::fmt;
+use std::str::FromStr;
+
+pub const BEGIN_LINE: &str = "-----BEGIN BITCOIN PSBT-----";
+pub const END_LINE: &str = "-----END BITCOIN PSBT-----";
+
+const BECH32_CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// Alphabet used for the armored body.
+///
+/// `Bech32` uses the case-insensitive bech32 character set, which survives
+/// systems that change case or choke on `+`, `/` and `=`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArmorEncoding {
+    Base64,
+    Bech32,
+}
+
+impl fmt::Display for ArmorEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArmorEncoding::Base64 => write!(f, "base64"),
+            ArmorEncoding::Bech32 => write!(f, "bech32"),
+        }
+    }
+}
+
+impl FromStr for ArmorEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "base64" => Ok(ArmorEncoding::Base64),
+            "bech32" => Ok(ArmorEncoding::Bech32),
+            other => Err(format!("unknown armor encoding: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ArmorOptions {
+    pub encoding: ArmorEncoding,
+    pub line_width: usize,
+    pub headers: Vec<(String, String)>,
+}
+
+impl Default for ArmorOptions {
+    fn default() -> Self {
+        Self {
+            encoding: ArmorEncoding::Base64,
+            line_width: 64,
+            headers: Vec::new(),
+        }
+    }
+}
+
+pub fn is_armored(text: &str) -> bool {
+    text.trim_start().starts_with(BEGIN_LINE)
+}
+
+/// Wraps serialized PSBT bytes in armor.
+pub fn armor(psbt: &[u8], opts: &ArmorOptions) -> String {
+    let body = match opts.encoding {
+        ArmorEncoding::Base64 => STANDARD.encode(psbt),
+        ArmorEncoding::Bech32 => encode_bech32_chars(psbt),
+    };
+
+    let mut out = String::new();
+    out.push_str(BEGIN_LINE);
+    out.push('\n');
+    out.push_str(&format!("Encoding: {}\n", opts.encoding));
+    for (key, value) in &opts.headers {
+        out.push_str(&format!("{}: {}\n", key, value));
+    }
+    out.push('\n');
+    for line in body.as_bytes().chunks(opts.line_width.max(1)) {
+        out.push_str(&String::from_utf8_lossy(line));
+        out.push('\n');
+    }
+    out.push_str(&format!("={}\n", checksum(psbt)));
+    out.push_str(END_LINE);
+    out.push('\n');
+    out
+}
+
+/// Strips armor and returns the serialized PSBT bytes, verifying the checksum.
+///
+/// Surrounding text (email quoting, signatures) outside BEGIN/END is ignored,
+/// as is whitespace added to or removed from line ends.
+pub fn dearmor(text: &str) -> Result<Vec<u8>, CoordinatorError> {
+    let mut lines = text
+        .lines()
+        .map(str::trim)
+        .skip_while(|l| *l != BEGIN_LINE)
+        .skip(1);
+
+    let mut encoding = ArmorEncoding::Base64;
+    for line in lines.by_ref() {
+        if line.is_empty() {
+            break;
+        }
+        let (key, value) = line
+            .split_once(':')
+            .ok_or_else(|| CoordinatorError::Psbt(format!("malformed armor header: {}", line)))?;
+        if key.trim().eq_ignore_ascii_case("encoding") {
+            encoding = value.trim().parse().map_err(CoordinatorError::Psbt)?;
+        }
+    }
+
+    let mut body = String::new();
+    let mut expected = None;
+    let mut terminated = false;
+    for line in lines {
+        if line == END_LINE {
+            terminated = true;
+            break;
+        }
+        if let Some(sum) = line.strip_prefix('=') {
+            // Base64 padding can also start a line, but is never followed by
+            // eight hex digits.
+            if sum.len() == 8 && sum.chars().all(|c| c.is_ascii_hexdigit()) {
+                expected = Some(sum.to_ascii_lowercase());
+                continue;
+            }
+        }
+        body.push_str(line);
+    }
+
+    if !terminated {
+        return Err(CoordinatorError::psbt(
+            "armored PSBT is missing its END line",
+        ));
+    }
+    let expected = expected
+        .ok_or_else(|| CoordinatorError::psbt("armored PSBT is missing its checksum line"))?;
+
+    let psbt = match encoding {
+        ArmorEncoding::Base64 => STANDARD.decode(&body).map_err(CoordinatorError::psbt)?,
+        ArmorEncoding::Bech32 => decode_bech32_chars(&body)?,
+    };
+
+    let actual = checksum(&psbt);
+    if actual != expected {
+        return Err(CoordinatorError::Psbt(format!(
+            "armor checksum mismatch: expected {}, got {}",
+            expected, actual
+        )));
+    }
+    Ok(psbt)
+}
+
+fn checksum(data: &[u8]) -> String {
+    let hash = sha256d::Hash::hash(data);
+    hash.as_byte_array()[..4]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn encode_bech32_chars(data: &[u8]) -> String {
+    let mut out = String::new();
+    let mut acc: u32 = 0;
+    let mut bits = 0;
+    for &byte in data {
+        acc = ((acc << 8) | byte as u32) & 0xfff;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BECH32_CHARSET[((acc >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BECH32_CHARSET[((acc << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    out
+}
+
+fn decode_bech32_chars(text: &str) -> Result<Vec<u8>, CoordinatorError> {
+    let mut out = Vec::new();
+    let mut acc: u32 = 0;
+    let mut bits = 0;
+    for c in text.chars() {
+        let c = c.to_ascii_lowercase();
+        let value = BECH32_CHARSET
+            .iter()
+            .position(|&b| b as char == c)
+            .ok_or_else(|| CoordinatorError::Psbt(format!("invalid bech32 character: {:?}", c)))?;
+        acc = ((acc << 5) | value as u32) & 0xfff;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    if bits >= 5 || acc & ((1 << bits) - 1) != 0 {
+        return Err(CoordinatorError::psbt("invalid bech32 padding"));
+    }
+    Ok(out)
+}
+
+/// Options for the `--armor=<encoding>` command-line flag.
+pub fn options_for(encoding: &str) -> Result<ArmorOptions, String> {
+    Ok(ArmorOptions {
+        encoding: encoding.parse()?,
+        ..ArmorOptions::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(encoding: ArmorEncoding) -> ArmorOptions {
+        ArmorOptions {
+            encoding,
+            line_width: 20,
+            headers: vec![("Comment".to_string(), "rent: March".to_string())],
+        }
+    }
+
+    #[test]
+    fn round_trips_in_both_encodings() {
+        for encoding in [ArmorEncoding::Base64, ArmorEncoding::Bech32] {
+            // Lengths that leave every possible number of padding bits.
+            for len in [1, 2, 3, 4, 5, 77] {
+                let data: Vec<u8> = (0..len).map(|i| (i * 37 + 11) as u8).collect();
+                let text = armor(&data, &options(encoding));
+                assert!(is_armored(&text));
+                assert!(
+                    text.lines()
+                        .filter(|line| !line.starts_with("-----"))
+                        .all(|line| line.len() <= 20)
+                );
+                assert_eq!(dearmor(&text).unwrap(), data);
+            }
+        }
+    }
+
+    #[test]
+    fn recovers_from_rewrapped_and_padded_lines() {
+        let data: Vec<u8> = (0..=255).collect();
+        for encoding in [ArmorEncoding::Base64, ArmorEncoding::Bech32] {
+            let text = armor(&data, &options(encoding));
+            let (head, rest) = text.split_once("\n\n").unwrap();
+            let (body, tail) = rest.split_once("\n=").unwrap();
+            // Re-flowed at another width, with CRLF line ends, indentation
+            // and trailing spaces, inside the text of an email.
+            let joined: String = body.lines().collect();
+            let rewrapped: Vec<String> = joined
+                .as_bytes()
+                .chunks(31)
+                .map(|chunk| format!("  {}  ", String::from_utf8_lossy(chunk)))
+                .collect();
+            let mangled = format!(
+                "Please sign this:\r\n{}\r\n\r\n{}\r\n={}\r\nThanks\r\n",
+                head,
+                rewrapped.join("\r\n"),
+                tail
+            );
+            assert_eq!(dearmor(&mangled).unwrap(), data);
+        }
+
+        // Bech32 survives a change of case, headers included.
+        let upper = armor(&data, &options(ArmorEncoding::Bech32)).to_uppercase();
+        assert_eq!(dearmor(&upper).unwrap(), data);
+    }
+
+    #[test]
+    fn refuses_damaged_armor() {
+        let data: Vec<u8> = (0..100).collect();
+        let text = armor(&data, &ArmorOptions::default());
+
+        // A changed character no longer matches the checksum.
+        let body_line = text.lines().nth(3).unwrap();
+        let flipped = body_line.replacen('A', "B", 1);
+        assert_ne!(flipped, body_line);
+        assert!(dearmor(&text.replacen(body_line, &flipped, 1)).is_err());
+
+        // A truncated transfer loses its checksum or END line.
+        let cut = text.find("\n=").unwrap();
+        assert!(dearmor(&text[..cut]).is_err());
+        assert!(dearmor(text.trim_end().strip_suffix(END_LINE).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,192 @@
+//! Verification of vendor/device signatures over exported xpubs.
+//!
+//! Some devices (e.g. Coldcard) can sign the xpub they export with a Bitcoin
+//! signed message. Checking that signature against a key known out of band
+//! detects an xpub that was swapped between the device and the coordinator.
+//! The known keys are the ones pinned in [`TRUSTED_DEVICES_FILE`]: the
+//! signer address a key file names is only a hint, since whoever swapped
+//! the xpub could swap it too. Registration proofs that rely on a
+//! device-held secret, such as Ledger's wallet-policy HMAC, cannot be
+//! checked off-device and are not handled here.
+
+use crate::CoordinatorError;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::sign_message::{MessageSignature, signed_msg_hash};
+use bitcoin::{Address, PublicKey};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Where the coordinator keeps the device keys it trusts to attest xpubs.
+pub const TRUSTED_DEVICES_FILE: &str = "trusted_devices.json";
+
+/// Signed-message signature over the exported xpub string.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct XpubAttestation {
+    /// Address of the attesting key (P2PKH, P2WPKH or P2SH-P2WPKH).
+    pub signer: String,
+    /// Base64 signature in Bitcoin signed-message format.
+    pub signature: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttestationStatus {
+    Unattested,
+    /// Signed by the pinned device `signer`.
+    Verified {
+        signer: String,
+    },
+    /// An attestation that did not check out against a pinned device.
+    Unverified {
+        signer: String,
+        reason: String,
+    },
+}
+
+/// A device key pinned out of band, e.g. read off the device's screen.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrustedDevice {
+    pub name: String,
+    /// Address of the device's attesting key.
+    pub address: String,
+}
+
+/// The devices whose xpub attestations count as verified.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TrustedDevices {
+    #[serde(default)]
+    pub devices: Vec<TrustedDevice>,
+}
+
+impl TrustedDevices {
+    /// Reads [`TRUSTED_DEVICES_FILE`], empty when there is none, so every
+    /// attestation is then unverified.
+    pub fn load_if_present() -> Result<Self, CoordinatorError> {
+        if !Path::new(TRUSTED_DEVICES_FILE).exists() {
+            return Ok(Self::default());
+        }
+        let devices: Self =
+            serde_json::from_str(&std::fs::read_to_string(TRUSTED_DEVICES_FILE)?)
+                .map_err(|e| CoordinatorError::Key(format!("{}: {}", TRUSTED_DEVICES_FILE, e)))?;
+        for device in &devices.devices {
+            Address::from_str(&device.address).map_err(|e| {
+                CoordinatorError::Key(format!("{}: {}: {}", TRUSTED_DEVICES_FILE, device.name, e))
+            })?;
+        }
+        Ok(devices)
+    }
+
+    /// The pinned device whose address belongs to `pubkey`.
+    fn find(&self, pubkey: &PublicKey) -> Option<&TrustedDevice> {
+        self.devices.iter().find(|device| {
+            Address::from_str(&device.address)
+                .is_ok_and(|address| address.assume_checked().is_related_to_pubkey(pubkey))
+        })
+    }
+}
+
+impl XpubAttestation {
+    /// Checks that a device pinned in `trusted` signed exactly `xpub`. A
+    /// signature that is malformed, over something else, or by any other
+    /// key, including the one the attestation names, is unverified.
+    pub fn verify(&self, xpub: &str, trusted: &TrustedDevices) -> AttestationStatus {
+        let unverified = |reason: String| AttestationStatus::Unverified {
+            signer: self.signer.clone(),
+            reason,
+        };
+        let pubkey = match self.recover(xpub) {
+            Ok(pubkey) => pubkey,
+            Err(e) => return unverified(e),
+        };
+        match trusted.find(&pubkey) {
+            Some(device) => AttestationStatus::Verified {
+                signer: device.name.clone(),
+            },
+            None => unverified(format!("signed by a key not in {}", TRUSTED_DEVICES_FILE)),
+        }
+    }
+
+    /// The key that signed `xpub`, if the signature is well formed.
+    fn recover(&self, xpub: &str) -> Result<PublicKey, String> {
+        let bytes = STANDARD
+            .decode(self.signature.trim())
+            .map_err(|e| e.to_string())?;
+        let sig = MessageSignature::from_slice(&bytes).map_err(|e| e.to_string())?;
+        let secp = Secp256k1::verification_only();
+        sig.recover_pubkey(&secp, signed_msg_hash(xpub))
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+    use bitcoin::secp256k1::{Message, SecretKey};
+    use bitcoin::{CompressedPublicKey, Network};
+
+    const XPUB: &str = "tpubD6NzVbkrYhZ4XgiXtGrdW5XDAPFCL9h7we1vwNCpn8tGbBcgfVYjXyhWo4E1xkh56hjod1RhGjxbaTLV3X4FyWuejifB9jusQ46QzG87VKp";
+
+    fn attest(seed: u8, xpub: &str) -> (XpubAttestation, String) {
+        let secp = Secp256k1::new();
+        let key = SecretKey::from_slice(&[seed; 32]).unwrap();
+        let msg = Message::from_digest(signed_msg_hash(xpub).to_byte_array());
+        let sig = MessageSignature::new(secp.sign_ecdsa_recoverable(&msg, &key), true);
+        let pubkey = CompressedPublicKey::from_private_key(
+            &secp,
+            &bitcoin::PrivateKey::new(key, Network::Regtest),
+        )
+        .unwrap();
+        let address = Address::p2wpkh(&pubkey, Network::Regtest).to_string();
+        let attestation = XpubAttestation {
+            signer: address.clone(),
+            signature: STANDARD.encode(sig.serialize()),
+        };
+        (attestation, address)
+    }
+
+    fn pinned(address: String) -> TrustedDevices {
+        TrustedDevices {
+            devices: vec![TrustedDevice {
+                name: "coldcard".to_string(),
+                address,
+            }],
+        }
+    }
+
+    #[test]
+    fn pinned_device_verifies() {
+        let (attestation, address) = attest(1, XPUB);
+        assert_eq!(
+            attestation.verify(XPUB, &pinned(address)),
+            AttestationStatus::Verified {
+                signer: "coldcard".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn self_named_signer_is_unverified() {
+        let (attestation, _) = attest(1, XPUB);
+        let (_, other) = attest(2, XPUB);
+        assert!(matches!(
+            attestation.verify(XPUB, &TrustedDevices::default()),
+            AttestationStatus::Unverified { .. }
+        ));
+        assert!(matches!(
+            attestation.verify(XPUB, &pinned(other)),
+            AttestationStatus::Unverified { .. }
+        ));
+    }
+
+    #[test]
+    fn signature_over_another_xpub_is_unverified() {
+        let (attestation, address) = attest(1, "tpub-something-else");
+        assert!(matches!(
+            attestation.verify(XPUB, &pinned(address)),
+            AttestationStatus::Unverified { .. }
+        ));
+    }
+}
@@ -0,0 +1,167 @@
+//! Auxiliary accounts: single-sig descriptors kept next to the multisig in
+//! `wallet.json`, such as a Taproot account one cosigner controls for
+//! small operational change.
+//!
+//! The coordinator can pay a share of a transaction into one, at the
+//! account's next unused index, and spend its UTXOs alongside the
+//! multisig's. For the cosigner's signer to sign those inputs, the
+//! account key must sit below that cosigner's account xpub, e.g.
+//! `tr([f00dbabe/48'/1'/0'/2']tpub.../5/*)`.
+
+use crate::CoordinatorError;
+use bitcoin::psbt::Psbt;
+use bitcoin::{Address, Network, Weight};
+use miniscript::descriptor::{DefiniteDescriptorKey, Descriptor, DescriptorPublicKey};
+use miniscript::psbt::PsbtExt;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuxiliaryAccount {
+    /// Name used on the command line, e.g. `ops-change`.
+    pub label: String,
+    /// Descriptor with a wildcard, e.g. `tr([fp/path]tpub.../5/*)`. With a
+    /// multipath step, addresses come from its first (receive) chain.
+    pub descriptor: String,
+    /// First index not yet paid to.
+    #[serde(default)]
+    pub next_index: u32,
+}
+
+impl AuxiliaryAccount {
+    /// Checks that `descriptor` is a single-key `wpkh()` or key-path-only
+    /// `tr()`, the kinds the signer and verifier handle, and has a
+    /// wildcard, so the account can hand out a fresh address each time.
+    pub fn new(label: &str, descriptor: &str) -> Result<Self, CoordinatorError> {
+        if label.is_empty() || label.contains(':') {
+            return Err(CoordinatorError::invalid(
+                "account labels must be non-empty and contain no ':'",
+            ));
+        }
+        let account = Self {
+            label: label.to_string(),
+            descriptor: descriptor.trim().to_string(),
+            next_index: 0,
+        };
+        let parsed = account.parsed()?;
+        match &parsed {
+            Descriptor::Wpkh(_) => {}
+            Descriptor::Tr(tr) if tr.leaves().next().is_none() => {}
+            Descriptor::Tr(_) => {
+                return Err(CoordinatorError::Descriptor(format!(
+                    "{}: tr() with a script tree is not supported; use a single-key tr()",
+                    label
+                )));
+            }
+            other => {
+                return Err(CoordinatorError::Descriptor(format!(
+                    "{}: {:?} descriptors are not supported; use a single-key wpkh() or tr()",
+                    label,
+                    other.desc_type()
+                )));
+            }
+        }
+        if !parsed.has_wildcard() {
+            return Err(CoordinatorError::Descriptor(format!(
+                "{}: descriptor needs a wildcard (/*) to derive addresses",
+                label
+            )));
+        }
+        Ok(account)
+    }
+
+    fn parsed(&self) -> Result<Descriptor<DescriptorPublicKey>, CoordinatorError> {
+        Descriptor::<DescriptorPublicKey>::from_str(&self.descriptor)
+            .map_err(|e| CoordinatorError::Descriptor(format!("{}: {}", self.label, e)))
+    }
+
+    /// The account's descriptor at `index` on its receive chain.
+    pub fn at_index(
+        &self,
+        index: u32,
+    ) -> Result<Descriptor<DefiniteDescriptorKey>, CoordinatorError> {
+        self.parsed()?
+            .into_single_descriptors()?
+            .into_iter()
+            .next()
+            .ok_or_else(|| CoordinatorError::descriptor("descriptor has no receive chain"))?
+            .at_derivation_index(index)
+            .map_err(CoordinatorError::descriptor)
+    }
+
+    /// Upper bound on the weight a signed input of the account adds to an
+    /// unsigned one.
+    pub fn max_satisfaction_weight(&self) -> Result<Weight, CoordinatorError> {
+        Ok(self.parsed()?.max_weight_to_satisfy()?)
+    }
+
+    pub fn address(&self, index: u32, network: Network) -> Result<Address, CoordinatorError> {
+        let script_pubkey = self.at_index(index)?.script_pubkey();
+        Address::from_script(&script_pubkey, network).map_err(CoordinatorError::descriptor)
+    }
+
+    /// Adds the key origins (and, for Taproot, the internal key) of address
+    /// `index` to a PSBT output paying it, so the account's owner can
+    /// recognize it.
+    pub fn update_psbt_output(
+        &self,
+        psbt: &mut Psbt,
+        output: usize,
+        index: u32,
+    ) -> Result<(), CoordinatorError> {
+        psbt.update_output_with_descriptor(output, &self.at_index(index)?)
+            .map_err(|e| CoordinatorError::Psbt(format!("output {}: {}", output, e)))
+    }
+
+    /// Fills in what the account's owner needs to sign a PSBT input
+    /// spending address `index`. The input's `witness_utxo` must already be
+    /// set; it is checked against the derived script.
+    pub fn update_psbt_input(
+        &self,
+        psbt: &mut Psbt,
+        input: usize,
+        index: u32,
+    ) -> Result<(), CoordinatorError> {
+        psbt.update_input_with_descriptor(input, &self.at_index(index)?)
+            .map_err(|e| CoordinatorError::Psbt(format!("input {}: {}", input, e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use bitcoin::bip32::Xpub;
+    use bitcoin::secp256k1::Secp256k1;
+
+    fn xpub(seed: u8) -> Xpub {
+        Xpub::from_priv(&Secp256k1::new(), &testing::account_xpriv(seed))
+    }
+
+    #[test]
+    fn accepts_single_key_wpkh_and_tr() {
+        for descriptor in [
+            format!("wpkh({}/5/*)", xpub(1)),
+            format!("tr({}/5/*)", xpub(1)),
+        ] {
+            assert!(AuxiliaryAccount::new("ops", &descriptor).is_ok());
+        }
+    }
+
+    #[test]
+    fn refuses_descriptors_the_signer_cannot_sign() {
+        for descriptor in [
+            format!("pkh({}/5/*)", xpub(1)),
+            format!("sh(wpkh({}/5/*))", xpub(1)),
+            format!("wsh(multi(1,{}/5/*,{}/5/*))", xpub(1), xpub(2)),
+            format!("tr({}/5/*,pk({}/5/*))", xpub(1), xpub(2)),
+        ] {
+            let result = AuxiliaryAccount::new("ops", &descriptor);
+            assert!(
+                matches!(result, Err(CoordinatorError::Descriptor(_))),
+                "{}",
+                descriptor
+            );
+        }
+    }
+}
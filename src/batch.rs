@@ -0,0 +1,234 @@
+//! Batch payouts read from a file.
+//!
+//! A treasury payout run pays dozens of recipients in one transaction. The
+//! file lists one payment per row, as CSV:
+//!
+//! ```text
+//! address,amount,label
+//! bc1q...,0.25btc,Invoice 2024-117
+//! bc1p...,150000sat,Payroll: Dana
+//! ```
+//!
+//! or as a JSON list of `{"address", "amount", "label"}` objects. The
+//! header row is optional, `#` starts a comment line, and the label, which
+//! is optional, is the rest of the row, so it may contain commas; fields
+//! are not quoted. Amounts take the units of [`parse_amount`]. Every row is
+//! checked before anything is built, and errors name the row.
+
+use crate::address_format::parse_address;
+use crate::{CoordinatorError, check_output_label, parse_amount};
+use bitcoin::{Amount, Network, ScriptBuf};
+use serde::{Deserialize, Serialize};
+
+/// Most rows a batch file may have.
+pub const MAX_BATCH_PAYMENTS: usize = 1000;
+
+/// One row of a batch file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchRow {
+    pub address: String,
+    pub amount: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+/// A checked payment.
+#[derive(Debug, Clone)]
+pub struct BatchPayment {
+    /// Line of a CSV file, or position in a JSON list, from 1.
+    pub row: usize,
+    pub address: String,
+    pub script_pubkey: ScriptBuf,
+    pub amount: Amount,
+    pub label: Option<String>,
+}
+
+/// Reads and checks a batch file: JSON if its name ends in `.json`, CSV
+/// otherwise. Addresses must be for `network`, and amounts above the dust
+/// limit of their output.
+pub fn load(path: &str, network: Network) -> Result<Vec<BatchPayment>, CoordinatorError> {
+    parse(path, &std::fs::read_to_string(path)?, network)
+}
+
+/// Checks the contents of batch file `path`, as [`load`] does.
+fn parse(path: &str, text: &str, network: Network) -> Result<Vec<BatchPayment>, CoordinatorError> {
+    let rows = if path.ends_with(".json") {
+        let rows: Vec<BatchRow> = serde_json::from_str(text)
+            .map_err(|e| CoordinatorError::Invalid(format!("{}: {}", path, e)))?;
+        rows.into_iter()
+            .enumerate()
+            .map(|(i, r)| (i + 1, r))
+            .collect()
+    } else {
+        parse_csv(path, text)?
+    };
+    if rows.is_empty() {
+        return Err(CoordinatorError::Invalid(format!(
+            "{} lists no payments",
+            path
+        )));
+    }
+    if rows.len() > MAX_BATCH_PAYMENTS {
+        return Err(CoordinatorError::Invalid(format!(
+            "{} lists {} payments, more than {}",
+            path,
+            rows.len(),
+            MAX_BATCH_PAYMENTS
+        )));
+    }
+    rows.into_iter()
+        .map(|(row, fields)| {
+            check_row(row, fields, network)
+                .map_err(|e| CoordinatorError::Invalid(format!("{}:{}: {}", path, row, e)))
+        })
+        .collect()
+}
+
+/// CSV rows with their line numbers, skipping the header, blank lines and
+/// comments.
+fn parse_csv(path: &str, text: &str) -> Result<Vec<(usize, BatchRow)>, CoordinatorError> {
+    let mut rows = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.splitn(3, ',').map(str::trim);
+        let address = fields.next().unwrap_or_default();
+        if rows.is_empty() && address.eq_ignore_ascii_case("address") {
+            continue;
+        }
+        let amount = fields.next().ok_or_else(|| {
+            CoordinatorError::Invalid(format!(
+                "{}:{}: expected address,amount[,label]",
+                path,
+                i + 1
+            ))
+        })?;
+        let label = fields.next().filter(|l| !l.is_empty()).map(String::from);
+        rows.push((
+            i + 1,
+            BatchRow {
+                address: address.to_string(),
+                amount: amount.to_string(),
+                label,
+            },
+        ));
+    }
+    Ok(rows)
+}
+
+fn check_row(
+    row: usize,
+    fields: BatchRow,
+    network: Network,
+) -> Result<BatchPayment, CoordinatorError> {
+    let script_pubkey = parse_address(&fields.address, network)
+        .map_err(|e| CoordinatorError::Invalid(format!("address {}: {}", fields.address, e)))?
+        .script_pubkey();
+    let amount = parse_amount(&fields.amount)?;
+    if amount < script_pubkey.minimal_non_dust() {
+        return Err(CoordinatorError::Invalid(format!(
+            "{} sat is below the dust limit of {} sat",
+            amount.to_sat(),
+            script_pubkey.minimal_non_dust().to_sat()
+        )));
+    }
+    if let Some(label) = &fields.label {
+        check_output_label(label)?;
+    }
+    Ok(BatchPayment {
+        row,
+        address: fields.address,
+        script_pubkey,
+        amount,
+        label: fields.label,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ScriptType;
+    use crate::testing;
+    use bitcoin::Address;
+
+    fn address(index: u32) -> Address {
+        testing::wallet(2, 3, ScriptType::Wsh)
+            .derive_address(index, false)
+            .unwrap()
+    }
+
+    fn error(text: &str) -> String {
+        parse("batch.csv", text, Network::Regtest)
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn skips_header_and_comments_and_keeps_line_numbers() {
+        let text = format!(
+            "address,amount,label\n# March payroll\n\n{},0.25btc\n{},150000sat,Dana\n",
+            address(0),
+            address(1)
+        );
+        let payments = parse("batch.csv", &text, Network::Regtest).unwrap();
+        assert_eq!(payments.len(), 2);
+        assert_eq!(payments[0].row, 4);
+        assert_eq!(payments[0].amount, Amount::from_sat(25_000_000));
+        assert_eq!(payments[0].label, None);
+        assert_eq!(payments[1].row, 5);
+        assert_eq!(payments[1].script_pubkey, address(1).script_pubkey());
+        assert_eq!(payments[1].label.as_deref(), Some("Dana"));
+    }
+
+    #[test]
+    fn label_is_the_rest_of_the_row() {
+        let text = format!(
+            "{},1000sat,Invoice 117, paid late, with interest",
+            address(0)
+        );
+        let payments = parse("batch.csv", &text, Network::Regtest).unwrap();
+        assert_eq!(
+            payments[0].label.as_deref(),
+            Some("Invoice 117, paid late, with interest")
+        );
+    }
+
+    #[test]
+    fn errors_name_the_file_and_row() {
+        let text = format!(
+            "address,amount\n{},1000sat\n{},lots\n",
+            address(0),
+            address(1)
+        );
+        assert!(error(&text).contains("batch.csv:3:"));
+        assert!(error("address,amount\nnot-an-address\n").contains("batch.csv:2:"));
+    }
+
+    #[test]
+    fn refuses_dust() {
+        let text = format!("{},100sat", address(0));
+        assert!(error(&text).contains("dust"));
+    }
+
+    #[test]
+    fn refuses_more_than_the_maximum_rows() {
+        let row = format!("{},1000sat\n", address(0));
+        let payments = parse(
+            "batch.csv",
+            &row.repeat(MAX_BATCH_PAYMENTS),
+            Network::Regtest,
+        );
+        assert_eq!(payments.unwrap().len(), MAX_BATCH_PAYMENTS);
+        let text = row.repeat(MAX_BATCH_PAYMENTS + 1);
+        assert!(error(&text).contains(&MAX_BATCH_PAYMENTS.to_string()));
+    }
+
+    #[test]
+    fn refuses_an_address_for_another_network() {
+        let mainnet = Address::from_script(&address(0).script_pubkey(), Network::Bitcoin).unwrap();
+        let text = format!("{},1000sat", mainnet);
+        assert!(error(&text).contains(&format!("batch.csv:1: address {}", mainnet)));
+    }
+}
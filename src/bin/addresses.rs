@@ -0,0 +1,43 @@
+//! Lists wallet receive (or change) addresses in the requested display format.
+
+use bitcoin::Network;
+use psbt_coordinator::address_format::{AddressFormat, format_address};
+use psbt_coordinator::cli::Args;
+use psbt_coordinator::{MultisigWallet, ScriptType};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse("addresses");
+    let format = AddressFormat {
+        uppercase: args.flag("--uppercase"),
+        uri: args.flag("--uri"),
+    };
+    let is_change = args.flag("--change");
+    let descriptor = args.value("--descriptor");
+    let script_type: ScriptType = args.parse_value("--script-type")?.unwrap_or_default();
+    let range = match args.value("--range") {
+        Some(spec) => {
+            let (start, end) = spec.split_once("..").ok_or("range must be START..END")?;
+            start.parse()?..end.parse()?
+        }
+        None => 0..10,
+    };
+
+    let key_files = [
+        "key_a.json",
+        "key_b.json",
+        "key_c.json",
+        "key_d.json",
+        "key_e.json",
+    ];
+    let wallet = match descriptor {
+        Some(descriptor) => MultisigWallet::from_descriptor(descriptor, Network::Regtest)?,
+        None => MultisigWallet::from_key_files(&key_files, 3, script_type, Network::Regtest)?,
+    };
+
+    for derived in wallet.iter_addresses(range, is_change)? {
+        let (index, address) = derived?;
+        println!("{}: {}", index, format_address(&address, format));
+    }
+
+    Ok(())
+}
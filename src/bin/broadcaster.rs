@@ -0,0 +1,117 @@
+//! Holds finalized transactions and broadcasts them in scheduled batches.
+//!
+//! Broadcasting the moment a withdrawal is approved ties on-chain timing to
+//! internal approval events. The finalizer drops `<txid>.hex` files into a
+//! queue directory; this tool releases them at window boundaries (e.g. the
+//! top of every hour) or as soon as enough are queued.
+
+use bitcoin::secp256k1::rand::seq::SliceRandom;
+use bitcoin::secp256k1::rand::thread_rng;
+use psbt_coordinator::cli::Args;
+use psbt_coordinator::shutdown::Shutdown;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse("broadcaster");
+    let queue_dir = PathBuf::from(args.required("queue_dir")?);
+    let window_secs: u64 = match args.parse_value::<u64>("--window-minutes")? {
+        Some(0) => return Err("window must be at least one minute".into()),
+        Some(minutes) => minutes.checked_mul(60).ok_or("window too long")?,
+        None => 3600,
+    };
+    let batch_size: Option<usize> = args.parse_value("--batch-size")?;
+    let chain = args.value("--chain").unwrap_or("regtest");
+    let once = args.flag("--once");
+
+    std::fs::create_dir_all(queue_dir.join("sent"))?;
+    std::fs::create_dir_all(queue_dir.join("failed"))?;
+
+    println!("Queue: {}", queue_dir.display());
+    println!("Window: every {} min", window_secs / 60);
+    if let Some(n) = batch_size {
+        println!("Early release when {} transaction(s) are queued", n);
+    }
+
+    let shutdown = Shutdown::install()?;
+    let mut next_window = next_boundary(unix_now(), window_secs);
+    loop {
+        let pending = queued(&queue_dir)?;
+        let due = once || unix_now() >= next_window;
+        let full = batch_size.is_some_and(|n| n > 0 && pending.len() >= n);
+
+        if (due || full) && !pending.is_empty() {
+            broadcast_batch(&queue_dir, pending, chain)?;
+        }
+        if once {
+            return Ok(());
+        }
+        if due {
+            next_window = next_boundary(unix_now(), window_secs);
+        }
+        // A batch in progress is finished first, so no queue file is left
+        // half moved.
+        if shutdown.wait(POLL_INTERVAL) {
+            println!("Stopped");
+            return Ok(());
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn next_boundary(now: u64, window_secs: u64) -> u64 {
+    (now / window_secs + 1) * window_secs
+}
+
+fn queued(dir: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|e| e == "hex") {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+fn broadcast_batch(
+    dir: &Path,
+    mut batch: Vec<PathBuf>,
+    chain: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Release order should not reveal queueing order either.
+    batch.shuffle(&mut thread_rng());
+    println!("\nBroadcasting {} transaction(s)", batch.len());
+
+    for path in batch {
+        let tx_hex = std::fs::read_to_string(&path)?;
+        let output = Command::new("bitcoin-cli")
+            .arg(format!("-chain={}", chain))
+            .arg("sendrawtransaction")
+            .arg(tx_hex.trim())
+            .output()?;
+
+        let file_name = path.file_name().ok_or("invalid queue entry")?;
+        if output.status.success() {
+            println!("  Sent: {}", String::from_utf8_lossy(&output.stdout).trim());
+            std::fs::rename(&path, dir.join("sent").join(file_name))?;
+        } else {
+            eprintln!(
+                "  Failed: {}: {}",
+                path.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            std::fs::rename(&path, dir.join("failed").join(file_name))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,52 @@
+//! Combines partially-signed PSBTs from several signers into one.
+//!
+//! Inputs may come from this tool's signer or from external wallets such as
+//! Electrum or a hardware signer, as long as they spend the same transaction.
+//! Submitting a PSBT twice is harmless: copies with identical content are
+//! skipped. Two different signatures by one key are refused instead.
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use bitcoin::hashes::{Hash, sha256};
+use psbt_coordinator::cli::Args;
+use psbt_coordinator::{
+    NormalizeOptions, combine_psbts, load_psbt, normalize_psbt, parse_untrusted_psbt, write_atomic,
+};
+use std::collections::BTreeMap;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse("combiner");
+
+    let mut psbts = Vec::new();
+    // Content hash of each PSBT kept, by the file it came from. Encoding
+    // differences (base64 or binary, field order) do not count.
+    let mut seen: BTreeMap<sha256::Hash, &str> = BTreeMap::new();
+    for input in args.values("psbt") {
+        let psbt = parse_untrusted_psbt(&load_psbt(input)?)?;
+        let hash = sha256::Hash::hash(&normalize_psbt(&psbt, NormalizeOptions::default())?);
+        if let Some(first) = seen.get(&hash) {
+            println!("Skipped {}: same content as {}", input, first);
+            continue;
+        }
+        seen.insert(hash, input);
+        let sigs: usize = psbt.inputs.iter().map(|i| i.partial_sigs.len()).sum();
+        println!("Loaded {}: {} signature(s)", input, sigs);
+        psbts.push(psbt);
+    }
+
+    let combined = combine_psbts(psbts)?;
+
+    println!();
+    for (i, input) in combined.inputs.iter().enumerate() {
+        println!("Input {}: {} signatures", i, input.partial_sigs.len());
+    }
+
+    let out_file = "combined.psbt.base64";
+    write_atomic(out_file, STANDARD.encode(combined.serialize()))?;
+    println!("\nOutput: {}", out_file);
+    println!(
+        "Next: cargo run --features full --bin finalizer -- {}",
+        out_file
+    );
+
+    Ok(())
+}
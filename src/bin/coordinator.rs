@@ -1,98 +1,947 @@
-//! Creates unsigned PSBTs for 3-of-5 multisig transactions.
+//! Creates unsigned PSBTs for M-of-N multisig transactions.
 
 use base64::{Engine, engine::general_purpose::STANDARD};
-use bitcoin::bip32::DerivationPath;
+use bitcoin::bip32::{Xpriv, Xpub};
 use bitcoin::psbt::Psbt;
-use bitcoin::secp256k1::Secp256k1;
+use bitcoin::secp256k1::rand::{Rng, thread_rng};
+use bitcoin::secp256k1::{Secp256k1, SecretKey};
 use bitcoin::{
     Address, Amount, Network, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid,
-    absolute, transaction,
+    Weight, absolute, transaction,
+};
+use psbt_coordinator::address_format::parse_address;
+use psbt_coordinator::armor::{self, ArmorOptions};
+use psbt_coordinator::batch;
+use psbt_coordinator::bundle::{COORDINATOR_KEY_FILE, CoordinatorKey, OfflineBundle};
+use psbt_coordinator::chain_source::{ChainConfig, DEFAULT_FEE_TARGET};
+use psbt_coordinator::cli::Args;
+use psbt_coordinator::core_rpc::CoreRpcConfig;
+use psbt_coordinator::destinations::descriptor_script_pubkey;
+use psbt_coordinator::training;
+use psbt_coordinator::utxo_store::{ManualUtxo, UTXO_FILE, UtxoStore};
+use psbt_coordinator::wallet_file::{WalletFile, check_account};
+use psbt_coordinator::{
+    AmountUnit, CoordinatorError, DEFAULT_GAP_LIMIT, Expiry, KeyData, MultisigWallet, OutputShare,
+    ScriptType, account_name, allocate_shares, check_same_network, format_amount, parse_amount,
+    parse_network, print_wallet_info, set_output_descriptor, set_output_label, set_psbt_created_at,
+    set_psbt_expiry, set_psbt_network, set_psbt_policy_id, split_amount, write_atomic,
 };
-use psbt_coordinator::{MultisigWallet, print_wallet_info};
 use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How long a signature request stays signable without `--expires-in` or
+/// `--expires-at-height`: a week.
+const DEFAULT_EXPIRY_SECS: u64 = 7 * 24 * 3600;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let key_files = [
-        "key_a.json",
-        "key_b.json",
-        "key_c.json",
-        "key_d.json",
-        "key_e.json",
-    ];
-    let network = Network::Regtest;
-    let wallet = MultisigWallet::from_key_files(&key_files, network)?;
+    let args = Args::parse("coordinator");
+    let armor_opts: Option<ArmorOptions> =
+        args.value("--armor").map(armor::options_for).transpose()?;
+    let unit: AmountUnit = args.parse_value("--denomination")?.unwrap_or_default();
+    let max_per_output: Option<Amount> = args
+        .value("--max-per-output")
+        .map(parse_amount)
+        .transpose()?;
+    // Payees keep their command-line order, which is the output order.
+    let mut payees: Vec<(Payee, OutputShare)> = Vec::new();
+    for (name, spec) in args.values_of(&["--pay", "--to-descriptor", "--to-aux"]) {
+        payees.push(parse_payee(name, spec)?);
+    }
+    let script_type: ScriptType = args.parse_value("--script-type")?.unwrap_or_default();
+    let change_index: Option<u32> = args.parse_value("--change-index")?;
+    let change_spread: Option<u32> = args.parse_value("--random-change")?;
+    // Wallets restoring from the keys stop looking after the gap limit of
+    // unused addresses, so change is never placed beyond it.
+    if change_spread.is_some_and(|spread| !(1..=DEFAULT_GAP_LIMIT).contains(&spread)) {
+        return Err(format!(
+            "--random-change range must be from 1 to the gap limit of {}",
+            DEFAULT_GAP_LIMIT
+        )
+        .into());
+    }
+    let policy = args.value("--policy");
+    let descriptor = args.value("--descriptor");
+    // Spending a recovery branch needs the input's relative timelock to be
+    // at least the policy's older() value.
+    let sequence = match args.value("--older") {
+        Some(blocks) => Sequence::from_height(
+            blocks
+                .parse()
+                .map_err(|_| "--older takes a block count up to 65535")?,
+        ),
+        None => Sequence::ENABLE_RBF_NO_LOCKTIME,
+    };
+    let bundle_key = args.value("--bundle");
+    let is_training = args.flag("--training");
+    // The later of --expires-in and --expires-at-height wins.
+    let expiry = match args.last_of(&["--expires-in", "--expires-at-height"]) {
+        Some(("--expires-in", secs)) => {
+            let secs: u64 = secs.parse()?;
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+            let at = now
+                .checked_add(secs)
+                .ok_or("--expires-in is too far ahead")?;
+            Some(Expiry::Time(at))
+        }
+        Some((_, height)) => Some(Expiry::Height(height.parse()?)),
+        None => None,
+    };
+    let lock_hours: Option<u64> = args.parse_value("--lock-hours")?;
+    let mut aux_inputs: Vec<AuxInput> = Vec::new();
+    for spec in args.values("--aux-input") {
+        aux_inputs.push(parse_aux_input(spec)?);
+    }
+    let account: u32 = args.parse_value("--account")?.unwrap_or(0);
+    let network_arg = args.value("--network").map(parse_network).transpose()?;
+    let threshold: Option<usize> = args.parse_value("--threshold")?;
+    let to = args.value("--to");
+    let amount: Option<Amount> = args.value("--amount").map(parse_amount).transpose()?;
+    let send_max = args.flag("--send-max");
+    let mut feerate: Option<f64> = args.parse_value("--feerate")?;
+    if feerate.is_some_and(|rate| !(rate.is_finite() && rate > 0.0)) {
+        return Err("--feerate must be a positive rate in sat/vB".into());
+    }
+    let mut selected: Vec<OutPoint> = Vec::new();
+    for spec in args.values("--utxo") {
+        let outpoint = OutPoint::from_str(spec)?;
+        if selected.contains(&outpoint) {
+            return Err(format!("--utxo {} given twice", outpoint).into());
+        }
+        selected.push(outpoint);
+    }
+    // --fee-target is the older spelling.
+    let fee_target = match args.last_of(&["--target-blocks", "--fee-target"]) {
+        Some((name, blocks)) => {
+            let blocks: u16 = blocks.parse()?;
+            if blocks == 0 {
+                return Err(format!("{} must be at least 1 block", name).into());
+            }
+            Some(blocks)
+        }
+        None => None,
+    };
+    let core_rpc = args.value("--core-rpc");
+    let chain_source = args.value("--chain-source");
+    let batch_file = args.value("--batch");
+
+    if (to.is_some() || send_max) && (!payees.is_empty() || batch_file.is_some()) {
+        return Err(
+            "--to cannot be combined with --pay, --to-descriptor, --to-aux or --batch".into(),
+        );
+    }
+    // --to and --amount make one payment, with change back to the wallet.
+    // --to with --send-max sweeps the inputs to it, leaving no change.
+    let payment = match (to, amount, send_max) {
+        (Some(to), Some(amount), false) => Some((to.to_string(), amount)),
+        (None, None, false) => None,
+        (Some(to), None, true) => {
+            if max_per_output.is_some() {
+                return Err("--send-max cannot be combined with --max-per-output".into());
+            }
+            payees.push((Payee::Address(to.to_string()), OutputShare::Remainder));
+            None
+        }
+        (_, _, true) => return Err("--send-max takes --to and no --amount".into()),
+        _ => return Err("--to and --amount must be given together".into()),
+    };
+    // --core-rpc is the same as --chain-source with a "core" backend.
+    let chain_config = match (&core_rpc, &chain_source) {
+        (Some(_), Some(_)) => {
+            return Err("--core-rpc cannot be combined with --chain-source".into());
+        }
+        (Some(path), None) => Some(ChainConfig::Core(CoreRpcConfig::load(path)?)),
+        (None, Some(path)) => Some(ChainConfig::load(path)?),
+        (None, None) => None,
+    };
+    if fee_target.is_some() && (feerate.is_some() || chain_config.is_none()) {
+        return Err("--target-blocks needs --core-rpc or --chain-source, and no --feerate".into());
+    }
+
+    if is_training {
+        training::enter()?;
+    }
+
+    // Each BIP 48 account is a separate wallet with its own key files and
+    // state: key_a.json and wallet.json for account 0, key_a_account1.json
+    // and wallet_account1.json for account 1.
+    let key_names: Vec<String> = ["key_a", "key_b", "key_c", "key_d", "key_e"]
+        .iter()
+        .map(|name| format!("{}.json", account_name(name, account)))
+        .collect();
+    let key_files: Vec<&str> = key_names.iter().map(String::as_str).collect();
+    let wallet_path = WalletFile::account_path(account);
+    // The account's wallet file, written by `wallet init`, is the wallet
+    // unless one is given on the command line.
+    let mut wallet_file = match (&descriptor, &policy) {
+        (None, None) => WalletFile::load_account_if_present(account)?,
+        _ => None,
+    };
+    let network = network_arg.unwrap_or(Network::Regtest);
+    let wallet = match (&descriptor, &policy, &wallet_file) {
+        (Some(_), Some(_), _) => {
+            return Err("--descriptor cannot be combined with --policy".into());
+        }
+        // An existing descriptor (e.g. from Sparrow or Core) needs no key files.
+        (Some(descriptor), None, _) => MultisigWallet::from_descriptor(descriptor, network)?,
+        (None, Some(policy), _) => MultisigWallet::from_policy(policy, &key_files, network)?,
+        (None, None, Some(file)) => {
+            println!("Using {}", wallet_path);
+            let wallet = file.wallet()?;
+            // The wallet file fixes the network; --network may only confirm it.
+            if let Some(network) = network_arg {
+                check_same_network(wallet.network, network)
+                    .map_err(|e| format!("{}: {}", wallet_path, e))?;
+            }
+            wallet
+        }
+        // Without a wallet file, the five key files make a 3-of-5 wallet
+        // unless --threshold says otherwise, as with `wallet init`.
+        (None, None, None) => MultisigWallet::from_key_files(
+            &key_files,
+            threshold.unwrap_or(3),
+            script_type,
+            network,
+        )?,
+    };
+    if let Some(threshold) = threshold.filter(|&t| t != wallet.threshold) {
+        return Err(format!(
+            "--threshold {} does not match the wallet's threshold of {}",
+            threshold, wallet.threshold
+        )
+        .into());
+    }
+    if descriptor.is_none() {
+        check_account(&wallet, account)?;
+    }
+    let network = wallet.network;
+    println!("Network: {}", network);
+
+    // Batch payouts are fixed amounts; unless another output takes the
+    // remainder, it goes back to the wallet as change.
+    let mut labels: Vec<(usize, String)> = Vec::new();
+    if let Some(path) = batch_file {
+        let batch = batch::load(path, network)?;
+        println!("Batch: {} payments from {}", batch.len(), path);
+        for payment in batch {
+            if let Some(label) = payment.label {
+                labels.push((payees.len(), label));
+            }
+            payees.push((
+                Payee::Address(payment.address),
+                OutputShare::Fixed(payment.amount),
+            ));
+        }
+        if !payees
+            .iter()
+            .any(|(_, share)| *share == OutputShare::Remainder)
+        {
+            payees.push((Payee::Change, OutputShare::Remainder));
+        }
+    }
+    if is_training {
+        for origin in &wallet.xpub_origins {
+            training::check_key_network(origin.xpub.network)?;
+        }
+    }
 
     println!("Loading wallet...\n");
     print_wallet_info(&wallet);
 
-    let addr_index: u32 = 0;
-    let receive_addr = wallet.derive_address(addr_index)?;
-    println!("\nReceive address: {}", receive_addr);
+    // UTXOs reported by the chain source with --core-rpc or
+    // --chain-source, or else recorded with `utxo add`: those picked with
+    // --utxo, or all of them. Only a regtest training drill may go without
+    // and spend a simulated one.
+    let chain = chain_config
+        .map(|config| config.connect(network))
+        .transpose()?;
+    let (available, source) = match &chain {
+        Some(chain) => {
+            println!("\nAsking {} for UTXOs...", chain.name());
+            // Change may have moved past the gap limit; look that far ahead.
+            let range = wallet_file
+                .as_ref()
+                .map_or(0, |f| f.next_change_index)
+                .saturating_add(DEFAULT_GAP_LIMIT);
+            let found = chain.find_utxos(&wallet, range)?;
+            if found.is_empty() {
+                return Err(format!("{} reports no UTXOs for this wallet", chain.name()).into());
+            }
+            (found, chain.name())
+        }
+        None => (UtxoStore::load_or_default()?.utxos, UTXO_FILE),
+    };
+    // With a chain and no --feerate, the fee follows the chain's estimate.
+    // The flat fee is left only for when there is none, as on a fresh
+    // regtest node, and no target was asked for.
+    if let (Some(chain), None) = (&chain, feerate) {
+        let blocks = fee_target.unwrap_or(DEFAULT_FEE_TARGET);
+        let estimate = chain.fee_rate(blocks).and_then(|rate| {
+            if rate.is_finite() && rate > 0.0 {
+                Ok(rate)
+            } else {
+                Err(CoordinatorError::Invalid(format!(
+                    "{} gave an unusable fee rate: {}",
+                    chain.name(),
+                    rate
+                )))
+            }
+        });
+        match estimate {
+            Ok(rate) => {
+                println!(
+                    "{} estimates {:.1} sat/vB to confirm within {} blocks",
+                    chain.name(),
+                    rate,
+                    blocks
+                );
+                feerate = Some(rate);
+            }
+            Err(e) if fee_target.is_some() => return Err(e.into()),
+            Err(e) => eprintln!("Warning: {}; using a flat {} sat fee", e, FLAT_FEE.to_sat()),
+        }
+    }
+    let utxos = if available.is_empty() {
+        if !selected.is_empty() {
+            return Err(format!(
+                "no UTXOs in {}; record them with `utxo add` before --utxo",
+                UTXO_FILE
+            )
+            .into());
+        }
+        let receive_addr = wallet.derive_address(0, false)?;
+        println!("\nReceive address: {}", receive_addr);
+        // Anywhere but a regtest drill, a made-up UTXO would give signers
+        // a PSBT to sign that can never be spent.
+        if !(is_training && network == Network::Regtest) {
+            if matches!(network, Network::Regtest | Network::Signet) {
+                println!("Fund the wallet with `cargo run --features full --bin dev -- fund`");
+            }
+            return Err(format!(
+                "no UTXOs in {}; record a UTXO with `utxo add` or pass a chain source \
+                 (--core-rpc or --chain-source)",
+                UTXO_FILE
+            )
+            .into());
+        }
+        println!(
+            "No UTXOs in {}; training mode spends a simulated one",
+            UTXO_FILE
+        );
+        let script_pubkey = receive_addr.script_pubkey();
+        // Find where the UTXO sits in the wallet so the input gets the right
+        // scripts and derivations.
+        let (index, is_change) = wallet
+            .is_mine(&script_pubkey, DEFAULT_GAP_LIMIT)?
+            .ok_or("UTXO does not pay to this wallet")?;
+        vec![ManualUtxo {
+            outpoint: OutPoint {
+                txid: Txid::from_str(
+                    "0000000000000000000000000000000000000000000000000000000000000001",
+                )?,
+                vout: 0,
+            },
+            value: Amount::from_sat(100_000_000),
+            script_pubkey,
+            index,
+            is_change,
+            funding_tx: String::new(),
+            network: Some(network),
+        }]
+    } else {
+        let utxos: Vec<ManualUtxo> = if selected.is_empty() {
+            available
+        } else {
+            selected
+                .iter()
+                .map(|outpoint| {
+                    available
+                        .iter()
+                        .find(|u| u.outpoint == *outpoint)
+                        .cloned()
+                        .ok_or_else(|| format!("{} is not among the UTXOs in {}", outpoint, source))
+                })
+                .collect::<Result<_, _>>()?
+        };
+        println!("\nSpending {} UTXO(s) from {}", utxos.len(), source);
+        for utxo in &utxos {
+            // The file may have been written for another wallet, or for the
+            // same keys on another network.
+            if let Some(utxo_network) = utxo.network {
+                check_same_network(utxo_network, network)
+                    .map_err(|e| format!("{} in {}: {}", utxo.outpoint, source, e))?;
+            }
+            let address = wallet.derive_address(utxo.index, utxo.is_change)?;
+            if address.script_pubkey() != utxo.script_pubkey {
+                return Err(format!("{} does not pay this wallet", utxo.outpoint).into());
+            }
+        }
+        utxos
+    };
+
+    // UTXOs of auxiliary accounts, spent alongside the multisig's.
+    let mut aux_utxos = Vec::new();
+    let mut total_in = utxos
+        .iter()
+        .try_fold(Amount::ZERO, |total, u| total.checked_add(u.value))
+        .ok_or("input amounts overflow")?;
+    for input in &aux_inputs {
+        let file = wallet_file
+            .as_ref()
+            .ok_or("auxiliary accounts are kept in wallet.json")?;
+        let account = file.auxiliary(&input.label)?.clone();
+        let txout = TxOut {
+            value: input.value,
+            script_pubkey: account.address(input.index, network)?.script_pubkey(),
+        };
+        total_in = total_in
+            .checked_add(input.value)
+            .ok_or("input amounts overflow")?;
+        println!(
+            "Spending {} from {} index {}",
+            format_amount(input.value, unit),
+            account.label,
+            input.index
+        );
+        aux_utxos.push((account, input, txout));
+    }
 
-    // Simulated UTXO - in production, query from Bitcoin Core
-    let utxo = TxOut {
-        value: Amount::from_sat(100_000_000),
-        script_pubkey: receive_addr.script_pubkey(),
+    let mut satisfaction_weight = wallet.expected_satisfaction_weight()? * utxos.len() as u64;
+    for (account, _, _) in &aux_utxos {
+        satisfaction_weight += account.max_satisfaction_weight()?;
+    }
+    let estimator = FeeEstimator {
+        feerate,
+        inputs: utxos.len() + aux_utxos.len(),
+        satisfaction_weight,
     };
-    let outpoint = OutPoint {
-        txid: Txid::from_str("0000000000000000000000000000000000000000000000000000000000000001")?,
-        vout: 0,
+
+    // Change has its own chain; change goes to the next unused index,
+    // tracked in wallet.json, unless told otherwise. A random offset within
+    // the unused range keeps change from landing on a predictable index.
+    let next_unused = wallet_file.as_ref().map_or(0, |f| f.next_change_index);
+    let change_index = match (change_index, change_spread) {
+        (Some(_), Some(_)) => {
+            return Err("--change-index cannot be combined with --random-change".into());
+        }
+        (Some(index), None) => index,
+        (None, Some(spread)) => next_unused
+            .checked_add(thread_rng().gen_range(0..spread))
+            .ok_or("no change index is left after the recorded one")?,
+        (None, None) => next_unused,
     };
 
-    let dest = Address::from_str("bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080")?
-        .require_network(network)?;
-    let send_amt = Amount::from_sat(50_000_000);
-    let fee = Amount::from_sat(1000);
-    let change_amt = utxo.value - send_amt - fee;
-    let change_addr = wallet.derive_address(1)?;
+    let mut aux_outputs = Vec::new();
+    let (outputs, fee) = if payees.is_empty() {
+        default_outputs(
+            &wallet,
+            payment.as_ref(),
+            total_in,
+            &estimator,
+            max_per_output,
+            change_index,
+            unit,
+        )?
+    } else {
+        if max_per_output.is_some() {
+            return Err("--max-per-output cannot be combined with --pay".into());
+        }
+        let mut destinations = Vec::new();
+        for (payee, _) in &payees {
+            let addr = match payee {
+                Payee::Address(addr) => parse_address(addr, network)?,
+                Payee::Descriptor { descriptor, index } => {
+                    let script = descriptor_script_pubkey(descriptor, *index)?;
+                    Address::from_script(&script, network)?
+                }
+                // The account's next unused address, then the one after for
+                // a second output to the same account.
+                Payee::Auxiliary(label) => {
+                    let account = wallet_file
+                        .as_mut()
+                        .ok_or("auxiliary accounts are kept in wallet.json")?
+                        .auxiliary_mut(label)?;
+                    let index = account.next_index;
+                    account.next_index = index.checked_add(1).ok_or("account exhausted")?;
+                    aux_outputs.push((destinations.len(), account.clone(), index));
+                    account.address(index, network)?
+                }
+                Payee::Change => wallet.derive_address(change_index, true)?,
+            };
+            destinations.push(addr);
+        }
+        let scripts: Vec<ScriptBuf> = destinations.iter().map(Address::script_pubkey).collect();
+        let fee = estimator.fee(&scripts)?;
+        let available = total_in.checked_sub(fee).ok_or("fee exceeds input value")?;
+        let shares: Vec<_> = payees.iter().map(|(_, share)| *share).collect();
+        let amounts = allocate_shares(available, &shares)?;
 
-    println!("\nBuilding transaction:");
-    println!("  Send: {} sat -> {}", send_amt.to_sat(), dest);
-    println!("  Change: {} sat -> {}", change_amt.to_sat(), change_addr);
-    println!("  Fee: {} sat", fee.to_sat());
+        println!("\nBuilding transaction:");
+        let mut outputs = Vec::new();
+        let mut paid = Amount::ZERO;
+        for (i, (((payee, share), addr), value)) in
+            payees.iter().zip(&destinations).zip(amounts).enumerate()
+        {
+            let label = match share {
+                OutputShare::Percent(bps) => format!(" ({}.{:02}%)", bps / 100, bps % 100),
+                OutputShare::Remainder => " (remainder)".to_string(),
+                OutputShare::Fixed(_) => String::new(),
+            };
+            let note = labels
+                .iter()
+                .find(|(at, _)| *at == i)
+                .map(|(_, note)| format!(" [{}]", note))
+                .unwrap_or_default();
+            if matches!(payee, Payee::Change) {
+                if value < addr.script_pubkey().minimal_non_dust() {
+                    return Err(format!(
+                        "change of {} would be dust; pay less or spend more UTXOs",
+                        format_amount(value, unit)
+                    )
+                    .into());
+                }
+                println!("  Change: {} -> {}", format_amount(value, unit), addr);
+            } else {
+                // What is left for a sweep, once the fee is paid, may be dust.
+                if *share == OutputShare::Remainder
+                    && value < addr.script_pubkey().minimal_non_dust()
+                {
+                    return Err(format!(
+                        "{} to {} would be dust; spend more UTXOs or lower the fee rate",
+                        format_amount(value, unit),
+                        addr
+                    )
+                    .into());
+                }
+                paid = paid.checked_add(value).ok_or("output amounts overflow")?;
+                println!(
+                    "  Pay: {}{} -> {}{}",
+                    format_amount(value, unit),
+                    label,
+                    addr,
+                    note
+                );
+            }
+            outputs.push(TxOut {
+                value,
+                script_pubkey: addr.script_pubkey(),
+            });
+        }
+        println!(
+            "  Fee: {}{}",
+            format_amount(fee, unit),
+            estimator.rate_label()
+        );
+        if send_max {
+            println!(
+                "  Sweep: all {} inputs, {}, no change",
+                estimator.inputs,
+                format_amount(total_in, unit)
+            );
+        }
+        if batch_file.is_some() {
+            println!(
+                "  Total: {} to {} payees, {} with the fee",
+                format_amount(paid, unit),
+                payees
+                    .iter()
+                    .filter(|(payee, _)| !matches!(payee, Payee::Change))
+                    .count(),
+                format_amount(paid.checked_add(fee).ok_or("amounts overflow")?, unit)
+            );
+        }
+        (outputs, fee)
+    };
 
-    let tx = Transaction {
-        version: transaction::Version::TWO,
-        lock_time: absolute::LockTime::ZERO,
-        input: vec![TxIn {
-            previous_output: outpoint,
+    let spent = outputs
+        .iter()
+        .try_fold(fee, |total, o| total.checked_add(o.value))
+        .ok_or("output amounts overflow")?;
+    if spent != total_in {
+        return Err("outputs and fee do not add up to the input value".into());
+    }
+
+    let inputs = utxos
+        .iter()
+        .map(|u| u.outpoint)
+        .chain(aux_utxos.iter().map(|(_, input, _)| input.outpoint))
+        .map(|previous_output| TxIn {
+            previous_output,
             script_sig: ScriptBuf::new(),
-            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            sequence,
             witness: bitcoin::Witness::new(),
-        }],
-        output: vec![
-            TxOut {
-                value: send_amt,
-                script_pubkey: dest.script_pubkey(),
-            },
-            TxOut {
-                value: change_amt,
-                script_pubkey: change_addr.script_pubkey(),
-            },
-        ],
+        })
+        .collect();
+    // A time lock, for the waiting period of a signer policy's approval
+    // tier: signers check it lies far enough ahead when they sign.
+    let lock_time = match lock_hours {
+        Some(hours) => {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+            let until = hours
+                .checked_mul(3600)
+                .and_then(|secs| now.checked_add(secs))
+                .and_then(|until| u32::try_from(until).ok())
+                .ok_or("--lock-hours is too far ahead")?;
+            let lock_time = absolute::LockTime::from_time(until)?;
+            println!("\nTime-locked until unix time {}", until);
+            lock_time
+        }
+        None => absolute::LockTime::ZERO,
+    };
+    let tx = Transaction {
+        version: transaction::Version::TWO,
+        lock_time,
+        input: inputs,
+        output: outputs,
     };
 
     let mut psbt = Psbt::from_unsigned_tx(tx)?;
-    psbt.inputs[0].witness_utxo = Some(utxo.clone());
-    psbt.inputs[0].witness_script = Some(wallet.witness_script(addr_index)?);
-
-    let secp = Secp256k1::new();
+    set_psbt_policy_id(&mut psbt, wallet.policy_id());
+    set_psbt_network(&mut psbt, network);
+    if is_training {
+        training::mark_psbt(&mut psbt);
+    }
+    set_psbt_created_at(
+        &mut psbt,
+        SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+    );
+    // Signers with a wallet file refuse requests that never expire.
+    let expiry = match expiry {
+        Some(expiry) => expiry,
+        None => {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+            Expiry::Time(now.saturating_add(DEFAULT_EXPIRY_SECS))
+        }
+    };
+    set_psbt_expiry(&mut psbt, expiry);
+    println!("\nSignature request expires after {}", expiry);
     for origin in &wallet.xpub_origins {
-        let child_path = DerivationPath::from_str(&format!("m/{}", addr_index))?;
-        let child_xpub = origin.xpub.derive_pub(&secp, &child_path)?;
-        let full_path =
-            DerivationPath::from_str(&format!("{}/{}", origin.derivation_path, addr_index))?;
-        psbt.inputs[0]
-            .bip32_derivation
-            .insert(child_xpub.public_key, (origin.fingerprint, full_path));
+        psbt.xpub.insert(
+            origin.xpub,
+            (origin.fingerprint, origin.derivation_path.clone()),
+        );
+    }
+    // Keep where descriptor payments came from, so reviewers can re-derive
+    // the address.
+    for (output, (payee, _)) in psbt.outputs.iter_mut().zip(&payees) {
+        if let Payee::Descriptor { descriptor, index } = payee {
+            set_output_descriptor(output, descriptor, *index);
+        }
+    }
+    for (i, label) in &labels {
+        set_output_label(&mut psbt.outputs[*i], label);
+    }
+    for (i, account, index) in &aux_outputs {
+        set_output_descriptor(&mut psbt.outputs[*i], &account.descriptor, *index);
+        account.update_psbt_output(&mut psbt, *i, *index)?;
+    }
+    for (input, utxo) in psbt.inputs.iter_mut().zip(&utxos) {
+        input.witness_utxo = Some(utxo.txout());
+        if !utxo.funding_tx.is_empty() {
+            input.non_witness_utxo = Some(utxo.funding_tx()?);
+        }
+        wallet.update_psbt_input(input, utxo.index, utxo.is_change)?;
+    }
+    for (i, (account, input, txout)) in aux_utxos.into_iter().enumerate() {
+        let i = utxos.len() + i;
+        psbt.inputs[i].witness_utxo = Some(txout);
+        account.update_psbt_input(&mut psbt, i, input.index)?;
+    }
+    // Record the change index in the PSBT so each signer can re-derive it.
+    let change_output = if payees.is_empty() {
+        Some(psbt.outputs.len() - 1)
+    } else {
+        payees
+            .iter()
+            .position(|(payee, _)| matches!(payee, Payee::Change))
+    };
+    if let Some(change) = change_output {
+        wallet.update_psbt_output(&mut psbt.outputs[change], change_index, true)?;
     }
 
+    let bundle_key = bundle_key
+        .map(|key_file| load_bundle_key(key_file, &wallet))
+        .transpose()?;
     let psbt_b64 = STANDARD.encode(psbt.serialize());
-    std::fs::write("unsigned.psbt", psbt.serialize())?;
-    std::fs::write("unsigned.psbt.base64", &psbt_b64)?;
+    write_atomic("unsigned.psbt", psbt.serialize())?;
+    write_atomic("unsigned.psbt.base64", &psbt_b64)?;
 
     println!("\nPSBT created: unsigned.psbt.base64");
-    println!("\nNext: cargo run --bin signer -- key_a.json unsigned.psbt.base64");
+    if let Some(opts) = &armor_opts {
+        write_atomic("unsigned.psbt.asc", armor::armor(&psbt.serialize(), opts))?;
+        println!("Armored copy: unsigned.psbt.asc");
+    }
+    if let Some((key_data, key)) = &bundle_key {
+        let bundle = OfflineBundle::new(&wallet, &psbt, key)?;
+        write_atomic(
+            "unsigned.bundle.json",
+            serde_json::to_string_pretty(&bundle)?,
+        )?;
+        println!("Offline bundle: unsigned.bundle.json");
+        println!("  Summary: {}", bundle.summary.digest()?);
+        println!("  Signed by coordinator key {}", bundle.coordinator);
+        // Signers pin this once; it is only written if missing, so a
+        // changed key shows up as a refused bundle, not a new pin.
+        if !std::path::Path::new(COORDINATOR_KEY_FILE).exists() {
+            let pin = CoordinatorKey::new(&key_data.name, key);
+            write_atomic(COORDINATOR_KEY_FILE, serde_json::to_string_pretty(&pin)?)?;
+            println!(
+                "  Copy {} to each signer and verifier once",
+                COORDINATOR_KEY_FILE
+            );
+        }
+    }
+    // Only the indexes used here are written back, under the file's lock,
+    // so a receive index `donation` handed out meanwhile is kept.
+    let used_aux = !aux_outputs.is_empty();
+    if wallet_file.is_some() && (change_output.is_some() || used_aux) {
+        WalletFile::update(&wallet_path, |file| {
+            if change_output.is_some() {
+                file.next_change_index = file.next_change_index.max(change_index.saturating_add(1));
+            }
+            for (_, account, index) in &aux_outputs {
+                let saved = file.auxiliary_mut(&account.label)?;
+                saved.next_index = saved.next_index.max(index.saturating_add(1));
+            }
+            Ok(())
+        })?;
+    }
+    println!(
+        "\nNext: cargo run --features full --bin signer -- {} unsigned.psbt.base64",
+        key_files[0]
+    );
 
     Ok(())
 }
+
+/// Reads the `--bundle` key file, which must not hold a cosigner's key:
+/// the coordinator never handles a cosigner's private key.
+fn load_bundle_key(
+    key_file: &str,
+    wallet: &MultisigWallet,
+) -> Result<(KeyData, SecretKey), Box<dyn std::error::Error>> {
+    let key_data: KeyData = serde_json::from_str(&std::fs::read_to_string(key_file)?)
+        .map_err(|e| format!("{}: {}", key_file, e))?;
+    let xprv = Xpriv::from_str(&key_data.xprv)?;
+    let xpub = Xpub::from_priv(&Secp256k1::new(), &xprv);
+    // A cosigner without a master fingerprint is known by its xpub's own.
+    let cosigner = wallet.xpub_origins.iter().find(|o| {
+        o.xpub == xpub
+            || o.fingerprint == xpub.fingerprint()
+            || o.fingerprint.to_string() == key_data.fingerprint
+    });
+    if let Some(cosigner) = cosigner {
+        return Err(format!(
+            "{} holds the key of cosigner {}; sign bundles with a key that is not a cosigner",
+            key_file,
+            wallet.cosigner_label(cosigner.fingerprint)
+        )
+        .into());
+    }
+    Ok((key_data, xprv.private_key))
+}
+
+/// Where a `--pay`, `--to-descriptor`, `--to-aux` or `--batch` output goes.
+enum Payee {
+    Address(String),
+    /// Address `index` of a descriptor's receive chain, derived locally.
+    Descriptor {
+        descriptor: String,
+        index: u32,
+    },
+    /// The next unused address of an auxiliary account in wallet.json.
+    Auxiliary(String),
+    /// The wallet's change address, for what a batch leaves over.
+    Change,
+}
+
+/// A UTXO of an auxiliary account, given with `--aux-input`.
+struct AuxInput {
+    label: String,
+    index: u32,
+    outpoint: OutPoint,
+    value: Amount,
+}
+
+/// Parses the value of a `--pay`, `--to-descriptor` or `--to-aux` flag.
+fn parse_payee(flag: &str, spec: &str) -> Result<(Payee, OutputShare), Box<dyn std::error::Error>> {
+    match flag {
+        "--pay" => {
+            let (addr, share) = spec
+                .split_once('=')
+                .ok_or("--pay expects ADDRESS=SHARE, e.g. bcrt1q...=70%")?;
+            Ok((Payee::Address(addr.to_string()), share.parse()?))
+        }
+        "--to-descriptor" => {
+            // Split from the right: miniscript itself may contain ':'.
+            let (rest, share) = spec
+                .rsplit_once(':')
+                .ok_or("--to-descriptor expects DESC:INDEX:SHARE")?;
+            let (descriptor, index) = rest
+                .rsplit_once(':')
+                .ok_or("--to-descriptor expects DESC:INDEX:SHARE")?;
+            let payee = Payee::Descriptor {
+                descriptor: descriptor.to_string(),
+                index: index.parse()?,
+            };
+            Ok((payee, share.parse()?))
+        }
+        _ => {
+            let (label, share) = spec
+                .split_once(':')
+                .ok_or("--to-aux expects LABEL:SHARE, e.g. ops-change:20000sat")?;
+            Ok((Payee::Auxiliary(label.to_string()), share.parse()?))
+        }
+    }
+}
+
+/// Parses the value of an `--aux-input` flag.
+fn parse_aux_input(spec: &str) -> Result<AuxInput, Box<dyn std::error::Error>> {
+    let parts: Vec<&str> = spec.split(':').collect();
+    let [label, index, txid, vout, amount] = parts.as_slice() else {
+        return Err("--aux-input expects LABEL:INDEX:TXID:VOUT:AMOUNT".into());
+    };
+    Ok(AuxInput {
+        label: label.to_string(),
+        index: index.parse()?,
+        outpoint: OutPoint {
+            txid: Txid::from_str(txid)?,
+            vout: vout.parse()?,
+        },
+        value: parse_amount(amount)?,
+    })
+}
+
+/// Fee charged without --feerate or a chain's estimate.
+const FLAT_FEE: Amount = Amount::from_sat(1000);
+
+/// Sets the fee once the outputs are known: the flat fee, or the
+/// --feerate (or the chain's estimate) over the expected size of the
+/// signed transaction.
+struct FeeEstimator {
+    /// sat/vB.
+    feerate: Option<f64>,
+    inputs: usize,
+    /// Script sigs and witnesses of all inputs, as expected once signed.
+    satisfaction_weight: Weight,
+}
+
+impl FeeEstimator {
+    fn fee(&self, scripts: &[ScriptBuf]) -> Result<Amount, Box<dyn std::error::Error>> {
+        let Some(rate) = self.feerate else {
+            return Ok(FLAT_FEE);
+        };
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn::default(); self.inputs],
+            output: scripts
+                .iter()
+                .map(|script_pubkey| TxOut {
+                    value: Amount::ZERO,
+                    script_pubkey: script_pubkey.clone(),
+                })
+                .collect(),
+        };
+        // Without witnesses the weight leaves out the segwit marker and
+        // flag and each input's empty witness count.
+        let weight =
+            tx.weight() + Weight::from_wu(2 + self.inputs as u64) + self.satisfaction_weight;
+        Ok(Amount::from_sat(
+            (rate * weight.to_vbytes_ceil() as f64).ceil() as u64,
+        ))
+    }
+
+    fn rate_label(&self) -> String {
+        self.feerate
+            .map(|rate| format!(" ({} sat/vB)", rate))
+            .unwrap_or_default()
+    }
+}
+
+/// The `--to`/`--amount` payment, or else the demo's fixed send,
+/// optionally split, plus change. Returns the outputs and the fee.
+fn default_outputs(
+    wallet: &MultisigWallet,
+    payment: Option<&(String, Amount)>,
+    input_value: Amount,
+    estimator: &FeeEstimator,
+    max_per_output: Option<Amount>,
+    change_index: u32,
+    unit: AmountUnit,
+) -> Result<(Vec<TxOut>, Amount), Box<dyn std::error::Error>> {
+    let (dest, send_amt) = match payment {
+        Some((to, amount)) => (parse_address(to, wallet.network)?, *amount),
+        None if wallet.network != Network::Regtest => {
+            return Err("the demo payment is regtest-only; pass --to and --amount".into());
+        }
+        None => (
+            parse_address(
+                "bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080",
+                wallet.network,
+            )?,
+            Amount::from_sat(50_000_000),
+        ),
+    };
+    let change_addr = wallet.derive_address(change_index, true)?;
+
+    let send_parts = match max_per_output {
+        Some(cap) => split_amount(send_amt, cap)?,
+        None => vec![send_amt],
+    };
+    let scripts: Vec<ScriptBuf> = send_parts
+        .iter()
+        .map(|_| dest.script_pubkey())
+        .chain(std::iter::once(change_addr.script_pubkey()))
+        .collect();
+    let fee = estimator.fee(&scripts)?;
+    let change_amt = input_value
+        .checked_sub(send_amt)
+        .and_then(|left| left.checked_sub(fee))
+        .ok_or("input value does not cover the payment and fee")?;
+    if change_amt < change_addr.script_pubkey().minimal_non_dust() {
+        return Err(format!(
+            "change of {} would be dust; send less or spend more UTXOs",
+            format_amount(change_amt, unit)
+        )
+        .into());
+    }
+
+    println!("\nBuilding transaction:");
+    println!("  Send: {} -> {}", format_amount(send_amt, unit), dest);
+    if send_parts.len() > 1 {
+        println!(
+            "    Split into {} outputs of at most {}:",
+            send_parts.len(),
+            format_amount(max_per_output.unwrap_or(send_amt), unit)
+        );
+        for part in &send_parts {
+            println!("      {}", format_amount(*part, unit));
+        }
+    }
+    println!(
+        "  Change: {} -> {} (index {})",
+        format_amount(change_amt, unit),
+        change_addr,
+        change_index
+    );
+    println!(
+        "  Fee: {}{}",
+        format_amount(fee, unit),
+        estimator.rate_label()
+    );
+
+    Ok((
+        send_parts
+            .iter()
+            .map(|&value| TxOut {
+                value,
+                script_pubkey: dest.script_pubkey(),
+            })
+            .chain(std::iter::once(TxOut {
+                value: change_amt,
+                script_pubkey: change_addr.script_pubkey(),
+            }))
+            .collect(),
+        fee,
+    ))
+}
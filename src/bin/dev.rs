@@ -0,0 +1,185 @@
+//! Development helpers for regtest and signet.
+//!
+//! `dev fund` pays a wallet address through Bitcoin Core and records the
+//! resulting UTXO in utxos.json, as `utxo add` would. On regtest it mines
+//! the coins; with `--from-wallet` it sends them from a funded Core wallet
+//! instead, which is the only way on signet.
+
+use bitcoin::consensus::encode::deserialize_hex;
+use bitcoin::constants::{COINBASE_MATURITY, genesis_block};
+use bitcoin::opcodes::OP_TRUE;
+use bitcoin::{Address, Amount, Network, ScriptBuf, Transaction, Txid};
+use psbt_coordinator::chain_source::{ChainConfig, ChainSource};
+use psbt_coordinator::cli::Args;
+use psbt_coordinator::core_rpc::{CoreRpc, CoreRpcConfig};
+use psbt_coordinator::utxo_store::{ManualUtxo, UTXO_FILE, UtxoStore};
+use psbt_coordinator::wallet_file::{WALLET_FILE, WalletFile};
+use psbt_coordinator::{AmountUnit, format_amount, parse_amount};
+use std::time::{Duration, Instant};
+
+/// How often to ask the node whether a sent transaction confirmed.
+const POLL_INTERVAL: Duration = Duration::from_secs(15);
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse("dev");
+    match args.subcommand() {
+        Some(("fund", args)) => fund(&args),
+        _ => Err("expected a subcommand".into()),
+    }
+}
+
+fn fund(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let core_rpc = args.value("--core-rpc");
+    let chain_source = args.value("--chain-source");
+    let index: Option<u32> = args.parse_value("--index")?;
+    let from_wallet = args.value("--from-wallet");
+    let amount: Option<Amount> = args.value("--amount").map(parse_amount).transpose()?;
+    let confirmations: u64 = args.parse_value("--confirmations")?.unwrap_or(1);
+    if confirmations == 0 {
+        return Err("--confirmations must be at least 1".into());
+    }
+    let wait_minutes: u64 = args.parse_value("--wait-minutes")?.unwrap_or(60);
+
+    let file = WalletFile::load(WALLET_FILE)?;
+    let wallet = file.wallet()?;
+    let network = wallet.network;
+    if !matches!(network, Network::Regtest | Network::Signet) {
+        return Err(format!("dev fund is for regtest and signet, not {}", network).into());
+    }
+    // Only Core can mine or send; without a config, bitcoin-cli's defaults
+    // for the chain reach a local node.
+    let config = match (core_rpc, chain_source) {
+        (Some(_), Some(_)) => {
+            return Err("--core-rpc cannot be combined with --chain-source".into());
+        }
+        (Some(path), None) => CoreRpcConfig::load(path)?,
+        (None, Some(path)) => match ChainConfig::load(path)? {
+            ChainConfig::Core(config) => config,
+            _ => return Err(format!("{}: dev fund needs a Core backend", path).into()),
+        },
+        (None, None) => CoreRpcConfig::default(),
+    };
+    let node = config.connect(network);
+    if node.genesis_hash()? != genesis_block(network).block_hash() {
+        return Err(format!("Bitcoin Core serves a different chain than {}", network).into());
+    }
+
+    let index = index.unwrap_or(file.next_receive_index);
+    let address = wallet.derive_address(index, false)?;
+    println!("Funding receive address {}: {}", index, address);
+
+    let raw_tx = match (from_wallet, amount) {
+        (Some(name), Some(amount)) => {
+            let wait = Duration::from_secs(wait_minutes.saturating_mul(60));
+            send(&node, network, name, &address, amount, confirmations, wait)?
+        }
+        (Some(_), None) => return Err("--from-wallet needs --amount".into()),
+        (None, Some(_)) => {
+            return Err("--amount needs --from-wallet; a mined coinbase pays the subsidy".into());
+        }
+        (None, None) if network != Network::Regtest => {
+            return Err("only regtest mines on demand; pass --from-wallet and --amount".into());
+        }
+        (None, None) => mine(&node, network, &address)?,
+    };
+
+    let tx: Transaction = deserialize_hex(&raw_tx)?;
+    let vout = tx
+        .output
+        .iter()
+        .position(|output| output.script_pubkey == address.script_pubkey())
+        .ok_or("the funding transaction does not pay the address")?;
+    let utxo = ManualUtxo::from_funding_tx(&wallet, &raw_tx, vout as u32, index, false)?;
+    println!(
+        "{}: {} to address index {}",
+        utxo.outpoint,
+        format_amount(utxo.value, AmountUnit::default()),
+        index
+    );
+    let mut store = UtxoStore::load_or_default()?;
+    store.add(utxo)?;
+    store.save(UTXO_FILE)?;
+    println!("Saved to {}", UTXO_FILE);
+    WalletFile::advance_receive_index(WALLET_FILE, index)?;
+    println!("\nNext: cargo run --features full --bin coordinator");
+    Ok(())
+}
+
+/// Address the blocks that mature a coinbase are mined to: a P2WSH of
+/// `OP_TRUE`, so their coinbases are nobody's and the wallet holds only
+/// what was recorded.
+fn burn_address(network: Network) -> Address {
+    Address::p2wsh(
+        &ScriptBuf::builder().push_opcode(OP_TRUE).into_script(),
+        network,
+    )
+}
+
+/// Mines a block paying `address`, then enough blocks on top for its
+/// coinbase to be spendable. Returns the coinbase, hex.
+fn mine(
+    node: &CoreRpc,
+    network: Network,
+    address: &Address,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let block = node
+        .generate_to_address(1, address)?
+        .pop()
+        .ok_or("Core mined no block")?;
+    let raw_tx = node.coinbase_transaction(&block)?;
+    println!("Mined block {}", block);
+    // A coinbase may be spent COINBASE_MATURITY blocks after its own, so
+    // the block after these can already include the spend.
+    node.generate_to_address(COINBASE_MATURITY - 1, &burn_address(network))?;
+    println!(
+        "Mined {} more blocks for the coinbase to mature",
+        COINBASE_MATURITY - 1
+    );
+    Ok(raw_tx)
+}
+
+/// Sends `amount` from the Core wallet `from` and waits for it to confirm:
+/// on regtest by mining, elsewhere by polling the node for up to `wait`.
+/// Returns the transaction, hex.
+fn send(
+    node: &CoreRpc,
+    network: Network,
+    from: &str,
+    address: &Address,
+    amount: Amount,
+    confirmations: u64,
+    wait: Duration,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let txid: Txid = node.send_to_address(from, address, amount)?;
+    println!(
+        "Sent {} from Core wallet {}: {}",
+        format_amount(amount, AmountUnit::default()),
+        from,
+        txid
+    );
+    let deadline = Instant::now() + wait;
+    loop {
+        let (confirmed, raw_tx) = node.wallet_transaction(from, &txid)?;
+        if confirmed >= confirmations {
+            println!("Confirmed {} times", confirmed);
+            return Ok(raw_tx);
+        }
+        if network == Network::Regtest {
+            let blocks = (confirmations - confirmed) as u32;
+            node.generate_to_address(blocks, &burn_address(Network::Regtest))?;
+            continue;
+        }
+        if Instant::now() >= deadline {
+            return Err(format!(
+                "{} has {} of {} confirmations; record it with `utxo add` once it confirms",
+                txid, confirmed, confirmations
+            )
+            .into());
+        }
+        println!(
+            "Waiting for confirmation ({} of {})...",
+            confirmed, confirmations
+        );
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
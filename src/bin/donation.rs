@@ -0,0 +1,180 @@
+//! Serves the wallet's next unused receive address for a donation page.
+//!
+//! Each request checks, through a watch-only Core wallet tracking the
+//! multisig (see `export --format core`), whether the address being shown
+//! has been paid. Once it has, the receive index in `wallet.json` moves on,
+//! so every donor after the first payment sees a fresh address. The index
+//! is read from the file on every request and advanced under its lock, so
+//! addresses handed out by other tools in the meantime are not reused.
+//!
+//! Routes: `/` (HTML), `/address.json` and `/qr.svg`.
+
+use bitcoin::{Address, Amount, Denomination, Network};
+use psbt_coordinator::address_format::bip21_uri;
+use psbt_coordinator::cli::Args;
+use psbt_coordinator::shutdown::Shutdown;
+use psbt_coordinator::wallet_file::{WALLET_FILE, WalletFile};
+use psbt_coordinator::{MultisigWallet, print_wallet_info};
+use qrcode::QrCode;
+use qrcode::render::svg;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::Command;
+use std::time::Duration;
+
+/// How long a client may take to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where the donation address is tracked.
+struct Donations {
+    wallet: MultisigWallet,
+    chain: String,
+    core_wallet: String,
+    label: Option<String>,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse("donation");
+    let listen = args.value("--listen").unwrap_or("127.0.0.1:8080");
+    let chain = args.value("--chain").unwrap_or("regtest").to_string();
+    let core_wallet = args.required("core_wallet_name")?.to_string();
+    let label = args.value("--label").map(String::from);
+
+    let wallet = WalletFile::load(WALLET_FILE)
+        .map_err(|e| format!("{} (create it with `wallet init`)", e))?
+        .wallet()?;
+    if Network::from_core_arg(&chain)? != wallet.network {
+        return Err(format!("{} is not a {} wallet", WALLET_FILE, chain).into());
+    }
+    print_wallet_info(&wallet);
+
+    let mut donations = Donations {
+        wallet,
+        chain,
+        core_wallet,
+        label,
+    };
+    let shutdown = Shutdown::install()?;
+    let listener = TcpListener::bind(listen)?;
+    println!("\nServing donation addresses on http://{}", listen);
+    for stream in shutdown.incoming(&listener)? {
+        // One bad request or Core hiccup must not stop the page.
+        let result = stream
+            .map_err(Into::into)
+            .and_then(|stream| handle(stream, &mut donations));
+        if let Err(e) = result {
+            eprintln!("Request failed: {}", e);
+        }
+    }
+    println!("Stopped");
+    Ok(())
+}
+
+fn handle(
+    mut stream: TcpStream,
+    donations: &mut Donations,
+) -> Result<(), Box<dyn std::error::Error>> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next(), parts.next());
+    if method != Some("GET") {
+        return respond(
+            &mut stream,
+            "405 Method Not Allowed",
+            "text/plain",
+            "GET only\n",
+        );
+    }
+
+    // Only the page's own routes look the address up, so stray requests
+    // on this unauthenticated port never reach bitcoin-cli.
+    if !matches!(path, Some("/" | "/address.json" | "/qr.svg")) {
+        return respond(&mut stream, "404 Not Found", "text/plain", "not found\n");
+    }
+    let (index, address) = donations.current_address()?;
+    let uri = bip21_uri(&address, None, donations.label.as_deref(), false);
+    match path {
+        Some("/") => {
+            let qr = qr_svg(&uri)?;
+            let page = format!(
+                "<!DOCTYPE html>\n<html><body>\n<p><a href=\"{uri}\">{address}</a></p>\n{qr}\n</body></html>\n"
+            );
+            respond(&mut stream, "200 OK", "text/html; charset=utf-8", &page)
+        }
+        Some("/address.json") => {
+            let json = serde_json::json!({
+                "index": index,
+                "address": address.to_string(),
+                "uri": uri,
+            });
+            respond(&mut stream, "200 OK", "application/json", &json.to_string())
+        }
+        _ => respond(&mut stream, "200 OK", "image/svg+xml", &qr_svg(&uri)?),
+    }
+}
+
+impl Donations {
+    /// The first receive address not yet paid, moving the recorded index
+    /// past any that have been.
+    fn current_address(&mut self) -> Result<(u32, Address), Box<dyn std::error::Error>> {
+        let mut index = WalletFile::load(WALLET_FILE)?.next_receive_index;
+        loop {
+            let address = self.wallet.derive_address(index, false)?;
+            if !self.has_received(&address)? {
+                return Ok((index, address));
+            }
+            println!("Address {} ({}) was paid; moving on", index, address);
+            index = WalletFile::advance_receive_index(WALLET_FILE, index)?;
+        }
+    }
+
+    /// Whether Core has seen any payment to `address`, confirmed or not.
+    fn has_received(&self, address: &Address) -> Result<bool, Box<dyn std::error::Error>> {
+        let output = Command::new("bitcoin-cli")
+            .arg(format!("-chain={}", self.chain))
+            .arg(format!("-rpcwallet={}", self.core_wallet))
+            .arg("getreceivedbyaddress")
+            .arg(address.to_string())
+            .arg("0")
+            .output()?;
+        if !output.status.success() {
+            return Err(format!(
+                "getreceivedbyaddress failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+        let received = Amount::from_str_in(
+            String::from_utf8_lossy(&output.stdout).trim(),
+            Denomination::Bitcoin,
+        )?;
+        Ok(received > Amount::ZERO)
+    }
+}
+
+fn qr_svg(uri: &str) -> Result<String, Box<dyn std::error::Error>> {
+    Ok(QrCode::new(uri.as_bytes())?
+        .render::<svg::Color>()
+        .min_dimensions(256, 256)
+        .build())
+}
+
+fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
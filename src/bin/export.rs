@@ -0,0 +1,75 @@
+//! Exports wallet data for external consumers.
+
+use bitcoin::Network;
+use bitcoin::Transaction;
+use bitcoin::consensus::encode;
+use psbt_coordinator::cli::Args;
+use psbt_coordinator::export::{
+    ImportTimestamp, audit_export, core_import_requests, electrum_wallet, specter_wallet,
+};
+use psbt_coordinator::schema::WalletDefinition;
+use psbt_coordinator::{MultisigWallet, ScriptType};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse("export");
+    let format = args
+        .value("--format")
+        .ok_or("missing --format auditor|wallet|core|electrum|sparrow|specter")?;
+    let range = match args.value("--range") {
+        Some(spec) => {
+            let (start, end) = spec.split_once("..").ok_or("range must be START..END")?;
+            Some(start.parse()?..end.parse()?)
+        }
+        None => None,
+    };
+    let timestamp = args
+        .parse_value("--timestamp")?
+        .unwrap_or(ImportTimestamp::Now);
+    let name = args.value("--name").unwrap_or("Multisig");
+    let blockheight = args.parse_value("--blockheight")?.unwrap_or(0);
+    let tx_files = args.values("tx.hex");
+
+    let key_files = [
+        "key_a.json",
+        "key_b.json",
+        "key_c.json",
+        "key_d.json",
+        "key_e.json",
+    ];
+    let wallet = MultisigWallet::from_key_files(&key_files, 3, ScriptType::Wsh, Network::Regtest)?;
+
+    match format {
+        "auditor" => {
+            let mut txs = Vec::new();
+            for path in &tx_files {
+                let tx: Transaction =
+                    encode::deserialize_hex(std::fs::read_to_string(path)?.trim())?;
+                txs.push(tx);
+            }
+            let export = audit_export(&wallet, range.unwrap_or(0..20), &txs)?;
+            println!("{}", serde_json::to_string_pretty(&export)?);
+        }
+        "wallet" => {
+            let definition = WalletDefinition::from_wallet(&wallet);
+            println!("{}", serde_json::to_string_pretty(&definition)?);
+        }
+        "core" => {
+            // Core keeps watching past the range as addresses get used;
+            // this is only what it derives up front.
+            let requests = core_import_requests(&wallet, range.unwrap_or(0..1000), timestamp)?;
+            println!("{}", serde_json::to_string_pretty(&requests)?);
+        }
+        "specter" => {
+            let file = specter_wallet(&wallet, name, blockheight)?;
+            println!("{}", serde_json::to_string_pretty(&file)?);
+        }
+        // Sparrow imports Electrum wallet files.
+        "electrum" | "sparrow" => println!(
+            "{}",
+            serde_json::to_string_pretty(&electrum_wallet(&wallet)?)?
+        ),
+        other => return Err(format!("unknown export format: {}", other).into()),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,72 @@
+//! Reports fees paid by finalized transactions, from the finalizer's fee
+//! history.
+
+use psbt_coordinator::cli::Args;
+use psbt_coordinator::fee_history::{FEE_HISTORY_FILE, load, summarize};
+use psbt_coordinator::{AmountUnit, format_amount};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse("fee_report");
+    let file = args.value("--file").unwrap_or(FEE_HISTORY_FILE);
+    let reference_feerate = args.parse_value("--reference-feerate")?.unwrap_or(10.0);
+
+    let records = load(file).map_err(|e| format!("{}: {}", file, e))?;
+    let months = summarize(&records, reference_feerate);
+    if args.flag("--json") {
+        println!("{}", serde_json::to_string_pretty(&months)?);
+        return Ok(());
+    }
+
+    println!("Transactions ({}):", file);
+    for record in &records {
+        let estimated = match record.estimated_vsize {
+            Some(vsize) => format!("{:>6} vB", vsize),
+            None => format!("{:>9}", "-"),
+        };
+        let fee = match (record.fee_sat, record.feerate()) {
+            (Some(fee), Some(rate)) => format!("{:>9} sat  {:>6.1} sat/vB", fee, rate),
+            _ => String::from("fee unknown"),
+        };
+        let kind = if record.is_consolidation() {
+            "  consolidation"
+        } else if record.change_outputs == 0 {
+            "  no change"
+        } else {
+            ""
+        };
+        println!(
+            "  {}  est {}  actual {:>6} vB  {}{}",
+            record.txid, estimated, record.actual_vsize, fee, kind
+        );
+    }
+
+    let sats = |sat: u64| format_amount(bitcoin::Amount::from_sat(sat), AmountUnit::default());
+    println!(
+        "\nBy month (consolidations against {} sat/vB):",
+        reference_feerate
+    );
+    for month in &months {
+        println!("  {}", month.month);
+        println!("    Transactions:         {}", month.transactions);
+        println!(
+            "    Fees:                 {} for {} vB",
+            sats(month.fee_sat),
+            month.vbytes
+        );
+        println!(
+            "    Estimate error:       {:+} vB",
+            month.estimate_error_vbytes
+        );
+        println!(
+            "    Saved, no change:     {}",
+            sats(month.change_avoidance_savings_sat)
+        );
+        let consolidation = month.consolidation_savings_sat;
+        println!(
+            "    Saved, consolidating: {}{}",
+            if consolidation < 0 { "-" } else { "" },
+            sats(consolidation.unsigned_abs())
+        );
+    }
+    Ok(())
+}
@@ -1,77 +1,338 @@
 //! Finalizes PSBTs and extracts broadcast-ready transactions.
 
 use base64::{Engine, engine::general_purpose::STANDARD};
-use bitcoin::Witness;
+use bitcoin::bip32::Fingerprint;
 use bitcoin::consensus::encode;
 use bitcoin::psbt::Psbt;
+use psbt_coordinator::cli::Args;
+use psbt_coordinator::fee_history::{self, FEE_HISTORY_FILE, FeeRecord};
+use psbt_coordinator::finalize::{
+    CosignerKey, SignatureSelection, check_readiness, decoded_transaction, finalize_psbt_input,
+};
+use psbt_coordinator::payment_proof::PaymentProof;
+use psbt_coordinator::signer_policy::{SIGNER_POLICY_FILE, SignedSignerPolicy};
+use psbt_coordinator::training;
+use psbt_coordinator::wallet_file::{WALLET_FILE, WalletFile};
+use psbt_coordinator::{
+    DEFAULT_GAP_LIMIT, MultisigWallet, check_same_network, load_psbt, parse_multisig_script,
+    parse_network, parse_untrusted_psbt, psbt_expiry, psbt_network, signer_label, write_atomic,
+};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() < 2 {
-        eprintln!("Usage: {} <psbt>", args[0]);
-        std::process::exit(1);
+    let args = Args::parse("finalizer");
+    let check_only = args.flag("--check");
+    let is_training = args.flag("--training");
+    let queue_dir = args.value("--queue");
+    let network_arg = args.value("--network").map(parse_network).transpose()?;
+    let mut selection = SignatureSelection::default();
+    if let Some(list) = args.value("--prefer") {
+        selection.prefer = parse_fingerprints(list)?;
+    }
+    if let Some(list) = args.value("--avoid") {
+        selection.avoid = parse_fingerprints(list)?;
+    }
+    if args.flag("--script-order") {
+        selection.smallest_first = false;
+    }
+    let mut emit = Vec::new();
+    for list in args.values("--emit") {
+        for format in list.split(',') {
+            emit.push(format.parse::<EmitFormat>()?);
+        }
+    }
+
+    if is_training {
+        training::enter()?;
+    }
+
+    let psbt_bytes = load_psbt(args.required("psbt")?)?;
+    let mut psbt = parse_untrusted_psbt(&psbt_bytes)?;
+    if is_training {
+        training::check_psbt(&psbt)?;
+    } else if training::is_marked(&psbt) {
+        eprintln!("Warning: this PSBT was made in training mode and spends no real funds\n");
+    }
+    if let Some(expiry) = psbt_expiry(&psbt)? {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        match expiry.is_expired(now, None) {
+            Some(true) => eprintln!(
+                "Warning: this signature request expired at {}; confirm it is still wanted \
+                 before broadcasting\n",
+                expiry
+            ),
+            Some(false) => {}
+            None => eprintln!(
+                "Note: this signature request expires at {}; check the chain height before \
+                 broadcasting\n",
+                expiry
+            ),
+        }
+    }
+
+    // The network is the one wallet.json and the PSBT record, which must
+    // agree; --network may only confirm it, or name it when neither does.
+    let wallet_file = WalletFile::load_if_present()?;
+    let wallet = wallet_file.as_ref().map(WalletFile::wallet).transpose()?;
+    let mut network = network_arg;
+    let recorded = [
+        (WALLET_FILE, wallet.as_ref().map(|w| w.network)),
+        ("PSBT", psbt_network(&psbt)?),
+    ];
+    for (source, found) in recorded {
+        let Some(found) = found else { continue };
+        match network {
+            Some(expected) => {
+                check_same_network(found, expected).map_err(|e| format!("{}: {}", source, e))?;
+            }
+            None => network = Some(found),
+        }
     }
+    let network = network.ok_or_else(|| {
+        format!(
+            "neither {} nor the PSBT records a network; pass --network",
+            WALLET_FILE
+        )
+    })?;
 
-    let psbt_bytes = load_psbt(&args[1])?;
-    let mut psbt = Psbt::deserialize(&psbt_bytes)?;
+    // An approval tier of the signer policy, when the payments fall under
+    // one, must be met before the transaction is built.
+    if std::path::Path::new(SIGNER_POLICY_FILE).exists() {
+        let (Some(file), Some(wallet)) = (&wallet_file, &wallet) else {
+            return Err(format!(
+                "{} can only be checked against {}",
+                SIGNER_POLICY_FILE, WALLET_FILE
+            )
+            .into());
+        };
+        let document = SignedSignerPolicy::load(SIGNER_POLICY_FILE)?;
+        document.verify(wallet)?;
+        let lookahead = file
+            .next_receive_index
+            .max(file.next_change_index)
+            .saturating_add(DEFAULT_GAP_LIMIT);
+        let external = document.policy.external_total(&psbt, wallet, lookahead)?;
+        if let Some(tier) = document.policy.approval_tier(external) {
+            println!(
+                "Approval tier: {} sat of external payments, over {} sat",
+                external.to_sat(),
+                tier.above_sat
+            );
+            tier.check_approved(wallet, &psbt)?;
+            println!("  Tier met\n");
+        }
+    }
 
-    // Verify sufficient signatures
+    if check_only {
+        return print_readiness(&psbt, wallet.as_ref());
+    }
+
+    // Verify sufficient signatures from keys in each witness script
     for (i, input) in psbt.inputs.iter().enumerate() {
-        let sigs = input.partial_sigs.len();
-        if sigs < 3 {
-            eprintln!("Input {}: only {}/3 signatures", i, sigs);
+        if !input.tap_scripts.is_empty() {
+            // Taproot leaves are checked when the witness is built.
+            println!(
+                "Input {}: {} taproot signatures",
+                i,
+                input.tap_script_sigs.len()
+            );
+            continue;
+        }
+        let Some(script) = &input.witness_script else {
+            // A single-sig UTXO spent alongside the wallet's.
+            let signed = !input.partial_sigs.is_empty() || input.tap_key_sig.is_some();
+            if !signed {
+                eprintln!("Input {}: single-key input is not signed", i);
+                std::process::exit(1);
+            }
+            println!("Input {}: single-key input, signed", i);
+            continue;
+        };
+        let Ok((threshold, pubkeys)) = parse_multisig_script(script) else {
+            // Policy inputs may have several ways to be satisfied, e.g. a
+            // timelocked recovery key; the satisfier checks them.
+            println!(
+                "Input {}: miniscript policy, {} signature(s)",
+                i,
+                input.partial_sigs.len()
+            );
+            continue;
+        };
+        let sigs = pubkeys
+            .iter()
+            .filter(|pk| input.partial_sigs.contains_key(pk))
+            .count();
+        if sigs < threshold {
+            eprintln!("Input {}: only {}/{} signatures", i, sigs, threshold);
             std::process::exit(1);
         }
         println!("Input {}: {} signatures", i, sigs);
+        let foreign = input.partial_sigs.len() - sigs;
+        if foreign > 0 {
+            println!(
+                "  Ignoring {} signature(s) from keys not in the script",
+                foreign
+            );
+        }
     }
 
+    // Projected before the witnesses exist, to compare with the result.
+    let estimated_weight = check_readiness(&psbt).ok().map(|r| r.projected_weight);
+
     // Finalize each input
     for idx in 0..psbt.inputs.len() {
-        let input = &psbt.inputs[idx];
-        let script = input
-            .witness_script
-            .as_ref()
-            .ok_or("missing witness script")?
-            .clone();
-
-        // Sort sigs by pubkey for sortedmulti
-        let mut sigs: Vec<_> = input.partial_sigs.iter().collect();
-        sigs.sort_by(|a, b| a.0.inner.serialize().cmp(&b.0.inner.serialize()));
-
-        // Build witness: <empty> <sig1> <sig2> <sig3> <script>
-        let mut witness = Witness::new();
-        witness.push([]);
-        for (_, sig) in sigs.iter().take(3) {
-            witness.push(sig.serialize());
-        }
-        witness.push(script.as_bytes());
-
-        psbt.inputs[idx].final_script_witness = Some(witness);
-        psbt.inputs[idx].partial_sigs.clear();
-        psbt.inputs[idx].bip32_derivation.clear();
-        psbt.inputs[idx].witness_script = None;
+        finalize_psbt_input(&mut psbt, idx, &selection)?;
     }
 
-    let tx = psbt.extract_tx()?;
+    let finalized_psbt = psbt.serialize();
+    let tx = psbt.clone().extract_tx()?;
     let tx_hex = encode::serialize_hex(&tx);
 
-    std::fs::write("final_tx.hex", &tx_hex)?;
-
     println!("\nTransaction finalized");
     println!("  TXID: {}", tx.compute_txid());
     println!("  Size: {} vbytes", tx.vsize());
-    println!("  Output: final_tx.hex");
-    println!("\nBroadcast: bitcoin-cli -regtest sendrawtransaction $(cat final_tx.hex)");
+    if emit.is_empty() {
+        emit.push(EmitFormat::Hex);
+    }
+    for format in &emit {
+        let file = match format {
+            EmitFormat::Hex => {
+                write_atomic("final_tx.hex", &tx_hex)?;
+                "final_tx.hex"
+            }
+            EmitFormat::Psbt => {
+                write_atomic("final.psbt.base64", STANDARD.encode(&finalized_psbt))?;
+                "final.psbt.base64"
+            }
+            EmitFormat::Json => {
+                let json = decoded_transaction(&tx, network);
+                write_atomic("final_tx.json", serde_json::to_string_pretty(&json)?)?;
+                "final_tx.json"
+            }
+        };
+        println!("  Output: {}", file);
+    }
+    // Payees of descriptor destinations get a proof they can check against
+    // their own descriptor.
+    for proof in PaymentProof::for_transaction(&psbt, &tx) {
+        let file = format!("payment_proof_{}.json", proof.vout);
+        write_atomic(&file, serde_json::to_string_pretty(&proof)?)?;
+        println!("  Payment proof: {}", file);
+    }
+
+    if !is_training {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let record = FeeRecord::new(&psbt, &tx, estimated_weight, now);
+        fee_history::append(FEE_HISTORY_FILE, &record)?;
+        println!("  Fee history: {}", FEE_HISTORY_FILE);
+    }
+
+    match queue_dir {
+        Some(dir) => {
+            std::fs::create_dir_all(dir)?;
+            let queued = std::path::Path::new(dir).join(format!("{}.hex", tx.compute_txid()));
+            write_atomic(&queued, &tx_hex)?;
+            println!("  Queued: {}", queued.display());
+            println!(
+                "\nBroadcast: cargo run --features full --bin broadcaster -- --chain {} {}",
+                network.to_core_arg(),
+                dir
+            );
+        }
+        None => {
+            println!(
+                "\nBroadcast: bitcoin-cli -chain={} sendrawtransaction $(cat final_tx.hex)",
+                network.to_core_arg()
+            );
+        }
+    }
 
     Ok(())
 }
 
-fn load_psbt(input: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    if input.ends_with(".base64") {
-        Ok(STANDARD.decode(std::fs::read_to_string(input)?.trim())?)
-    } else if std::path::Path::new(input).exists() {
-        Ok(std::fs::read(input)?)
+/// Extra renderings of the finalized transaction, chosen with `--emit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EmitFormat {
+    /// Raw transaction hex, for `sendrawtransaction`.
+    Hex,
+    /// The PSBT with final witnesses, for tools that re-verify it.
+    Psbt,
+    /// `decoderawtransaction`-style JSON.
+    Json,
+}
+
+impl FromStr for EmitFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hex" => Ok(EmitFormat::Hex),
+            "psbt" => Ok(EmitFormat::Psbt),
+            "json" => Ok(EmitFormat::Json),
+            other => Err(format!(
+                "unknown --emit format: {} (expected hex, psbt or json)",
+                other
+            )),
+        }
+    }
+}
+
+/// Reports per-input signature status and projected size without finalizing.
+/// Without a wallet file, signers are labelled by fingerprint alone.
+fn print_readiness(
+    psbt: &Psbt,
+    wallet: Option<&MultisigWallet>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let report = check_readiness(psbt)?;
+    let label = |key: &CosignerKey| signer_label(wallet, key.fingerprint, &key.pubkey);
+
+    for input in &report.inputs {
+        println!(
+            "Input {}: {}/{} valid signatures{}",
+            input.input,
+            input.signed.len(),
+            input.threshold,
+            if input.is_ready() { "" } else { " (NOT READY)" }
+        );
+        if !input.signed.is_empty() {
+            let names: Vec<_> = input.signed.iter().map(label).collect();
+            println!("  signed by:  {}", names.join(", "));
+        }
+        for (key, err) in &input.invalid {
+            println!("  invalid:    {} {}", label(key), err);
+        }
+        for key in &input.missing {
+            println!("  waiting on: {}", label(key));
+        }
+    }
+
+    let vsize = report.projected_weight.to_vbytes_ceil();
+    println!(
+        "\nProjected size: {} vbytes ({} WU)",
+        vsize,
+        report.projected_weight.to_wu()
+    );
+    match report.fee {
+        Some(fee) => println!(
+            "Fee: {} sat ({:.1} sat/vB)",
+            fee.to_sat(),
+            fee.to_sat() as f64 / vsize as f64
+        ),
+        None => println!("Fee: unknown (missing input amounts)"),
+    }
+
+    if report.is_ready() {
+        println!("\nReady to finalize.");
+        Ok(())
     } else {
-        Ok(STANDARD.decode(input)?)
+        println!("\nNot ready: more signatures are needed.");
+        std::process::exit(1);
     }
 }
+
+fn parse_fingerprints(list: &str) -> Result<Vec<Fingerprint>, Box<dyn std::error::Error>> {
+    list.split(',')
+        .map(|fp| Ok(Fingerprint::from_str(fp.trim())?))
+        .collect()
+}
@@ -0,0 +1,74 @@
+//! Imports a multisig wallet from a Bitcoin Core watch-only descriptor wallet.
+//!
+//! Reads the wallet's active receive descriptor with `listdescriptors`,
+//! checks that it is an M-of-N `wsh(sortedmulti(...))` or `wsh(multi(...))`
+//! (optionally wrapped in `sh()`), and reports the
+//! first address Core has not handed out yet.
+
+use bitcoin::Network;
+use psbt_coordinator::cli::Args;
+use psbt_coordinator::{MultisigWallet, print_wallet_info};
+use serde_json::Value;
+use std::process::Command;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse("import_core");
+    let wallet_name = args.required("core_wallet_name")?;
+    let chain = args.value("--chain").unwrap_or("regtest");
+    let network = Network::from_core_arg(chain)?;
+
+    let output = Command::new("bitcoin-cli")
+        .arg(format!("-chain={}", chain))
+        .arg(format!("-rpcwallet={}", wallet_name))
+        .arg("listdescriptors")
+        .output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "listdescriptors failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    let listing: Value = serde_json::from_slice(&output.stdout)?;
+
+    // The active, external (receive) descriptor defines the wallet.
+    let entry = listing["descriptors"]
+        .as_array()
+        .ok_or("unexpected listdescriptors output")?
+        .iter()
+        .find(|d| {
+            d["active"].as_bool() == Some(true)
+                && d["internal"].as_bool() != Some(true)
+                && d["desc"]
+                    .as_str()
+                    .is_some_and(|s| s.starts_with("wsh(") || s.starts_with("sh(wsh("))
+        })
+        .ok_or_else(|| {
+            format!(
+                "{} has no active wsh or sh(wsh) receive descriptor",
+                wallet_name
+            )
+        })?;
+    let desc = entry["desc"].as_str().ok_or("descriptor is not a string")?;
+
+    let wallet = MultisigWallet::from_descriptor(desc, network)?;
+
+    println!("Imported from Core wallet '{}'\n", wallet_name);
+    print_wallet_info(&wallet);
+
+    // Core reports the next index it will hand out; everything below it
+    // may already have been given to a payer.
+    match entry["next_index"].as_u64().or(entry["next"].as_u64()) {
+        Some(next) => {
+            let next = u32::try_from(next)?;
+            println!(
+                "\nAddress watermark: {} (next unused: {})",
+                next,
+                wallet.derive_address(next, false)?
+            );
+        }
+        None => println!("\nAddress watermark: unknown (Core did not report one)"),
+    }
+
+    Ok(())
+}
@@ -1,12 +1,27 @@
-//! Generates 3 key pairs for 2-of-3 multisig (BIP 48 P2WSH).
+//! Generates key pairs for the default 3-of-5 multisig (BIP 48 P2WSH).
 
+use bip39::{Language, Mnemonic};
 use bitcoin::Network;
 use bitcoin::bip32::{DerivationPath, Xpriv, Xpub};
 use bitcoin::secp256k1::Secp256k1;
-use rand::RngCore;
+use psbt_coordinator::cli::Args;
+use psbt_coordinator::entropy::{
+    DICE_ROLLS_256_BITS, DeviceEntropy, DiceEntropy, EntropySource, MixedEntropy, OsEntropy,
+    SeededEntropy,
+};
+use psbt_coordinator::{account_name, bip48_path, format_path, parse_network, training};
 use serde::Serialize;
 use std::fs;
-use std::str::FromStr;
+use std::io::Read;
+
+#[derive(Serialize)]
+struct PublicKeyData {
+    name: String,
+    xpub: String,
+    fingerprint: String,
+    derivation_path: String,
+    network: String,
+}
 
 #[derive(Serialize)]
 struct KeyData {
@@ -15,39 +30,175 @@ struct KeyData {
     xpub: String,
     fingerprint: String,
     derivation_path: String,
+    network: String,
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let secp = Secp256k1::new();
-    let network = Network::Regtest;
-    let path_str = "m/48'/1'/0'/2'";
-    let path = DerivationPath::from_str(path_str)?;
+    let args = Args::parse("keygen");
+    // --language implies --mnemonic.
+    let language: Option<Language> = match args.value("--language") {
+        Some(name) => Some(parse_language(name)?),
+        None if args.flag("--mnemonic") => Some(Language::English),
+        None => None,
+    };
+    let import_name = args.value("--import");
+    let dice_file = args.value("--dice");
+    let devices = args.values("--entropy-device");
+    let deterministic = args.value("--deterministic");
+    let account: u32 = args.parse_value("--account")?.unwrap_or(0);
+    let network = match args.value("--network") {
+        Some(name) => parse_network(name)?,
+        None => Network::Regtest,
+    };
+
+    if args.flag("--training") {
+        training::enter()?;
+        training::check_key_network(network.into())?;
+    }
+
+    // The coin type follows the network: 0' on mainnet, 1' on the test
+    // networks. One seed backs a separate wallet at each BIP 48 account. Key files for
+    // account 0 keep their plain names; others get an `_accountN` suffix.
+    let path = bip48_path(network, account)?;
+
+    // Re-importing a mnemonic with --account emits that account's key
+    // without generating new seeds.
+    if let Some(name) = import_name {
+        // Read from stdin so the words never land in shell history.
+        let language = language.unwrap_or(Language::English);
+        println!("Enter the {:?} mnemonic for {}:", language, name);
+        let mut words = String::new();
+        std::io::stdin().read_to_string(&mut words)?;
+        let mnemonic = Mnemonic::parse_in(language, words.trim())?;
+        write_key(
+            &account_name(name, account),
+            &mnemonic.to_seed(""),
+            network,
+            &path,
+        )?;
+        return Ok(());
+    }
+
+    let mut entropy: Box<dyn EntropySource> = match deterministic {
+        Some(seed) => {
+            if dice_file.is_some() || !devices.is_empty() {
+                return Err("--deterministic cannot be combined with other entropy sources".into());
+            }
+            // Anyone who knows the seed string has the keys.
+            if network == Network::Bitcoin {
+                return Err("--deterministic keys are for test networks, not mainnet".into());
+            }
+            Box::new(SeededEntropy::new(seed.as_bytes()))
+        }
+        None => {
+            let mut sources: Vec<Box<dyn EntropySource>> = vec![Box::new(OsEntropy)];
+            if let Some(path) = dice_file {
+                let rolls = std::fs::read_to_string(path)?;
+                sources.push(Box::new(DiceEntropy::parse(&rolls, DICE_ROLLS_256_BITS)?));
+            }
+            for path in &devices {
+                sources.push(Box::new(DeviceEntropy::open(path)?));
+            }
+            if sources.len() == 1 {
+                Box::new(OsEntropy)
+            } else {
+                Box::new(MixedEntropy::new(sources))
+            }
+        }
+    };
 
     println!("Generating keys for 3-of-5 multisig");
-    println!("Network: {:?}, Path: {}\n", network, path_str);
+    println!("Network: {:?}, Path: {}", network, format_path(&path));
+    println!("Entropy: {}\n", entropy.describe());
 
     for name in ["key_a", "key_b", "key_c", "key_d", "key_e"] {
-        let mut seed = [0u8; 32];
-        rand::rngs::OsRng.fill_bytes(&mut seed);
-
-        let master = Xpriv::new_master(network, &seed)?;
-        let fingerprint = master.fingerprint(&secp);
-        let derived = master.derive_priv(&secp, &path)?;
-        let xpub = Xpub::from_priv(&secp, &derived);
-
-        let data = KeyData {
-            name: name.into(),
-            xprv: derived.to_string(),
-            xpub: xpub.to_string(),
-            fingerprint: fingerprint.to_string(),
-            derivation_path: path_str.into(),
-        };
-
-        let filename = format!("{}.json", name);
-        fs::write(&filename, serde_json::to_string_pretty(&data)?)?;
-        println!("{}: {} -> {}", name, fingerprint, filename);
+        let name = &account_name(name, account);
+        match language {
+            Some(language) => {
+                let mut bytes = [0u8; 32];
+                entropy.fill(&mut bytes)?;
+                let mnemonic = Mnemonic::from_entropy_in(language, &bytes)?;
+                write_key(name, &mnemonic.to_seed(""), network, &path)?;
+                println!("  {:?} words: {}", language, display_words(&mnemonic));
+            }
+            None => {
+                let mut seed = [0u8; 32];
+                entropy.fill(&mut seed)?;
+                write_key(name, &seed, network, &path)?;
+            }
+        }
     }
 
     println!("\nKeys generated. Keep xprv secret, share only xpub with coordinator.");
+    if language.is_some() {
+        println!("Write each mnemonic on its signer's word card; it is not stored.");
+    }
     Ok(())
 }
+
+fn write_key(
+    name: &str,
+    seed: &[u8],
+    network: Network,
+    path: &DerivationPath,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let secp = Secp256k1::new();
+
+    let master = Xpriv::new_master(network, seed)?;
+    let fingerprint = master.fingerprint(&secp);
+    let derived = master.derive_priv(&secp, path)?;
+    let xpub = Xpub::from_priv(&secp, &derived);
+
+    let data = KeyData {
+        name: name.into(),
+        xprv: derived.to_string(),
+        xpub: xpub.to_string(),
+        fingerprint: fingerprint.to_string(),
+        derivation_path: format_path(path),
+        network: network.to_string(),
+    };
+
+    let filename = format!("{}.json", name);
+    fs::write(&filename, serde_json::to_string_pretty(&data)?)?;
+    // The watch-only half, for the coordinator machine.
+    let public = PublicKeyData {
+        name: data.name,
+        xpub: data.xpub,
+        fingerprint: data.fingerprint,
+        derivation_path: data.derivation_path,
+        network: data.network,
+    };
+    let public_file = format!("{}.pub.json", name);
+    fs::write(&public_file, serde_json::to_string_pretty(&public)?)?;
+    println!(
+        "{}: {} -> {} ({})",
+        name, fingerprint, filename, public_file
+    );
+    Ok(())
+}
+
+fn parse_language(name: &str) -> Result<Language, Box<dyn std::error::Error>> {
+    Ok(match name.to_ascii_lowercase().as_str() {
+        "english" | "en" => Language::English,
+        "spanish" | "es" => Language::Spanish,
+        "japanese" | "ja" => Language::Japanese,
+        "french" | "fr" => Language::French,
+        "italian" | "it" => Language::Italian,
+        "portuguese" | "pt" => Language::Portuguese,
+        "czech" | "cs" => Language::Czech,
+        "korean" | "ko" => Language::Korean,
+        "chinese-simplified" | "zh-hans" => Language::SimplifiedChinese,
+        "chinese-traditional" | "zh-hant" => Language::TraditionalChinese,
+        other => return Err(format!("unsupported mnemonic language: {}", other).into()),
+    })
+}
+
+/// Japanese mnemonics are conventionally written with ideographic spaces.
+fn display_words(mnemonic: &Mnemonic) -> String {
+    let separator = if mnemonic.language() == Language::Japanese {
+        "\u{3000}"
+    } else {
+        " "
+    };
+    mnemonic.words().collect::<Vec<_>>().join(separator)
+}
@@ -0,0 +1,98 @@
+//! Hardware signer simulator for tests, demos and training.
+//!
+//! Answers a subset of HWI's command line (`enumerate`, `getxpub`,
+//! `signtx`) with HWI-shaped JSON, holding a fixed, publicly known test
+//! seed (BIP 32 test vector 1). Scripts written against HWI can run the
+//! full device flow without hardware. Never send real funds to it.
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use bitcoin::Network;
+use bitcoin::bip32::{DerivationPath, Xpriv, Xpub};
+use bitcoin::ecdsa::Signature as EcdsaSignature;
+use bitcoin::hashes::Hash;
+use bitcoin::psbt::Psbt;
+use bitcoin::secp256k1::{Message, Secp256k1};
+use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use psbt_coordinator::cli::Args;
+use psbt_coordinator::{load_psbt, parse_untrusted_psbt};
+use serde_json::json;
+use std::str::FromStr;
+
+/// BIP 32 test vector 1. Public knowledge: anything it holds can be taken.
+const TEST_SEED: [u8; 16] = [
+    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+];
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse("mock_signer");
+    // --device-type, --device-path and --fingerprint are accepted for HWI
+    // compatibility; there is only one device.
+    let network = match args.value("--chain") {
+        Some(chain) => Network::from_core_arg(chain)?,
+        None => Network::Regtest,
+    };
+
+    let secp = Secp256k1::new();
+    let master = Xpriv::new_master(network, &TEST_SEED)?;
+    let fingerprint = master.fingerprint(&secp);
+
+    let response = match args.subcommand() {
+        Some(("enumerate", _)) => json!([{
+            "type": "mock",
+            "model": "psbt_coordinator_mock_signer",
+            "path": "mock:0",
+            "fingerprint": fingerprint.to_string(),
+            "needs_pin_sent": false,
+            "needs_passphrase_sent": false,
+        }]),
+        Some(("getxpub", args)) => {
+            let path = DerivationPath::from_str(args.required("path")?)?;
+            let xpub = Xpub::from_priv(&secp, &master.derive_priv(&secp, &path)?);
+            json!({ "xpub": xpub.to_string() })
+        }
+        Some(("signtx", args)) => {
+            let mut psbt = parse_untrusted_psbt(&load_psbt(args.required("psbt")?)?)?;
+            let signed = sign_all(&mut psbt, &master)?;
+            json!({ "psbt": STANDARD.encode(psbt.serialize()), "signed": signed })
+        }
+        _ => return Err("expected enumerate, getxpub or signtx".into()),
+    };
+
+    println!("{}", response);
+    Ok(())
+}
+
+/// Signs every P2WSH input that lists a key under this device's fingerprint.
+fn sign_all(psbt: &mut Psbt, master: &Xpriv) -> Result<bool, Box<dyn std::error::Error>> {
+    let secp = Secp256k1::new();
+    let fingerprint = master.fingerprint(&secp);
+    let mut cache = SighashCache::new(&psbt.unsigned_tx);
+    let mut signed = false;
+
+    for (idx, input) in psbt.inputs.iter_mut().enumerate() {
+        let ours: Vec<_> = input
+            .bip32_derivation
+            .iter()
+            .filter(|(_, (fp, _))| *fp == fingerprint)
+            .map(|(pk, (_, path))| (*pk, path.clone()))
+            .collect();
+        for (pubkey, path) in ours {
+            let privkey = master.derive_priv(&secp, &path)?.private_key;
+            if privkey.public_key(&secp) != pubkey {
+                continue;
+            }
+            let (Some(script), Some(utxo)) = (&input.witness_script, &input.witness_utxo) else {
+                continue;
+            };
+            let sighash =
+                cache.p2wsh_signature_hash(idx, script, utxo.value, EcdsaSighashType::All)?;
+            let msg = Message::from_digest(*sighash.as_byte_array());
+            input.partial_sigs.insert(
+                bitcoin::PublicKey::new(pubkey),
+                EcdsaSignature::sighash_all(secp.sign_ecdsa_low_r(&msg, &privkey)),
+            );
+            signed = true;
+        }
+    }
+    Ok(signed)
+}
@@ -0,0 +1,28 @@
+//! Re-serializes PSBTs into a byte-stable canonical form.
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use bitcoin::hashes::{Hash, sha256};
+use psbt_coordinator::cli::Args;
+use psbt_coordinator::{
+    NormalizeOptions, load_psbt, normalize_psbt, parse_untrusted_psbt, write_atomic,
+};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse("normalize");
+    let opts = NormalizeOptions {
+        strip_unknown: args.flag("--strip-unknown"),
+        strip_proprietary: args.flag("--strip-proprietary"),
+    };
+
+    let psbt = parse_untrusted_psbt(&load_psbt(args.required("psbt")?)?)?;
+    let normalized = normalize_psbt(&psbt, opts)?;
+
+    let out_file = "normalized.psbt.base64";
+    write_atomic(out_file, STANDARD.encode(&normalized))?;
+
+    println!("Output: {}", out_file);
+    println!("Size:   {} bytes", normalized.len());
+    println!("SHA256: {}", sha256::Hash::hash(&normalized));
+
+    Ok(())
+}
@@ -0,0 +1,118 @@
+//! Finds signing sessions stuck below threshold and reminds the cosigners
+//! they are waiting on, with reminders escalating each window.
+
+use psbt_coordinator::cli::Args;
+use psbt_coordinator::escalation::{EscalationPolicy, Stage, assess};
+use psbt_coordinator::wallet_file::WalletFile;
+use psbt_coordinator::{load_psbt, parse_untrusted_psbt};
+use std::collections::BTreeMap;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse("reminders");
+    let mut policy = EscalationPolicy::default();
+    if let Some(hours) = args.parse_value::<u64>("--window-hours")? {
+        policy.window = hours.checked_mul(3600).ok_or("window too long")?;
+    }
+    if let Some(windows) = args.parse_value("--at-risk-after")? {
+        policy.at_risk_after = windows;
+    }
+    let json = args.flag("--json");
+    let notify = args.value("--notify");
+    let sessions = args.values("psbt");
+
+    let wallet = WalletFile::load_if_present()?
+        .map(|file| file.wallet())
+        .transpose()?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let mut statuses = Vec::new();
+    for session in &sessions {
+        let psbt = parse_untrusted_psbt(&load_psbt(session)?)?;
+        let status = assess(session, &psbt, wallet.as_ref(), now, &policy)
+            .map_err(|e| format!("{}: {}", session, e))?;
+        statuses.push(status);
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&statuses)?);
+    } else {
+        println!("Sessions:");
+        for status in &statuses {
+            println!(
+                "  {}  {}/{} signatures, waiting {}h  [{}]",
+                status.session,
+                status.signatures,
+                status.threshold,
+                status.age / 3600,
+                status.stage
+            );
+        }
+    }
+
+    // One digest per cosigner, listing every session waiting on them.
+    let mut digests: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for status in statuses.iter().filter(|s| s.stage != Stage::OnTrack) {
+        for cosigner in &status.missing {
+            digests
+                .entry(cosigner.name.clone())
+                .or_default()
+                .push(format!(
+                    "{} ({}/{} signatures, waiting {}h, {})",
+                    status.session,
+                    status.signatures,
+                    status.threshold,
+                    status.age / 3600,
+                    status.stage
+                ));
+        }
+    }
+    if !json && !digests.is_empty() {
+        println!("\nReminders:");
+    }
+    for status in statuses.iter().filter(|s| s.stage != Stage::OnTrack) {
+        for cosigner in &status.missing {
+            let Some(lines) = digests.remove(&cosigner.name) else {
+                continue;
+            };
+            let contact = cosigner.metadata.contact.as_deref().unwrap_or("");
+            let transport = cosigner.metadata.transport.as_deref().unwrap_or("");
+            let message = format!(
+                "Your signature is needed on:\n{}",
+                lines
+                    .iter()
+                    .map(|l| format!("  {}", l))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            );
+            if !json {
+                let via = match (contact, transport) {
+                    ("", "") => String::from("no contact configured"),
+                    (contact, "") => contact.to_string(),
+                    ("", transport) => format!("via {}", transport),
+                    (contact, transport) => format!("{} via {}", contact, transport),
+                };
+                println!("  {} ({})", cosigner.name, via);
+                for line in &lines {
+                    println!("    {}", line);
+                }
+            }
+            // Delivery is left to a command, e.g. a mail or chat script:
+            // it gets the contact, transport and message as arguments.
+            if let Some(command) = notify {
+                let result = Command::new(command)
+                    .args([contact, transport, message.as_str()])
+                    .status()?;
+                if !result.success() {
+                    eprintln!("{} failed for {}", command, cosigner.name);
+                }
+            }
+        }
+    }
+
+    // Nonzero when a session is at risk, for cron and monitoring.
+    if statuses.iter().any(|s| s.stage == Stage::AtRisk) {
+        std::process::exit(2);
+    }
+    Ok(())
+}
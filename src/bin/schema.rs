@@ -0,0 +1,10 @@
+//! Prints the JSON Schema of an exported artifact.
+
+use psbt_coordinator::cli::Args;
+use psbt_coordinator::schema::json_schema;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse("schema");
+    println!("{}", json_schema(args.required("artifact")?)?);
+    Ok(())
+}
@@ -0,0 +1,66 @@
+//! Exports and imports Coldcard, BlueWallet/Nunchuk and Caravan multisig
+//! setup files.
+
+use bitcoin::Network;
+use psbt_coordinator::caravan::CaravanConfig;
+use psbt_coordinator::cli::Args;
+use psbt_coordinator::cosigner_file::{export_coldcard, export_setup, import_setup};
+use psbt_coordinator::{MultisigWallet, ScriptType, print_wallet_info};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse("setup_file");
+    let network = Network::Regtest;
+
+    match args.subcommand() {
+        Some(("export", args)) => {
+            let name = args.value("--name").unwrap_or("psbt-coordinator");
+            let out_file = args.value("--out");
+            let format = match args.value("--format") {
+                None => "bluewallet",
+                Some(f @ ("bluewallet" | "coldcard" | "caravan")) => f,
+                Some(_) => return Err("--format takes bluewallet, coldcard or caravan".into()),
+            };
+
+            let key_files = [
+                "key_a.json",
+                "key_b.json",
+                "key_c.json",
+                "key_d.json",
+                "key_e.json",
+            ];
+            let wallet = MultisigWallet::from_key_files(&key_files, 3, ScriptType::Wsh, network)?;
+            if format == "caravan" {
+                let out_file = out_file.unwrap_or("caravan.json");
+                let config = CaravanConfig::from_wallet(&wallet, name)?;
+                std::fs::write(out_file, serde_json::to_string_pretty(&config)?)?;
+                println!("Wallet config: {}", out_file);
+                println!("Import it in Caravan (Wallet > Import wallet configuration).");
+            } else if format == "coldcard" {
+                let out_file = out_file.unwrap_or("coldcard_multisig.txt");
+                std::fs::write(out_file, export_coldcard(&wallet, name)?)?;
+                println!("Setup file: {}", out_file);
+                println!(
+                    "Import it on each Coldcard (Settings > Multisig Wallets > Import from File)."
+                );
+            } else {
+                let out_file = out_file.unwrap_or("wallet_setup.txt");
+                std::fs::write(out_file, export_setup(&wallet, name)?)?;
+                println!("Setup file: {}", out_file);
+                println!("Import it in BlueWallet (Vaults) or Nunchuk as a multisig wallet.");
+            }
+        }
+        Some(("import", args)) => {
+            let path = args.required("file")?;
+            let text = std::fs::read_to_string(path)?;
+            let wallet = if text.trim_start().starts_with('{') {
+                serde_json::from_str::<CaravanConfig>(&text)?.wallet(network)?
+            } else {
+                import_setup(&text, network)?
+            };
+            println!("Imported {}\n", path);
+            print_wallet_info(&wallet);
+        }
+        _ => return Err("expected export or import".into()),
+    }
+    Ok(())
+}
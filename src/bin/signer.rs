@@ -1,139 +1,1129 @@
 //! Signs PSBTs using a single key from the multisig set.
 
 use base64::{Engine, engine::general_purpose::STANDARD};
-use bitcoin::bip32::{DerivationPath, Xpriv};
-use bitcoin::ecdsa::Signature as EcdsaSignature;
-use bitcoin::hashes::Hash;
+use bitcoin::bip32::{ChildNumber, DerivationPath, Fingerprint, Xpriv};
 use bitcoin::psbt::Psbt;
-use bitcoin::secp256k1::{Message, Secp256k1};
-use bitcoin::sighash::{EcdsaSighashType, SighashCache};
-use psbt_coordinator::KeyData;
+use bitcoin::secp256k1::{All, Secp256k1};
+use bitcoin::sighash::SighashCache;
+use bitcoin::taproot::TapLeafHash;
+use bitcoin::{Amount, Network, NetworkKind, Script, Transaction, TxOut};
+use psbt_coordinator::armor::{self, ArmorOptions};
+use psbt_coordinator::auxiliary::AuxiliaryAccount;
+use psbt_coordinator::bundle::{CoordinatorKey, OfflineBundle};
+#[cfg(feature = "chain")]
+use psbt_coordinator::chain_source::ChainConfig;
+use psbt_coordinator::cli::Args;
+#[cfg(feature = "chain")]
+use psbt_coordinator::core_rpc::CoreRpcConfig;
+use psbt_coordinator::destinations::{InternalDestinations, descriptor_script_pubkey};
+use psbt_coordinator::i18n::{Arg, Localizer};
+use psbt_coordinator::receipt::{ReceiptBody, SigningReceipt};
+use psbt_coordinator::signer_policy::{
+    POLICY_SEQUENCE_FILE, PolicySequences, SIGNER_POLICY_FILE, SignedSignerPolicy,
+};
+use psbt_coordinator::signing::{
+    EcdsaSegwitV0, SchnorrTaproot, SignResult, SignatureScheme, TaprootSpend,
+};
+use psbt_coordinator::training;
+use psbt_coordinator::verify::sighash_preimages;
+use psbt_coordinator::wallet_file::WalletFile;
+use psbt_coordinator::{
+    AmountUnit, DEFAULT_GAP_LIMIT, KeyData, MultisigWallet, bip48_account, check_same_network,
+    format_amount, format_path, load_psbt, output_descriptor, output_label, parse_amount,
+    parse_multi_a_script, parse_network, parse_untrusted_psbt, parse_witness_script, psbt_expiry,
+    psbt_network, psbt_policy_id, write_atomic,
+};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() < 3 {
-        eprintln!("Usage: {} <key.json> <psbt>", args[0]);
-        std::process::exit(1);
+    let args = Args::parse("signer");
+    let key_file = args.required("key.json")?;
+    let psbt_file = args.required("psbt")?;
+    let armor_opts: Option<ArmorOptions> =
+        args.value("--armor").map(armor::options_for).transpose()?;
+    let unit: AmountUnit = args.parse_value("--denomination")?.unwrap_or_default();
+    let preimage_file = args.value("--preimages");
+    let is_training = args.flag("--training");
+    let require_expiry = args.flag("--require-expiry");
+    let current_height: Option<u32> = args.parse_value("--height")?;
+    let internal_file = args.value("--internal");
+    let mut max_external: Option<Amount> =
+        args.value("--max-external").map(parse_amount).transpose()?;
+    let fetch_chain = args.value("--fetch-utxos");
+    let chain_source = args.value("--chain-source");
+    let lang = args.value("--lang");
+
+    if is_training {
+        training::enter()?;
     }
+    let l10n = match lang {
+        Some(lang) => Localizer::new(lang)?,
+        None => Localizer::from_env()?,
+    };
 
-    let key_data: KeyData = serde_json::from_str(&std::fs::read_to_string(&args[1])?)?;
+    let key_data: KeyData = serde_json::from_str(&std::fs::read_to_string(key_file)?)?;
     let xprv = Xpriv::from_str(&key_data.xprv)?;
+    if is_training {
+        training::check_key_network(xprv.network)?;
+    }
     let my_fp = &key_data.fingerprint;
+    let account_path = DerivationPath::from_str(&key_data.derivation_path)?;
 
-    println!("Signer: {} [{}]", key_data.name, my_fp);
+    println!(
+        "{}",
+        l10n.tr(
+            "signer-key",
+            &[
+                ("name", Arg::Text(key_data.name.clone())),
+                ("fingerprint", Arg::Ltr(my_fp.clone())),
+            ]
+        )
+    );
+    println!(
+        "{}",
+        l10n.tr(
+            "signer-account",
+            &[
+                (
+                    "account",
+                    Arg::Ltr(
+                        bip48_account(&account_path).map_or("?".to_string(), |a| a.to_string())
+                    ),
+                ),
+                ("path", Arg::Ltr(format_path(&account_path))),
+            ]
+        )
+    );
+    // Sub-paths are derived from the account xprv, whatever its depth.
+    if usize::from(xprv.depth) != account_path.len() {
+        return Err(format!(
+            "xprv is at depth {} but the account path {} has {} steps",
+            xprv.depth,
+            format_path(&account_path),
+            account_path.len()
+        )
+        .into());
+    }
 
-    let psbt_bytes = load_psbt(&args[2])?;
-    let mut psbt = Psbt::deserialize(&psbt_bytes)?;
+    let (psbt_bytes, mut psbt) = if psbt_file.ends_with(".bundle.json") {
+        let bundle: OfflineBundle = serde_json::from_str(&std::fs::read_to_string(psbt_file)?)?;
+        // A bundle vouches for its descriptor only once it is checked
+        // against the wallet this signer already trusts.
+        let account = bip48_account(&account_path).unwrap_or(0);
+        let wallet = WalletFile::load_account_if_present(account)?
+            .ok_or_else(|| {
+                format!(
+                    "a bundle can only be checked against {}",
+                    WalletFile::account_path(account)
+                )
+            })?
+            .account_wallet(account)?;
+        let psbt = bundle.open(&CoordinatorKey::load()?, &wallet)?;
+        println!("Bundle checked: policy {}", bundle.policy_id);
+        println!("  Summary: {}", bundle.summary.digest()?);
+        (psbt.serialize(), psbt)
+    } else {
+        let psbt_bytes = load_psbt(psbt_file)?;
+        let psbt = parse_untrusted_psbt(&psbt_bytes)?;
+        (psbt_bytes, psbt)
+    };
 
-    print_tx_summary(&psbt);
+    if is_training {
+        training::check_psbt(&psbt)?;
+    } else if training::is_marked(&psbt) {
+        eprintln!("{}\n", l10n.tr("training-psbt", &[]));
+    }
 
-    let secp = Secp256k1::new();
-    let tx = psbt.unsigned_tx.clone();
-    let mut signed = 0;
+    // A PSBT made for one network is not signed with a key, wallet or
+    // chain of another.
+    let network = psbt_network(&psbt)?;
+    if let Some(network) = network {
+        if NetworkKind::from(network) != xprv.network {
+            return Err(format!(
+                "PSBT is for {}, but {} holds a key for another network",
+                network, key_file
+            )
+            .into());
+        }
+        if let Some(name) = &key_data.network {
+            check_same_network(parse_network(name)?, network)
+                .map_err(|e| format!("{}: {}", key_file, e))?;
+        }
+        if let Some(chain) = &fetch_chain {
+            check_same_network(Network::from_core_arg(chain)?, network)
+                .map_err(|e| format!("--fetch-utxos {}: {}", chain, e))?;
+        }
+    }
 
-    for idx in 0..psbt.inputs.len() {
-        let Some((pubkey, path)) = find_our_key(&psbt.inputs[idx], my_fp) else {
-            continue;
-        };
+    let chain = chain_backend(fetch_chain, chain_source, network)?;
+    let fetched = fill_witness_utxos(&mut psbt, chain)?;
+    print_tx_summary(&psbt, unit, &fetched, &l10n)?;
 
-        let child_idx = path.into_iter().last().ok_or("empty path")?;
-        let child_path = DerivationPath::from_str(&format!("m/{}", child_idx))?;
-        let privkey = xprv.derive_priv(&secp, &child_path)?;
+    // With a wallet file for this key's account (wallet.json for account
+    // 0), only sign for that wallet, and only as one of its cosigners. A
+    // signer policy is only trusted once a quorum of those cosigners is
+    // found to have signed it, and once installed it cannot be removed or
+    // replaced with an older one.
+    let account = bip48_account(&account_path).unwrap_or(0);
+    let wallet_path = WalletFile::account_path(account);
+    let mut sequences = PolicySequences::load(POLICY_SEQUENCE_FILE)?;
+    let mut signer_policy = None;
+    let mut key_wallet = None;
+    let mut auxiliary = Vec::new();
+    if let Some(file) = WalletFile::load_account_if_present(account)? {
+        let wallet = file.account_wallet(account)?;
+        if let Some(network) = network {
+            check_same_network(wallet.network, network)
+                .map_err(|e| format!("{}: {}", wallet_path, e))?;
+        }
+        if psbt_policy_id(&psbt) != Some(wallet.policy_id()) {
+            return Err(format!(
+                "PSBT is not for the wallet in {} (policy {})",
+                wallet_path,
+                wallet.policy_id()
+            )
+            .into());
+        }
+        if !wallet
+            .xpub_origins
+            .iter()
+            .any(|o| o.fingerprint.to_string() == *my_fp)
+        {
+            return Err(format!("{} is not a cosigner of {}", my_fp, wallet_path).into());
+        }
+        if std::path::Path::new(SIGNER_POLICY_FILE).exists() {
+            let document = SignedSignerPolicy::load(SIGNER_POLICY_FILE)?;
+            let signers = document.verify(&wallet)?;
+            println!(
+                "{}\n",
+                l10n.tr(
+                    "review-signer-policy",
+                    &[
+                        ("sequence", Arg::Ltr(document.policy.sequence.to_string())),
+                        ("signers", Arg::Text(signers.join(", "))),
+                    ]
+                )
+            );
+            signer_policy = Some(document.policy);
+        }
+        sequences.check_installed(signer_policy.as_ref(), &wallet)?;
+        if let Some(policy) = &signer_policy {
+            sequences.record(POLICY_SEQUENCE_FILE, policy)?;
+        }
+        key_wallet = Some(wallet);
+        auxiliary = file.auxiliary;
+    }
+    if key_wallet.is_none() && !sequences.sequences.is_empty() {
+        return Err(format!(
+            "a signer policy was installed, but {} is missing",
+            wallet_path
+        )
+        .into());
+    }
+    if signer_policy.is_none() && std::path::Path::new(SIGNER_POLICY_FILE).exists() {
+        return Err(format!(
+            "{} can only be checked against {}",
+            SIGNER_POLICY_FILE, wallet_path
+        )
+        .into());
+    }
+    if signer_policy.is_some() && (internal_file.is_some() || max_external.is_some()) {
+        return Err(format!(
+            "--internal and --max-external cannot override {}",
+            SIGNER_POLICY_FILE
+        )
+        .into());
+    }
+    if let Some(max) = signer_policy.as_ref().and_then(|p| p.max_external_sat) {
+        max_external = Some(Amount::from_sat(max));
+    }
+
+    // A leaked request stops being signable once it expires. A signer that
+    // knows its wallet requires every request to expire.
+    let expiry = psbt_expiry(&psbt)?;
+    if expiry.is_none() && (require_expiry || key_wallet.is_some()) {
+        return Err(
+            "signature request never expires; have the coordinator set --expires-in \
+             or --expires-at-height"
+                .into(),
+        );
+    }
+    if let Some(expiry) = expiry {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        match expiry.is_expired(now, current_height) {
+            Some(true) => {
+                return Err(format!("signature request expired at {}; not signing", expiry).into());
+            }
+            Some(false) => println!(
+                "{}\n",
+                l10n.tr(
+                    "review-valid-until",
+                    &[("expiry", Arg::Ltr(expiry.to_string()))]
+                )
+            ),
+            None => {
+                return Err(format!(
+                    "signature request expires at {}; pass --height with the current \
+                     chain height to sign it",
+                    expiry
+                )
+                .into());
+            }
+        }
+    }
 
-        let derived_pub =
-            bitcoin::secp256k1::PublicKey::from_secret_key(&secp, &privkey.private_key);
-        if derived_pub != pubkey {
-            eprintln!("  Input {}: key mismatch, skipping", idx);
+    if let Some(path) = &preimage_file {
+        let preimages = sighash_preimages(&psbt);
+        write_atomic(path, serde_json::to_string_pretty(&preimages.preimages)?)?;
+        println!("Sighash preimages: {}", path);
+        for (idx, reason) in &preimages.skipped {
+            println!("  Input {}: no preimage: {}", idx, reason);
+        }
+        println!();
+    }
+
+    let secp = Secp256k1::new();
+    let own_outputs = check_change_outputs(
+        &secp,
+        &psbt,
+        &xprv,
+        my_fp,
+        &account_path,
+        key_wallet.as_ref(),
+        &auxiliary,
+    )?;
+
+    // Everything that is neither change nor one of our internal wallets
+    // leaves our control.
+    let internal = match (&internal_file, &signer_policy) {
+        (Some(path), _) => InternalDestinations::load(path, DEFAULT_GAP_LIMIT)?,
+        (None, Some(policy)) => {
+            InternalDestinations::new(&policy.internal_wallets, DEFAULT_GAP_LIMIT)?
+        }
+        (None, None) => InternalDestinations::default(),
+    };
+    let mut external_total = Amount::ZERO;
+    for (idx, txout) in psbt.unsigned_tx.output.iter().enumerate() {
+        if own_outputs.change.contains(&idx) {
             continue;
         }
+        if let Some((descriptor, index)) = output_descriptor(&psbt.outputs[idx]) {
+            if descriptor_script_pubkey(&descriptor, index)? != txout.script_pubkey {
+                return Err(format!(
+                    "output {}: script does not match its recorded descriptor",
+                    idx
+                )
+                .into());
+            }
+            println!(
+                "  {}",
+                l10n.tr(
+                    "review-output-descriptor",
+                    &[
+                        ("output", Arg::Ltr(idx.to_string())),
+                        ("index", Arg::Ltr(index.to_string())),
+                        ("descriptor", Arg::Ltr(descriptor)),
+                    ]
+                )
+            );
+        }
+        // Labels are the coordinator's word, like the rest of the PSBT; the
+        // amount and destination lines are what was checked.
+        if let Some(label) = output_label(&psbt.outputs[idx])? {
+            println!(
+                "  {}",
+                l10n.tr(
+                    "review-output-label",
+                    &[
+                        ("output", Arg::Ltr(idx.to_string())),
+                        ("label", Arg::Text(label)),
+                    ]
+                )
+            );
+        }
+        let amount = Arg::Ltr(format_amount(txout.value, unit));
+        let aux_label = own_outputs
+            .auxiliary
+            .iter()
+            .find(|(i, _)| *i == idx)
+            .map(|(_, label)| label.as_str());
+        match aux_label.or_else(|| internal.label(&txout.script_pubkey)) {
+            Some(label) => println!(
+                "  {}",
+                l10n.tr(
+                    "review-internal",
+                    &[
+                        ("output", Arg::Ltr(idx.to_string())),
+                        ("label", Arg::Text(label.to_string())),
+                        ("amount", amount),
+                    ]
+                )
+            ),
+            None => {
+                external_total = external_total
+                    .checked_add(txout.value)
+                    .ok_or("external payment amounts overflow")?;
+                println!(
+                    "  {}",
+                    l10n.tr(
+                        "review-external",
+                        &[("output", Arg::Ltr(idx.to_string())), ("amount", amount)]
+                    )
+                );
+            }
+        }
+    }
+    if let Some(max) = max_external.filter(|max| external_total > *max) {
+        return Err(format!(
+            "external payments total {}, over the --max-external limit of {}",
+            format_amount(external_total, unit),
+            format_amount(max, unit)
+        )
+        .into());
+    }
+    println!();
 
-        let script = psbt.inputs[idx]
-            .witness_script
-            .as_ref()
-            .ok_or("no witness script")?;
-        let value = psbt.inputs[idx]
-            .witness_utxo
-            .as_ref()
-            .ok_or("no witness utxo")?
-            .value;
+    // Larger withdrawals may fall under an approval tier of the signer
+    // policy: a waiting period, and room left for required cosigners.
+    let tier = signer_policy
+        .as_ref()
+        .and_then(|policy| policy.approval_tier(external_total));
+    if let (Some(tier), Some(wallet)) = (tier, &key_wallet) {
+        println!(
+            "{}",
+            l10n.tr(
+                "review-approval-tier",
+                &[
+                    (
+                        "amount",
+                        Arg::Ltr(format_amount(Amount::from_sat(tier.above_sat), unit)),
+                    ),
+                    (
+                        "signatures",
+                        Arg::Ltr(tier.signatures_needed(wallet).to_string()),
+                    ),
+                ]
+            )
+        );
+        let required: Vec<_> = tier
+            .required_cosigners()?
+            .into_iter()
+            .map(|fp| wallet.cosigner_label(fp))
+            .collect();
+        if !required.is_empty() {
+            println!(
+                "{}",
+                l10n.tr(
+                    "review-tier-required",
+                    &[("cosigners", Arg::Text(required.join(", ")))]
+                )
+            );
+        }
+        if tier.delay_secs > 0 {
+            println!(
+                "{}",
+                l10n.tr(
+                    "review-tier-delay",
+                    &[("hours", Arg::Ltr((tier.delay_secs / 3600).to_string()))]
+                )
+            );
+        }
+        println!();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        tier.check_signer(wallet, &psbt, Fingerprint::from_str(my_fp)?, now)?;
+    }
 
-        let mut cache = SighashCache::new(&tx);
-        let sighash = cache.p2wsh_signature_hash(idx, script, value, EcdsaSighashType::All)?;
+    let mut jobs = Vec::new();
+    for (idx, input) in psbt.inputs.iter().enumerate() {
+        let Some((key, path)) = find_our_key(input, my_fp) else {
+            // The wallet may know this key without its master fingerprint;
+            // then only the derived public key identifies it.
+            if let Some((key, sub_path)) = find_our_key_by_pubkey(&secp, input, &xprv) {
+                jobs.push((idx, key, sub_path));
+            }
+            continue;
+        };
+        // The same xpubs may back several accounts. Only sign for the
+        // account this key file is registered for, so a PSBT for another
+        // account cannot be passed off as this one.
+        let sub_path = match path.as_ref().strip_prefix(account_path.as_ref()) {
+            Some(rest) => DerivationPath::from(rest.to_vec()),
+            None => {
+                eprintln!(
+                    "  Input {}: path {} is for account {}, not this key's account {}; \
+                     skipping",
+                    idx,
+                    format_path(&path),
+                    bip48_account(&path).map_or("?".to_string(), |a| a.to_string()),
+                    bip48_account(&account_path).map_or("?".to_string(), |a| a.to_string()),
+                );
+                continue;
+            }
+        };
+        jobs.push((idx, key, sub_path));
+    }
+
+    let prevouts: Option<Vec<TxOut>> = psbt.inputs.iter().map(|i| i.witness_utxo.clone()).collect();
 
-        let msg = Message::from_digest(*sighash.as_byte_array());
-        let sig = secp.sign_ecdsa(&msg, &privkey.private_key);
+    // With the parallel feature, sighashes and signatures are computed in
+    // parallel; collect() keeps input order, so the output does not depend
+    // on scheduling. Each worker has one sighash cache for all its inputs:
+    // hashes of prevouts, sequences and outputs are computed once per
+    // thread, not per input.
+    let sign = |cache: &mut SighashCache<&Transaction>,
+                job: &(usize, Box<dyn SignatureScheme>, DerivationPath)| {
+        sign_input(
+            &secp,
+            cache,
+            &xprv,
+            &psbt.inputs[job.0],
+            job,
+            prevouts.as_deref(),
+        )
+    };
+    #[cfg(feature = "parallel")]
+    let results: Vec<_> = jobs
+        .par_iter()
+        .map_init(|| SighashCache::new(&psbt.unsigned_tx), sign)
+        .collect();
+    #[cfg(not(feature = "parallel"))]
+    let results: Vec<_> = {
+        let mut cache = SighashCache::new(&psbt.unsigned_tx);
+        jobs.iter().map(|job| sign(&mut cache, job)).collect()
+    };
 
-        psbt.inputs[idx].partial_sigs.insert(
-            bitcoin::PublicKey::new(derived_pub),
-            EcdsaSignature::sighash_all(sig),
+    let mut signed_inputs = Vec::new();
+    for ((idx, _, _), result) in jobs.iter().zip(results) {
+        let input = &mut psbt.inputs[*idx];
+        match result.map_err(|e| format!("input {}: {}", idx, e))? {
+            Some(sig) => sig.add_to(input),
+            None => {
+                eprintln!(
+                    "  {}",
+                    l10n.tr(
+                        "sign-input-mismatch",
+                        &[("input", Arg::Ltr(idx.to_string()))]
+                    )
+                );
+                continue;
+            }
+        }
+        signed_inputs.push(*idx);
+        println!(
+            "  {}",
+            l10n.tr("sign-input-signed", &[("input", Arg::Ltr(idx.to_string()))])
         );
-        signed += 1;
-        println!("  Input {}: signed", idx);
     }
 
-    let total_sigs: usize = psbt.inputs.iter().map(|i| i.partial_sigs.len()).sum();
-    let out_file = format!("signed_by_{}.psbt.base64", key_data.name);
-    std::fs::write(&out_file, STANDARD.encode(psbt.serialize()))?;
+    let out_file = match &armor_opts {
+        Some(opts) => {
+            let out_file = format!("signed_by_{}.psbt.asc", key_data.name);
+            write_atomic(&out_file, armor::armor(&psbt.serialize(), opts))?;
+            out_file
+        }
+        None => {
+            let out_file = format!("signed_by_{}.psbt.base64", key_data.name);
+            write_atomic(&out_file, STANDARD.encode(psbt.serialize()))?;
+            out_file
+        }
+    };
 
+    // Progress is that of the least-signed input, against its own script's
+    // threshold.
+    let mut progress = Vec::new();
+    for input in &psbt.inputs {
+        progress.push(signature_progress(input)?);
+    }
+    let (sigs, threshold) = progress
+        .into_iter()
+        .max_by_key(|(sigs, threshold)| threshold.saturating_sub(*sigs))
+        .unwrap_or((0, 0));
+
+    println!(
+        "\n{}",
+        l10n.tr(
+            "sign-summary",
+            &[
+                ("inputs", Arg::Count(signed_inputs.len() as u64)),
+                ("signatures", Arg::Ltr(sigs.to_string())),
+                ("threshold", Arg::Ltr(threshold.to_string())),
+            ]
+        )
+    );
     println!(
-        "\nSigned {} input(s), total signatures: {}/3",
-        signed, total_sigs
+        "{}",
+        l10n.tr("sign-output", &[("file", Arg::Ltr(out_file.clone()))])
     );
-    println!("Output: {}", out_file);
 
-    if total_sigs >= 3 {
+    if !signed_inputs.is_empty() {
+        let signed_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let body = ReceiptBody::new(
+            &key_data.name,
+            my_fp,
+            &psbt_bytes,
+            &psbt,
+            signed_inputs,
+            signed_at,
+        );
+        let receipt = SigningReceipt::sign(body, &xprv.private_key)?;
+        let receipt_file = format!("receipt_{}.json", key_data.name);
+        write_atomic(&receipt_file, serde_json::to_string_pretty(&receipt)?)?;
+        println!(
+            "{}",
+            l10n.tr("sign-receipt", &[("file", Arg::Ltr(receipt_file.clone()))])
+        );
+    }
+
+    if !psbt.inputs.is_empty() && sigs >= threshold {
         println!(
-            "\nThreshold met. Run: cargo run --bin finalizer -- {}",
-            out_file
+            "\n{}",
+            l10n.tr(
+                "sign-threshold-met",
+                &[("file", Arg::Ltr(out_file.clone()))]
+            )
         );
     }
 
     Ok(())
 }
 
-fn load_psbt(input: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    if input.ends_with(".base64") {
-        Ok(STANDARD.decode(std::fs::read_to_string(input)?.trim())?)
-    } else if std::path::Path::new(input).exists() {
-        Ok(std::fs::read(input)?)
-    } else {
-        Ok(STANDARD.decode(input)?)
+/// Signs one input, or returns `None` if our derived key does not match
+/// the one the PSBT lists for us.
+fn sign_input(
+    secp: &Secp256k1<All>,
+    cache: &mut SighashCache<&Transaction>,
+    xprv: &Xpriv,
+    input: &bitcoin::psbt::Input,
+    (idx, scheme, sub_path): &(usize, Box<dyn SignatureScheme>, DerivationPath),
+    prevouts: Option<&[TxOut]>,
+) -> SignResult {
+    let privkey = xprv.derive_priv(secp, sub_path)?.private_key;
+    scheme.sign(secp, cache, input, *idx, &privkey, prevouts)
+}
+
+/// Signatures present from script keys, and the script's threshold.
+fn signature_progress(
+    input: &bitcoin::psbt::Input,
+) -> Result<(usize, usize), Box<dyn std::error::Error>> {
+    if input.witness_script.is_none() && input.tap_scripts.is_empty() {
+        // Single-key inputs: P2WPKH or a taproot key path spend.
+        let signed = !input.partial_sigs.is_empty() || input.tap_key_sig.is_some();
+        return Ok((usize::from(signed), 1));
+    }
+    if let Some((script, version)) = input.tap_scripts.values().next() {
+        let leaf_hash = TapLeafHash::from_script(script, *version);
+        let (threshold, keys) = parse_multi_a_script(script)?;
+        let sigs = keys
+            .iter()
+            .filter(|k| input.tap_script_sigs.contains_key(&(**k, leaf_hash)))
+            .count();
+        return Ok((sigs, threshold));
     }
+    let script = input.witness_script.as_ref().ok_or("no witness script")?;
+    let (threshold, keys) = parse_witness_script(script)?;
+    let sigs = keys
+        .iter()
+        .filter(|k| input.partial_sigs.contains_key(k))
+        .count();
+    Ok((sigs, threshold))
+}
+
+/// Outputs carrying our key, sorted by what they turn out to pay.
+#[derive(Debug, Default)]
+struct OwnOutputs {
+    /// Verified change, by output index.
+    change: Vec<usize>,
+    /// Outputs paying an auxiliary account of the wallet file, with the
+    /// account's label.
+    auxiliary: Vec<(usize, String)>,
 }
 
+/// Outputs the PSBT claims pay back to our account, checked against the
+/// wallet file rather than the scripts the coordinator supplied: our key
+/// must be at the claimed path, and the output must pay the wallet's own
+/// address at that chain and index, byte for byte. Outputs paying one of
+/// the file's `auxiliary` accounts are internal, not change. Without a
+/// wallet file nothing counts as change.
+fn check_change_outputs(
+    secp: &Secp256k1<All>,
+    psbt: &Psbt,
+    xprv: &Xpriv,
+    fp: &str,
+    account_path: &DerivationPath,
+    wallet: Option<&MultisigWallet>,
+    auxiliary: &[AuxiliaryAccount],
+) -> Result<OwnOutputs, Box<dyn std::error::Error>> {
+    let mut own = OwnOutputs::default();
+    for (idx, (output, txout)) in psbt
+        .outputs
+        .iter()
+        .zip(&psbt.unsigned_tx.output)
+        .enumerate()
+    {
+        let ecdsa = output
+            .bip32_derivation
+            .iter()
+            .find(|(_, (f, _))| f.to_string() == fp)
+            .map(|(pk, (_, path))| (pk.x_only_public_key().0, path));
+        let schnorr = output
+            .tap_key_origins
+            .iter()
+            .find(|(_, (_, (f, _)))| f.to_string() == fp)
+            .map(|(key, (_, (_, path)))| (*key, path));
+        let Some((claimed, path)) = ecdsa.or(schnorr) else {
+            continue;
+        };
+        let Some(wallet) = wallet else {
+            eprintln!(
+                "  Output {}: claims to be change, but there is no wallet file to check it \
+                 against; counting it as external",
+                idx
+            );
+            continue;
+        };
+
+        let chain = path
+            .as_ref()
+            .strip_prefix(account_path.as_ref())
+            .and_then(|sub_path| match sub_path {
+                [
+                    ChildNumber::Normal { index: 0 },
+                    ChildNumber::Normal { index },
+                ] => Some((sub_path, false, *index)),
+                [
+                    ChildNumber::Normal { index: 1 },
+                    ChildNumber::Normal { index },
+                ] => Some((sub_path, true, *index)),
+                _ => None,
+            });
+        if let Some((sub_path, is_change, index)) = chain {
+            let address = wallet.derive_address(index, is_change)?;
+            if address.script_pubkey() == txout.script_pubkey {
+                let derived = xprv
+                    .derive_priv(secp, &DerivationPath::from(sub_path.to_vec()))?
+                    .private_key
+                    .x_only_public_key(secp)
+                    .0;
+                if derived != claimed {
+                    return Err(format!(
+                        "output {}: change key does not match {}",
+                        idx,
+                        format_path(path)
+                    )
+                    .into());
+                }
+                println!(
+                    "  Change: output {} at {} (verified)",
+                    idx,
+                    format_path(path)
+                );
+                own.change.push(idx);
+                continue;
+            }
+        }
+
+        // An auxiliary account may sit below our account xpub, on a chain
+        // of its own or even on the multisig's.
+        if let Some(label) = auxiliary_label(auxiliary, path, &txout.script_pubkey)? {
+            println!(
+                "  Output {}: pays auxiliary account {} at {}",
+                idx,
+                label,
+                format_path(path)
+            );
+            own.auxiliary.push((idx, label.to_string()));
+            continue;
+        }
+        if let Some((_, is_change, index)) = chain {
+            return Err(format!(
+                "output {}: does not pay the wallet's {} address {} ({})",
+                idx,
+                if is_change { "change" } else { "receive" },
+                index,
+                wallet.derive_address(index, is_change)?
+            )
+            .into());
+        }
+        // Our key on any other path is not change. Classify the output with
+        // the rest, rather than refusing a path we do not recognize.
+        eprintln!(
+            "  Output {}: path {} is not on our receive or change chain; not change",
+            idx,
+            format_path(path)
+        );
+    }
+    Ok(own)
+}
+
+/// Label of the auxiliary account whose address at the last step of
+/// `path` is `script_pubkey`, if any.
+fn auxiliary_label<'a>(
+    auxiliary: &'a [AuxiliaryAccount],
+    path: &DerivationPath,
+    script_pubkey: &Script,
+) -> Result<Option<&'a str>, Box<dyn std::error::Error>> {
+    let Some(ChildNumber::Normal { index }) = path.as_ref().last() else {
+        return Ok(None);
+    };
+    for account in auxiliary {
+        if account.at_index(*index)?.script_pubkey() == *script_pubkey {
+            return Ok(Some(account.label.as_str()));
+        }
+    }
+    Ok(None)
+}
+
+/// The unhardened steps at the end of a path, i.e. those below the xpub.
+fn unhardened_tail(path: &DerivationPath) -> DerivationPath {
+    let steps = path.as_ref();
+    let start = steps
+        .iter()
+        .rposition(ChildNumber::is_hardened)
+        .map_or(0, |i| i + 1);
+    DerivationPath::from(steps[start..].to_vec())
+}
+
+/// Finds our key by deriving the unhardened tail of each listed path from
+/// our account xprv and comparing public keys, for wallets that record the
+/// key under another fingerprint. A match proves the key is ours at that
+/// sub-path, whatever account the fingerprint suggests.
+fn find_our_key_by_pubkey(
+    secp: &Secp256k1<All>,
+    input: &bitcoin::psbt::Input,
+    xprv: &Xpriv,
+) -> Option<(Box<dyn SignatureScheme>, DerivationPath)> {
+    let derive = |path: &DerivationPath| {
+        let sub_path = unhardened_tail(path);
+        let privkey = xprv.derive_priv(secp, &sub_path).ok()?.private_key;
+        Some((privkey.public_key(secp), sub_path))
+    };
+    let ecdsa = input.bip32_derivation.iter().find_map(|(pk, (_, path))| {
+        let (derived, sub_path) = derive(path)?;
+        let scheme: Box<dyn SignatureScheme> = Box::new(EcdsaSegwitV0 { pubkey: *pk });
+        (derived == *pk).then_some((scheme, sub_path))
+    });
+    ecdsa.or_else(|| {
+        input
+            .tap_key_origins
+            .iter()
+            .find_map(|(xonly, (leaf_hashes, (_, path)))| {
+                let (derived, sub_path) = derive(path)?;
+                (derived.x_only_public_key().0 == *xonly && !leaf_hashes.is_empty()).then(|| {
+                    let scheme: Box<dyn SignatureScheme> = Box::new(SchnorrTaproot {
+                        key: *xonly,
+                        spend: TaprootSpend::ScriptPath(leaf_hashes.clone()),
+                    });
+                    (scheme, sub_path)
+                })
+            })
+    })
+}
+
+/// The signature scheme for the key an input lists for us, and its path.
 fn find_our_key(
     input: &bitcoin::psbt::Input,
     fp: &str,
-) -> Option<(bitcoin::secp256k1::PublicKey, DerivationPath)> {
+) -> Option<(Box<dyn SignatureScheme>, DerivationPath)> {
     for (pk, (fingerprint, path)) in &input.bip32_derivation {
         if fingerprint.to_string() == fp {
-            return Some((*pk, path.clone()));
+            return Some((Box::new(EcdsaSegwitV0 { pubkey: *pk }), path.clone()));
         }
     }
+    for (xonly, (leaf_hashes, (fingerprint, path))) in &input.tap_key_origins {
+        if fingerprint.to_string() != fp {
+            continue;
+        }
+        let spend = if !leaf_hashes.is_empty() {
+            TaprootSpend::ScriptPath(leaf_hashes.clone())
+        } else if input.tap_internal_key == Some(*xonly) {
+            TaprootSpend::KeyPath
+        } else {
+            continue;
+        };
+        let scheme = SchnorrTaproot { key: *xonly, spend };
+        return Some((Box::new(scheme), path.clone()));
+    }
     None
 }
 
-fn print_tx_summary(psbt: &Psbt) {
-    let total_in: u64 = psbt
-        .inputs
-        .iter()
-        .filter_map(|i| i.witness_utxo.as_ref())
-        .map(|u| u.value.to_sat())
-        .sum();
-    let total_out: u64 = psbt
+/// The backend to look up missing outputs in: --fetch-utxos asks the
+/// local node; --chain-source any backend, for the network the PSBT
+/// records.
+#[cfg(feature = "chain")]
+fn chain_backend(
+    fetch_chain: Option<&str>,
+    chain_source: Option<&str>,
+    network: Option<Network>,
+) -> Result<Option<Chain>, Box<dyn std::error::Error>> {
+    Ok(match (fetch_chain, chain_source) {
+        (Some(_), Some(_)) => {
+            return Err("--fetch-utxos cannot be combined with --chain-source".into());
+        }
+        (Some(chain), None) => Some((
+            ChainConfig::Core(CoreRpcConfig::default()),
+            Network::from_core_arg(chain)?,
+        )),
+        (None, Some(path)) => {
+            let network = network.ok_or(
+                "PSBT does not record its network; use --fetch-utxos CHAIN instead of --chain-source",
+            )?;
+            Some((ChainConfig::load(path)?, network))
+        }
+        (None, None) => None,
+    })
+}
+
+/// Built without the `chain` feature, the signer stays offline.
+#[cfg(not(feature = "chain"))]
+fn chain_backend(
+    fetch_chain: Option<&str>,
+    chain_source: Option<&str>,
+    _network: Option<Network>,
+) -> Result<Option<Chain>, Box<dyn std::error::Error>> {
+    match (fetch_chain, chain_source) {
+        (None, None) => Ok(None),
+        _ => Err(
+            "--fetch-utxos and --chain-source need a signer built with the chain feature".into(),
+        ),
+    }
+}
+
+#[cfg(feature = "chain")]
+type Chain = (ChainConfig, Network);
+#[cfg(not(feature = "chain"))]
+type Chain = std::convert::Infallible;
+
+/// Fills in missing `witness_utxo` fields: from the input's previous
+/// transaction when the PSBT carries it, otherwise, if `chain` is given,
+/// from that backend, only connected when needed. Returns the inputs filled
+/// from the backend, with its name.
+fn fill_witness_utxos(
+    psbt: &mut Psbt,
+    chain: Option<Chain>,
+) -> Result<Vec<(usize, &'static str)>, Box<dyn std::error::Error>> {
+    let mut missing = Vec::new();
+    for (idx, input) in psbt.inputs.iter_mut().enumerate() {
+        if input.witness_utxo.is_some() {
+            continue;
+        }
+        let outpoint = psbt.unsigned_tx.input[idx].previous_output;
+        // parse_untrusted_psbt checked that this is the transaction spent.
+        let Some(prev_tx) = &input.non_witness_utxo else {
+            missing.push((idx, outpoint));
+            continue;
+        };
+        let utxo = prev_tx.output.get(outpoint.vout as usize).ok_or_else(|| {
+            format!(
+                "input {}: previous transaction has no output {}",
+                idx, outpoint.vout
+            )
+        })?;
+        input.witness_utxo = Some(utxo.clone());
+    }
+    let Some(&(first, outpoint)) = missing.first() else {
+        return Ok(Vec::new());
+    };
+    let Some(chain) = chain else {
+        return Err(format!(
+            "input {}: PSBT has no witness_utxo for {}; pass --fetch-utxos <chain> or \
+             --chain-source <chain.json> to look it up",
+            first, outpoint
+        )
+        .into());
+    };
+    #[cfg(not(feature = "chain"))]
+    match chain {}
+    #[cfg(feature = "chain")]
+    {
+        let (config, network) = chain;
+        let source = config.connect(network)?;
+        let mut fetched = Vec::new();
+        for (idx, outpoint) in missing {
+            psbt.inputs[idx].witness_utxo = Some(
+                source
+                    .prevout(&outpoint)
+                    .map_err(|e| format!("input {}: {}", idx, e))?,
+            );
+            fetched.push((idx, source.name()));
+        }
+        Ok(fetched)
+    }
+}
+
+/// Fails if an input's amount is missing or the outputs spend more than
+/// the inputs, rather than showing a made-up fee. Amounts of the
+/// `fetched` inputs came from the named backend, and the summary says so.
+fn print_tx_summary(
+    psbt: &Psbt,
+    unit: AmountUnit,
+    fetched: &[(usize, &str)],
+    l10n: &Localizer,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let fee = psbt.fee()?;
+    let total_out = psbt
         .unsigned_tx
         .output
         .iter()
-        .map(|o| o.value.to_sat())
-        .sum();
+        .try_fold(Amount::ZERO, |total, o| total.checked_add(o.value))
+        .ok_or("output amounts overflow")?;
+    let total_in = total_out.checked_add(fee).ok_or("input amounts overflow")?;
 
     println!(
-        "\nTransaction: {} input(s), {} output(s)",
-        psbt.inputs.len(),
-        psbt.unsigned_tx.output.len()
+        "\n{}",
+        l10n.tr(
+            "review-transaction",
+            &[
+                ("inputs", Arg::Count(psbt.inputs.len() as u64)),
+                ("outputs", Arg::Count(psbt.unsigned_tx.output.len() as u64)),
+            ]
+        )
     );
-    println!("  Total in:  {} sat", total_in);
-    println!("  Total out: {} sat", total_out);
-    println!("  Fee:       {} sat\n", total_in.saturating_sub(total_out));
+    match psbt_policy_id(psbt) {
+        Some(id) => println!(
+            "  {}",
+            l10n.tr("review-policy-id", &[("id", Arg::Ltr(id.to_string()))])
+        ),
+        None => println!("  {}", l10n.tr("review-policy-id-missing", &[])),
+    }
+    for (id, amount) in [
+        ("review-total-in", total_in),
+        ("review-total-out", total_out),
+        ("review-fee", fee),
+    ] {
+        let amount = Arg::Ltr(format_amount(amount, unit));
+        println!("  {}", l10n.tr(id, &[("amount", amount)]));
+    }
+    for (idx, source) in fetched {
+        println!(
+            "  {}",
+            l10n.tr(
+                "review-fetched-input",
+                &[
+                    ("input", Arg::Ltr(idx.to_string())),
+                    ("source", Arg::Text(source.to_string())),
+                ]
+            )
+        );
+    }
+    println!();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::bip32::Xpub;
+    use bitcoin::{OutPoint, TxIn, absolute, transaction};
+    use psbt_coordinator::{ScriptType, bip48_path};
+
+    /// Our account key, and a 2-of-3 wallet it is the first cosigner of.
+    fn setup() -> (Xpriv, Fingerprint, DerivationPath, MultisigWallet) {
+        let secp = Secp256k1::new();
+        let path = bip48_path(Network::Regtest, 0).unwrap();
+        let masters: Vec<_> = (1..=3u8)
+            .map(|seed| Xpriv::new_master(Network::Regtest, &[seed; 32]).unwrap())
+            .collect();
+        let origins: Vec<_> = masters
+            .iter()
+            .map(|master| {
+                let account = master.derive_priv(&secp, &path).unwrap();
+                (
+                    master.fingerprint(&secp),
+                    path.clone(),
+                    Xpub::from_priv(&secp, &account),
+                )
+            })
+            .collect();
+        let wallet =
+            MultisigWallet::from_xpubs(&origins, 2, ScriptType::Wsh, Network::Regtest).unwrap();
+        let xprv = masters[0].derive_priv(&secp, &path).unwrap();
+        (xprv, origins[0].0, path, wallet)
+    }
+
+    #[test]
+    fn signs_a_psbt_paying_our_own_auxiliary_account() {
+        let secp = Secp256k1::new();
+        let (xprv, fp, account_path, wallet) = setup();
+        let xpub = Xpub::from_priv(&secp, &xprv);
+        // On a chain of its own, and on the multisig's receive chain.
+        for chain in [5, 0] {
+            let aux = AuxiliaryAccount::new(
+                "ops-change",
+                &format!("tr([{}/{}]{}/{}/*)", fp, account_path, xpub, chain),
+            )
+            .unwrap();
+            let tx = Transaction {
+                version: transaction::Version::TWO,
+                lock_time: absolute::LockTime::ZERO,
+                input: vec![TxIn {
+                    previous_output: OutPoint::null(),
+                    ..TxIn::default()
+                }],
+                output: vec![TxOut {
+                    value: Amount::from_sat(40_000),
+                    script_pubkey: aux.address(0, Network::Regtest).unwrap().script_pubkey(),
+                }],
+            };
+            let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+            psbt.inputs[0].witness_utxo = Some(TxOut {
+                value: Amount::from_sat(50_000),
+                script_pubkey: wallet.derive_address(0, false).unwrap().script_pubkey(),
+            });
+            wallet
+                .update_psbt_input(&mut psbt.inputs[0], 0, false)
+                .unwrap();
+            aux.update_psbt_output(&mut psbt, 0, 0).unwrap();
+
+            let own = check_change_outputs(
+                &secp,
+                &psbt,
+                &xprv,
+                &fp.to_string(),
+                &account_path,
+                Some(&wallet),
+                std::slice::from_ref(&aux),
+            )
+            .unwrap();
+            assert!(own.change.is_empty());
+            assert_eq!(own.auxiliary, vec![(0, "ops-change".to_string())]);
+
+            let (scheme, path) = find_our_key(&psbt.inputs[0], &fp.to_string()).unwrap();
+            let sub_path = path.as_ref().strip_prefix(account_path.as_ref()).unwrap();
+            let job = (0, scheme, DerivationPath::from(sub_path.to_vec()));
+            let mut cache = SighashCache::new(&psbt.unsigned_tx);
+            let sig = sign_input(&secp, &mut cache, &xprv, &psbt.inputs[0], &job, None).unwrap();
+            assert!(sig.is_some());
+        }
+    }
+
+    #[test]
+    fn refuses_an_output_on_our_change_chain_paying_elsewhere() {
+        let (xprv, fp, account_path, wallet) = setup();
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: Vec::new(),
+            output: vec![TxOut {
+                value: Amount::from_sat(40_000),
+                script_pubkey: wallet.derive_address(1, true).unwrap().script_pubkey(),
+            }],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        wallet
+            .update_psbt_output(&mut psbt.outputs[0], 0, true)
+            .unwrap();
+        let result = check_change_outputs(
+            &Secp256k1::new(),
+            &psbt,
+            &xprv,
+            &fp.to_string(),
+            &account_path,
+            Some(&wallet),
+            &[],
+        );
+        assert!(result.is_err());
+    }
 }
@@ -0,0 +1,301 @@
+//! Drafts, signs, verifies and serves quorum-signed signer policies.
+//!
+//! `serve` runs on the signer machine. `GET /policy` returns the current
+//! policy; `PUT /policy` (or `POST`) replaces it with a newer document, if
+//! enough cosigners signed it. The wallet comes from `wallet.json`.
+
+use bitcoin::bip32::Xpriv;
+use psbt_coordinator::cli::Args;
+use psbt_coordinator::destinations::InternalWallet;
+use psbt_coordinator::shutdown::Shutdown;
+use psbt_coordinator::signer_policy::{
+    ApprovalTier, POLICY_SEQUENCE_FILE, PolicySequences, SIGNER_POLICY_FILE, SignedSignerPolicy,
+    SignerPolicy,
+};
+use psbt_coordinator::wallet_file::{WALLET_FILE, WalletFile};
+use psbt_coordinator::{AmountUnit, KeyData, MultisigWallet, format_amount, parse_amount};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Largest request body `serve` reads.
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// How long `serve` waits on a client before dropping the request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse("signer_policy");
+    let wallet = || -> Result<MultisigWallet, Box<dyn std::error::Error>> {
+        let file = WalletFile::load(WALLET_FILE)
+            .map_err(|e| format!("{} (create it with `wallet init`)", e))?;
+        Ok(file.wallet()?)
+    };
+
+    match args.subcommand() {
+        Some(("draft", args)) => {
+            let sequence = args.parse_value("--sequence")?;
+            let internal_wallets = match args.value("--internal") {
+                Some(path) => {
+                    serde_json::from_str::<Vec<InternalWallet>>(&std::fs::read_to_string(path)?)?
+                }
+                None => Vec::new(),
+            };
+            let max_external = args.value("--max-external").map(parse_amount).transpose()?;
+            let out_file = args.value("--out").unwrap_or("signer_policy.draft.json");
+            let wallet = wallet()?;
+            let mut policy = SignerPolicy::new(&wallet, sequence.ok_or("missing --sequence")?);
+            policy.internal_wallets = internal_wallets;
+            policy.max_external_sat = max_external.map(|a| a.to_sat());
+            for spec in args.values("--tier") {
+                policy.approval_tiers.push(parse_tier(spec, &wallet)?);
+            }
+            policy.approval_tiers.sort_by_key(|tier| tier.above_sat);
+            SignedSignerPolicy::new(policy).save(out_file)?;
+            println!("Draft: {}", out_file);
+            println!(
+                "Next: have each cosigner run `sign <key.json> {}`",
+                out_file
+            );
+        }
+        Some(("sign", args)) => {
+            let key_file = args.required("key.json")?;
+            let path = args.required("policy.json")?;
+            let key_data: KeyData = serde_json::from_str(&std::fs::read_to_string(key_file)?)?;
+            let xprv = Xpriv::from_str(&key_data.xprv)?;
+            let mut document = SignedSignerPolicy::load(path)?;
+            print_policy(&document.policy);
+            document.sign(&key_data.fingerprint, &xprv.private_key)?;
+            document.save(path)?;
+            println!(
+                "\nSigned by {} [{}]: {} signature(s)",
+                key_data.name,
+                key_data.fingerprint,
+                document.signatures.len()
+            );
+        }
+        Some(("verify", args)) => {
+            let path = args.required("policy.json")?;
+            let document = SignedSignerPolicy::load(path)?;
+            print_policy(&document.policy);
+            let signers = document.verify(&wallet()?)?;
+            println!("\nValid, signed by: {}", signers.join(", "));
+        }
+        Some(("serve", args)) => {
+            let listen = args.value("--listen").unwrap_or("127.0.0.1:8081");
+            let wallet = wallet()?;
+            let shutdown = Shutdown::install()?;
+            let listener = TcpListener::bind(listen)?;
+            println!("Serving {} on http://{}/policy", SIGNER_POLICY_FILE, listen);
+            for stream in shutdown.incoming(&listener)? {
+                let result = stream
+                    .map_err(Into::into)
+                    .and_then(|stream| handle(stream, &wallet));
+                if let Err(e) = result {
+                    eprintln!("Request failed: {}", e);
+                }
+            }
+            println!("Stopped");
+        }
+        _ => return Err("expected a subcommand".into()),
+    }
+    Ok(())
+}
+
+fn print_policy(policy: &SignerPolicy) {
+    println!(
+        "Signer policy {} for wallet {}",
+        policy.sequence, policy.policy_id
+    );
+    for internal in &policy.internal_wallets {
+        println!(
+            "  Internal wallet: {} {}",
+            internal.label, internal.descriptor
+        );
+    }
+    match policy.max_external_sat {
+        Some(max) => println!(
+            "  External payments capped at {}",
+            format_amount(bitcoin::Amount::from_sat(max), AmountUnit::default())
+        ),
+        None => println!("  External payments uncapped"),
+    }
+    for tier in &policy.approval_tiers {
+        let mut rules = Vec::new();
+        if !tier.required.is_empty() {
+            rules.push(format!("signed by {}", tier.required.join(", ")));
+        }
+        if let Some(n) = tier.signatures {
+            rules.push(format!("{} signatures", n));
+        }
+        if tier.delay_secs > 0 {
+            rules.push(format!("{}h delay", tier.delay_secs / 3600));
+        }
+        println!(
+            "  Over {}: {}",
+            format_amount(
+                bitcoin::Amount::from_sat(tier.above_sat),
+                AmountUnit::default()
+            ),
+            rules.join(", ")
+        );
+    }
+}
+
+/// Parses `AMOUNT[:require=FP,..][:signatures=N|all][:delay-hours=H]`,
+/// where `all` is every cosigner of `wallet`.
+fn parse_tier(
+    spec: &str,
+    wallet: &MultisigWallet,
+) -> Result<ApprovalTier, Box<dyn std::error::Error>> {
+    let mut parts = spec.split(':');
+    let above = parse_amount(parts.next().unwrap_or_default())?;
+    let mut tier = ApprovalTier {
+        above_sat: above.to_sat(),
+        required: Vec::new(),
+        signatures: None,
+        delay_secs: 0,
+    };
+    for part in parts {
+        match part.split_once('=') {
+            Some(("require", list)) => {
+                tier.required = list.split(',').map(|fp| fp.trim().to_string()).collect();
+            }
+            Some(("signatures", "all")) => tier.signatures = Some(wallet.xpub_origins.len()),
+            Some(("signatures", n)) => tier.signatures = Some(n.parse()?),
+            Some(("delay-hours", hours)) => {
+                let hours: u64 = hours.parse()?;
+                tier.delay_secs = hours.checked_mul(3600).ok_or("delay too long")?;
+            }
+            _ => return Err(format!("--tier {}: unexpected {}", spec, part).into()),
+        }
+    }
+    tier.check(wallet)?;
+    Ok(tier)
+}
+
+fn handle(
+    mut stream: TcpStream,
+    wallet: &MultisigWallet,
+) -> Result<(), Box<dyn std::error::Error>> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or_default(), parts.next());
+    if path != Some("/policy") {
+        return respond(&mut stream, "404 Not Found", "text/plain", "not found\n");
+    }
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        let length = header
+            .split_once(':')
+            .filter(|(name, _)| name.eq_ignore_ascii_case("content-length"));
+        if let Some((_, value)) = length {
+            content_length = value.trim().parse()?;
+        }
+    }
+
+    match method {
+        "GET" => match std::fs::read_to_string(SIGNER_POLICY_FILE) {
+            Ok(current) => respond(&mut stream, "200 OK", "application/json", &current),
+            Err(_) => respond(
+                &mut stream,
+                "404 Not Found",
+                "text/plain",
+                "no signer policy yet\n",
+            ),
+        },
+        "PUT" | "POST" => {
+            if content_length > MAX_BODY_BYTES {
+                return respond(
+                    &mut stream,
+                    "413 Content Too Large",
+                    "text/plain",
+                    "policy too large\n",
+                );
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body)?;
+            let update = match serde_json::from_slice::<SignedSignerPolicy>(&body) {
+                Ok(update) => update,
+                Err(e) => {
+                    return respond(
+                        &mut stream,
+                        "400 Bad Request",
+                        "text/plain",
+                        &format!("{}\n", e),
+                    );
+                }
+            };
+            let current = if std::path::Path::new(SIGNER_POLICY_FILE).exists() {
+                Some(SignedSignerPolicy::load(SIGNER_POLICY_FILE)?)
+            } else {
+                None
+            };
+            // The record catches an older document put in place of a
+            // deleted policy file.
+            let mut sequences = PolicySequences::load(POLICY_SEQUENCE_FILE)?;
+            let checked = update
+                .check_update(current.as_ref(), wallet)
+                .and_then(|signers| {
+                    sequences.check_not_older(&update.policy)?;
+                    Ok(signers)
+                });
+            match checked {
+                Ok(signers) => {
+                    update.save(SIGNER_POLICY_FILE)?;
+                    sequences.record(POLICY_SEQUENCE_FILE, &update.policy)?;
+                    println!(
+                        "Signer policy {} installed, signed by {}",
+                        update.policy.sequence,
+                        signers.join(", ")
+                    );
+                    respond(&mut stream, "200 OK", "text/plain", "installed\n")
+                }
+                Err(e) => {
+                    eprintln!("Rejected signer policy update: {}", e);
+                    respond(
+                        &mut stream,
+                        "403 Forbidden",
+                        "text/plain",
+                        &format!("{}\n", e),
+                    )
+                }
+            }
+        }
+        _ => respond(
+            &mut stream,
+            "405 Method Not Allowed",
+            "text/plain",
+            "GET or PUT only\n",
+        ),
+    }
+}
+
+fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
@@ -0,0 +1,77 @@
+//! Moves a signer's policy state, with its wallet files, to a replacement
+//! machine in a package only that signer's key can open.
+//!
+//! `export` runs on the old machine and `import` on the new one, after the
+//! key was restored from its mnemonic.
+
+use bitcoin::bip32::Xpriv;
+use psbt_coordinator::KeyData;
+use psbt_coordinator::cli::Args;
+use psbt_coordinator::signer_state::{STATE_FILE, SealedSignerState, SignerState};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse("signer_state");
+    match args.subcommand() {
+        Some(("export", args)) => export(&args),
+        Some(("import", args)) => import(&args),
+        _ => Err("expected a subcommand".into()),
+    }
+}
+
+fn export(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let key_file = args.required("key.json")?;
+    let out_file = args.value("--out").unwrap_or(STATE_FILE);
+    let key_data: KeyData = serde_json::from_str(&std::fs::read_to_string(key_file)?)?;
+    let xprv = Xpriv::from_str(&key_data.xprv)?;
+    let state = SignerState::collect(&key_data.name)?;
+    if state.files.is_empty() {
+        return Err("no wallet or signer policy files to export".into());
+    }
+    state.check(&key_data.name)?;
+    let exported_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    SealedSignerState::seal(
+        &state,
+        &key_data.name,
+        &key_data.fingerprint,
+        &xprv.private_key,
+        exported_at,
+    )?
+    .save(out_file)?;
+    for file in &state.files {
+        println!("  {}", file.name);
+    }
+    println!(
+        "Exported to {}, encrypted to {} [{}]",
+        out_file, key_data.name, key_data.fingerprint
+    );
+    Ok(())
+}
+
+fn import(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let key_file = args.required("key.json")?;
+    let path = args.required("package.json")?;
+    let force = args.flag("--force");
+    let key_data: KeyData = serde_json::from_str(&std::fs::read_to_string(key_file)?)?;
+    let xprv = Xpriv::from_str(&key_data.xprv)?;
+    let package = SealedSignerState::load(path)?;
+    if package.body.fingerprint != key_data.fingerprint {
+        return Err(format!(
+            "package is for {} [{}], not {} [{}]",
+            package.body.signer, package.body.fingerprint, key_data.name, key_data.fingerprint
+        )
+        .into());
+    }
+    let state = package.open(&xprv.private_key)?;
+    state.check(&key_data.name)?;
+    for name in state.install(Path::new("."), force)? {
+        println!("  {}", name);
+    }
+    println!(
+        "Imported state exported by {} at unix time {}",
+        package.body.signer, package.body.exported_at
+    );
+    Ok(())
+}
@@ -0,0 +1,39 @@
+//! Timestamps a PSBT's hash with OpenTimestamps.
+//!
+//! Proves a PSBT existed at a given time, independent of our own logs:
+//! the SHA256 of its normalized form is written to `<txid>.sha256` and
+//! stamped with the `ots` client, which aggregates it into a Bitcoin
+//! transaction via the public calendar servers.
+
+use bitcoin::hashes::{Hash, sha256};
+use psbt_coordinator::cli::Args;
+use psbt_coordinator::{NormalizeOptions, load_psbt, normalize_psbt, parse_untrusted_psbt};
+use std::process::Command;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse("timestamp");
+    let psbt = parse_untrusted_psbt(&load_psbt(args.required("psbt")?)?)?;
+    // Hash the canonical form so the proof does not depend on encoding.
+    let normalized = normalize_psbt(&psbt, NormalizeOptions::default())?;
+    let digest = sha256::Hash::hash(&normalized);
+
+    let hash_file = format!("{}.sha256", psbt.unsigned_tx.compute_txid());
+    std::fs::write(&hash_file, format!("{}\n", digest))?;
+
+    let output = Command::new("ots").arg("stamp").arg(&hash_file).output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "ots stamp failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+
+    println!("PSBT SHA256: {}", digest);
+    println!("Hash file:   {}", hash_file);
+    println!("Proof:       {}.ots", hash_file);
+    println!("\nOnce the calendar has committed (a few hours), complete the proof:");
+    println!("  ots upgrade {}.ots", hash_file);
+    println!("  ots verify {}.ots", hash_file);
+    Ok(())
+}
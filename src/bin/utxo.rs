@@ -0,0 +1,70 @@
+//! Records wallet UTXOs by hand, from their raw funding transactions, for
+//! the coordinator to spend when no backend is available.
+
+use bitcoin::OutPoint;
+use psbt_coordinator::cli::Args;
+use psbt_coordinator::utxo_store::{ManualUtxo, UTXO_FILE, UtxoStore};
+use psbt_coordinator::wallet_file::{WALLET_FILE, WalletFile};
+use psbt_coordinator::{AmountUnit, format_amount};
+use std::str::FromStr;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse("utxo");
+    match args.subcommand() {
+        Some(("add", args)) => add(&args),
+        Some(("list", _)) => list(),
+        Some(("remove", args)) => remove(&args),
+        _ => Err("expected a subcommand".into()),
+    }
+}
+
+fn add(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let raw_tx = args.value("--rawtx").ok_or("--rawtx is required")?;
+    let vout: u32 = args.parse_value("--vout")?.ok_or("--vout is required")?;
+    let index: u32 = args.parse_value("--index")?.ok_or("--index is required")?;
+    let is_change = args.flag("--change");
+
+    let wallet = WalletFile::load(WALLET_FILE)?.wallet()?;
+    let utxo = ManualUtxo::from_funding_tx(&wallet, raw_tx, vout, index, is_change)?;
+    let mut store = UtxoStore::load_or_default()?;
+    println!(
+        "{}: {} to address index {}",
+        utxo.outpoint,
+        format_amount(utxo.value, AmountUnit::default()),
+        index
+    );
+    store.add(utxo)?;
+    store.save(UTXO_FILE)?;
+    println!("Saved to {}", UTXO_FILE);
+    Ok(())
+}
+
+fn list() -> Result<(), Box<dyn std::error::Error>> {
+    let store = UtxoStore::load_or_default()?;
+    if store.utxos.is_empty() {
+        println!("No UTXOs in {}", UTXO_FILE);
+        return Ok(());
+    }
+    for utxo in &store.utxos {
+        let chain = if utxo.is_change { "change" } else { "receive" };
+        println!(
+            "{}  {}  {} {}",
+            utxo.outpoint,
+            format_amount(utxo.value, AmountUnit::default()),
+            chain,
+            utxo.index
+        );
+    }
+    let total = store.total().ok_or("UTXO amounts overflow")?;
+    println!("Total: {}", format_amount(total, AmountUnit::default()));
+    Ok(())
+}
+
+fn remove(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let outpoint = OutPoint::from_str(args.required("TXID:VOUT")?)?;
+    let mut store = UtxoStore::load_or_default()?;
+    store.remove(outpoint)?;
+    store.save(UTXO_FILE)?;
+    println!("Removed {} from {}", outpoint, UTXO_FILE);
+    Ok(())
+}
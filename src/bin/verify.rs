@@ -0,0 +1,125 @@
+//! Verifies the partial signatures in a PSBT before it is forwarded.
+
+use bitcoin::Network;
+use bitcoin::secp256k1::{Parity, XOnlyPublicKey};
+use psbt_coordinator::cli::Args;
+use psbt_coordinator::receipt::SigningReceipt;
+use psbt_coordinator::verify::{verify_partial_sigs, verify_tap_key_sig, verify_tap_script_sigs};
+use psbt_coordinator::wallet_file::WalletFile;
+use psbt_coordinator::{
+    CoordinatorError, MultisigWallet, ScriptType, load_psbt, parse_untrusted_psbt, signer_label,
+};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse("verify");
+    let psbt = parse_untrusted_psbt(&load_psbt(args.required("psbt")?)?)?;
+    let checks = verify_partial_sigs(&psbt);
+
+    // On the coordinator the key files name each cosigner; elsewhere
+    // signatures are labelled by fingerprint only.
+    let key_files = [
+        "key_a.json",
+        "key_b.json",
+        "key_c.json",
+        "key_d.json",
+        "key_e.json",
+    ];
+    // Without the key files, signers are labelled by fingerprint alone; a
+    // key file that is present but broken is still an error.
+    let wallet = match WalletFile::load_if_present()? {
+        Some(file) => Some(file.wallet()?),
+        None => {
+            match MultisigWallet::from_key_files(&key_files, 3, ScriptType::Wsh, Network::Regtest) {
+                Ok(wallet) => Some(wallet),
+                Err(CoordinatorError::Io(_)) => None,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    };
+
+    let mut checked = 0;
+    let mut invalid = 0;
+    for idx in 0..psbt.inputs.len() {
+        println!("Input {}:", idx);
+        let input = &psbt.inputs[idx];
+        // Taproot keys are x-only; they are labelled by the fingerprint in
+        // their key origin, or shown with an even Y.
+        let tap_label = |key: XOnlyPublicKey| {
+            let fingerprint = input.tap_key_origins.get(&key).map(|(_, (fp, _))| *fp);
+            let pubkey = bitcoin::PublicKey::new(key.public_key(Parity::Even));
+            signer_label(wallet.as_ref(), fingerprint, &pubkey)
+        };
+        let mut results: Vec<(String, Result<(), String>)> = checks
+            .iter()
+            .filter(|c| c.input == idx)
+            .map(|check| {
+                let signer = signer_label(wallet.as_ref(), check.fingerprint, &check.pubkey);
+                (signer, check.result.clone())
+            })
+            .collect();
+        if let Some(result) = verify_tap_key_sig(&psbt, idx) {
+            let signer = match input.tap_internal_key {
+                Some(key) => format!("{}, key path", tap_label(key)),
+                None => "key path".to_string(),
+            };
+            results.push((signer, result));
+        }
+        for check in verify_tap_script_sigs(&psbt, idx) {
+            results.push((tap_label(check.key), check.result));
+        }
+
+        let mut signed_by = Vec::new();
+        for (signer, result) in &results {
+            match result {
+                Ok(()) => {
+                    println!("  [{}] valid", signer);
+                    signed_by.push(signer.as_str());
+                }
+                Err(e) => {
+                    println!("  [{}] INVALID: {}", signer, e);
+                    invalid += 1;
+                }
+            }
+        }
+        checked += results.len();
+        if results.is_empty() {
+            println!("  no signatures");
+        } else if !signed_by.is_empty() {
+            println!("  signed by: {}", signed_by.join(", "));
+        }
+    }
+
+    let mut bad_receipts = 0;
+    for path in args.values("--receipt") {
+        let receipt: SigningReceipt = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        match receipt.verify_against(&psbt) {
+            Ok(()) => {
+                println!(
+                    "Receipt {}: valid, signed by {} [{}]",
+                    path, receipt.body.signer, receipt.body.fingerprint
+                );
+                if let Some(software) = &receipt.body.software {
+                    println!(
+                        "  signer build: {} {} ({}), commit {}, features {}",
+                        software.name,
+                        software.version,
+                        software.rustc,
+                        software.git_commit,
+                        software.features.join(",")
+                    );
+                }
+            }
+            Err(e) => {
+                bad_receipts += 1;
+                println!("Receipt {}: INVALID: {}", path, e);
+            }
+        }
+    }
+
+    println!("\n{} signature(s) checked, {} invalid", checked, invalid);
+    if invalid > 0 || bad_receipts > 0 {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,109 @@
+//! Checks a finalized transaction against the offline bundle its signers
+//! approved, before the hex goes to the broadcasting host.
+//!
+//! Meant for a machine other than the finalizer's: it needs no keys and no
+//! network, only the final transaction, the `.bundle.json` the coordinator
+//! wrote, `wallet.json` and the pinned coordinator key. The bundle is
+//! checked as the signers check it, and the transaction's summary must
+//! hash to the bundle's, which is the hash signers saw when they signed.
+
+use bitcoin::Transaction;
+use bitcoin::consensus::encode::deserialize_hex;
+use psbt_coordinator::bundle::{BundleSummary, CoordinatorKey, OfflineBundle};
+use psbt_coordinator::cli::Args;
+use psbt_coordinator::wallet_file::{WALLET_FILE, WalletFile};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse("verify_final");
+    let tx_file = args.required("final_tx.hex")?;
+    let tx: Transaction = deserialize_hex(std::fs::read_to_string(tx_file)?.trim())
+        .map_err(|e| format!("{}: {}", tx_file, e))?;
+    let bundle: OfflineBundle =
+        serde_json::from_str(&std::fs::read_to_string(args.required("bundle.json")?)?)?;
+    let wallet = WalletFile::load(WALLET_FILE)
+        .map_err(|e| format!("{} (the bundle is checked against it)", e))?
+        .wallet()?;
+    let psbt = bundle.open(&CoordinatorKey::load()?, &wallet)?;
+
+    let approved = &bundle.summary;
+    let actual = BundleSummary::from_transaction(&tx, &psbt);
+    let approved_digest = approved.digest()?;
+    let actual_digest = actual.digest()?;
+    println!("Policy:   {}", bundle.policy_id);
+    println!("Approved: {}", approved_digest);
+    println!("Final:    {}", actual_digest);
+
+    let mut problems = Vec::new();
+    if actual.txid != approved.txid {
+        problems.push(format!(
+            "TXID is {}, signers approved {}",
+            actual.txid, approved.txid
+        ));
+    }
+    if actual.inputs.len() != approved.inputs.len() {
+        problems.push(format!(
+            "{} inputs, signers approved {}",
+            actual.inputs.len(),
+            approved.inputs.len()
+        ));
+    }
+    for (i, (ours, theirs)) in actual.inputs.iter().zip(&approved.inputs).enumerate() {
+        if ours.outpoint != theirs.outpoint {
+            problems.push(format!(
+                "input {} spends {}, signers approved {}",
+                i, ours.outpoint, theirs.outpoint
+            ));
+        }
+    }
+    if actual.outputs.len() != approved.outputs.len() {
+        problems.push(format!(
+            "{} outputs, signers approved {}",
+            actual.outputs.len(),
+            approved.outputs.len()
+        ));
+    }
+    for (i, (ours, theirs)) in actual.outputs.iter().zip(&approved.outputs).enumerate() {
+        if ours != theirs {
+            problems.push(format!(
+                "output {} pays {} sat to {}, signers approved {} sat to {}",
+                i, ours.amount_sat, ours.script_pubkey, theirs.amount_sat, theirs.script_pubkey
+            ));
+        }
+    }
+    if actual.fee_sat != approved.fee_sat {
+        let show = |fee: Option<u64>| fee.map_or(String::from("unknown"), |f| format!("{} sat", f));
+        problems.push(format!(
+            "fee is {}, signers approved {}",
+            show(actual.fee_sat),
+            show(approved.fee_sat)
+        ));
+    }
+    // Anything else the summaries disagree on, such as input amounts.
+    if problems.is_empty() && actual_digest != approved_digest {
+        problems.push(String::from("summary differs from the approved one"));
+    }
+    for (i, txin) in tx.input.iter().enumerate() {
+        if txin.witness.is_empty() && txin.script_sig.is_empty() {
+            problems.push(format!("input {} is not signed", i));
+        }
+    }
+
+    if !problems.is_empty() {
+        eprintln!("\nDoes NOT match what the signers approved:");
+        for problem in &problems {
+            eprintln!("  {}", problem);
+        }
+        std::process::exit(1);
+    }
+
+    println!("\nMatches what the signers approved");
+    println!("  TXID: {}", tx.compute_txid());
+    if let Some(fee) = actual.fee_sat {
+        println!(
+            "  Fee:  {} sat ({:.1} sat/vB)",
+            fee,
+            fee as f64 / tx.vsize() as f64
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,34 @@
+//! Checks a payment proof from another treasury against our descriptor.
+
+use psbt_coordinator::cli::Args;
+use psbt_coordinator::payment_proof::PaymentProof;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse("verify_payment");
+    let proof_arg = args.required("payment_proof.json")?;
+    let proof: PaymentProof = serde_json::from_str(&std::fs::read_to_string(proof_arg)?)
+        .map_err(|e| format!("{}: {}", proof_arg, e))?;
+    // The payer's copy of the descriptor only shows the proof is
+    // consistent; our own shows the output is ours.
+    let descriptor = match args.value("--descriptor") {
+        Some(descriptor) => descriptor,
+        None => {
+            eprintln!(
+                "Warning: checking against the proof's own descriptor; pass --descriptor with yours\n"
+            );
+            proof.descriptor.as_str()
+        }
+    };
+    let amount = proof.verify(descriptor)?;
+
+    println!("Payment verified");
+    println!("  TXID:    {}", proof.txid);
+    println!("  Output:  {}", proof.vout);
+    println!("  Amount:  {} sat", amount.to_sat());
+    println!("  Address: index {} of the receive chain", proof.index);
+    println!(
+        "\nCheck that {} is confirmed before treating it as settled.",
+        proof.txid
+    );
+    Ok(())
+}
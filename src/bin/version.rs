@@ -0,0 +1,34 @@
+//! Prints the tool version, or with `--attest` a JSON description of this
+//! build and the wallet it is set up for, as embedded in exports.
+
+use bitcoin::Network;
+use psbt_coordinator::cli::Args;
+use psbt_coordinator::provenance::SoftwareAttestation;
+use psbt_coordinator::{CoordinatorError, MultisigWallet, ScriptType};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse("version");
+    if !args.flag("--attest") {
+        println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        return Ok(());
+    }
+
+    let key_files = [
+        "key_a.json",
+        "key_b.json",
+        "key_c.json",
+        "key_d.json",
+        "key_e.json",
+    ];
+    // Outside a wallet directory the attestation covers the build alone.
+    let wallet =
+        match MultisigWallet::from_key_files(&key_files, 3, ScriptType::Wsh, Network::Regtest) {
+            Ok(wallet) => Some(wallet),
+            Err(CoordinatorError::Io(_)) => None,
+            Err(e) => return Err(e.into()),
+        };
+    let attestation = SoftwareAttestation::new(wallet.as_ref());
+    println!("{}", serde_json::to_string_pretty(&attestation)?);
+
+    Ok(())
+}
@@ -0,0 +1,139 @@
+//! Creates and shows `wallet.json`, the wallet every other tool loads, and
+//! manages its auxiliary single-sig accounts.
+
+use bitcoin::Network;
+use psbt_coordinator::auxiliary::AuxiliaryAccount;
+use psbt_coordinator::caravan::CaravanConfig;
+use psbt_coordinator::cli::Args;
+use psbt_coordinator::cosigner_file::import_setup;
+use psbt_coordinator::wallet_file::check_account;
+use psbt_coordinator::wallet_file::{WALLET_FILE, WalletFile};
+use psbt_coordinator::{
+    MultisigWallet, ScriptType, account_name, parse_network, print_wallet_info,
+};
+use std::path::Path;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse("wallet");
+    match args.subcommand() {
+        Some(("init", args)) => init(&args),
+        Some(("show", args)) => show(&args),
+        Some(("aux", args)) => aux(&args),
+        _ => Err("expected a subcommand".into()),
+    }
+}
+
+fn init(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let descriptor = args.value("--descriptor");
+    let setup_file = args.value("--setup-file");
+    let threshold = args.parse_value("--threshold")?.unwrap_or(3);
+    let script_type: ScriptType = args.parse_value("--script-type")?.unwrap_or_default();
+    let network = match args.value("--network") {
+        Some(name) => parse_network(name)?,
+        None => Network::Regtest,
+    };
+    let force = args.flag("--force");
+    let unsorted = args.flag("--unsorted");
+    let account: u32 = args.parse_value("--account")?.unwrap_or(0);
+    let mut key_files: Vec<String> = args
+        .values("key.json")
+        .into_iter()
+        .map(String::from)
+        .collect();
+    if key_files.is_empty() {
+        key_files = ["key_a", "key_b", "key_c", "key_d", "key_e"]
+            .iter()
+            .map(|name| format!("{}.json", account_name(name, account)))
+            .collect();
+    }
+    let wallet_path = WalletFile::account_path(account);
+    if Path::new(&wallet_path).exists() && !force {
+        return Err(format!("{} already exists; pass --force to replace it", wallet_path).into());
+    }
+
+    let wallet = match (descriptor, setup_file) {
+        (Some(_), Some(_)) => {
+            return Err("--descriptor cannot be combined with --setup-file".into());
+        }
+        (Some(descriptor), None) => MultisigWallet::from_descriptor(descriptor, network)?,
+        // A Coldcard, BlueWallet or Nunchuk setup file, or a Caravan config.
+        (None, Some(path)) => {
+            let text = std::fs::read_to_string(path)?;
+            if text.trim_start().starts_with('{') {
+                serde_json::from_str::<CaravanConfig>(&text)?.wallet(network)?
+            } else {
+                import_setup(&text, network)?
+            }
+        }
+        (None, None) => {
+            let key_files: Vec<&str> = key_files.iter().map(String::as_str).collect();
+            MultisigWallet::from_key_files(&key_files, threshold, script_type, network)?
+        }
+    };
+    let wallet = match (unsorted, descriptor) {
+        (true, Some(_)) => {
+            return Err("--unsorted does not apply to --descriptor; write multi() instead".into());
+        }
+        (true, None) => wallet.preserve_key_order()?,
+        (false, _) => wallet,
+    };
+    check_account(&wallet, account)?;
+    let file = WalletFile::new(&wallet);
+    // Other tools rebuild the wallet from the descriptor, so make sure that
+    // works before writing anything.
+    file.wallet()?;
+    file.save(&wallet_path)?;
+
+    print_wallet_info(&wallet);
+    println!("\nWallet saved: {}", wallet_path);
+    Ok(())
+}
+
+fn show(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let account = args.parse_value("--account")?.unwrap_or(0);
+    let file = WalletFile::load(WalletFile::account_path(account))?;
+    print_wallet_info(&file.account_wallet(account)?);
+    println!("\nNext receive index: {}", file.next_receive_index);
+    println!("Next change index:  {}", file.next_change_index);
+    for account in &file.auxiliary {
+        println!(
+            "\nAuxiliary account {}: {}\n  Next index: {}",
+            account.label, account.descriptor, account.next_index
+        );
+    }
+    Ok(())
+}
+
+fn aux(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let file = WalletFile::load(WALLET_FILE)?;
+    let network = file.wallet()?.network;
+    match args.subcommand() {
+        Some(("add", args)) => {
+            let label = args.required("label")?;
+            let account = AuxiliaryAccount::new(label, args.required("descriptor")?)?;
+            println!("First address: {}", account.address(0, network)?);
+            WalletFile::update(WALLET_FILE, |file| file.add_auxiliary(account))?;
+            println!("Auxiliary account {} saved to {}", label, WALLET_FILE);
+        }
+        Some(("addresses", args)) => {
+            let account = file.auxiliary(args.required("label")?)?;
+            let range = match args.value("START..END") {
+                Some(range) => {
+                    let (start, end) = range.split_once("..").ok_or("range must be START..END")?;
+                    start.parse()?..end.parse()?
+                }
+                None => account.next_index..account.next_index.saturating_add(10),
+            };
+            for index in range {
+                let used = if index < account.next_index {
+                    "  (used)"
+                } else {
+                    ""
+                };
+                println!("{}: {}{}", index, account.address(index, network)?, used);
+            }
+        }
+        _ => return Err("expected `aux add` or `aux addresses`".into()),
+    }
+    Ok(())
+}
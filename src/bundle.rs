@@ -0,0 +1,369 @@
+//! Offline signing bundles.
+//!
+//! An air-gapped signer should not have to trust loose files handed to it
+//! separately. A bundle carries the PSBT, with the previous transaction of
+//! every input, together with the wallet descriptor, its policy ID and a
+//! plain summary of the transaction. The coordinator signs it with its own
+//! key, and [`OfflineBundle::open`] checks that signature against the key
+//! pinned on the receiving machine, and the bundle against that machine's
+//! wallet, before the PSBT is used.
+
+use crate::receipt::ReceiptOutput;
+use crate::schema::SCHEMA_VERSION;
+use crate::{CoordinatorError, MultisigWallet, parse_untrusted_psbt, psbt_policy_id};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use bitcoin::hashes::{Hash, sha256};
+use bitcoin::psbt::Psbt;
+use bitcoin::secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+use bitcoin::sign_message::{MessageSignature, signed_msg_hash};
+use bitcoin::{Amount, OutPoint, Transaction};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Where signers and verifiers pin the coordinator's bundle key.
+pub const COORDINATOR_KEY_FILE: &str = "coordinator_pubkey.json";
+
+/// The coordinator key bundles must be signed with, pinned out of band.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CoordinatorKey {
+    pub name: String,
+    /// Public key, hex.
+    pub pubkey: String,
+}
+
+impl CoordinatorKey {
+    pub fn new(name: &str, key: &SecretKey) -> Self {
+        Self {
+            name: name.to_string(),
+            pubkey: PublicKey::from_secret_key(&Secp256k1::signing_only(), key).to_string(),
+        }
+    }
+
+    /// Reads the pinned key from [`COORDINATOR_KEY_FILE`].
+    pub fn load() -> Result<PublicKey, CoordinatorError> {
+        let text = std::fs::read_to_string(COORDINATOR_KEY_FILE).map_err(|e| {
+            CoordinatorError::Key(format!(
+                "{}: {} (pin the coordinator's bundle key first)",
+                COORDINATOR_KEY_FILE, e
+            ))
+        })?;
+        let key: Self = serde_json::from_str(&text)
+            .map_err(|e| CoordinatorError::Key(format!("{}: {}", COORDINATOR_KEY_FILE, e)))?;
+        PublicKey::from_str(&key.pubkey)
+            .map_err(|e| CoordinatorError::Key(format!("{}: {}", COORDINATOR_KEY_FILE, e)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct BundleInput {
+    pub outpoint: String,
+    pub amount_sat: Option<u64>,
+}
+
+/// What the transaction does, in a form a person can check by eye.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct BundleSummary {
+    pub txid: String,
+    pub inputs: Vec<BundleInput>,
+    pub outputs: Vec<ReceiptOutput>,
+    pub fee_sat: Option<u64>,
+}
+
+impl BundleSummary {
+    pub fn from_psbt(psbt: &Psbt) -> Self {
+        Self {
+            txid: psbt.unsigned_tx.compute_txid().to_string(),
+            inputs: psbt
+                .unsigned_tx
+                .input
+                .iter()
+                .zip(funding_amounts(psbt))
+                .map(|(txin, amount)| BundleInput {
+                    outpoint: txin.previous_output.to_string(),
+                    amount_sat: amount.map(|a| a.to_sat()),
+                })
+                .collect(),
+            outputs: psbt
+                .unsigned_tx
+                .output
+                .iter()
+                .map(|o| ReceiptOutput {
+                    script_pubkey: o.script_pubkey.to_hex_string(),
+                    amount_sat: o.value.to_sat(),
+                })
+                .collect(),
+            fee_sat: psbt.fee().ok().map(|f| f.to_sat()),
+        }
+    }
+
+    /// The summary of a finalized transaction. A transaction does not say
+    /// what its inputs are worth, so amounts come from the inputs of `psbt`
+    /// with the same outpoints, as they do in [`Self::from_psbt`]; an input
+    /// `psbt` does not spend has none.
+    pub fn from_transaction(tx: &Transaction, psbt: &Psbt) -> Self {
+        let find = |outpoint: &OutPoint| {
+            psbt.unsigned_tx
+                .input
+                .iter()
+                .position(|txin| txin.previous_output == *outpoint)
+        };
+        let funding = funding_amounts(psbt);
+        let fee = tx
+            .input
+            .iter()
+            .try_fold(Amount::ZERO, |total, txin| {
+                let index = find(&txin.previous_output)?;
+                total.checked_add((*funding.get(index)?)?)
+            })
+            .and_then(|total| {
+                tx.output
+                    .iter()
+                    .try_fold(total, |left, output| left.checked_sub(output.value))
+            });
+        Self {
+            txid: tx.compute_txid().to_string(),
+            inputs: tx
+                .input
+                .iter()
+                .map(|txin| BundleInput {
+                    outpoint: txin.previous_output.to_string(),
+                    amount_sat: find(&txin.previous_output)
+                        .and_then(|index| *funding.get(index)?)
+                        .map(|a| a.to_sat()),
+                })
+                .collect(),
+            outputs: tx
+                .output
+                .iter()
+                .map(|o| ReceiptOutput {
+                    script_pubkey: o.script_pubkey.to_hex_string(),
+                    amount_sat: o.value.to_sat(),
+                })
+                .collect(),
+            fee_sat: fee.map(|f| f.to_sat()),
+        }
+    }
+
+    /// SHA256 of the summary's JSON encoding. Two machines that print the
+    /// same hash agree on every input, output and the fee, so cosigners and
+    /// whoever checks the final transaction can compare a single line.
+    pub fn digest(&self) -> Result<sha256::Hash, serde_json::Error> {
+        Ok(sha256::Hash::hash(&serde_json::to_vec(self)?))
+    }
+}
+
+/// What each input of `psbt` is worth, from its `witness_utxo` or else its
+/// previous transaction.
+fn funding_amounts(psbt: &Psbt) -> Vec<Option<Amount>> {
+    psbt.iter_funding_utxos()
+        .map(|utxo| utxo.ok().map(|u| u.value))
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct OfflineBundle {
+    pub schema_version: u32,
+    pub descriptor: String,
+    pub policy_id: String,
+    pub summary: BundleSummary,
+    /// Base64 PSBT, with the previous transaction of every input.
+    pub psbt: String,
+    /// Public key of the coordinator that made the bundle, hex.
+    pub coordinator: String,
+    /// Base64 signed-message signature by `coordinator` over the bundle's
+    /// JSON encoding with this field empty.
+    pub signature: String,
+}
+
+impl OfflineBundle {
+    /// Bundles `psbt` for `wallet`, signed with the coordinator's `key`.
+    /// Every input must carry its previous transaction, so the signer can
+    /// check input amounts without trusting the `witness_utxo`.
+    pub fn new(
+        wallet: &MultisigWallet,
+        psbt: &Psbt,
+        key: &SecretKey,
+    ) -> Result<Self, CoordinatorError> {
+        check_previous_transactions(psbt)?;
+        let secp = Secp256k1::signing_only();
+        let mut bundle = Self {
+            schema_version: SCHEMA_VERSION,
+            descriptor: wallet.descriptor_with_checksum(),
+            policy_id: wallet.policy_id().to_string(),
+            summary: BundleSummary::from_psbt(psbt),
+            psbt: STANDARD.encode(psbt.serialize()),
+            coordinator: PublicKey::from_secret_key(&secp, key).to_string(),
+            signature: String::new(),
+        };
+        let msg = Message::from_digest(bundle.signed_hash()?.to_byte_array());
+        let sig = MessageSignature::new(secp.sign_ecdsa_recoverable(&msg, key), true);
+        bundle.signature = STANDARD.encode(sig.serialize());
+        Ok(bundle)
+    }
+
+    /// Hash the coordinator signs: the bundle with its signature empty.
+    fn signed_hash(&self) -> Result<bitcoin::hashes::sha256d::Hash, serde_json::Error> {
+        let unsigned = Self {
+            signature: String::new(),
+            ..self.clone()
+        };
+        Ok(signed_msg_hash(&serde_json::to_string(&unsigned)?))
+    }
+
+    /// Checks the bundle and returns its PSBT. It must be signed by the
+    /// pinned `coordinator` key and be for `wallet`, the receiving
+    /// machine's own: its descriptor must have the wallet's policy ID, as
+    /// must the bundle and the PSBT. Every input must carry its previous
+    /// transaction, and the summary must describe the PSBT's transaction.
+    pub fn open(
+        &self,
+        coordinator: &PublicKey,
+        wallet: &MultisigWallet,
+    ) -> Result<Psbt, CoordinatorError> {
+        let bytes = STANDARD
+            .decode(self.signature.trim())
+            .map_err(CoordinatorError::key)?;
+        let sig = MessageSignature::from_slice(&bytes).map_err(CoordinatorError::key)?;
+        let recovered = sig
+            .recover_pubkey(&Secp256k1::verification_only(), self.signed_hash()?)
+            .map_err(CoordinatorError::key)?;
+        if recovered.inner != *coordinator || self.coordinator != coordinator.to_string() {
+            return Err(CoordinatorError::key(
+                "bundle is not signed by the pinned coordinator key",
+            ));
+        }
+
+        let policy_id = wallet.policy_id();
+        let described = MultisigWallet::from_descriptor(&self.descriptor, wallet.network)?;
+        if described.policy_id() != policy_id {
+            return Err(CoordinatorError::Descriptor(format!(
+                "bundle descriptor is for policy {}, this wallet is {}",
+                described.policy_id(),
+                policy_id
+            )));
+        }
+        if self.policy_id != policy_id.to_string() {
+            return Err(CoordinatorError::Descriptor(format!(
+                "bundle is for policy {}, this wallet is {}",
+                self.policy_id, policy_id
+            )));
+        }
+
+        let bytes = STANDARD
+            .decode(self.psbt.trim())
+            .map_err(CoordinatorError::psbt)?;
+        let psbt = parse_untrusted_psbt(&bytes)?;
+        match psbt_policy_id(&psbt) {
+            Some(id) if id == policy_id => {}
+            Some(id) => {
+                return Err(CoordinatorError::Psbt(format!(
+                    "PSBT is for policy {}, bundle is for {}",
+                    id, policy_id
+                )));
+            }
+            None => return Err(CoordinatorError::psbt("PSBT does not record a policy ID")),
+        }
+        check_previous_transactions(&psbt)?;
+        if BundleSummary::from_psbt(&psbt) != self.summary {
+            return Err(CoordinatorError::psbt(
+                "bundle summary does not match its PSBT",
+            ));
+        }
+        Ok(psbt)
+    }
+}
+
+/// Fails unless every input of `psbt` carries the transaction it spends.
+/// `parse_untrusted_psbt` checks that it is the one spent, and that a
+/// `witness_utxo`, if any, equals the output spent.
+fn check_previous_transactions(psbt: &Psbt) -> Result<(), CoordinatorError> {
+    for (i, input) in psbt.inputs.iter().enumerate() {
+        if input.non_witness_utxo.is_none() {
+            return Err(CoordinatorError::Psbt(format!(
+                "input {}: bundle lacks its previous transaction",
+                i
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::{ScriptType, set_psbt_policy_id};
+    use bitcoin::{TxIn, absolute, transaction};
+
+    /// The shared test PSBT, spending a funding transaction it carries.
+    fn psbt(wallet: &MultisigWallet) -> Psbt {
+        let mut psbt = testing::psbt(wallet);
+        let funding = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![psbt.inputs[0].witness_utxo.clone().unwrap()],
+        };
+        psbt.unsigned_tx.input[0].previous_output = OutPoint::new(funding.compute_txid(), 0);
+        psbt.inputs[0].non_witness_utxo = Some(funding);
+        set_psbt_policy_id(&mut psbt, wallet.policy_id());
+        psbt
+    }
+
+    fn key(seed: u8) -> SecretKey {
+        SecretKey::from_slice(&[seed; 32]).unwrap()
+    }
+
+    fn pubkey(seed: u8) -> PublicKey {
+        PublicKey::from_secret_key(&Secp256k1::signing_only(), &key(seed))
+    }
+
+    #[test]
+    fn opens_when_signed_by_the_pinned_key_for_our_wallet() {
+        let wallet = testing::wallet(2, 3, ScriptType::Wsh);
+        let psbt = psbt(&wallet);
+        let bundle = OfflineBundle::new(&wallet, &psbt, &key(9)).unwrap();
+        let opened = bundle.open(&pubkey(9), &wallet).unwrap();
+        assert_eq!(opened.unsigned_tx, psbt.unsigned_tx);
+        assert_eq!(bundle.summary.fee_sat, Some(10_000));
+    }
+
+    #[test]
+    fn refuses_another_coordinator_key_or_wallet() {
+        let wallet = testing::wallet(2, 3, ScriptType::Wsh);
+        let bundle = OfflineBundle::new(&wallet, &psbt(&wallet), &key(9)).unwrap();
+        assert!(bundle.open(&pubkey(8), &wallet).is_err());
+
+        let other = testing::wallet(2, 4, ScriptType::Wsh);
+        assert!(bundle.open(&pubkey(9), &other).is_err());
+
+        let mut tampered = bundle.clone();
+        tampered.summary.fee_sat = Some(0);
+        assert!(tampered.open(&pubkey(9), &wallet).is_err());
+    }
+
+    #[test]
+    fn needs_previous_transactions() {
+        let wallet = testing::wallet(2, 3, ScriptType::Wsh);
+        let mut psbt = psbt(&wallet);
+        psbt.inputs[0].non_witness_utxo = None;
+        assert!(OfflineBundle::new(&wallet, &psbt, &key(9)).is_err());
+    }
+
+    #[test]
+    fn refuses_a_witness_utxo_other_than_the_output_spent() {
+        let wallet = testing::wallet(2, 3, ScriptType::Wsh);
+        let mut psbt = psbt(&wallet);
+        assert!(parse_untrusted_psbt(&psbt.serialize()).is_ok());
+
+        psbt.inputs[0].witness_utxo.as_mut().unwrap().value = Amount::from_sat(1);
+        assert!(parse_untrusted_psbt(&psbt.serialize()).is_err());
+
+        let mut psbt = self::psbt(&wallet);
+        psbt.unsigned_tx.input[0].previous_output.vout = 1;
+        assert!(parse_untrusted_psbt(&psbt.serialize()).is_err());
+    }
+}
@@ -0,0 +1,263 @@
+//! Caravan wallet configuration files, as used by Unchained-style
+//! collaborative custody.
+//!
+//! ```json
+//! {
+//!   "name": "Treasury",
+//!   "addressType": "P2WSH",
+//!   "network": "testnet",
+//!   "quorum": { "requiredSigners": 3, "totalSigners": 5 },
+//!   "extendedPublicKeys": [
+//!     { "name": "Alice", "bip32Path": "m/48'/1'/0'/2'", "xpub": "tpub...", "xfp": "f00dbabe" }
+//!   ]
+//! }
+//! ```
+//!
+//! Caravan wallets are always `sortedmulti`, with receive addresses under
+//! `/0/*` and change under `/1/*`, as this tool derives them.
+
+use crate::{CoordinatorError, MultisigWallet, ScriptType, check_threshold, format_path, slip132};
+use bitcoin::Network;
+use bitcoin::bip32::{DerivationPath, Fingerprint};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaravanConfig {
+    pub name: String,
+    /// `P2WSH` or `P2SH-P2WSH`; Caravan's legacy `P2SH` is not supported.
+    pub address_type: String,
+    /// `mainnet`, `testnet`, `signet` or `regtest`.
+    pub network: String,
+    #[serde(default = "CaravanClient::public")]
+    pub client: CaravanClient,
+    pub quorum: CaravanQuorum,
+    pub extended_public_keys: Vec<CaravanKey>,
+    #[serde(default)]
+    pub starting_address_index: u32,
+}
+
+/// Where Caravan looks up UTXOs. Kept as is on import, so a private node
+/// setting survives a round trip through a tool that ignores it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaravanClient {
+    #[serde(rename = "type")]
+    pub client_type: String,
+    #[serde(flatten)]
+    pub settings: serde_json::Map<String, serde_json::Value>,
+}
+
+impl CaravanClient {
+    /// Caravan's public block explorer.
+    pub fn public() -> Self {
+        Self {
+            client_type: "public".to_string(),
+            settings: serde_json::Map::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaravanQuorum {
+    pub required_signers: usize,
+    pub total_signers: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaravanKey {
+    pub name: String,
+    /// `m/48'/1'/0'/2'`. Caravan writes `Unknown` when it was not given.
+    pub bip32_path: String,
+    pub xpub: String,
+    /// Master fingerprint in lowercase hex; may be empty.
+    #[serde(default)]
+    pub xfp: String,
+    /// How the key was entered in Caravan, e.g. `text` or `coldcard`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+}
+
+impl CaravanConfig {
+    /// Describes `wallet` for Caravan. Cosigner names are their display
+    /// names, where configured.
+    pub fn from_wallet(wallet: &MultisigWallet, name: &str) -> Result<Self, CoordinatorError> {
+        if wallet.is_policy() {
+            return Err(CoordinatorError::descriptor(
+                "Caravan only describes plain multisig wallets",
+            ));
+        }
+        if !wallet.is_sorted() && wallet.script_type != ScriptType::Tr {
+            return Err(CoordinatorError::descriptor(
+                "Caravan only describes sortedmulti wallets",
+            ));
+        }
+        let address_type = match wallet.script_type {
+            ScriptType::Wsh => "P2WSH",
+            ScriptType::ShWsh => "P2SH-P2WSH",
+            ScriptType::Tr => {
+                return Err(CoordinatorError::descriptor(
+                    "Caravan does not support taproot multisig",
+                ));
+            }
+        };
+        Ok(Self {
+            name: name.to_string(),
+            address_type: address_type.to_string(),
+            network: network_name(wallet.network).to_string(),
+            client: CaravanClient::public(),
+            quorum: CaravanQuorum {
+                required_signers: wallet.threshold,
+                total_signers: wallet.xpub_origins.len(),
+            },
+            extended_public_keys: wallet
+                .xpub_origins
+                .iter()
+                .map(|o| CaravanKey {
+                    name: o
+                        .metadata
+                        .display_name
+                        .clone()
+                        .unwrap_or_else(|| o.name.clone()),
+                    bip32_path: format_path(&o.derivation_path),
+                    xpub: o.xpub.to_string(),
+                    xfp: o.fingerprint.to_string(),
+                    method: Some("text".to_string()),
+                })
+                .collect(),
+            starting_address_index: 0,
+        })
+    }
+
+    /// Builds the wallet the config describes, refusing one for a network
+    /// other than `network`. Caravan key names become display names.
+    pub fn wallet(&self, network: Network) -> Result<MultisigWallet, CoordinatorError> {
+        if self.network != network_name(network) {
+            return Err(CoordinatorError::Invalid(format!(
+                "Caravan config is for {}, not {}",
+                self.network,
+                network_name(network)
+            )));
+        }
+        let script_type = match self.address_type.to_ascii_uppercase().as_str() {
+            "P2WSH" => ScriptType::Wsh,
+            "P2SH-P2WSH" => ScriptType::ShWsh,
+            other => {
+                return Err(CoordinatorError::Descriptor(format!(
+                    "unsupported address type {}: only P2WSH and P2SH-P2WSH",
+                    other
+                )));
+            }
+        };
+        let (threshold, total) = (self.quorum.required_signers, self.quorum.total_signers);
+        if self.extended_public_keys.len() != total {
+            return Err(CoordinatorError::Descriptor(format!(
+                "quorum is {} of {} but {} keys listed",
+                threshold,
+                total,
+                self.extended_public_keys.len()
+            )));
+        }
+        check_threshold(threshold, total)?;
+
+        let mut keys = Vec::new();
+        for key in &self.extended_public_keys {
+            let xpub = slip132::parse_xpub(&key.xpub)?;
+            // Without origin info the xpub stands in for its own origin, as
+            // for key files.
+            let (fingerprint, path) =
+                if key.xfp.is_empty() || key.bip32_path.eq_ignore_ascii_case("unknown") {
+                    (xpub.fingerprint(), DerivationPath::master())
+                } else {
+                    (
+                        Fingerprint::from_str(&key.xfp).map_err(CoordinatorError::key)?,
+                        DerivationPath::from_str(&key.bip32_path)?,
+                    )
+                };
+            keys.push((fingerprint, path, xpub));
+        }
+        let mut wallet = MultisigWallet::from_xpubs(&keys, threshold, script_type, network)?;
+        for (origin, key) in wallet
+            .xpub_origins
+            .iter_mut()
+            .zip(&self.extended_public_keys)
+        {
+            if !key.name.is_empty() {
+                origin.metadata.display_name = Some(key.name.clone());
+            }
+        }
+        Ok(wallet)
+    }
+}
+
+/// Caravan's name for `network`.
+fn network_name(network: Network) -> &'static str {
+    match network {
+        Network::Bitcoin => "mainnet",
+        Network::Signet => "signet",
+        Network::Regtest => "regtest",
+        _ => "testnet",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn config() -> CaravanConfig {
+        CaravanConfig::from_wallet(&testing::wallet(2, 3, ScriptType::Wsh), "Treasury").unwrap()
+    }
+
+    #[test]
+    fn imports_what_it_exports() {
+        let config = config();
+        let wallet = config.wallet(Network::Regtest).unwrap();
+        assert_eq!(
+            wallet.policy_id(),
+            testing::wallet(2, 3, ScriptType::Wsh).policy_id()
+        );
+        let exported = CaravanConfig::from_wallet(&wallet, "Treasury").unwrap();
+        assert_eq!(
+            serde_json::to_value(&exported).unwrap(),
+            serde_json::to_value(&config).unwrap()
+        );
+    }
+
+    #[test]
+    fn unknown_origins_stand_for_the_xpub_itself() {
+        let mut config = config();
+        config.extended_public_keys[0].bip32_path = "Unknown".to_string();
+        config.extended_public_keys[1].xfp = String::new();
+        let wallet = config.wallet(Network::Regtest).unwrap();
+        for origin in &wallet.xpub_origins[..2] {
+            assert_eq!(origin.fingerprint, origin.xpub.fingerprint());
+            assert!(origin.derivation_path.is_master());
+        }
+        assert!(!wallet.xpub_origins[2].derivation_path.is_master());
+    }
+
+    #[test]
+    fn refuses_a_quorum_that_does_not_match_the_keys() {
+        let mut config = config();
+        config.quorum.total_signers = 4;
+        assert!(matches!(
+            config.wallet(Network::Regtest),
+            Err(CoordinatorError::Descriptor(_))
+        ));
+        config.extended_public_keys.pop();
+        config.quorum.total_signers = 2;
+        config.quorum.required_signers = 3;
+        assert!(config.wallet(Network::Regtest).is_err());
+    }
+
+    #[test]
+    fn refuses_another_network() {
+        assert!(matches!(
+            config().wallet(Network::Testnet),
+            Err(CoordinatorError::Invalid(_))
+        ));
+    }
+}
@@ -0,0 +1,131 @@
+//! Where the coordinator and signer get chain data.
+//!
+//! A [`ChainSource`] finds the wallet's UTXOs, looks up the outputs a PSBT
+//! spends, and estimates fee rates. Bitcoin Core is one; for coordinator
+//! machines without a full node, an Esplora HTTP API or an Electrum server
+//! can stand in. A [`ChainConfig`] file picks the backend with its
+//! `backend` field:
+//!
+//! ```json
+//! { "backend": "core", "url": "http://127.0.0.1:18443", "cookie_file": "..." }
+//! { "backend": "esplora", "url": "https://mempool.space/testnet4/api" }
+//! { "backend": "electrum", "server": "127.0.0.1:50001" }
+//! ```
+//!
+//! Connecting checks the backend's genesis block, so a server for another
+//! chain is refused before anything is asked of it. Esplora and Electrum
+//! serve funding transactions whole, and each UTXO is read from its
+//! transaction and checked against the wallet's addresses rather than
+//! taken from the server's listing.
+
+use crate::core_rpc::CoreRpcConfig;
+use crate::electrum::ElectrumConfig;
+use crate::esplora::EsploraConfig;
+use crate::utxo_store::ManualUtxo;
+use crate::{CoordinatorError, MultisigWallet};
+use bitcoin::consensus::encode::deserialize_hex;
+use bitcoin::constants::genesis_block;
+use bitcoin::{BlockHash, Network, OutPoint, Transaction, TxOut};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Blocks the coordinator's fee estimate aims to confirm within, unless
+/// told otherwise.
+pub const DEFAULT_FEE_TARGET: u16 = 6;
+
+pub trait ChainSource {
+    /// Backend name for messages, e.g. "Bitcoin Core".
+    fn name(&self) -> &'static str;
+
+    /// Hash of the backend's block 0, which names its chain.
+    fn genesis_hash(&self) -> Result<BlockHash, CoordinatorError>;
+
+    /// The wallet's unspent outputs among the first `gap_limit` addresses
+    /// of each chain.
+    fn find_utxos(
+        &self,
+        wallet: &MultisigWallet,
+        gap_limit: u32,
+    ) -> Result<Vec<ManualUtxo>, CoordinatorError>;
+
+    /// The output at `outpoint`, which must still be unspent.
+    fn prevout(&self, outpoint: &OutPoint) -> Result<TxOut, CoordinatorError>;
+
+    /// Fee rate in sat/vB to confirm within `blocks` blocks.
+    fn fee_rate(&self, blocks: u16) -> Result<f64, CoordinatorError>;
+}
+
+/// A backend and how to reach it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "backend", rename_all = "lowercase")]
+pub enum ChainConfig {
+    Core(CoreRpcConfig),
+    Esplora(EsploraConfig),
+    Electrum(ElectrumConfig),
+}
+
+impl ChainConfig {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, CoordinatorError> {
+        let path = path.as_ref();
+        serde_json::from_str(&std::fs::read_to_string(path)?)
+            .map_err(|e| CoordinatorError::Invalid(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Connects to the backend and checks that it serves `network`.
+    pub fn connect(self, network: Network) -> Result<Box<dyn ChainSource>, CoordinatorError> {
+        let source: Box<dyn ChainSource> = match self {
+            ChainConfig::Core(config) => Box::new(config.connect(network)),
+            ChainConfig::Esplora(config) => Box::new(config),
+            ChainConfig::Electrum(config) => Box::new(config),
+        };
+        if source.genesis_hash()? != genesis_block(network).block_hash() {
+            return Err(CoordinatorError::Invalid(format!(
+                "{} serves a different chain than {}",
+                source.name(),
+                network
+            )));
+        }
+        Ok(source)
+    }
+}
+
+/// Core and Electrum estimate in BTC/kvB.
+pub(crate) fn btc_per_kvb_to_sat_per_vb(rate: f64) -> f64 {
+    rate * 100_000.0
+}
+
+/// Reads `outpoint`'s output from its raw transaction (hex) as served by a
+/// backend, which must be the transaction asked for.
+pub(crate) fn output_of(raw_tx: &str, outpoint: &OutPoint) -> Result<TxOut, CoordinatorError> {
+    let tx: Transaction = deserialize_hex(raw_tx.trim())
+        .map_err(|e| CoordinatorError::Invalid(format!("transaction {}: {}", outpoint.txid, e)))?;
+    if tx.compute_txid() != outpoint.txid {
+        return Err(CoordinatorError::Invalid(format!(
+            "asked for transaction {}, got {}",
+            outpoint.txid,
+            tx.compute_txid()
+        )));
+    }
+    tx.output
+        .get(outpoint.vout as usize)
+        .cloned()
+        .ok_or_else(|| {
+            CoordinatorError::Invalid(format!(
+                "transaction {} has no output {}",
+                outpoint.txid, outpoint.vout
+            ))
+        })
+}
+
+/// A UTXO a backend listed for address `index`, read from its funding
+/// transaction.
+pub(crate) fn listed_utxo(
+    wallet: &MultisigWallet,
+    raw_tx: &str,
+    outpoint: &OutPoint,
+    index: u32,
+    is_change: bool,
+) -> Result<ManualUtxo, CoordinatorError> {
+    output_of(raw_tx, outpoint)?;
+    ManualUtxo::from_funding_tx(wallet, raw_tx, outpoint.vout, index, is_change)
+}
@@ -0,0 +1,1169 @@
+//! The command-line tools and their arguments, in one table.
+//!
+//! Every tool parses its arguments with the clap command built from its
+//! entry in [`COMMANDS`], so the table is the tools' real argument
+//! definitions. Shell completions and man pages are generated from the
+//! same commands, so operators can complete flags and read the positional
+//! arguments in order rather than recalling them under pressure.
+
+use crate::schema::ARTIFACTS;
+use crate::{CoordinatorError, NETWORK_NAMES};
+use clap::builder::{PossibleValue, StringValueParser, TypedValueParser};
+use clap::{Arg, ArgAction, ArgMatches};
+use std::ffi::OsStr;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+pub use clap_complete::Shell;
+
+pub struct Command {
+    /// Binary name, as given to `cargo run --bin`, or subcommand name.
+    pub name: &'static str,
+    pub about: &'static str,
+    /// Positional arguments, in order.
+    pub args: &'static [Positional],
+    pub flags: &'static [Flag],
+    /// Subcommands, each with its own arguments and flags. The command's
+    /// own flags are accepted after the subcommand as well.
+    pub subcommands: &'static [Command],
+}
+
+pub struct Positional {
+    pub name: &'static str,
+    pub count: Count,
+    /// Accepted words, offered to completions; empty for any value.
+    pub choices: &'static [&'static str],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Count {
+    One,
+    Optional,
+    AtLeast(usize),
+}
+
+pub struct Flag {
+    pub name: &'static str,
+    /// Single-letter alias, as HWI's `-t`.
+    pub short: Option<char>,
+    /// Placeholder for the flag's value; `a|b|c` lists the accepted words.
+    /// `None` for a switch.
+    pub value: Option<&'static str>,
+    /// The value may be left out, and must then be attached with `=`.
+    pub value_optional: bool,
+    pub help: &'static str,
+}
+
+const fn flag(name: &'static str, value: &'static str, help: &'static str) -> Flag {
+    Flag {
+        name,
+        short: None,
+        value: Some(value),
+        value_optional: false,
+        help,
+    }
+}
+
+const fn switch(name: &'static str, help: &'static str) -> Flag {
+    Flag {
+        name,
+        short: None,
+        value: None,
+        value_optional: false,
+        help,
+    }
+}
+
+impl Flag {
+    const fn short(self, short: char) -> Flag {
+        Flag {
+            short: Some(short),
+            ..self
+        }
+    }
+}
+
+const fn arg(name: &'static str) -> Positional {
+    Positional {
+        name,
+        count: Count::One,
+        choices: &[],
+    }
+}
+
+const fn optional(name: &'static str) -> Positional {
+    Positional {
+        name,
+        count: Count::Optional,
+        choices: &[],
+    }
+}
+
+const fn at_least(name: &'static str, min: usize) -> Positional {
+    Positional {
+        name,
+        count: Count::AtLeast(min),
+        choices: &[],
+    }
+}
+
+/// A subcommand with no arguments or flags of its own.
+const fn leaf(name: &'static str, about: &'static str) -> Command {
+    Command {
+        name,
+        about,
+        args: &[],
+        flags: &[],
+        subcommands: &[],
+    }
+}
+
+const ARMOR: Flag = Flag {
+    name: "--armor",
+    short: None,
+    value: Some("base64|bech32"),
+    value_optional: true,
+    help: "Also write an armored text copy; --armor=bech32 for bech32",
+};
+const TRAINING: Flag = switch("--training", "Work in training/ on regtest, for drills");
+const NETWORK: Flag = flag("--network", NETWORK_NAMES, "Network of the wallet");
+const ACCOUNT: Flag = flag("--account", "N", "BIP 48 account number");
+/// Bitcoin Core's names for the chains, as `bitcoin-cli -chain` takes them.
+const CORE_CHAINS: &str = "main|test|testnet4|signet|regtest";
+const CHAIN: Flag = flag("--chain", CORE_CHAINS, "Chain of the bitcoin-cli node");
+const OUT: Flag = flag("--out", "FILE", "Output file");
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "keygen",
+        about: "Generate 5 key pairs",
+        args: &[],
+        flags: &[
+            switch("--mnemonic", "Derive keys from new BIP 39 mnemonics"),
+            flag("--language", "LANG", "Mnemonic word list language"),
+            flag("--import", "NAME", "Restore key NAME from its mnemonic"),
+            ACCOUNT,
+            NETWORK,
+            TRAINING,
+            flag(
+                "--dice",
+                "FILE",
+                "Mix dice rolls from FILE into the entropy",
+            ),
+            flag("--entropy-device", "PATH", "Mix entropy read from PATH"),
+            flag(
+                "--deterministic",
+                "SEED",
+                "Derive keys from SEED, test networks only",
+            ),
+        ],
+        subcommands: &[],
+    },
+    Command {
+        name: "wallet",
+        about: "Create wallet.json from key files or a descriptor",
+        args: &[],
+        flags: &[],
+        subcommands: &[
+            Command {
+                name: "init",
+                about: "Write wallet.json",
+                args: &[at_least("key.json", 0)],
+                flags: &[
+                    flag("--descriptor", "DESC", "Build the wallet from a descriptor"),
+                    flag("--setup-file", "FILE", "Build the wallet from a setup file"),
+                    flag("--threshold", "M", "Signatures needed"),
+                    flag("--script-type", "wsh|sh-wsh|tr", "Output script type"),
+                    NETWORK,
+                    switch("--unsorted", "Keep keys in the order given, as multi()"),
+                    ACCOUNT,
+                    switch("--force", "Replace an existing wallet.json"),
+                ],
+                subcommands: &[],
+            },
+            Command {
+                name: "show",
+                about: "Show wallet.json",
+                args: &[],
+                flags: &[ACCOUNT],
+                subcommands: &[],
+            },
+            Command {
+                name: "aux",
+                about: "Manage auxiliary single-sig accounts",
+                args: &[],
+                flags: &[],
+                subcommands: &[
+                    Command {
+                        name: "add",
+                        about: "Add an account with a wildcard descriptor",
+                        args: &[arg("label"), arg("descriptor")],
+                        flags: &[],
+                        subcommands: &[],
+                    },
+                    Command {
+                        name: "addresses",
+                        about: "List an account's addresses",
+                        args: &[arg("label"), optional("START..END")],
+                        flags: &[],
+                        subcommands: &[],
+                    },
+                ],
+            },
+        ],
+    },
+    Command {
+        name: "addresses",
+        about: "List receive addresses",
+        args: &[],
+        flags: &[
+            flag("--range", "START..END", "Address indexes to list"),
+            switch("--uppercase", "Print bech32 addresses in upper case"),
+            switch("--uri", "Print BIP 21 URIs"),
+            switch("--change", "List change addresses"),
+            flag("--script-type", "wsh|sh-wsh|tr", "Output script type"),
+            flag("--descriptor", "DESC", "List addresses of a descriptor"),
+        ],
+        subcommands: &[],
+    },
+    Command {
+        name: "utxo",
+        about: "Record UTXOs from raw funding transactions",
+        args: &[],
+        flags: &[],
+        subcommands: &[
+            Command {
+                name: "add",
+                about: "Record an output of a funding transaction",
+                args: &[],
+                flags: &[
+                    flag("--rawtx", "HEX", "Funding transaction"),
+                    flag("--vout", "N", "Output of the funding transaction"),
+                    flag("--index", "M", "Address index the output pays"),
+                    switch("--change", "The address is on the change chain"),
+                ],
+                subcommands: &[],
+            },
+            leaf("list", "List the recorded UTXOs"),
+            Command {
+                name: "remove",
+                about: "Forget a UTXO",
+                args: &[arg("TXID:VOUT")],
+                flags: &[],
+                subcommands: &[],
+            },
+        ],
+    },
+    Command {
+        name: "dev",
+        about: "Fund the wallet on regtest or signet",
+        args: &[],
+        flags: &[],
+        subcommands: &[Command {
+            name: "fund",
+            about: "Pay a wallet address through Bitcoin Core",
+            args: &[],
+            flags: &[
+                flag("--core-rpc", "FILE", "Bitcoin Core node to fund through"),
+                flag(
+                    "--chain-source",
+                    "FILE",
+                    "Chain source file with a core backend",
+                ),
+                flag("--index", "N", "Receive address index to fund"),
+                flag(
+                    "--from-wallet",
+                    "NAME",
+                    "Send from this Core wallet instead of mining",
+                ),
+                flag("--amount", "AMOUNT", "Amount to send with --from-wallet"),
+                flag("--confirmations", "N", "Confirmations to wait for"),
+                flag("--wait-minutes", "N", "How long to wait for them"),
+            ],
+            subcommands: &[],
+        }],
+    },
+    Command {
+        name: "coordinator",
+        about: "Create unsigned PSBT",
+        args: &[],
+        flags: &[
+            flag("--to", "ADDRESS", "Recipient of a single payment"),
+            flag("--amount", "AMOUNT", "Amount of the --to payment"),
+            switch("--send-max", "Sweep the inputs to --to, with no change"),
+            flag("--pay", "ADDRESS=SHARE", "Pay a share of the spend"),
+            flag(
+                "--to-descriptor",
+                "DESC:INDEX:SHARE",
+                "Pay an address of a descriptor",
+            ),
+            flag("--to-aux", "LABEL:SHARE", "Pay an auxiliary account"),
+            flag("--batch", "FILE", "Pay every row of a CSV or JSON file"),
+            flag(
+                "--aux-input",
+                "LABEL:INDEX:TXID:VOUT:AMOUNT",
+                "Spend an auxiliary account UTXO",
+            ),
+            flag("--utxo", "TXID:VOUT", "Spend this UTXO"),
+            flag("--feerate", "SAT_PER_VB", "Fee rate"),
+            flag(
+                "--target-blocks",
+                "N",
+                "Estimate the fee rate for confirmation within N blocks",
+            ),
+            flag("--fee-target", "N", "Older spelling of --target-blocks"),
+            flag("--core-rpc", "FILE", "Find UTXOs through Bitcoin Core"),
+            flag(
+                "--chain-source",
+                "FILE",
+                "Find UTXOs through Core, Esplora or Electrum",
+            ),
+            flag(
+                "--max-per-output",
+                "AMOUNT",
+                "Split the payment into outputs under AMOUNT",
+            ),
+            flag("--change-index", "N", "Change address index"),
+            flag(
+                "--random-change",
+                "N",
+                "Pick change among the next N unused indexes",
+            ),
+            flag("--denomination", "btc|sat", "Unit amounts are shown in"),
+            flag("--descriptor", "DESC", "Spend from a descriptor wallet"),
+            flag(
+                "--policy",
+                "POLICY",
+                "Spend from a miniscript policy wallet",
+            ),
+            flag("--older", "BLOCKS", "Relative timelock of the inputs"),
+            flag("--script-type", "wsh|sh-wsh|tr", "Output script type"),
+            flag(
+                "--threshold",
+                "M",
+                "Signatures needed, without a wallet file",
+            ),
+            ACCOUNT,
+            NETWORK,
+            flag(
+                "--expires-in",
+                "SECONDS",
+                "Signers refuse the request after this",
+            ),
+            flag(
+                "--expires-at-height",
+                "HEIGHT",
+                "Signers refuse the request from this height",
+            ),
+            flag(
+                "--lock-hours",
+                "H",
+                "Time-lock the transaction H hours ahead, for approval tier delays",
+            ),
+            flag(
+                "--bundle",
+                "KEY_FILE",
+                "Also write an offline signing bundle, signed with this key",
+            ),
+            ARMOR,
+            TRAINING,
+        ],
+        subcommands: &[],
+    },
+    Command {
+        name: "signer",
+        about: "Sign PSBT with a key",
+        args: &[arg("key.json"), arg("psbt")],
+        flags: &[
+            flag("--denomination", "btc|sat", "Unit amounts are shown in"),
+            flag("--preimages", "FILE", "Write the sighash preimages signed"),
+            flag("--height", "N", "Current chain height, for height expiries"),
+            switch(
+                "--require-expiry",
+                "Refuse requests without an expiry, as with a wallet file",
+            ),
+            flag(
+                "--internal",
+                "FILE",
+                "Internal wallets, left out of external totals",
+            ),
+            flag(
+                "--max-external",
+                "AMOUNT",
+                "Refuse larger external payments",
+            ),
+            flag(
+                "--fetch-utxos",
+                CORE_CHAINS,
+                "Look up missing input amounts in Core",
+            ),
+            flag(
+                "--chain-source",
+                "FILE",
+                "Look up missing input amounts in a backend",
+            ),
+            flag("--lang", "en|es", "Language of the review screen"),
+            ARMOR,
+            TRAINING,
+        ],
+        subcommands: &[],
+    },
+    Command {
+        name: "signer_policy",
+        about: "Sign and serve quorum-signed signer policies",
+        args: &[],
+        flags: &[],
+        subcommands: &[
+            Command {
+                name: "draft",
+                about: "Write an unsigned policy for wallet.json",
+                args: &[],
+                flags: &[
+                    flag("--sequence", "N", "Sequence number of the draft"),
+                    flag("--internal", "FILE", "Internal wallets of the draft"),
+                    flag(
+                        "--max-external",
+                        "AMOUNT",
+                        "External payment cap of the draft",
+                    ),
+                    flag(
+                        "--tier",
+                        "AMOUNT[:require=FP,..][:signatures=N|all][:delay-hours=H]",
+                        "Approval tier of the draft",
+                    ),
+                    OUT,
+                ],
+                subcommands: &[],
+            },
+            Command {
+                name: "sign",
+                about: "Add a cosigner's signature to a policy",
+                args: &[arg("key.json"), arg("policy.json")],
+                flags: &[],
+                subcommands: &[],
+            },
+            Command {
+                name: "verify",
+                about: "Check a policy's signatures against wallet.json",
+                args: &[arg("policy.json")],
+                flags: &[],
+                subcommands: &[],
+            },
+            Command {
+                name: "serve",
+                about: "Serve and accept policies over HTTP",
+                args: &[],
+                flags: &[flag("--listen", "HOST:PORT", "Address to serve on")],
+                subcommands: &[],
+            },
+        ],
+    },
+    Command {
+        name: "signer_state",
+        about: "Move a signer's wallet files and policy to a new machine",
+        args: &[],
+        flags: &[],
+        subcommands: &[
+            Command {
+                name: "export",
+                about: "Package the signer's state for a new machine",
+                args: &[arg("key.json")],
+                flags: &[OUT],
+                subcommands: &[],
+            },
+            Command {
+                name: "import",
+                about: "Install a packaged signer state",
+                args: &[arg("key.json"), arg("package.json")],
+                flags: &[switch("--force", "Replace existing files")],
+                subcommands: &[],
+            },
+        ],
+    },
+    Command {
+        name: "verify",
+        about: "Check partial signatures in a PSBT",
+        args: &[arg("psbt")],
+        flags: &[flag(
+            "--receipt",
+            "FILE",
+            "Check a signing receipt against the PSBT",
+        )],
+        subcommands: &[],
+    },
+    Command {
+        name: "verify_final",
+        about: "Check a final transaction against its offline bundle",
+        args: &[arg("final_tx.hex"), arg("bundle.json")],
+        flags: &[],
+        subcommands: &[],
+    },
+    Command {
+        name: "verify_payment",
+        about: "Check a payment proof against our descriptor",
+        args: &[arg("payment_proof.json")],
+        flags: &[flag(
+            "--descriptor",
+            "DESC",
+            "Our copy of the descriptor paid",
+        )],
+        subcommands: &[],
+    },
+    Command {
+        name: "combiner",
+        about: "Merge signatures from several PSBTs",
+        args: &[at_least("psbt", 2)],
+        flags: &[],
+        subcommands: &[],
+    },
+    Command {
+        name: "normalize",
+        about: "Canonicalize a PSBT for hashing/storage",
+        args: &[arg("psbt")],
+        flags: &[
+            switch("--strip-unknown", "Drop unknown fields"),
+            switch("--strip-proprietary", "Drop proprietary fields"),
+        ],
+        subcommands: &[],
+    },
+    Command {
+        name: "reminders",
+        about: "Remind cosigners of stalled signing sessions",
+        args: &[at_least("psbt", 1)],
+        flags: &[
+            flag("--window-hours", "N", "Hours between reminders"),
+            flag(
+                "--at-risk-after",
+                "WINDOWS",
+                "Windows after which a session is at risk",
+            ),
+            flag("--notify", "CMD", "Command run for each reminder"),
+            switch("--json", "Print JSON"),
+        ],
+        subcommands: &[],
+    },
+    Command {
+        name: "finalizer",
+        about: "Finalize and extract TX",
+        args: &[arg("psbt")],
+        flags: &[
+            switch("--check", "Report readiness without finalizing"),
+            flag(
+                "--queue",
+                "DIR",
+                "Queue the transaction for the broadcaster",
+            ),
+            flag("--prefer", "FP,..", "Use these cosigners' signatures first"),
+            flag(
+                "--avoid",
+                "FP,..",
+                "Use these cosigners' signatures only if needed",
+            ),
+            switch("--script-order", "Take signatures in script order"),
+            flag("--emit", "hex,psbt,json", "Renderings to write"),
+            NETWORK,
+            TRAINING,
+        ],
+        subcommands: &[],
+    },
+    Command {
+        name: "broadcaster",
+        about: "Broadcast queued TXs in batches",
+        args: &[arg("queue_dir")],
+        flags: &[
+            flag("--window-minutes", "N", "Minutes between releases"),
+            flag("--batch-size", "N", "Release early once N are waiting"),
+            CHAIN,
+            switch("--once", "Release one window and exit"),
+        ],
+        subcommands: &[],
+    },
+    Command {
+        name: "fee_report",
+        about: "Monthly fees and consolidation savings",
+        args: &[],
+        flags: &[
+            flag("--file", "FILE", "Fee history file"),
+            flag(
+                "--reference-feerate",
+                "SAT_PER_VB",
+                "Rate later spends are valued at",
+            ),
+            switch("--json", "Print JSON"),
+        ],
+        subcommands: &[],
+    },
+    Command {
+        name: "export",
+        about: "Export wallet data (auditor view)",
+        args: &[at_least("tx.hex", 0)],
+        flags: &[
+            flag(
+                "--format",
+                "auditor|wallet|core|electrum|sparrow|specter",
+                "Export format",
+            ),
+            flag("--range", "START..END", "Address indexes to export"),
+            flag("--timestamp", "now|UNIX", "Rescan start for Core"),
+            flag("--name", "NAME", "Wallet name"),
+            flag("--blockheight", "N", "Wallet birth height"),
+        ],
+        subcommands: &[],
+    },
+    Command {
+        name: "mock_signer",
+        about: "Simulated hardware signer (HWI commands)",
+        args: &[],
+        flags: &[
+            CHAIN,
+            flag("--device-type", "TYPE", "HWI device type").short('t'),
+            flag("--device-path", "PATH", "HWI device path").short('d'),
+            flag("--fingerprint", "FP", "Device fingerprint").short('f'),
+        ],
+        subcommands: &[
+            leaf("enumerate", "List the simulated device"),
+            Command {
+                name: "getxpub",
+                about: "Print the xpub at a path",
+                args: &[arg("path")],
+                flags: &[],
+                subcommands: &[],
+            },
+            Command {
+                name: "signtx",
+                about: "Sign the inputs that list the device's keys",
+                args: &[arg("psbt")],
+                flags: &[],
+                subcommands: &[],
+            },
+        ],
+    },
+    Command {
+        name: "timestamp",
+        about: "Timestamp a PSBT hash with OpenTimestamps",
+        args: &[arg("psbt")],
+        flags: &[],
+        subcommands: &[],
+    },
+    Command {
+        name: "schema",
+        about: "Print JSON Schema of an exported artifact",
+        args: &[Positional {
+            name: "artifact",
+            count: Count::One,
+            choices: ARTIFACTS,
+        }],
+        flags: &[],
+        subcommands: &[],
+    },
+    Command {
+        name: "import_core",
+        about: "Import wallet from a Core descriptor wallet",
+        args: &[arg("core_wallet_name")],
+        flags: &[CHAIN],
+        subcommands: &[],
+    },
+    Command {
+        name: "setup_file",
+        about: "Export/import wallet setup files (BlueWallet, Caravan...)",
+        args: &[],
+        flags: &[],
+        subcommands: &[
+            Command {
+                name: "export",
+                about: "Write a setup file for the wallet",
+                args: &[],
+                flags: &[
+                    flag(
+                        "--format",
+                        "bluewallet|coldcard|caravan",
+                        "Setup file format",
+                    ),
+                    flag("--name", "NAME", "Wallet name"),
+                    OUT,
+                ],
+                subcommands: &[],
+            },
+            Command {
+                name: "import",
+                about: "Read a setup file and show its wallet",
+                args: &[arg("file")],
+                flags: &[],
+                subcommands: &[],
+            },
+        ],
+    },
+    Command {
+        name: "version",
+        about: "Print version; --attest for build details",
+        args: &[],
+        flags: &[switch("--attest", "Print a build attestation")],
+        subcommands: &[],
+    },
+    Command {
+        name: "donation",
+        about: "Serve donation addresses (--features donation)",
+        args: &[arg("core_wallet_name")],
+        flags: &[
+            flag("--listen", "HOST:PORT", "Address to serve on"),
+            CHAIN,
+            flag("--label", "TEXT", "Label shown on the page"),
+        ],
+        subcommands: &[],
+    },
+];
+
+/// The words a `a|b|c` placeholder lists, if it is such a list.
+fn choices(placeholder: &str) -> Option<Vec<&str>> {
+    let words: Vec<&str> = placeholder.split('|').collect();
+    let is_word = |w: &&str| {
+        !w.is_empty()
+            && w.bytes()
+                .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+    };
+    (words.len() > 1 && words.iter().all(is_word)).then_some(words)
+}
+
+/// Takes any value, and offers `words` to completions and the man page.
+/// The tool parses the value itself, so aliases such as `p2wsh` or
+/// `bitcoin` keep working without being listed.
+#[derive(Clone)]
+struct Suggest(Vec<&'static str>);
+
+impl TypedValueParser for Suggest {
+    type Value = String;
+
+    fn parse_ref(
+        &self,
+        cmd: &clap::Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<String, clap::Error> {
+        StringValueParser::new().parse_ref(cmd, arg, value)
+    }
+
+    fn possible_values(&self) -> Option<Box<dyn Iterator<Item = PossibleValue> + '_>> {
+        Some(Box::new(
+            self.0.iter().map(|word| PossibleValue::new(*word)),
+        ))
+    }
+}
+
+fn flag_arg(flag: &Flag, global: bool) -> Arg {
+    let id = flag.name.trim_start_matches("--");
+    let mut arg = Arg::new(id).long(id).help(flag.help).global(global);
+    if let Some(short) = flag.short {
+        arg = arg.short(short);
+    }
+    let Some(value) = flag.value else {
+        // Repeating a switch is harmless.
+        return arg.action(ArgAction::SetTrue).overrides_with(id);
+    };
+    // Value flags may repeat; single-valued ones take the last.
+    arg = arg.action(ArgAction::Append).value_name(value);
+    arg = match choices(value) {
+        Some(words) => arg.value_parser(Suggest(words)),
+        None => arg.value_hint(hint(value)),
+    };
+    if flag.value_optional {
+        // A bare `--armor` means the first of the listed words.
+        let default = value.split('|').next().unwrap_or(value);
+        arg = arg
+            .num_args(0..=1)
+            .require_equals(true)
+            .default_missing_value(default);
+    }
+    arg
+}
+
+/// Whether completions should offer files for a value named `name`.
+fn hint(name: &str) -> clap::ValueHint {
+    let file = [
+        "FILE", "DIR", "PATH", ".json", ".hex", "psbt", "file", "_dir",
+    ];
+    if file.iter().any(|word| name.contains(word)) {
+        clap::ValueHint::AnyPath
+    } else {
+        clap::ValueHint::Other
+    }
+}
+
+fn positional_arg(positional: &Positional) -> Arg {
+    let arg = Arg::new(positional.name)
+        .value_name(positional.name)
+        .value_hint(hint(positional.name));
+    let arg = match positional.count {
+        Count::One => arg.required(true),
+        Count::Optional => arg.required(false),
+        Count::AtLeast(min) => arg
+            .action(ArgAction::Append)
+            .num_args(min.max(1)..)
+            .required(min > 0),
+    };
+    if positional.choices.is_empty() {
+        arg
+    } else {
+        arg.value_parser(Suggest(positional.choices.to_vec()))
+    }
+}
+
+impl Command {
+    /// The clap command the tool parses its arguments with.
+    pub fn clap(&self) -> clap::Command {
+        let mut cmd = clap::Command::new(self.name)
+            .about(self.about)
+            .disable_help_subcommand(true)
+            .subcommand_required(!self.subcommands.is_empty());
+        let global = !self.subcommands.is_empty();
+        for flag in self.flags {
+            cmd = cmd.arg(flag_arg(flag, global));
+        }
+        for positional in self.args {
+            cmd = cmd.arg(positional_arg(positional));
+        }
+        for sub in self.subcommands {
+            cmd = cmd.subcommand(sub.clap());
+        }
+        cmd
+    }
+}
+
+/// The table entry of tool `name`.
+pub fn command(name: &str) -> Result<&'static Command, String> {
+    COMMANDS
+        .iter()
+        .find(|command| command.name == name)
+        .ok_or_else(|| format!("unknown command: {}", name))
+}
+
+/// A tool's parsed arguments, looked up by the names in its [`Command`]:
+/// `--flag` for flags, the bare name for positional arguments.
+pub struct Args {
+    matches: ArgMatches,
+}
+
+impl Args {
+    /// Parses this process's arguments as tool `name`'s. On a usage error,
+    /// or for `--help`, prints the message and exits.
+    pub fn parse(name: &str) -> Self {
+        let clap = match command(name) {
+            Ok(command) => command.clap(),
+            Err(e) => clap::Error::raw(clap::error::ErrorKind::InvalidSubcommand, e).exit(),
+        };
+        Self {
+            matches: clap.get_matches(),
+        }
+    }
+
+    /// Parses `argv`, program name first, as tool `name`'s.
+    pub fn try_parse_from<I, T>(name: &str, argv: I) -> Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<std::ffi::OsString> + Clone,
+    {
+        let command = command(name)
+            .map_err(|e| clap::Error::raw(clap::error::ErrorKind::InvalidSubcommand, e))?;
+        Ok(Self {
+            matches: command.clap().try_get_matches_from(argv)?,
+        })
+    }
+
+    /// The subcommand given, with its arguments.
+    pub fn subcommand(&self) -> Option<(&str, Args)> {
+        self.matches.subcommand().map(|(name, matches)| {
+            (
+                name,
+                Args {
+                    matches: matches.clone(),
+                },
+            )
+        })
+    }
+
+    /// Whether switch `name` was given.
+    pub fn flag(&self, name: &str) -> bool {
+        self.matches.get_flag(id(name))
+    }
+
+    /// Every value given for `name`, in order.
+    pub fn values(&self, name: &str) -> Vec<&str> {
+        self.matches
+            .get_many::<String>(id(name))
+            .map(|values| values.map(String::as_str).collect())
+            .unwrap_or_default()
+    }
+
+    /// The last value given for `name`, if any.
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.values(name).pop()
+    }
+
+    /// The value of positional argument `name`, which the parser requires.
+    pub fn required(&self, name: &str) -> Result<&str, String> {
+        self.value(name)
+            .ok_or_else(|| format!("missing <{}>", name))
+    }
+
+    /// Parses the last value given for `name`.
+    pub fn parse_value<T>(&self, name: &str) -> Result<Option<T>, String>
+    where
+        T: FromStr,
+        T::Err: std::fmt::Display,
+    {
+        self.value(name)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|e| format!("{} {}: {}", name, value, e))
+            })
+            .transpose()
+    }
+
+    /// Of the flags in `names`, the one given last on the command line,
+    /// with its value.
+    pub fn last_of<'a>(&self, names: &[&'a str]) -> Option<(&'a str, &str)> {
+        names
+            .iter()
+            .filter_map(|name| {
+                let index = self.matches.indices_of(id(name))?.next_back()?;
+                Some((index, *name, self.value(name)?))
+            })
+            .max_by_key(|(index, _, _)| *index)
+            .map(|(_, name, value)| (name, value))
+    }
+
+    /// The values of the flags in `names`, in command-line order.
+    pub fn values_of<'a>(&self, names: &[&'a str]) -> Vec<(&'a str, &str)> {
+        let mut values: Vec<(usize, &'a str, &str)> = Vec::new();
+        for name in names {
+            let indices = self.matches.indices_of(id(name)).into_iter().flatten();
+            for (index, value) in indices.zip(self.values(name)) {
+                values.push((index, *name, value));
+            }
+        }
+        values.sort_by_key(|(index, _, _)| *index);
+        values
+            .into_iter()
+            .map(|(_, name, value)| (name, value))
+            .collect()
+    }
+}
+
+fn id(name: &str) -> &str {
+    name.trim_start_matches("--")
+}
+
+/// Writes a completion script for every tool into `dir`, one file each,
+/// named as `shell` expects. Returns the files written.
+pub fn write_completions(
+    shell: Shell,
+    dir: &Path,
+) -> Result<Vec<std::path::PathBuf>, std::io::Error> {
+    COMMANDS
+        .iter()
+        .map(|command| clap_complete::generate_to(shell, &mut command.clap(), command.name, dir))
+        .collect()
+}
+
+/// Completion scripts for every tool, one after the other, for shells
+/// that can source them together.
+pub fn completions(shell: Shell, out: &mut dyn Write) {
+    for command in COMMANDS {
+        clap_complete::generate(shell, &mut command.clap(), command.name, out);
+    }
+}
+
+/// Writes a man page, section 1, for every tool and subcommand into `dir`.
+pub fn write_man_pages(dir: &Path) -> Result<(), std::io::Error> {
+    COMMANDS
+        .iter()
+        .try_for_each(|command| clap_mangen::generate_to(command.clap(), dir))
+}
+
+/// Man page of tool `name`.
+pub fn man_page(name: &str, out: &mut dyn Write) -> Result<(), CoordinatorError> {
+    let command = command(name).map_err(CoordinatorError::Invalid)?;
+    Ok(clap_mangen::Man::new(command.clap()).render(out)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    /// Every command in the tree under `command`, with its path of names.
+    fn walk<'a>(
+        path: Vec<&'a str>,
+        command: &'a Command,
+        out: &mut Vec<(Vec<&'a str>, &'a Command)>,
+    ) {
+        out.push((path.clone(), command));
+        for sub in command.subcommands {
+            let mut path = path.clone();
+            path.push(sub.name);
+            walk(path, sub, out);
+        }
+    }
+
+    /// Arguments that satisfy `command`'s positional arguments.
+    fn positionals(command: &Command) -> Vec<String> {
+        let mut argv = Vec::new();
+        for positional in command.args {
+            let count = match positional.count {
+                Count::One => 1,
+                Count::Optional => 0,
+                Count::AtLeast(min) => min,
+            };
+            let value = positional.choices.first().copied().unwrap_or("x");
+            argv.extend(std::iter::repeat_n(value.to_string(), count));
+        }
+        argv
+    }
+
+    #[test]
+    fn commands_build() {
+        for command in COMMANDS {
+            command.clap().debug_assert();
+        }
+    }
+
+    #[test]
+    fn every_flag_parses() {
+        for tool in COMMANDS {
+            let mut commands = Vec::new();
+            walk(vec![tool.name], tool, &mut commands);
+            for (path, command) in commands.iter().filter(|(_, c)| c.subcommands.is_empty()) {
+                let mut flags: Vec<&Flag> = command.flags.iter().collect();
+                // Flags of the tool itself apply to its subcommands too.
+                if path.len() > 1 {
+                    flags.extend(tool.flags);
+                }
+                let mut base: Vec<String> = path.iter().map(|name| name.to_string()).collect();
+                base.extend(positionals(command));
+                let parsed = Args::try_parse_from(tool.name, &base);
+                assert!(parsed.is_ok(), "{:?}: {:?}", base, parsed.err());
+
+                for flag in flags {
+                    let value = flag.value.map(|value| match choices(value) {
+                        Some(words) => words[0].to_string(),
+                        None => String::from("value"),
+                    });
+                    let mut argv = base.clone();
+                    match (&value, flag.value_optional) {
+                        (Some(value), false) => {
+                            argv.push(flag.name.to_string());
+                            argv.push(value.clone());
+                        }
+                        (Some(value), true) => argv.push(format!("{}={}", flag.name, value)),
+                        (None, _) => argv.push(flag.name.to_string()),
+                    }
+                    let args = Args::try_parse_from(tool.name, &argv)
+                        .unwrap_or_else(|e| panic!("{:?}: {}", argv, e));
+                    let mut args = args;
+                    for _ in 1..path.len() {
+                        args = args.subcommand().unwrap().1;
+                    }
+                    match value {
+                        Some(value) => assert_eq!(args.value(flag.name), Some(value.as_str())),
+                        None => assert!(args.flag(flag.name), "{:?}", argv),
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn repeated_flags_keep_their_order() {
+        let args = Args::try_parse_from(
+            "coordinator",
+            [
+                "coordinator",
+                "--pay",
+                "a=1",
+                "--to-aux",
+                "b:2",
+                "--pay",
+                "c=3",
+                "--expires-at-height",
+                "900",
+                "--expires-in",
+                "60",
+                "--armor",
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            args.values_of(&["--pay", "--to-aux"]),
+            [("--pay", "a=1"), ("--to-aux", "b:2"), ("--pay", "c=3")]
+        );
+        assert_eq!(
+            args.last_of(&["--expires-in", "--expires-at-height"]),
+            Some(("--expires-in", "60"))
+        );
+        assert_eq!(args.value("--armor"), Some("base64"));
+        let args = Args::try_parse_from("coordinator", ["coordinator", "--armor=bech32"]).unwrap();
+        assert_eq!(args.value("--armor"), Some("bech32"));
+    }
+
+    #[test]
+    fn positional_arguments_are_checked() {
+        assert!(Args::try_parse_from("signer", ["signer", "key.json"]).is_err());
+        assert!(Args::try_parse_from("combiner", ["combiner", "a.psbt"]).is_err());
+        assert!(Args::try_parse_from("wallet", ["wallet"]).is_err());
+        let args =
+            Args::try_parse_from("signer", ["signer", "key.json", "--training", "a.psbt"]).unwrap();
+        assert_eq!(args.required("key.json"), Ok("key.json"));
+        assert_eq!(args.required("psbt"), Ok("a.psbt"));
+        assert!(args.flag("--training"));
+    }
+
+    /// Flag names in a tool's source: string literals such as `"--network"`.
+    fn flags_in_source(source: &str) -> BTreeSet<String> {
+        let mut flags = BTreeSet::new();
+        for (start, _) in source.match_indices("\"--") {
+            let rest = &source[start + 1..];
+            let len = rest
+                .bytes()
+                .skip(2)
+                .take_while(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || *b == b'-')
+                .count();
+            if len > 0 && rest.as_bytes().get(2 + len) == Some(&b'"') {
+                flags.insert(rest[..2 + len].to_string());
+            }
+        }
+        flags
+    }
+
+    #[test]
+    fn tools_read_exactly_their_flags() {
+        for tool in COMMANDS {
+            let path = format!("{}/src/bin/{}.rs", env!("CARGO_MANIFEST_DIR"), tool.name);
+            let source = std::fs::read_to_string(&path).unwrap();
+            let mut commands = Vec::new();
+            walk(vec![tool.name], tool, &mut commands);
+            // The mock signer takes HWI's device flags and ignores them.
+            let ignored = match tool.name {
+                "mock_signer" => &["--device-type", "--device-path", "--fingerprint"][..],
+                _ => &[],
+            };
+            let table: BTreeSet<String> = commands
+                .iter()
+                .flat_map(|(_, command)| command.flags)
+                .map(|flag| flag.name)
+                .filter(|name| !ignored.contains(name))
+                .map(String::from)
+                .collect();
+            assert_eq!(flags_in_source(&source), table, "{}", path);
+        }
+    }
+
+    #[test]
+    fn schema_lists_every_artifact() {
+        let schema = command("schema").unwrap();
+        assert_eq!(schema.args[0].choices, ARTIFACTS);
+        for artifact in ARTIFACTS {
+            assert!(Args::try_parse_from("schema", ["schema", artifact]).is_ok());
+        }
+    }
+}
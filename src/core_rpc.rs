@@ -0,0 +1,413 @@
+//! Bitcoin Core as a [`ChainSource`].
+//!
+//! Instead of UTXOs entered by hand, the coordinator
+//! can ask a node for the wallet's unspent outputs. Calls go through
+//! `bitcoin-cli`, like the other tools that talk to Core, pointed at the
+//! node and credentials in a [`CoreRpcConfig`]. With a Core wallet that
+//! watches the multisig (see `export --format core`), `listunspent` is
+//! used and unconfirmed outputs count too. Without one, `scantxoutset`
+//! searches the UTXO set for the wallet's descriptors: no Core wallet is
+//! needed, but only confirmed outputs are found and a scan takes a while.
+
+use crate::chain_source::{ChainSource, btc_per_kvb_to_sat_per_vb, output_of};
+use crate::utxo_store::ManualUtxo;
+use crate::{CoordinatorError, MultisigWallet, check_same_network};
+use bitcoin::{
+    Address, Amount, BlockHash, Denomination, Network, OutPoint, ScriptBuf, TxOut, Txid,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+/// How to reach the node. Absent fields fall back to `bitcoin-cli`'s own
+/// defaults for the wallet's chain, including its `bitcoin.conf` and
+/// cookie file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CoreRpcConfig {
+    /// `http://host:port`, e.g. `http://127.0.0.1:18443`. Core's RPC
+    /// server has no TLS, so `https` is refused.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Cookie file written by the node, e.g. `~/.bitcoin/regtest/.cookie`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cookie_file: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// Passed on stdin, so it never shows up in the process list.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// Core wallet watching the multisig, for `listunspent`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wallet: Option<String>,
+}
+
+impl CoreRpcConfig {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, CoordinatorError> {
+        let path = path.as_ref();
+        serde_json::from_str(&std::fs::read_to_string(path)?)
+            .map_err(|e| CoordinatorError::Invalid(format!("{}: {}", path.display(), e)))
+    }
+
+    /// The node, for `network`'s chain. Unlike
+    /// [`ChainConfig::connect`](crate::chain_source::ChainConfig::connect),
+    /// this does not ask the node which chain it serves.
+    pub fn connect(self, network: Network) -> CoreRpc {
+        CoreRpc {
+            config: self,
+            network,
+        }
+    }
+}
+
+/// A node on a known chain.
+#[derive(Debug, Clone)]
+pub struct CoreRpc {
+    config: CoreRpcConfig,
+    network: Network,
+}
+
+impl CoreRpc {
+    /// `bitcoin-cli` options for this node.
+    fn cli_args(&self) -> Result<Vec<String>, CoordinatorError> {
+        let mut args = vec![format!("-chain={}", self.network.to_core_arg())];
+        if let Some(url) = &self.config.url {
+            let address = url
+                .strip_prefix("http://")
+                .ok_or_else(|| {
+                    CoordinatorError::Invalid(format!(
+                        "RPC URL {} must start with http:// (Core's RPC has no TLS)",
+                        url
+                    ))
+                })?
+                .trim_end_matches('/');
+            match address.rsplit_once(':') {
+                Some((host, port)) => {
+                    let port: u16 = port.parse().map_err(|_| {
+                        CoordinatorError::Invalid(format!("RPC URL {}: invalid port", url))
+                    })?;
+                    args.push(format!("-rpcconnect={}", host));
+                    args.push(format!("-rpcport={}", port));
+                }
+                None => args.push(format!("-rpcconnect={}", address)),
+            }
+        }
+        match (
+            &self.config.cookie_file,
+            &self.config.user,
+            &self.config.password,
+        ) {
+            (Some(_), Some(_), _) => {
+                return Err(CoordinatorError::invalid(
+                    "give either cookie_file or user and password, not both",
+                ));
+            }
+            (Some(cookie), None, _) => args.push(format!("-rpccookiefile={}", cookie)),
+            (None, Some(user), Some(_)) => {
+                args.push(format!("-rpcuser={}", user));
+                args.push(String::from("-stdinrpcpass"));
+            }
+            (None, Some(_), None) => {
+                return Err(CoordinatorError::invalid(
+                    "RPC user given without a password",
+                ));
+            }
+            (None, None, _) => {}
+        }
+        Ok(args)
+    }
+
+    /// Runs `method` and returns its result. Results that are not JSON,
+    /// such as `getblockhash`'s bare hash, come back as a string.
+    fn call(
+        &self,
+        rpc_wallet: Option<&str>,
+        method: &str,
+        params: &[String],
+    ) -> Result<Value, CoordinatorError> {
+        let mut command = Command::new("bitcoin-cli");
+        command.args(self.cli_args()?);
+        if let Some(name) = rpc_wallet {
+            command.arg(format!("-rpcwallet={}", name));
+        }
+        let mut child = command
+            .arg(method)
+            .args(params)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        if let (Some(password), Some(mut stdin)) = (&self.config.password, child.stdin.take()) {
+            writeln!(stdin, "{}", password)?;
+        }
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(CoordinatorError::Invalid(format!(
+                "{} failed: {}",
+                method,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        Ok(serde_json::from_str(&stdout).unwrap_or_else(|_| Value::String(stdout.trim().into())))
+    }
+
+    /// A confirmed transaction by its block, which needs no `-txindex`.
+    fn raw_transaction_in_block(&self, txid: &Txid, height: &Value) -> Option<String> {
+        let height = height.as_u64()?;
+        let block_hash = self
+            .call(None, "getblockhash", &[height.to_string()])
+            .ok()?;
+        let raw = self
+            .call(
+                None,
+                "getrawtransaction",
+                &[
+                    txid.to_string(),
+                    String::from("false"),
+                    block_hash.as_str()?.to_string(),
+                ],
+            )
+            .ok()?;
+        raw.as_str().map(String::from)
+    }
+}
+
+/// Funding on test chains, for `dev fund`.
+impl CoreRpc {
+    /// Mines `blocks` blocks with their coinbase paying `address`. Only
+    /// regtest mines on demand.
+    pub fn generate_to_address(
+        &self,
+        blocks: u32,
+        address: &Address,
+    ) -> Result<Vec<BlockHash>, CoordinatorError> {
+        let result = self.call(
+            None,
+            "generatetoaddress",
+            &[blocks.to_string(), address.to_string()],
+        )?;
+        result
+            .as_array()
+            .map(|hashes| {
+                hashes
+                    .iter()
+                    .filter_map(|hash| BlockHash::from_str(hash.as_str()?).ok())
+                    .collect::<Vec<_>>()
+            })
+            .filter(|hashes| hashes.len() == blocks as usize)
+            .ok_or_else(|| CoordinatorError::invalid("unexpected block hashes from Core"))
+    }
+
+    /// The coinbase transaction of `block`, hex.
+    pub fn coinbase_transaction(&self, block: &BlockHash) -> Result<String, CoordinatorError> {
+        let result = self.call(None, "getblock", &[block.to_string(), String::from("2")])?;
+        result["tx"][0]["hex"]
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| CoordinatorError::Invalid(format!("block {} has no coinbase", block)))
+    }
+
+    /// Sends `amount` to `address` from the Core wallet `wallet`, which
+    /// must hold the coins.
+    pub fn send_to_address(
+        &self,
+        wallet: &str,
+        address: &Address,
+        amount: Amount,
+    ) -> Result<Txid, CoordinatorError> {
+        let result = self.call(
+            Some(wallet),
+            "sendtoaddress",
+            &[
+                address.to_string(),
+                amount.display_in(Denomination::Bitcoin).to_string(),
+            ],
+        )?;
+        result
+            .as_str()
+            .and_then(|txid| Txid::from_str(txid).ok())
+            .ok_or_else(|| CoordinatorError::invalid("unexpected txid from Core"))
+    }
+
+    /// Confirmations and hex of a transaction of the Core wallet `wallet`.
+    pub fn wallet_transaction(
+        &self,
+        wallet: &str,
+        txid: &Txid,
+    ) -> Result<(u64, String), CoordinatorError> {
+        let result = self.call(Some(wallet), "gettransaction", &[txid.to_string()])?;
+        // Negative confirmations mean a conflicting transaction confirmed.
+        let confirmations = result["confirmations"].as_i64().unwrap_or(0);
+        if confirmations < 0 {
+            return Err(CoordinatorError::Invalid(format!(
+                "{} conflicts with a confirmed transaction",
+                txid
+            )));
+        }
+        let hex = result["hex"]
+            .as_str()
+            .ok_or_else(|| CoordinatorError::invalid("gettransaction returned no hex"))?;
+        Ok((confirmations as u64, hex.to_string()))
+    }
+}
+
+impl ChainSource for CoreRpc {
+    fn name(&self) -> &'static str {
+        "Bitcoin Core"
+    }
+
+    fn genesis_hash(&self) -> Result<BlockHash, CoordinatorError> {
+        let hash = self.call(None, "getblockhash", &[String::from("0")])?;
+        hash.as_str()
+            .and_then(|hash| BlockHash::from_str(hash).ok())
+            .ok_or_else(|| CoordinatorError::invalid("unexpected block hash from Core"))
+    }
+
+    /// Funding transactions come from the node where it can provide them.
+    fn find_utxos(
+        &self,
+        wallet: &MultisigWallet,
+        gap_limit: u32,
+    ) -> Result<Vec<ManualUtxo>, CoordinatorError> {
+        check_same_network(wallet.network, self.network)?;
+        let (unspents, scanned) = match &self.config.wallet {
+            Some(name) => {
+                let result = self.call(
+                    Some(name),
+                    "listunspent",
+                    &[String::from("0"), String::from("9999999")],
+                )?;
+                (result, false)
+            }
+            None => {
+                let descriptors = [false, true]
+                    .iter()
+                    .map(|is_change| {
+                        Ok(json!({
+                            "desc": wallet.chain_descriptor(*is_change)?.to_string(),
+                            "range": gap_limit.saturating_sub(1),
+                        }))
+                    })
+                    .collect::<Result<Vec<_>, CoordinatorError>>()?;
+                let result = self.call(
+                    None,
+                    "scantxoutset",
+                    &[String::from("start"), Value::from(descriptors).to_string()],
+                )?;
+                (result["unspents"].clone(), true)
+            }
+        };
+        let unspents = unspents
+            .as_array()
+            .ok_or_else(|| CoordinatorError::invalid("unexpected UTXO listing from Core"))?;
+
+        let mut utxos = Vec::new();
+        for unspent in unspents {
+            let script_pubkey = unspent["scriptPubKey"]
+                .as_str()
+                .and_then(|hex| ScriptBuf::from_hex(hex).ok())
+                .ok_or_else(|| CoordinatorError::invalid("UTXO without a scriptPubKey"))?;
+            // listunspent covers the whole Core wallet, which may watch
+            // more than this multisig.
+            let Some((index, is_change)) = wallet.is_mine(&script_pubkey, gap_limit)? else {
+                continue;
+            };
+            let txid = unspent["txid"]
+                .as_str()
+                .and_then(|txid| Txid::from_str(txid).ok())
+                .ok_or_else(|| CoordinatorError::invalid("UTXO without a txid"))?;
+            let vout = unspent["vout"]
+                .as_u64()
+                .and_then(|vout| u32::try_from(vout).ok())
+                .ok_or_else(|| CoordinatorError::invalid("UTXO without an output number"))?;
+            let value = unspent["amount"]
+                .as_f64()
+                .and_then(|btc| Amount::from_btc(btc).ok())
+                .ok_or_else(|| CoordinatorError::invalid("UTXO without an amount"))?;
+            let outpoint = OutPoint { txid, vout };
+            // Pruned nodes may no longer have the block; the input then
+            // only gets its witness UTXO.
+            let funding_tx = if scanned {
+                self.raw_transaction_in_block(&txid, &unspent["height"])
+            } else {
+                self.call(
+                    self.config.wallet.as_deref(),
+                    "gettransaction",
+                    &[txid.to_string(), String::from("true")],
+                )
+                .ok()
+                .and_then(|tx| tx["hex"].as_str().map(String::from))
+            };
+            // It becomes the input's non_witness_utxo, so it must be the
+            // transaction listed and hold the listed output, as
+            // `listed_utxo` checks for the other backends.
+            if let Some(raw_tx) = &funding_tx {
+                let output = output_of(raw_tx, &outpoint)?;
+                if output.script_pubkey != script_pubkey || output.value != value {
+                    return Err(CoordinatorError::Invalid(format!(
+                        "transaction {} does not hold the output Core listed for {}",
+                        txid, outpoint
+                    )));
+                }
+            }
+            utxos.push(ManualUtxo {
+                outpoint,
+                value,
+                script_pubkey,
+                index,
+                is_change,
+                funding_tx: funding_tx.unwrap_or_default(),
+                network: Some(wallet.network),
+            });
+        }
+        Ok(utxos)
+    }
+
+    /// Mempool included, like the node's wallet.
+    fn prevout(&self, outpoint: &OutPoint) -> Result<TxOut, CoordinatorError> {
+        let result = self.call(
+            None,
+            "gettxout",
+            &[
+                outpoint.txid.to_string(),
+                outpoint.vout.to_string(),
+                String::from("true"),
+            ],
+        )?;
+        // An empty result means spent or unknown.
+        if !result.is_object() {
+            return Err(CoordinatorError::Invalid(format!(
+                "{} is spent or unknown to Bitcoin Core",
+                outpoint
+            )));
+        }
+        let value = result["value"]
+            .as_f64()
+            .and_then(|btc| Amount::from_btc(btc).ok())
+            .ok_or_else(|| CoordinatorError::invalid("gettxout returned no value"))?;
+        let script_pubkey = result["scriptPubKey"]["hex"]
+            .as_str()
+            .and_then(|hex| ScriptBuf::from_hex(hex).ok())
+            .ok_or_else(|| CoordinatorError::invalid("gettxout returned no scriptPubKey"))?;
+        Ok(TxOut {
+            value,
+            script_pubkey,
+        })
+    }
+
+    fn fee_rate(&self, blocks: u16) -> Result<f64, CoordinatorError> {
+        let result = self.call(None, "estimatesmartfee", &[blocks.to_string()])?;
+        result["feerate"]
+            .as_f64()
+            .map(btc_per_kvb_to_sat_per_vb)
+            .ok_or_else(|| {
+                CoordinatorError::Invalid(format!(
+                    "Bitcoin Core has no fee estimate for {} blocks yet",
+                    blocks
+                ))
+            })
+    }
+}
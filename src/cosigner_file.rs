@@ -0,0 +1,211 @@
+//! Text multisig setup files: Coldcard's registration format, and the
+//! BlueWallet Vaults and Nunchuk variant of it.
+//!
+//! ```text
+//! # BlueWallet Multisig setup file
+//! Name: Treasury
+//! Policy: 3 of 5
+//! Derivation: m/48'/1'/0'/2'
+//! Format: P2WSH
+//!
+//! F00DBABE: Vpub5...
+//! ```
+//!
+//! BlueWallet/Nunchuk keys are written with SLIP 132 version bytes (`Zpub`
+//! on mainnet, `Vpub` elsewhere); Coldcard's as plain `xpub`/`tpub`. A
+//! `Derivation:` line applies to every key after it, so cosigners on
+//! different paths each get their own line.
+
+use crate::{
+    CoordinatorError, MultisigWallet, ScriptType, check_threshold, format_path, key_expression,
+    slip132,
+};
+use bitcoin::Network;
+use bitcoin::bip32::{DerivationPath, Fingerprint};
+use std::str::FromStr;
+
+/// Writes the wallet as a BlueWallet/Nunchuk multisig setup file.
+pub fn export_setup(wallet: &MultisigWallet, name: &str) -> Result<String, CoordinatorError> {
+    if !wallet.is_sorted() {
+        return Err(CoordinatorError::descriptor(
+            "setup files only describe sortedmulti P2WSH wallets",
+        ));
+    }
+    let mut out = String::from("# BlueWallet Multisig setup file\n");
+    out.push_str("# this file contains only public keys and is safe to\n");
+    out.push_str("# distribute among cosigners\n#\n");
+    out.push_str(&format!("Name: {}\n", name));
+    out.push_str(&format!(
+        "Policy: {} of {}\n",
+        wallet.threshold,
+        wallet.xpub_origins.len()
+    ));
+
+    let mut current_path = None;
+    for (i, origin) in wallet.xpub_origins.iter().enumerate() {
+        if current_path != Some(&origin.derivation_path) {
+            out.push_str(&format!(
+                "Derivation: {}\n",
+                format_path(&origin.derivation_path)
+            ));
+            current_path = Some(&origin.derivation_path);
+        }
+        if i == 0 {
+            out.push_str("Format: P2WSH\n\n");
+        }
+        out.push_str(&format!(
+            "{}: {}\n",
+            origin.fingerprint.to_string().to_uppercase(),
+            slip132::to_multisig_p2wsh(&origin.xpub)
+        ));
+    }
+    Ok(out)
+}
+
+/// Longest wallet name Coldcard accepts.
+pub const COLDCARD_MAX_NAME_LEN: usize = 20;
+
+/// Writes the wallet as a Coldcard multisig registration file, to import
+/// on the device (Settings > Multisig Wallets > Import from File) so it
+/// can show addresses and check PSBTs against the registered wallet.
+pub fn export_coldcard(wallet: &MultisigWallet, name: &str) -> Result<String, CoordinatorError> {
+    if name.is_empty() || name.len() > COLDCARD_MAX_NAME_LEN || !name.is_ascii() {
+        return Err(CoordinatorError::Invalid(format!(
+            "Coldcard wallet names are 1 to {} ASCII characters",
+            COLDCARD_MAX_NAME_LEN
+        )));
+    }
+    if wallet.is_policy() {
+        return Err(CoordinatorError::descriptor(
+            "Coldcard setup files only describe plain multisig wallets",
+        ));
+    }
+    if !wallet.is_sorted() && wallet.script_type != ScriptType::Tr {
+        return Err(CoordinatorError::descriptor(
+            "Coldcard setup files only describe sortedmulti wallets",
+        ));
+    }
+    let format = match wallet.script_type {
+        ScriptType::Wsh => "P2WSH",
+        ScriptType::ShWsh => "P2SH-P2WSH",
+        ScriptType::Tr => {
+            return Err(CoordinatorError::descriptor(
+                "Coldcard does not support taproot multisig",
+            ));
+        }
+    };
+
+    let mut out = String::from("# Coldcard Multisig setup file (created by psbt-coordinator)\n#\n");
+    out.push_str(&format!("Name: {}\n", name));
+    out.push_str(&format!(
+        "Policy: {} of {}\n",
+        wallet.threshold,
+        wallet.xpub_origins.len()
+    ));
+    out.push_str(&format!("Format: {}\n", format));
+
+    let mut current_path = None;
+    for origin in &wallet.xpub_origins {
+        if current_path != Some(&origin.derivation_path) {
+            out.push_str(&format!(
+                "\nDerivation: {}\n",
+                format_path(&origin.derivation_path)
+            ));
+            current_path = Some(&origin.derivation_path);
+        }
+        out.push_str(&format!(
+            "{}: {}\n",
+            origin.fingerprint.to_string().to_uppercase(),
+            origin.xpub
+        ));
+    }
+    Ok(out)
+}
+
+/// Reads a Coldcard, BlueWallet or Nunchuk setup file into a wallet.
+/// Besides `Derivation:` lines, Coldcard's per-key `# derivation:`
+/// comments set the path of the keys after them.
+///
+/// Mobile wallets derive receive addresses under `/0/*` and change under
+/// `/1/*`, as this tool does, so the imported wallet's addresses are the
+/// ones the mobile wallet shows.
+pub fn import_setup(text: &str, network: Network) -> Result<MultisigWallet, CoordinatorError> {
+    let mut policy = None;
+    let mut derivation = None;
+    let mut script_type = ScriptType::Wsh;
+    let mut keys = Vec::new();
+
+    for line in text.lines().map(str::trim) {
+        if let Some(comment) = line.strip_prefix('#') {
+            let derivation_comment = comment
+                .split_once(':')
+                .filter(|(field, _)| field.trim().eq_ignore_ascii_case("derivation"));
+            if let Some((_, value)) = derivation_comment {
+                derivation = Some(DerivationPath::from_str(value.trim())?);
+            }
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+        let (field, value) = line
+            .split_once(':')
+            .ok_or_else(|| CoordinatorError::Descriptor(format!("malformed line: {}", line)))?;
+        let value = value.trim();
+        match field.trim() {
+            "Name" => {}
+            "Policy" => {
+                let (m, n) = value.split_once(" of ").ok_or_else(|| {
+                    CoordinatorError::Descriptor(format!("malformed policy: {}", value))
+                })?;
+                let count = |s: &str| s.trim().parse::<usize>().map_err(CoordinatorError::invalid);
+                policy = Some((count(m)?, count(n)?));
+            }
+            "Derivation" => derivation = Some(DerivationPath::from_str(value)?),
+            "Format" => {
+                script_type = match value.to_ascii_uppercase().as_str() {
+                    "P2WSH" => ScriptType::Wsh,
+                    // Older Coldcard firmware writes the wrapped form backwards.
+                    "P2SH-P2WSH" | "P2WSH-P2SH" => ScriptType::ShWsh,
+                    _ => {
+                        return Err(CoordinatorError::Descriptor(format!(
+                            "unsupported format {}: only P2WSH and P2SH-P2WSH",
+                            value
+                        )));
+                    }
+                };
+            }
+            fingerprint => {
+                let fingerprint =
+                    Fingerprint::from_str(fingerprint).map_err(CoordinatorError::key)?;
+                let path = derivation.clone().ok_or_else(|| {
+                    CoordinatorError::descriptor("key listed before any Derivation line")
+                })?;
+                keys.push((fingerprint, path, slip132::parse_xpub(value)?));
+            }
+        }
+    }
+
+    let (threshold, total) =
+        policy.ok_or_else(|| CoordinatorError::descriptor("missing Policy line"))?;
+    if keys.len() != total {
+        return Err(CoordinatorError::Descriptor(format!(
+            "policy is {} of {} but {} keys listed",
+            threshold,
+            total,
+            keys.len()
+        )));
+    }
+    check_threshold(threshold, total)?;
+
+    let key_exprs: Vec<String> = keys
+        .iter()
+        .map(|(fp, path, xpub)| key_expression(*fp, path, xpub))
+        .collect();
+    let multi = format!("wsh(sortedmulti({},{}))", threshold, key_exprs.join(","));
+    let descriptor = match script_type {
+        ScriptType::ShWsh => format!("sh({})", multi),
+        _ => multi,
+    };
+    MultisigWallet::from_descriptor(&descriptor, network)
+}
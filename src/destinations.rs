@@ -0,0 +1,86 @@
+//! Destinations treated as our own, such as a cold-storage wallet.
+//!
+//! Payments to an internal wallet are transfers between our own funds, so
+//! signers label them as such and leave them out of external payment
+//! limits.
+
+use crate::CoordinatorError;
+use bitcoin::{Script, ScriptBuf};
+use miniscript::descriptor::{Descriptor, DescriptorPublicKey};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// One entry of an internal wallets file, a JSON list of these.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct InternalWallet {
+    /// Name shown for payments to the wallet, e.g. `cold storage`.
+    pub label: String,
+    /// Any descriptor, with or without a wildcard or multipath step.
+    pub descriptor: String,
+}
+
+/// Output scripts of the internal wallets, down to a lookahead.
+#[derive(Debug, Clone, Default)]
+pub struct InternalDestinations {
+    scripts: HashMap<ScriptBuf, String>,
+}
+
+impl InternalDestinations {
+    /// Reads an internal wallets file; see [`InternalDestinations::new`].
+    pub fn load(path: &str, lookahead: u32) -> Result<Self, CoordinatorError> {
+        let wallets: Vec<InternalWallet> = serde_json::from_str(&std::fs::read_to_string(path)?)
+            .map_err(|e| CoordinatorError::Descriptor(format!("{}: {}", path, e)))?;
+        Self::new(&wallets, lookahead)
+    }
+
+    /// Derives the first `lookahead` scripts of every chain of each
+    /// wallet, or its one script if it has no wildcard.
+    pub fn new(wallets: &[InternalWallet], lookahead: u32) -> Result<Self, CoordinatorError> {
+        let mut scripts = HashMap::new();
+        for wallet in wallets {
+            let descriptor = Descriptor::<DescriptorPublicKey>::from_str(&wallet.descriptor)
+                .map_err(|e| CoordinatorError::Descriptor(format!("{}: {}", wallet.label, e)))?;
+            let count = if descriptor.has_wildcard() {
+                lookahead
+            } else {
+                1
+            };
+            for chain in descriptor.into_single_descriptors()? {
+                for index in 0..count {
+                    let derived = chain
+                        .at_derivation_index(index)
+                        .map_err(CoordinatorError::descriptor)?;
+                    scripts.insert(derived.script_pubkey(), wallet.label.clone());
+                }
+            }
+        }
+        Ok(Self { scripts })
+    }
+
+    /// Label of the internal wallet `script_pubkey` pays to, if any.
+    pub fn label(&self, script_pubkey: &Script) -> Option<&str> {
+        self.scripts.get(script_pubkey).map(String::as_str)
+    }
+}
+
+/// The output script of address `index` on `descriptor`'s receive chain,
+/// derived locally so a payment into another descriptor wallet does not
+/// rely on an address pasted from elsewhere. A `#checksum` is checked.
+pub fn descriptor_script_pubkey(
+    descriptor: &str,
+    index: u32,
+) -> Result<ScriptBuf, CoordinatorError> {
+    let descriptor = Descriptor::<DescriptorPublicKey>::from_str(descriptor.trim())
+        .map_err(CoordinatorError::descriptor)?;
+    let receive = descriptor
+        .into_single_descriptors()?
+        .into_iter()
+        .next()
+        .ok_or_else(|| CoordinatorError::descriptor("descriptor has no receive chain"))?;
+    Ok(receive
+        .at_derivation_index(index)
+        .map_err(CoordinatorError::descriptor)?
+        .script_pubkey())
+}
@@ -0,0 +1,178 @@
+//! An Electrum server as a [`ChainSource`].
+//!
+//! Electrum servers (ElectrumX, Fulcrum, electrs) answer JSON-RPC, one
+//! request per line, and index outputs by script hash. Only the plain TCP
+//! port is supported: this crate has no TLS stack, so reach a remote
+//! server's SSL port through an SSH tunnel or `stunnel`, or run one next
+//! to your own node. As with Esplora, the server learns which addresses
+//! belong together.
+
+use crate::chain_source::{ChainSource, btc_per_kvb_to_sat_per_vb, listed_utxo, output_of};
+use crate::utxo_store::ManualUtxo;
+use crate::{CoordinatorError, MultisigWallet};
+use bitcoin::block::Header;
+use bitcoin::consensus::encode::deserialize_hex;
+use bitcoin::hashes::{Hash, sha256};
+use bitcoin::hex::DisplayHex;
+use bitcoin::{BlockHash, OutPoint, Script, TxOut, Txid};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Protocol version asked for; 1.4 has every method used here.
+const PROTOCOL_VERSION: &str = "1.4";
+
+/// Longest response line accepted, so a hostile server cannot exhaust
+/// memory.
+const MAX_RESPONSE_BYTES: u64 = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ElectrumConfig {
+    /// `host:port` of the server's TCP port, e.g. `127.0.0.1:50001`.
+    pub server: String,
+}
+
+impl ElectrumConfig {
+    /// Sends `method` on a fresh connection and returns its result.
+    fn request(&self, method: &str, params: Value) -> Result<Value, CoordinatorError> {
+        let stream = TcpStream::connect(&self.server)?;
+        stream.set_read_timeout(Some(Duration::from_secs(30)))?;
+        stream.set_write_timeout(Some(Duration::from_secs(30)))?;
+        // Servers expect the protocol version to be negotiated first.
+        let requests = [
+            json!({
+                "jsonrpc": "2.0",
+                "id": 0,
+                "method": "server.version",
+                "params": ["psbt-coordinator", PROTOCOL_VERSION],
+            }),
+            json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params}),
+        ];
+        let mut writer = &stream;
+        for request in &requests {
+            writeln!(writer, "{}", request)?;
+        }
+        let mut reader = BufReader::new((&stream).take(MAX_RESPONSE_BYTES));
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(CoordinatorError::Invalid(format!(
+                    "Electrum server closed the connection during {}",
+                    method
+                )));
+            }
+            let response: Value = serde_json::from_str(&line)
+                .map_err(|e| CoordinatorError::Invalid(format!("Electrum {}: {}", method, e)))?;
+            if !response["error"].is_null() {
+                return Err(CoordinatorError::Invalid(format!(
+                    "Electrum {} failed: {}",
+                    method, response["error"]
+                )));
+            }
+            if response["id"] == 1 {
+                return Ok(response["result"].clone());
+            }
+        }
+    }
+
+    fn transaction(&self, txid: &Txid) -> Result<String, CoordinatorError> {
+        self.request("blockchain.transaction.get", json!([txid.to_string()]))?
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| CoordinatorError::invalid("unexpected transaction from Electrum"))
+    }
+
+    fn list_unspent(&self, script_pubkey: &Script) -> Result<Vec<OutPoint>, CoordinatorError> {
+        let listing = self.request(
+            "blockchain.scripthash.listunspent",
+            json!([script_hash(script_pubkey)]),
+        )?;
+        let listing = listing
+            .as_array()
+            .ok_or_else(|| CoordinatorError::invalid("unexpected UTXO listing from Electrum"))?;
+        listing
+            .iter()
+            .map(|unspent| {
+                let txid = unspent["tx_hash"]
+                    .as_str()
+                    .and_then(|txid| Txid::from_str(txid).ok())
+                    .ok_or_else(|| CoordinatorError::invalid("UTXO without a txid"))?;
+                let vout = unspent["tx_pos"]
+                    .as_u64()
+                    .and_then(|vout| u32::try_from(vout).ok())
+                    .ok_or_else(|| CoordinatorError::invalid("UTXO without an output number"))?;
+                Ok(OutPoint { txid, vout })
+            })
+            .collect()
+    }
+}
+
+/// The key Electrum indexes outputs by: the script's SHA256, byte-reversed.
+fn script_hash(script_pubkey: &Script) -> String {
+    let mut hash = sha256::Hash::hash(script_pubkey.as_bytes()).to_byte_array();
+    hash.reverse();
+    hash.to_lower_hex_string()
+}
+
+impl ChainSource for ElectrumConfig {
+    fn name(&self) -> &'static str {
+        "Electrum"
+    }
+
+    fn genesis_hash(&self) -> Result<BlockHash, CoordinatorError> {
+        let header = self.request("blockchain.block.header", json!([0]))?;
+        let header: Header = header
+            .as_str()
+            .and_then(|hex| deserialize_hex(hex).ok())
+            .ok_or_else(|| CoordinatorError::invalid("unexpected block header from Electrum"))?;
+        Ok(header.block_hash())
+    }
+
+    /// Unconfirmed outputs count too.
+    fn find_utxos(
+        &self,
+        wallet: &MultisigWallet,
+        gap_limit: u32,
+    ) -> Result<Vec<ManualUtxo>, CoordinatorError> {
+        let mut utxos = Vec::new();
+        for is_change in [false, true] {
+            for derived in wallet.iter_addresses(0..gap_limit, is_change)? {
+                let (index, address) = derived?;
+                for outpoint in self.list_unspent(&address.script_pubkey())? {
+                    let raw_tx = self.transaction(&outpoint.txid)?;
+                    utxos.push(listed_utxo(wallet, &raw_tx, &outpoint, index, is_change)?);
+                }
+            }
+        }
+        Ok(utxos)
+    }
+
+    fn prevout(&self, outpoint: &OutPoint) -> Result<TxOut, CoordinatorError> {
+        let output = output_of(&self.transaction(&outpoint.txid)?, outpoint)?;
+        if !self.list_unspent(&output.script_pubkey)?.contains(outpoint) {
+            return Err(CoordinatorError::Invalid(format!(
+                "{} is spent according to Electrum",
+                outpoint
+            )));
+        }
+        Ok(output)
+    }
+
+    fn fee_rate(&self, blocks: u16) -> Result<f64, CoordinatorError> {
+        let rate = self
+            .request("blockchain.estimatefee", json!([blocks]))?
+            .as_f64()
+            .ok_or_else(|| CoordinatorError::invalid("unexpected fee estimate from Electrum"))?;
+        // -1 means the server's node has no estimate.
+        if rate <= 0.0 {
+            return Err(CoordinatorError::Invalid(format!(
+                "Electrum has no fee estimate for {} blocks",
+                blocks
+            )));
+        }
+        Ok(btc_per_kvb_to_sat_per_vb(rate))
+    }
+}
@@ -0,0 +1,85 @@
+//! Error type for the wallet, key and PSBT handling in this crate.
+
+use std::fmt::Display;
+
+/// What went wrong, by the kind of input at fault, so callers can tell a
+/// bad key file from a bad descriptor or a bad PSBT without matching on
+/// message text. Messages are shown as-is; errors from the `bitcoin`,
+/// miniscript and JSON libraries are kept as the error's source.
+#[derive(Debug, thiserror::Error)]
+pub enum CoordinatorError {
+    /// A key file, xpub, fingerprint or public key could not be read.
+    #[error("{0}")]
+    Key(String),
+    /// A descriptor or policy is malformed, unsupported, or cannot be
+    /// turned into scripts.
+    #[error("{0}")]
+    Descriptor(String),
+    /// A BIP 32 path is malformed or cannot be derived from the key.
+    #[error("{0}")]
+    Derivation(String),
+    /// A PSBT, or a script inside one, is malformed, oversized or does not
+    /// match what it should.
+    #[error("{0}")]
+    Psbt(String),
+    /// An amount, threshold or other parameter is out of range.
+    #[error("{0}")]
+    Invalid(String),
+    /// Two submissions for the same signing session disagree, such as two
+    /// different signatures by one key on the same input.
+    #[error("{0}")]
+    Conflict(String),
+    /// An extended key or BIP 32 path rejected by the `bitcoin` crate.
+    #[error("{0}")]
+    Bip32(#[from] bitcoin::bip32::Error),
+    /// A descriptor, policy or script rejected by miniscript.
+    #[error("{0}")]
+    Miniscript(#[from] miniscript::Error),
+    /// A PSBT that does not deserialize, combine or add up.
+    #[error("{0}")]
+    PsbtEncoding(#[from] bitcoin::psbt::Error),
+    /// A JSON file or artifact that does not parse or serialize.
+    #[error("{0}")]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl CoordinatorError {
+    pub(crate) fn key(e: impl Display) -> Self {
+        CoordinatorError::Key(e.to_string())
+    }
+
+    pub(crate) fn descriptor(e: impl Display) -> Self {
+        CoordinatorError::Descriptor(e.to_string())
+    }
+
+    pub(crate) fn psbt(e: impl Display) -> Self {
+        CoordinatorError::Psbt(e.to_string())
+    }
+
+    pub(crate) fn invalid(e: impl Display) -> Self {
+        CoordinatorError::Invalid(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::bip32::DerivationPath;
+    use std::error::Error;
+    use std::str::FromStr;
+
+    #[test]
+    fn library_errors_are_kept_as_sources() {
+        let e: CoordinatorError = DerivationPath::from_str("m/x").unwrap_err().into();
+        assert!(matches!(e, CoordinatorError::Bip32(_)));
+        let source = e.source().unwrap();
+        assert!(source.downcast_ref::<bitcoin::bip32::Error>().is_some());
+        assert_eq!(e.to_string(), source.to_string());
+
+        let e: CoordinatorError = serde_json::from_str::<u32>("{").unwrap_err().into();
+        let source = e.source().unwrap();
+        assert!(source.downcast_ref::<serde_json::Error>().is_some());
+    }
+}
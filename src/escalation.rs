@@ -0,0 +1,136 @@
+//! Reminders for signing sessions stuck below threshold.
+//!
+//! A session is one PSBT, from the time the coordinator created it. Once
+//! it has been waiting longer than a window, each cosigner whose signature
+//! is still missing is due a reminder, one more for every window that
+//! passes; after a set number of windows the session is at risk. There is
+//! no server here: the `reminders` command assesses PSBT files on each
+//! run, e.g. from cron, and prints a digest per cosigner.
+
+use crate::finalize::check_readiness;
+use crate::{CoordinatorError, CosignerMetadata, MultisigWallet, psbt_created_at};
+use bitcoin::psbt::Psbt;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy)]
+pub struct EscalationPolicy {
+    /// Seconds a session may wait before the first reminder, and between
+    /// reminders after that.
+    pub window: u64,
+    /// Windows after which a session is at risk.
+    pub at_risk_after: u64,
+}
+
+impl Default for EscalationPolicy {
+    /// Daily reminders; at risk after three days.
+    fn default() -> Self {
+        Self {
+            window: 24 * 60 * 60,
+            at_risk_after: 3,
+        }
+    }
+}
+
+impl EscalationPolicy {
+    pub fn stage(&self, age: u64) -> Stage {
+        let windows = age / self.window.max(1);
+        if windows == 0 {
+            Stage::OnTrack
+        } else if windows >= self.at_risk_after {
+            Stage::AtRisk
+        } else {
+            Stage::Reminder(windows)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    OnTrack,
+    /// The n-th reminder is due.
+    Reminder(u64),
+    AtRisk,
+}
+
+impl std::fmt::Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stage::OnTrack => write!(f, "on track"),
+            Stage::Reminder(n) => write!(f, "reminder {}", n),
+            Stage::AtRisk => write!(f, "AT RISK"),
+        }
+    }
+}
+
+/// A cosigner whose signature a session still needs.
+#[derive(Debug, Clone, Serialize)]
+pub struct MissingCosigner {
+    /// Display name from the wallet, or the fingerprint without one.
+    pub name: String,
+    pub fingerprint: String,
+    #[serde(skip_serializing_if = "CosignerMetadata::is_empty")]
+    pub metadata: CosignerMetadata,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionStatus {
+    pub session: String,
+    pub created_at: u64,
+    pub age: u64,
+    pub signatures: usize,
+    pub threshold: usize,
+    pub stage: Stage,
+    pub missing: Vec<MissingCosigner>,
+}
+
+/// Where `psbt` stands at unix time `now`. Progress is that of its
+/// least-signed input. Cosigners are named from `wallet` when given.
+pub fn assess(
+    session: &str,
+    psbt: &Psbt,
+    wallet: Option<&MultisigWallet>,
+    now: u64,
+    policy: &EscalationPolicy,
+) -> Result<SessionStatus, CoordinatorError> {
+    let created_at = psbt_created_at(psbt)?.ok_or_else(|| {
+        CoordinatorError::psbt("PSBT has no creation time; recreate it with the coordinator")
+    })?;
+    let report = check_readiness(psbt)?;
+    let least_signed = report
+        .inputs
+        .iter()
+        .max_by_key(|input| input.threshold.saturating_sub(input.signed.len()))
+        .ok_or_else(|| CoordinatorError::psbt("PSBT has no inputs"))?;
+    let age = now.saturating_sub(created_at);
+    let stage = if least_signed.is_ready() {
+        Stage::OnTrack
+    } else {
+        policy.stage(age)
+    };
+    let missing = least_signed
+        .missing
+        .iter()
+        .map(|key| {
+            let origin = wallet.and_then(|w| {
+                w.xpub_origins
+                    .iter()
+                    .find(|o| Some(o.fingerprint) == key.fingerprint)
+            });
+            MissingCosigner {
+                name: origin.map_or_else(|| key.to_string(), |o| o.display_name()),
+                fingerprint: key.to_string(),
+                metadata: origin.map(|o| o.metadata.clone()).unwrap_or_default(),
+            }
+        })
+        .collect();
+    Ok(SessionStatus {
+        session: session.to_string(),
+        created_at,
+        age,
+        signatures: least_signed.signed.len(),
+        threshold: least_signed.threshold,
+        stage,
+        missing,
+    })
+}
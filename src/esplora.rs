@@ -0,0 +1,135 @@
+//! An Esplora HTTP API as a [`ChainSource`].
+//!
+//! Esplora is the REST API behind blockstream.info and mempool.space, and
+//! can be self-hosted. Requests go through `curl`, which brings TLS and
+//! proxy support without an HTTP client in this crate. Each of the first
+//! `gap_limit` addresses of both chains costs one request, plus one per
+//! funding transaction; a public instance learns which addresses belong
+//! together, so self-host one, or use Tor via `curl`'s proxy settings,
+//! where that matters.
+
+use crate::chain_source::{ChainSource, listed_utxo, output_of};
+use crate::utxo_store::ManualUtxo;
+use crate::{CoordinatorError, MultisigWallet};
+use bitcoin::{BlockHash, OutPoint, TxOut, Txid};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::process::Command;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EsploraConfig {
+    /// API base, e.g. `https://mempool.space/api` or
+    /// `https://blockstream.info/testnet/api`.
+    pub url: String,
+}
+
+impl EsploraConfig {
+    /// The response body of `GET {url}{path}`.
+    fn get(&self, path: &str) -> Result<String, CoordinatorError> {
+        let base = self.url.trim_end_matches('/');
+        if !base.starts_with("https://") && !base.starts_with("http://") {
+            return Err(CoordinatorError::Invalid(format!(
+                "Esplora URL {} must start with https:// or http://",
+                self.url
+            )));
+        }
+        let output = Command::new("curl")
+            .args(["--silent", "--show-error", "--fail", "--max-time", "30"])
+            .arg(format!("{}{}", base, path))
+            .output()?;
+        if !output.status.success() {
+            return Err(CoordinatorError::Invalid(format!(
+                "Esplora request {} failed: {}",
+                path,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    fn get_json(&self, path: &str) -> Result<Value, CoordinatorError> {
+        serde_json::from_str(&self.get(path)?)
+            .map_err(|e| CoordinatorError::Invalid(format!("Esplora {}: {}", path, e)))
+    }
+}
+
+impl ChainSource for EsploraConfig {
+    fn name(&self) -> &'static str {
+        "Esplora"
+    }
+
+    fn genesis_hash(&self) -> Result<BlockHash, CoordinatorError> {
+        BlockHash::from_str(&self.get("/block-height/0")?)
+            .map_err(|e| CoordinatorError::Invalid(format!("Esplora genesis hash: {}", e)))
+    }
+
+    /// Unconfirmed outputs count too.
+    fn find_utxos(
+        &self,
+        wallet: &MultisigWallet,
+        gap_limit: u32,
+    ) -> Result<Vec<ManualUtxo>, CoordinatorError> {
+        let mut utxos = Vec::new();
+        for is_change in [false, true] {
+            for derived in wallet.iter_addresses(0..gap_limit, is_change)? {
+                let (index, address) = derived?;
+                let listing = self.get_json(&format!("/address/{}/utxo", address))?;
+                let listing = listing.as_array().ok_or_else(|| {
+                    CoordinatorError::invalid("unexpected UTXO listing from Esplora")
+                })?;
+                for unspent in listing {
+                    let txid = unspent["txid"]
+                        .as_str()
+                        .and_then(|txid| Txid::from_str(txid).ok())
+                        .ok_or_else(|| CoordinatorError::invalid("UTXO without a txid"))?;
+                    let vout = unspent["vout"]
+                        .as_u64()
+                        .and_then(|vout| u32::try_from(vout).ok())
+                        .ok_or_else(|| {
+                            CoordinatorError::invalid("UTXO without an output number")
+                        })?;
+                    let outpoint = OutPoint { txid, vout };
+                    let raw_tx = self.get(&format!("/tx/{}/hex", txid))?;
+                    utxos.push(listed_utxo(wallet, &raw_tx, &outpoint, index, is_change)?);
+                }
+            }
+        }
+        Ok(utxos)
+    }
+
+    fn prevout(&self, outpoint: &OutPoint) -> Result<TxOut, CoordinatorError> {
+        let raw_tx = self.get(&format!("/tx/{}/hex", outpoint.txid))?;
+        let output = output_of(&raw_tx, outpoint)?;
+        let outspend =
+            self.get_json(&format!("/tx/{}/outspend/{}", outpoint.txid, outpoint.vout))?;
+        if outspend["spent"].as_bool() != Some(false) {
+            return Err(CoordinatorError::Invalid(format!(
+                "{} is spent according to Esplora",
+                outpoint
+            )));
+        }
+        Ok(output)
+    }
+
+    /// Esplora estimates for a fixed set of targets; this takes the one
+    /// for the longest target not beyond `blocks`.
+    fn fee_rate(&self, blocks: u16) -> Result<f64, CoordinatorError> {
+        let estimates: BTreeMap<String, f64> =
+            serde_json::from_value(self.get_json("/fee-estimates")?)
+                .map_err(|e| CoordinatorError::Invalid(format!("Esplora fee estimates: {}", e)))?;
+        estimates
+            .iter()
+            .filter_map(|(target, rate)| Some((target.parse::<u16>().ok()?, *rate)))
+            .filter(|(target, _)| *target <= blocks)
+            .max_by_key(|(target, _)| *target)
+            .map(|(_, rate)| rate)
+            .ok_or_else(|| {
+                CoordinatorError::Invalid(format!(
+                    "Esplora has no fee estimate for {} blocks",
+                    blocks
+                ))
+            })
+    }
+}
@@ -0,0 +1,301 @@
+//! Wallet exports for external consumers.
+
+use crate::provenance::SoftwareAttestation;
+use crate::schema::SCHEMA_VERSION;
+use crate::{CoordinatorError, MultisigWallet, ScriptType, format_path, slip132};
+use bitcoin::{Address, ScriptBuf, Transaction};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Auditor view of the wallet: addresses, amounts and scripts, with the
+/// xpubs and descriptor replaced by the policy ID so future addresses
+/// cannot be derived from it.
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct AuditExport {
+    pub schema_version: u32,
+    pub policy_id: String,
+    pub network: String,
+    pub threshold: usize,
+    pub cosigners: usize,
+    pub addresses: Vec<AuditAddress>,
+    pub transactions: Vec<AuditTransaction>,
+    /// Build that produced the export.
+    pub software: SoftwareAttestation,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct AuditAddress {
+    pub index: u32,
+    /// Whether the address is on the change chain.
+    pub change: bool,
+    pub address: String,
+    /// Witness script for this index only. Lets the auditor check the address
+    /// commits to an M-of-N script without revealing any xpub.
+    pub witness_script: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct AuditTransaction {
+    pub txid: String,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<AuditOutput>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct AuditOutput {
+    pub vout: u32,
+    pub address: Option<String>,
+    pub amount_sat: u64,
+    /// Derivation index when the output pays this wallet.
+    pub wallet_index: Option<u32>,
+    /// Whether that index is on the change chain.
+    pub wallet_change: Option<bool>,
+}
+
+pub fn audit_export(
+    wallet: &MultisigWallet,
+    range: Range<u32>,
+    transactions: &[Transaction],
+) -> Result<AuditExport, CoordinatorError> {
+    let mut addresses = Vec::new();
+    let mut owned: HashMap<ScriptBuf, (u32, bool)> = HashMap::new();
+    for change in [false, true] {
+        for (index, address, witness_script) in wallet.derive_addresses(range.clone(), change)? {
+            owned.insert(address.script_pubkey(), (index, change));
+            addresses.push(AuditAddress {
+                index,
+                change,
+                address: address.to_string(),
+                witness_script: witness_script.to_hex_string(),
+            });
+        }
+    }
+
+    let transactions = transactions
+        .iter()
+        .map(|tx| AuditTransaction {
+            txid: tx.compute_txid().to_string(),
+            inputs: tx
+                .input
+                .iter()
+                .map(|i| i.previous_output.to_string())
+                .collect(),
+            outputs: tx
+                .output
+                .iter()
+                .enumerate()
+                .map(|(vout, out)| AuditOutput {
+                    vout: vout as u32,
+                    address: Address::from_script(&out.script_pubkey, wallet.network)
+                        .ok()
+                        .map(|a| a.to_string()),
+                    amount_sat: out.value.to_sat(),
+                    wallet_index: owned.get(&out.script_pubkey).map(|(index, _)| *index),
+                    wallet_change: owned.get(&out.script_pubkey).map(|(_, change)| *change),
+                })
+                .collect(),
+        })
+        .collect();
+
+    Ok(AuditExport {
+        schema_version: SCHEMA_VERSION,
+        policy_id: wallet.policy_id().to_string(),
+        network: wallet.network.to_string(),
+        threshold: wallet.threshold,
+        cosigners: wallet.xpub_origins.len(),
+        addresses,
+        transactions,
+        software: SoftwareAttestation::new(Some(wallet)),
+    })
+}
+
+/// When Core starts rescanning an imported descriptor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportTimestamp {
+    /// No rescan: the wallet has not received anything yet.
+    Now,
+    /// Unix time of the wallet's creation, or earlier.
+    Time(u64),
+}
+
+impl Serialize for ImportTimestamp {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ImportTimestamp::Now => serializer.serialize_str("now"),
+            ImportTimestamp::Time(time) => serializer.serialize_u64(*time),
+        }
+    }
+}
+
+impl std::str::FromStr for ImportTimestamp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "now" => Ok(ImportTimestamp::Now),
+            _ => s
+                .parse()
+                .map(ImportTimestamp::Time)
+                .map_err(|_| format!("timestamp must be \"now\" or unix time, got {}", s)),
+        }
+    }
+}
+
+/// One request of a `bitcoin-cli importdescriptors` call.
+#[derive(Debug, Clone, Serialize)]
+pub struct CoreImportRequest {
+    /// Single-chain descriptor with its checksum.
+    pub desc: String,
+    pub active: bool,
+    /// First and last index to derive, both inclusive.
+    pub range: [u32; 2],
+    pub next_index: u32,
+    pub timestamp: ImportTimestamp,
+    /// Whether this is the change chain.
+    pub internal: bool,
+}
+
+/// The `importdescriptors` payload for a watch-only Core wallet tracking
+/// `wallet`: its receive and change descriptors, each active over `range`.
+/// Core takes them one chain at a time, so the multipath descriptor is
+/// split. Paste the JSON array as the call's only argument.
+pub fn core_import_requests(
+    wallet: &MultisigWallet,
+    range: Range<u32>,
+    timestamp: ImportTimestamp,
+) -> Result<Vec<CoreImportRequest>, CoordinatorError> {
+    let last = range.end.checked_sub(1).filter(|last| *last >= range.start);
+    let last = last.ok_or_else(|| CoordinatorError::invalid("import range must not be empty"))?;
+    let chains = wallet.descriptor.clone().into_single_descriptors()?;
+    if chains.len() != 2 {
+        return Err(CoordinatorError::descriptor(
+            "wallet descriptor must have a receive and a change chain",
+        ));
+    }
+    Ok(chains
+        .into_iter()
+        .enumerate()
+        .map(|(chain, descriptor)| CoreImportRequest {
+            desc: descriptor.to_string(),
+            active: true,
+            range: [range.start, last],
+            next_index: range.start,
+            timestamp,
+            internal: chain == 1,
+        })
+        .collect())
+}
+
+/// Wallet file for Specter Desktop's wallet import.
+#[derive(Debug, Clone, Serialize)]
+pub struct SpecterWallet {
+    pub label: String,
+    /// Height to rescan from; 0 scans the whole chain.
+    pub blockheight: u32,
+    /// Receive descriptor; Specter derives change itself.
+    pub descriptor: String,
+    pub devices: Vec<SpecterDevice>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SpecterDevice {
+    /// Device type as Specter names it, e.g. `coldcard`, or `other`.
+    #[serde(rename = "type")]
+    pub device_type: String,
+    pub label: String,
+}
+
+pub fn specter_wallet(
+    wallet: &MultisigWallet,
+    label: &str,
+    blockheight: u32,
+) -> Result<SpecterWallet, CoordinatorError> {
+    let receive = wallet
+        .descriptor
+        .clone()
+        .into_single_descriptors()?
+        .into_iter()
+        .next()
+        .ok_or_else(|| CoordinatorError::descriptor("wallet descriptor has no receive chain"))?;
+    Ok(SpecterWallet {
+        label: label.to_string(),
+        blockheight,
+        descriptor: receive.to_string(),
+        devices: wallet
+            .xpub_origins
+            .iter()
+            .map(|o| SpecterDevice {
+                device_type: o
+                    .metadata
+                    .device
+                    .as_deref()
+                    .map_or("other".to_string(), |device| {
+                        device.to_lowercase().replace(' ', "")
+                    }),
+                label: o
+                    .metadata
+                    .display_name
+                    .clone()
+                    .unwrap_or_else(|| o.name.clone()),
+            })
+            .collect(),
+    })
+}
+
+/// Seed version written to Electrum wallet files. Electrum upgrades older
+/// files when it opens them, so this is the oldest format with `xN/`
+/// keystores carrying a root fingerprint.
+pub const ELECTRUM_SEED_VERSION: u32 = 17;
+
+/// Electrum multisig wallet file, which Electrum opens directly and Sparrow
+/// imports ("Electrum" in its import dialog): `wallet_type` `MofN` and one
+/// `xN/` keystore per cosigner with its SLIP 132 xpub, derivation and
+/// master fingerprint. The script type is carried by the xpub version
+/// (`Zpub`/`Vpub` or `Ypub`/`Upub`), so taproot and miniscript policy
+/// wallets cannot be expressed.
+pub fn electrum_wallet(wallet: &MultisigWallet) -> Result<serde_json::Value, CoordinatorError> {
+    if wallet.is_policy() {
+        return Err(CoordinatorError::descriptor(
+            "Electrum wallet files only describe plain multisig wallets",
+        ));
+    }
+    if !wallet.is_sorted() && wallet.script_type != ScriptType::Tr {
+        return Err(CoordinatorError::descriptor(
+            "Electrum wallet files only describe sortedmulti wallets",
+        ));
+    }
+    let encode = match wallet.script_type {
+        ScriptType::Wsh => slip132::to_multisig_p2wsh,
+        ScriptType::ShWsh => slip132::to_multisig_p2sh_p2wsh,
+        ScriptType::Tr => {
+            return Err(CoordinatorError::descriptor(
+                "Electrum wallet files do not support taproot multisig",
+            ));
+        }
+    };
+    let mut file = serde_json::Map::new();
+    file.insert(
+        "wallet_type".to_string(),
+        format!("{}of{}", wallet.threshold, wallet.xpub_origins.len()).into(),
+    );
+    file.insert("seed_version".to_string(), ELECTRUM_SEED_VERSION.into());
+    file.insert("use_encryption".to_string(), false.into());
+    for (i, origin) in wallet.xpub_origins.iter().enumerate() {
+        file.insert(
+            format!("x{}/", i + 1),
+            serde_json::json!({
+                "type": "bip32",
+                "xpub": encode(&origin.xpub),
+                "derivation": format_path(&origin.derivation_path),
+                "root_fingerprint": origin.fingerprint.to_string(),
+                "label": origin.name,
+            }),
+        );
+    }
+    Ok(file.into())
+}
@@ -0,0 +1,176 @@
+//! Fee history of finalized transactions, for tuning fee policy.
+//!
+//! The finalizer appends one [`FeeRecord`] per transaction to
+//! [`FEE_HISTORY_FILE`]. [`summarize`] groups them by month and estimates
+//! what consolidations and change-free payments saved.
+
+use crate::CoordinatorError;
+use bitcoin::psbt::Psbt;
+use bitcoin::{Transaction, Weight};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// JSON Lines file the finalizer appends to.
+pub const FEE_HISTORY_FILE: &str = "fee_history.jsonl";
+
+/// Size of a P2WSH or P2TR change output: amount, script length and a
+/// 34-byte script.
+pub const CHANGE_OUTPUT_VBYTES: u64 = 43;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeRecord {
+    pub txid: String,
+    /// Unix time the transaction was finalized.
+    pub finalized_at: u64,
+    /// Size projected from the PSBT before its witnesses were built.
+    /// Absent for inputs the projection cannot size, such as taproot.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub estimated_vsize: Option<u64>,
+    pub actual_vsize: u64,
+    /// Absent if the PSBT lacked input amounts.
+    pub fee_sat: Option<u64>,
+    pub inputs: usize,
+    /// Virtual size of the inputs alone, witnesses included.
+    pub input_vsize: u64,
+    pub outputs: usize,
+    /// Outputs back to the wallet, as marked by the coordinator.
+    pub change_outputs: usize,
+}
+
+impl FeeRecord {
+    /// Describes `tx`, extracted from the finalized `psbt`, against the
+    /// weight `estimated` before finalization.
+    pub fn new(
+        psbt: &Psbt,
+        tx: &Transaction,
+        estimated: Option<Weight>,
+        finalized_at: u64,
+    ) -> Self {
+        let input_weight = tx
+            .input
+            .iter()
+            .fold(Weight::ZERO, |total, input| total + input.segwit_weight());
+        Self {
+            txid: tx.compute_txid().to_string(),
+            finalized_at,
+            estimated_vsize: estimated.map(Weight::to_vbytes_ceil),
+            actual_vsize: tx.vsize() as u64,
+            fee_sat: psbt.fee().ok().map(|fee| fee.to_sat()),
+            inputs: tx.input.len(),
+            input_vsize: input_weight.to_vbytes_ceil(),
+            outputs: tx.output.len(),
+            change_outputs: psbt
+                .outputs
+                .iter()
+                .filter(|o| !o.bip32_derivation.is_empty() || !o.tap_key_origins.is_empty())
+                .count(),
+        }
+    }
+
+    pub fn feerate(&self) -> Option<f64> {
+        self.fee_sat
+            .map(|fee| fee as f64 / self.actual_vsize as f64)
+    }
+
+    /// Every output returns to the wallet.
+    pub fn is_consolidation(&self) -> bool {
+        self.inputs > 1 && self.change_outputs == self.outputs
+    }
+
+    /// What a later spend of one of the inputs would weigh.
+    fn average_input_vsize(&self) -> f64 {
+        self.input_vsize as f64 / self.inputs.max(1) as f64
+    }
+}
+
+/// Appends `record` to `path`, creating the file if needed.
+pub fn append(path: &str, record: &FeeRecord) -> Result<(), CoordinatorError> {
+    let line = serde_json::to_string(record).map_err(CoordinatorError::invalid)?;
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    writeln!(file, "{}", line)?;
+    Ok(())
+}
+
+pub fn load(path: &str) -> Result<Vec<FeeRecord>, CoordinatorError> {
+    std::fs::read_to_string(path)?
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line)
+                .map_err(|e| CoordinatorError::Invalid(format!("{} line {}: {}", path, i + 1, e)))
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MonthlySummary {
+    /// `YYYY-MM`, UTC.
+    pub month: String,
+    pub transactions: usize,
+    pub fee_sat: u64,
+    pub vbytes: u64,
+    /// Sum of actual minus estimated vsize, where estimated.
+    pub estimate_error_vbytes: i64,
+    /// Fees not paid for change outputs, and later spends of them, that
+    /// payments without change avoided, at each payment's feerate.
+    pub change_avoidance_savings_sat: u64,
+    /// Fees consolidations saved by spending inputs now rather than at the
+    /// reference feerate; negative if they paid more than it.
+    pub consolidation_savings_sat: i64,
+}
+
+/// Groups records by month. Consolidation savings compare each
+/// consolidation's feerate with `reference_feerate` (sat/vB), the rate
+/// its inputs would otherwise be spent at.
+pub fn summarize(records: &[FeeRecord], reference_feerate: f64) -> Vec<MonthlySummary> {
+    let mut months: BTreeMap<String, MonthlySummary> = BTreeMap::new();
+    for record in records {
+        let month = month_of(record.finalized_at);
+        let summary = months
+            .entry(month.clone())
+            .or_insert_with(|| MonthlySummary {
+                month,
+                ..Default::default()
+            });
+        summary.transactions += 1;
+        summary.fee_sat += record.fee_sat.unwrap_or(0);
+        summary.vbytes += record.actual_vsize;
+        if let Some(estimated) = record.estimated_vsize {
+            summary.estimate_error_vbytes += record.actual_vsize as i64 - estimated as i64;
+        }
+        let Some(feerate) = record.feerate() else {
+            continue;
+        };
+        if record.change_outputs == 0 {
+            let avoided = CHANGE_OUTPUT_VBYTES as f64 + record.average_input_vsize();
+            summary.change_avoidance_savings_sat += (avoided * feerate).round() as u64;
+        }
+        if record.is_consolidation() {
+            // One input is left to spend later, as the consolidated output.
+            let spared = (record.inputs - 1) as f64 * record.average_input_vsize();
+            summary.consolidation_savings_sat +=
+                (spared * (reference_feerate - feerate)).round() as i64;
+        }
+    }
+    months.into_values().collect()
+}
+
+/// `YYYY-MM` of a unix time, UTC.
+fn month_of(unix_time: u64) -> String {
+    // Civil-from-days, as in Howard Hinnant's date algorithms.
+    let days = (unix_time / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}", year, month)
+}
@@ -0,0 +1,879 @@
+//! Multisig witness construction and finalization readiness checks.
+
+use crate::CoordinatorError;
+use crate::verify::{
+    SignatureCheck, verify_input_sigs, verify_partial_sigs, verify_tap_key_sig,
+    verify_tap_script_sigs,
+};
+use crate::{EXPECTED_SIG_LEN, parse_multi_a_script, parse_multisig_script, parse_policy_script};
+use bitcoin::bip32::Fingerprint;
+use bitcoin::hex::DisplayHex;
+use bitcoin::psbt::{Input, Psbt};
+use bitcoin::script::{Builder, PushBytesBuf};
+use bitcoin::secp256k1::{Parity, Secp256k1, XOnlyPublicKey};
+use bitcoin::taproot::TapLeafHash;
+use bitcoin::{Address, Amount, Network, Script, ScriptBuf, Transaction, Weight, Witness};
+use miniscript::psbt::PsbtExt;
+use miniscript::{Descriptor, Miniscript, Segwitv0};
+use serde_json::json;
+
+/// A BIP 340 signature with the default sighash type, which adds no byte.
+const TAPROOT_SIG_LEN: usize = 64;
+
+/// Which signatures to use when an input has more than the threshold.
+///
+/// Every extra byte of signature is paid for in the witness, so the choice
+/// affects the final weight: DER signatures vary between 71 and 73 bytes.
+/// By default the shortest encodings are used, which minimizes the witness.
+#[derive(Debug, Clone)]
+pub struct SignatureSelection {
+    /// Fingerprints whose signatures are used first, in order of preference.
+    pub prefer: Vec<Fingerprint>,
+    /// Fingerprints (e.g. a backup key) used only when needed for the threshold.
+    pub avoid: Vec<Fingerprint>,
+    /// Among equally preferred signatures, use the shortest encodings first.
+    pub smallest_first: bool,
+}
+
+impl Default for SignatureSelection {
+    fn default() -> Self {
+        Self {
+            prefer: Vec::new(),
+            avoid: Vec::new(),
+            smallest_first: true,
+        }
+    }
+}
+
+impl SignatureSelection {
+    fn tier(&self, fingerprint: Option<Fingerprint>) -> usize {
+        let Some(fp) = fingerprint else {
+            return self.prefer.len();
+        };
+        if let Some(pos) = self.prefer.iter().position(|p| *p == fp) {
+            pos
+        } else if self.avoid.contains(&fp) {
+            self.prefer.len() + 1
+        } else {
+            self.prefer.len()
+        }
+    }
+}
+
+/// Builds the final witness for multisig input `idx` from its partial
+/// signatures, in the order of the script's keys, whether `multi` or
+/// `sortedmulti`. Only signatures that verify against the input's sighash
+/// are candidates, so an invalid one cannot take the place of a valid one.
+pub fn multisig_witness(
+    psbt: &Psbt,
+    idx: usize,
+    selection: &SignatureSelection,
+) -> Result<Witness, CoordinatorError> {
+    let input = &psbt.inputs[idx];
+    let valid: Vec<bitcoin::PublicKey> = verify_input_sigs(psbt, idx)
+        .into_iter()
+        .filter(|check| check.is_valid())
+        .map(|check| check.pubkey)
+        .collect();
+    let script = input
+        .witness_script
+        .as_ref()
+        .ok_or_else(|| CoordinatorError::psbt("missing witness script"))?;
+    let (threshold, pubkeys) = parse_multisig_script(script)?;
+
+    let mut candidates: Vec<_> = pubkeys
+        .iter()
+        .enumerate()
+        .filter(|(_, pk)| valid.contains(pk))
+        .filter_map(|(pos, pk)| {
+            let sig = input.partial_sigs.get(pk)?.serialize();
+            let fingerprint = input.bip32_derivation.get(&pk.inner).map(|(fp, _)| *fp);
+            let size = if selection.smallest_first {
+                sig.len()
+            } else {
+                0
+            };
+            Some(((selection.tier(fingerprint), size, pos), sig))
+        })
+        .collect();
+    if candidates.len() < threshold {
+        return Err(CoordinatorError::Psbt(format!(
+            "only {}/{} valid signatures",
+            candidates.len(),
+            threshold
+        )));
+    }
+
+    candidates.sort_by_key(|(rank, _)| *rank);
+    candidates.truncate(threshold);
+    // CHECKMULTISIG consumes signatures in the script's key order, no
+    // matter how an external signer ordered its partial_sigs.
+    candidates.sort_by_key(|((_, _, pos), _)| *pos);
+
+    // Witness: <empty> <sig1> ... <sigM> <script>
+    let mut witness = Witness::new();
+    witness.push([]);
+    for (_, sig) in &candidates {
+        witness.push(sig);
+    }
+    witness.push(script.as_bytes());
+    Ok(witness)
+}
+
+/// Builds the script path witness for taproot input `idx` with a `multi_a`
+/// leaf, from the signatures that verify against its sighash.
+///
+/// Schnorr signatures are all 64 bytes, so only the fingerprint preferences
+/// of `selection` apply.
+pub fn multi_a_witness(
+    psbt: &Psbt,
+    idx: usize,
+    selection: &SignatureSelection,
+) -> Result<Witness, CoordinatorError> {
+    let input = &psbt.inputs[idx];
+    let valid: Vec<_> = verify_tap_script_sigs(psbt, idx)
+        .into_iter()
+        .filter(|check| check.result.is_ok())
+        .map(|check| (check.key, check.leaf_hash))
+        .collect();
+    let (control_block, (script, version)) = input
+        .tap_scripts
+        .iter()
+        .next()
+        .ok_or_else(|| CoordinatorError::psbt("missing taproot leaf"))?;
+    let leaf_hash = TapLeafHash::from_script(script, *version);
+    let (threshold, keys) = parse_multi_a_script(script)?;
+
+    let mut candidates: Vec<_> = keys
+        .iter()
+        .enumerate()
+        .filter(|(_, key)| valid.contains(&(**key, leaf_hash)))
+        .map(|(pos, key)| {
+            let fingerprint = input.tap_key_origins.get(key).map(|(_, (fp, _))| *fp);
+            (selection.tier(fingerprint), pos)
+        })
+        .collect();
+    if candidates.len() < threshold {
+        return Err(CoordinatorError::Psbt(format!(
+            "only {}/{} valid signatures",
+            candidates.len(),
+            threshold
+        )));
+    }
+    candidates.sort();
+    candidates.truncate(threshold);
+
+    // multi_a checks every key in script order, taking the first key's
+    // signature from the top of the stack, so the witness lists them in
+    // reverse, with an empty element for each key that does not sign.
+    // Witness: <sigN|empty> ... <sig1|empty> <script> <control block>
+    let mut witness = Witness::new();
+    for (pos, key) in keys.iter().enumerate().rev() {
+        if candidates.iter().any(|(_, p)| *p == pos) {
+            witness.push(input.tap_script_sigs[&(*key, leaf_hash)].to_vec());
+        } else {
+            witness.push([]);
+        }
+    }
+    witness.push(script.as_bytes());
+    witness.push(control_block.serialize());
+    Ok(witness)
+}
+
+/// The scriptSig of a P2SH-wrapped segwit input: a single push of the
+/// redeem script. `None` for native segwit inputs.
+fn nested_script_sig(input: &Input) -> Result<Option<ScriptBuf>, CoordinatorError> {
+    let Some(redeem_script) = &input.redeem_script else {
+        return Ok(None);
+    };
+    let push = PushBytesBuf::try_from(redeem_script.to_bytes()).map_err(CoordinatorError::psbt)?;
+    Ok(Some(Builder::new().push_slice(push).into_script()))
+}
+
+/// Sets the final witness of input `idx` and strips the fields BIP 174
+/// says a finalizer must remove, keeping the UTXO data for later
+/// verification.
+pub fn finalize_input(
+    psbt: &mut Psbt,
+    idx: usize,
+    selection: &SignatureSelection,
+) -> Result<(), CoordinatorError> {
+    let script_sig = nested_script_sig(&psbt.inputs[idx])?;
+    let witness = if psbt.inputs[idx].tap_scripts.is_empty() {
+        multisig_witness(psbt, idx, selection)?
+    } else {
+        multi_a_witness(psbt, idx, selection)?
+    };
+    let input = &mut psbt.inputs[idx];
+    input.final_script_witness = Some(witness);
+    input.final_script_sig = script_sig;
+    input.partial_sigs.clear();
+    input.sighash_type = None;
+    input.redeem_script = None;
+    input.witness_script = None;
+    input.bip32_derivation.clear();
+    input.tap_script_sigs.clear();
+    input.tap_scripts.clear();
+    input.tap_key_origins.clear();
+    input.tap_internal_key = None;
+    input.tap_merkle_root = None;
+    Ok(())
+}
+
+/// A key from an input's witness script or taproot leaf.
+#[derive(Debug, Clone)]
+pub struct CosignerKey {
+    pub pubkey: bitcoin::PublicKey,
+    pub fingerprint: Option<Fingerprint>,
+}
+
+impl std::fmt::Display for CosignerKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.fingerprint {
+            Some(fp) => write!(f, "{}", fp),
+            None => write!(f, "pubkey {}", self.pubkey),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InputReadiness {
+    pub input: usize,
+    /// Signatures needed; for a policy input, before any timelock expires.
+    pub threshold: usize,
+    pub signed: Vec<CosignerKey>,
+    pub invalid: Vec<(CosignerKey, String)>,
+    pub missing: Vec<CosignerKey>,
+    /// For a policy input, whether the miniscript satisfier can already
+    /// finalize it with the signatures and timelocks it has.
+    pub satisfiable: Option<bool>,
+}
+
+impl InputReadiness {
+    fn new(input: usize, threshold: usize) -> Self {
+        Self {
+            input,
+            threshold,
+            signed: Vec::new(),
+            invalid: Vec::new(),
+            missing: Vec::new(),
+            satisfiable: None,
+        }
+    }
+
+    /// Files `key` as signed, invalid or missing by its signature check.
+    fn add(&mut self, key: CosignerKey, result: Option<&Result<(), String>>) {
+        match result {
+            Some(Ok(())) => self.signed.push(key),
+            Some(Err(e)) => self.invalid.push((key, e.clone())),
+            None => self.missing.push(key),
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.satisfiable
+            .unwrap_or(self.signed.len() >= self.threshold)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReadinessReport {
+    pub inputs: Vec<InputReadiness>,
+    /// Weight of the final transaction, using placeholder signatures for
+    /// any that are still missing, and the costliest satisfaction for
+    /// policy inputs the satisfier cannot finalize yet.
+    pub projected_weight: Weight,
+    pub fee: Option<Amount>,
+}
+
+impl ReadinessReport {
+    pub fn is_ready(&self) -> bool {
+        self.inputs.iter().all(InputReadiness::is_ready)
+    }
+}
+
+/// Finalizes input `idx`: plain multisig and `multi_a` inputs with
+/// [`finalize_input`], miniscript policy inputs with the miniscript
+/// satisfier, which picks the cheapest branch the signatures and the
+/// transaction's timelocks allow. Single-key inputs mixed in with the
+/// wallet's (P2WPKH, taproot key path) also go to the satisfier.
+pub fn finalize_psbt_input(
+    psbt: &mut Psbt,
+    idx: usize,
+    selection: &SignatureSelection,
+) -> Result<(), CoordinatorError> {
+    let input = &psbt.inputs[idx];
+    let use_satisfier = match &input.witness_script {
+        Some(script) => parse_multisig_script(script).is_err(),
+        None => input.tap_scripts.is_empty(),
+    };
+    if !use_satisfier {
+        return finalize_input(psbt, idx, selection);
+    }
+    psbt.finalize_inp_mut(&Secp256k1::verification_only(), idx)
+        .map_err(|e| CoordinatorError::Psbt(format!("input {}: {}", idx, e)))?;
+    Ok(())
+}
+
+/// Renders a transaction like Bitcoin Core's `decoderawtransaction`.
+pub fn decoded_transaction(tx: &Transaction, network: Network) -> serde_json::Value {
+    let vin: Vec<_> = tx
+        .input
+        .iter()
+        .map(|txin| {
+            let witness: Vec<_> = txin
+                .witness
+                .iter()
+                .map(|w| w.to_lower_hex_string())
+                .collect();
+            json!({
+                "txid": txin.previous_output.txid.to_string(),
+                "vout": txin.previous_output.vout,
+                "scriptSig": {
+                    "asm": txin.script_sig.to_asm_string(),
+                    "hex": txin.script_sig.to_hex_string(),
+                },
+                "txinwitness": witness,
+                "sequence": txin.sequence.0,
+            })
+        })
+        .collect();
+    let vout: Vec<_> = tx
+        .output
+        .iter()
+        .enumerate()
+        .map(|(n, txout)| {
+            let script = &txout.script_pubkey;
+            let mut script_pubkey = json!({
+                "asm": script.to_asm_string(),
+                "hex": script.to_hex_string(),
+                "type": script_type_name(script),
+            });
+            if let Ok(address) = Address::from_script(script, network) {
+                script_pubkey["address"] = json!(address.to_string());
+            }
+            json!({
+                "value": txout.value.to_btc(),
+                "n": n,
+                "scriptPubKey": script_pubkey,
+            })
+        })
+        .collect();
+
+    json!({
+        "txid": tx.compute_txid().to_string(),
+        "hash": tx.compute_wtxid().to_string(),
+        "version": tx.version.0,
+        "size": tx.total_size(),
+        "vsize": tx.vsize(),
+        "weight": tx.weight().to_wu(),
+        "locktime": tx.lock_time.to_consensus_u32(),
+        "vin": vin,
+        "vout": vout,
+    })
+}
+
+/// Bitcoin Core's name for a scriptPubKey type.
+fn script_type_name(script: &Script) -> &'static str {
+    if script.is_p2wsh() {
+        "witness_v0_scripthash"
+    } else if script.is_p2wpkh() {
+        "witness_v0_keyhash"
+    } else if script.is_p2tr() {
+        "witness_v1_taproot"
+    } else if script.is_p2sh() {
+        "scripthash"
+    } else if script.is_p2pkh() {
+        "pubkeyhash"
+    } else if script.is_op_return() {
+        "nulldata"
+    } else {
+        "nonstandard"
+    }
+}
+
+/// How an input's share of the final weight is projected.
+enum Projected {
+    /// The witness as it would be, with placeholders for missing signatures.
+    Witness(Witness),
+    /// An upper bound on the witness, when it cannot be built yet.
+    Bound(Weight),
+}
+
+/// Works out what finalization would need without modifying the PSBT.
+///
+/// Multisig and `multi_a` inputs are ready with threshold-many valid
+/// signatures, policy inputs once the miniscript satisfier can finalize
+/// them, and single-key inputs (P2WPKH, taproot key path) with a valid
+/// signature by their key.
+pub fn check_readiness(psbt: &Psbt) -> Result<ReadinessReport, CoordinatorError> {
+    let checks = verify_partial_sigs(psbt);
+    let mut tx = psbt.unsigned_tx.clone();
+    let mut bounds = Weight::ZERO;
+    let mut inputs = Vec::new();
+
+    for (idx, input) in psbt.inputs.iter().enumerate() {
+        let (readiness, projected) = if !input.tap_scripts.is_empty() {
+            multi_a_readiness(psbt, idx)?
+        } else if let Some(script) = &input.witness_script {
+            match parse_multisig_script(script) {
+                Ok((threshold, pubkeys)) => {
+                    multisig_readiness(input, idx, &checks, script, threshold, pubkeys)
+                }
+                Err(_) => policy_readiness(psbt, idx, &checks, script)?,
+            }
+        } else if input.tap_internal_key.is_some() {
+            key_path_readiness(psbt, idx)
+        } else {
+            p2wpkh_readiness(input, idx, &checks)?
+        };
+
+        match projected {
+            Projected::Witness(witness) => tx.input[idx].witness = witness,
+            Projected::Bound(weight) => bounds += weight,
+        }
+        if let Some(script_sig) = nested_script_sig(input)? {
+            tx.input[idx].script_sig = script_sig;
+        }
+        inputs.push(readiness);
+    }
+
+    // Missing amounts leave the fee unknown; outputs worth more than the
+    // inputs are an error, not a fee of zero.
+    let fee = match psbt.fee() {
+        Ok(fee) => Some(fee),
+        Err(bitcoin::psbt::Error::MissingUtxo) => None,
+        Err(e) => return Err(e.into()),
+    };
+    Ok(ReadinessReport {
+        inputs,
+        projected_weight: tx.weight() + bounds,
+        fee,
+    })
+}
+
+/// A segwit v0 key of `input`, with its fingerprint if listed.
+fn ecdsa_cosigner(input: &Input, pubkey: bitcoin::PublicKey) -> CosignerKey {
+    CosignerKey {
+        pubkey,
+        fingerprint: input.bip32_derivation.get(&pubkey.inner).map(|(fp, _)| *fp),
+    }
+}
+
+/// A taproot key of `input`, with its fingerprint if listed. Taproot keys
+/// are x-only, so they are given an even Y.
+fn taproot_cosigner(input: &Input, key: XOnlyPublicKey) -> CosignerKey {
+    CosignerKey {
+        pubkey: bitcoin::PublicKey::new(key.public_key(Parity::Even)),
+        fingerprint: input.tap_key_origins.get(&key).map(|(_, (fp, _))| *fp),
+    }
+}
+
+fn multisig_readiness(
+    input: &Input,
+    idx: usize,
+    checks: &[SignatureCheck],
+    script: &Script,
+    threshold: usize,
+    pubkeys: Vec<bitcoin::PublicKey>,
+) -> (InputReadiness, Projected) {
+    let mut readiness = InputReadiness::new(idx, threshold);
+    for pubkey in pubkeys {
+        let check = checks.iter().find(|c| c.input == idx && c.pubkey == pubkey);
+        readiness.add(ecdsa_cosigner(input, pubkey), check.map(|c| &c.result));
+    }
+
+    let mut witness = Witness::new();
+    witness.push([]);
+    for i in 0..threshold {
+        match readiness.signed.get(i) {
+            Some(key) => witness.push(input.partial_sigs[&key.pubkey].serialize()),
+            None => witness.push([0u8; EXPECTED_SIG_LEN]),
+        }
+    }
+    witness.push(script.as_bytes());
+    (readiness, Projected::Witness(witness))
+}
+
+fn multi_a_readiness(
+    psbt: &Psbt,
+    idx: usize,
+) -> Result<(InputReadiness, Projected), CoordinatorError> {
+    let input = &psbt.inputs[idx];
+    // The leaf the finalizer spends: see multi_a_witness.
+    let (control_block, (script, version)) = input
+        .tap_scripts
+        .iter()
+        .next()
+        .ok_or_else(|| CoordinatorError::psbt("missing taproot leaf"))?;
+    let leaf_hash = TapLeafHash::from_script(script, *version);
+    let (threshold, keys) = parse_multi_a_script(script)?;
+    let checks = verify_tap_script_sigs(psbt, idx);
+
+    let mut readiness = InputReadiness::new(idx, threshold);
+    let mut signing = Vec::new();
+    for (pos, key) in keys.iter().enumerate() {
+        let check = checks
+            .iter()
+            .find(|c| c.key == *key && c.leaf_hash == leaf_hash);
+        if check.is_some_and(|c| c.result.is_ok()) && signing.len() < threshold {
+            signing.push(pos);
+        }
+        readiness.add(taproot_cosigner(input, *key), check.map(|c| &c.result));
+    }
+    let signed = signing.clone();
+    // Placeholders stand in for the signatures still missing.
+    for pos in 0..keys.len() {
+        if signing.len() >= threshold {
+            break;
+        }
+        if !signing.contains(&pos) {
+            signing.push(pos);
+        }
+    }
+
+    // Laid out like multi_a_witness: in reverse script order, with an
+    // empty element for each key that does not sign.
+    let mut witness = Witness::new();
+    for (pos, key) in keys.iter().enumerate().rev() {
+        if signed.contains(&pos) {
+            witness.push(input.tap_script_sigs[&(*key, leaf_hash)].to_vec());
+        } else if signing.contains(&pos) {
+            witness.push([0u8; TAPROOT_SIG_LEN]);
+        } else {
+            witness.push([]);
+        }
+    }
+    witness.push(script.as_bytes());
+    witness.push(control_block.serialize());
+    Ok((readiness, Projected::Witness(witness)))
+}
+
+/// A miniscript policy input is ready once the satisfier can finalize it,
+/// which after a timelock may take fewer signatures than the threshold.
+fn policy_readiness(
+    psbt: &Psbt,
+    idx: usize,
+    checks: &[SignatureCheck],
+    script: &Script,
+) -> Result<(InputReadiness, Projected), CoordinatorError> {
+    let input = &psbt.inputs[idx];
+    let (threshold, pubkeys) = parse_policy_script(script)?;
+    let mut readiness = InputReadiness::new(idx, threshold);
+    for pubkey in pubkeys {
+        let check = checks.iter().find(|c| c.input == idx && c.pubkey == pubkey);
+        readiness.add(ecdsa_cosigner(input, pubkey), check.map(|c| &c.result));
+    }
+
+    let mut trial = psbt.clone();
+    let satisfied = trial
+        .finalize_inp_mut(&Secp256k1::verification_only(), idx)
+        .is_ok();
+    readiness.satisfiable = Some(satisfied);
+    let projected = match trial.inputs[idx].final_script_witness.take() {
+        Some(witness) if satisfied => Projected::Witness(witness),
+        _ => {
+            let ms = Miniscript::<bitcoin::PublicKey, Segwitv0>::decode(script)
+                .map_err(CoordinatorError::descriptor)?;
+            let descriptor = Descriptor::new_wsh(ms).map_err(CoordinatorError::descriptor)?;
+            Projected::Bound(descriptor.max_weight_to_satisfy()?)
+        }
+    };
+    Ok((readiness, projected))
+}
+
+/// A taproot input spent by its internal key: one signature.
+fn key_path_readiness(psbt: &Psbt, idx: usize) -> (InputReadiness, Projected) {
+    let input = &psbt.inputs[idx];
+    let mut readiness = InputReadiness::new(idx, 1);
+    if let Some(key) = input.tap_internal_key {
+        let result = verify_tap_key_sig(psbt, idx);
+        readiness.add(taproot_cosigner(input, key), result.as_ref());
+    }
+    let mut witness = Witness::new();
+    match input.tap_key_sig {
+        Some(sig) if readiness.signed.len() == 1 => witness.push(sig.to_vec()),
+        _ => witness.push([0u8; TAPROOT_SIG_LEN]),
+    }
+    (readiness, Projected::Witness(witness))
+}
+
+/// A P2WPKH input: one signature by the key it pays.
+fn p2wpkh_readiness(
+    input: &Input,
+    idx: usize,
+    checks: &[SignatureCheck],
+) -> Result<(InputReadiness, Projected), CoordinatorError> {
+    let pays = |pubkey: &bitcoin::PublicKey| {
+        pubkey.wpubkey_hash().is_ok_and(|hash| {
+            input
+                .witness_utxo
+                .as_ref()
+                .is_none_or(|utxo| utxo.script_pubkey == ScriptBuf::new_p2wpkh(&hash))
+        })
+    };
+    let pubkey = input
+        .partial_sigs
+        .keys()
+        .copied()
+        .chain(
+            input
+                .bip32_derivation
+                .keys()
+                .map(|pk| bitcoin::PublicKey::new(*pk)),
+        )
+        .find(pays)
+        .ok_or_else(|| {
+            CoordinatorError::Psbt(format!(
+                "input {}: no witness script, taproot data or P2WPKH key",
+                idx
+            ))
+        })?;
+
+    let mut readiness = InputReadiness::new(idx, 1);
+    let check = checks.iter().find(|c| c.input == idx && c.pubkey == pubkey);
+    readiness.add(ecdsa_cosigner(input, pubkey), check.map(|c| &c.result));
+
+    let mut witness = Witness::new();
+    if readiness.signed.is_empty() {
+        witness.push([0u8; EXPECTED_SIG_LEN]);
+    } else {
+        witness.push(input.partial_sigs[&pubkey].serialize());
+    }
+    witness.push(pubkey.to_bytes());
+    Ok((readiness, Projected::Witness(witness)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ScriptType;
+    use crate::signing::{EcdsaSegwitV0, SchnorrTaproot, SignatureScheme, TaprootSpend};
+    use crate::testing;
+    use crate::verify::SignatureCheck;
+    use bitcoin::TxOut;
+    use bitcoin::bip32::{ChildNumber, DerivationPath};
+    use bitcoin::ecdsa::Signature as EcdsaSignature;
+    use bitcoin::hashes::Hash;
+    use bitcoin::secp256k1::{Message, SecretKey};
+    use bitcoin::sighash::SighashCache;
+
+    /// Private key of cosigner `seed` for receive address 0.
+    fn receive_key(seed: u8) -> SecretKey {
+        let secp = Secp256k1::new();
+        let receive: DerivationPath = vec![ChildNumber::from(0), ChildNumber::from(0)].into();
+        testing::account_xpriv(seed)
+            .derive_priv(&secp, &receive)
+            .unwrap()
+            .private_key
+    }
+
+    /// Adds cosigner `seed`'s signature, over the input's sighash or, if
+    /// not `valid`, over something else.
+    fn sign(psbt: &mut Psbt, seed: u8, valid: bool) {
+        let secp = Secp256k1::new();
+        let key = receive_key(seed);
+        let input = &psbt.inputs[0];
+        let sighash = SighashCache::new(&psbt.unsigned_tx)
+            .p2wsh_signature_hash(
+                0,
+                input.witness_script.as_ref().unwrap(),
+                input.witness_utxo.as_ref().unwrap().value,
+                bitcoin::EcdsaSighashType::All,
+            )
+            .unwrap();
+        let digest = if valid {
+            sighash.to_byte_array()
+        } else {
+            [seed; 32]
+        };
+        let sig = secp.sign_ecdsa(&Message::from_digest(digest), &key);
+        let pubkey = bitcoin::PublicKey::new(key.public_key(&secp));
+        psbt.inputs[0]
+            .partial_sigs
+            .insert(pubkey, EcdsaSignature::sighash_all(sig));
+    }
+
+    /// Adds cosigner `seed`'s signature for the input's `multi_a` leaf.
+    fn sign_leaf(psbt: &mut Psbt, seed: u8) {
+        let secp = Secp256k1::new();
+        let (script, version) = psbt.inputs[0].tap_scripts.values().next().unwrap();
+        let leaf_hash = TapLeafHash::from_script(script, *version);
+        let prevouts: Vec<TxOut> = psbt
+            .inputs
+            .iter()
+            .map(|input| input.witness_utxo.clone().unwrap())
+            .collect();
+        let privkey = receive_key(seed);
+        let scheme = SchnorrTaproot {
+            key: privkey.x_only_public_key(&secp).0,
+            spend: TaprootSpend::ScriptPath(vec![leaf_hash]),
+        };
+        let mut cache = SighashCache::new(&psbt.unsigned_tx);
+        let sig = scheme
+            .sign(
+                &secp,
+                &mut cache,
+                &psbt.inputs[0],
+                0,
+                &privkey,
+                Some(&prevouts),
+            )
+            .unwrap()
+            .unwrap();
+        sig.add_to(&mut psbt.inputs[0]);
+    }
+
+    #[test]
+    fn invalid_signatures_are_not_candidates() {
+        let wallet = testing::wallet(2, 3, ScriptType::Wsh);
+        let mut psbt = testing::psbt(&wallet);
+        sign(&mut psbt, 1, true);
+        sign(&mut psbt, 3, false);
+        let prefer_bad = SignatureSelection {
+            prefer: vec![wallet.xpub_origins[2].fingerprint],
+            ..SignatureSelection::default()
+        };
+        assert!(multisig_witness(&psbt, 0, &prefer_bad).is_err());
+
+        sign(&mut psbt, 2, true);
+        let witness = multisig_witness(&psbt, 0, &prefer_bad).unwrap();
+        let bad = psbt.inputs[0]
+            .partial_sigs
+            .iter()
+            .find(|(pk, _)| {
+                psbt.inputs[0].bip32_derivation[&pk.inner].0 == wallet.xpub_origins[2].fingerprint
+            })
+            .map(|(_, sig)| sig.serialize().to_vec())
+            .unwrap();
+        assert!(witness.iter().all(|element| element != bad.as_slice()));
+        assert_eq!(witness.len(), 4);
+    }
+
+    #[test]
+    fn readiness_reports_the_fee_and_refuses_overspending() {
+        let wallet = testing::wallet(2, 3, ScriptType::Wsh);
+        let mut psbt = testing::psbt(&wallet);
+        let report = check_readiness(&psbt).unwrap();
+        assert_eq!(report.fee, Some(Amount::from_sat(10_000)));
+        assert_eq!(report.inputs[0].missing.len(), 3);
+
+        psbt.unsigned_tx.output[0].value = Amount::from_sat(60_000);
+        assert!(check_readiness(&psbt).is_err());
+
+        psbt.inputs[0].witness_utxo.as_mut().unwrap().value = Amount::from_sat(60_000);
+        assert_eq!(check_readiness(&psbt).unwrap().fee, Some(Amount::ZERO));
+    }
+
+    #[test]
+    fn readiness_projects_the_weight_of_a_multi_a_spend() {
+        let wallet = testing::wallet(2, 3, ScriptType::Tr);
+        let mut psbt = testing::psbt(&wallet);
+        let report = check_readiness(&psbt).unwrap();
+        assert!(!report.is_ready());
+        assert_eq!(report.inputs[0].threshold, 2);
+        assert_eq!(report.inputs[0].missing.len(), 3);
+
+        sign_leaf(&mut psbt, 1);
+        sign_leaf(&mut psbt, 3);
+
+        let report = check_readiness(&psbt).unwrap();
+        assert!(report.is_ready());
+        assert_eq!(report.inputs[0].signed.len(), 2);
+        finalize_input(&mut psbt, 0, &SignatureSelection::default()).unwrap();
+        let tx = psbt.extract_tx_unchecked_fee_rate();
+        assert_eq!(report.projected_weight, tx.weight());
+    }
+
+    #[test]
+    fn readiness_and_verification_cover_p2wpkh_inputs() {
+        let secp = Secp256k1::new();
+        let privkey = receive_key(1);
+        let pubkey = bitcoin::PublicKey::new(privkey.public_key(&secp));
+        let mut psbt = testing::psbt(&testing::wallet(2, 3, ScriptType::Wsh));
+        let input = &mut psbt.inputs[0];
+        input.witness_script = None;
+        input.bip32_derivation.clear();
+        input.bip32_derivation.insert(
+            pubkey.inner,
+            (Fingerprint::default(), DerivationPath::master()),
+        );
+        input.witness_utxo.as_mut().unwrap().script_pubkey =
+            ScriptBuf::new_p2wpkh(&pubkey.wpubkey_hash().unwrap());
+        let report = check_readiness(&psbt).unwrap();
+        assert!(!report.is_ready());
+        assert_eq!(report.inputs[0].missing.len(), 1);
+
+        let scheme = EcdsaSegwitV0 {
+            pubkey: pubkey.inner,
+        };
+        let mut cache = SighashCache::new(&psbt.unsigned_tx);
+        let sig = scheme
+            .sign(&secp, &mut cache, &psbt.inputs[0], 0, &privkey, None)
+            .unwrap()
+            .unwrap();
+        sig.add_to(&mut psbt.inputs[0]);
+        let checks = verify_partial_sigs(&psbt);
+        assert_eq!(checks.len(), 1);
+        assert!(checks.iter().all(SignatureCheck::is_valid));
+
+        let report = check_readiness(&psbt).unwrap();
+        assert!(report.is_ready());
+        finalize_psbt_input(&mut psbt, 0, &SignatureSelection::default()).unwrap();
+        let tx = psbt.extract_tx_unchecked_fee_rate();
+        assert_eq!(report.projected_weight, tx.weight());
+    }
+
+    #[test]
+    fn multi_a_witness_lists_signatures_in_reverse_key_order() {
+        let wallet = testing::wallet(2, 3, ScriptType::Tr);
+        let mut psbt = testing::psbt(&wallet);
+        sign_leaf(&mut psbt, 1);
+        sign_leaf(&mut psbt, 3);
+        let (control_block, (script, version)) = psbt.inputs[0]
+            .tap_scripts
+            .iter()
+            .next()
+            .map(|(cb, leaf)| (cb.serialize(), leaf.clone()))
+            .unwrap();
+        let leaf_hash = TapLeafHash::from_script(&script, version);
+        let sig = |seed: u8| {
+            let key = receive_key(seed).x_only_public_key(&Secp256k1::new()).0;
+            psbt.inputs[0].tap_script_sigs[&(key, leaf_hash)].to_vec()
+        };
+        let expected = [sig(3), Vec::new(), sig(1), script.to_bytes(), control_block];
+
+        finalize_input(&mut psbt, 0, &SignatureSelection::default()).unwrap();
+        let witness = psbt.inputs[0].final_script_witness.as_ref().unwrap();
+        assert_eq!(witness.to_vec(), expected);
+        assert!(psbt.inputs[0].final_script_sig.is_none());
+        assert!(psbt.inputs[0].tap_scripts.is_empty());
+    }
+
+    #[test]
+    fn nested_segwit_inputs_push_the_redeem_script() {
+        let wallet = testing::wallet(2, 3, ScriptType::ShWsh);
+        let mut psbt = testing::psbt(&wallet);
+        let witness_script = wallet.witness_script(0, false).unwrap();
+        let redeem_script = ScriptBuf::new_p2wsh(&witness_script.wscript_hash());
+        assert_eq!(psbt.inputs[0].redeem_script.as_ref(), Some(&redeem_script));
+        assert_eq!(
+            psbt.inputs[0].witness_utxo.as_ref().unwrap().script_pubkey,
+            ScriptBuf::new_p2sh(&redeem_script.script_hash())
+        );
+        sign(&mut psbt, 1, true);
+        sign(&mut psbt, 3, true);
+
+        finalize_input(&mut psbt, 0, &SignatureSelection::default()).unwrap();
+        let tx = psbt.extract_tx_unchecked_fee_rate();
+        // scriptSig: one push of the 34-byte `OP_0 <script hash>` program.
+        let mut script_sig = vec![34];
+        script_sig.extend_from_slice(redeem_script.as_bytes());
+        assert_eq!(tx.input[0].script_sig.as_bytes(), script_sig);
+        // Witness: the empty CHECKMULTISIG dummy, two signatures, the script.
+        let witness = tx.input[0].witness.to_vec();
+        assert_eq!(witness.len(), 4);
+        assert!(witness[0].is_empty());
+        assert_eq!(witness[3], witness_script.to_bytes());
+    }
+}
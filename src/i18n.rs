@@ -0,0 +1,323 @@
+//! Localized text for the signer's review screen.
+//!
+//! Messages live in Fluent files under `locales/<lang>/`, compiled in, with
+//! English as the fallback for any message a translation lacks. The
+//! language comes from `--lang` or, failing that, `LC_ALL`,
+//! `LC_MESSAGES` or `LANG`.
+//!
+//! Only the surrounding words are translated. Amounts keep this tool's
+//! own format (`0.5 BTC`, never `0,5 BTC`), so the figure a cosigner
+//! reads matches their hardware wallet's screen whatever their locale.
+//! Amounts, addresses and other identifiers are passed as [`Arg::Ltr`]:
+//! under a right-to-left language they are wrapped in a left-to-right
+//! isolate, so surrounding text cannot reorder their characters. Text
+//! from files ([`Arg::Text`]), such as wallet labels, has bidirectional
+//! control characters removed first, so it cannot reorder the rest of
+//! the line either.
+//!
+//! Built without the `i18n` feature, only English is available. It is
+//! rendered from the same file by a small formatter that knows just the
+//! placeables and plural selectors the English messages use.
+
+use crate::CoordinatorError;
+#[cfg(feature = "i18n")]
+use fluent_bundle::{FluentArgs, FluentBundle, FluentResource, FluentValue};
+#[cfg(feature = "i18n")]
+use unic_langid::{CharacterDirection, LanguageIdentifier};
+
+/// Languages with a translation, as accepted by [`Localizer::new`].
+#[cfg(feature = "i18n")]
+pub const LANGUAGES: &[&str] = &["en", "es"];
+#[cfg(not(feature = "i18n"))]
+pub const LANGUAGES: &[&str] = &["en"];
+
+const FALLBACK: &str = "en";
+
+fn resource(lang: &str) -> Option<&'static str> {
+    match lang {
+        "en" => Some(include_str!("../locales/en/signer.ftl")),
+        "es" => Some(include_str!("../locales/es/signer.ftl")),
+        _ => None,
+    }
+}
+
+/// A message argument.
+pub enum Arg {
+    /// A number that can select a plural form.
+    Count(u64),
+    /// Free text from a file; bidirectional controls are removed.
+    Text(String),
+    /// An amount, address, path or ID that must read left to right.
+    Ltr(String),
+}
+
+pub struct Localizer {
+    lang: String,
+    rtl: bool,
+    #[cfg(feature = "i18n")]
+    bundle: FluentBundle<FluentResource>,
+    #[cfg(feature = "i18n")]
+    fallback: Option<FluentBundle<FluentResource>>,
+}
+
+impl Localizer {
+    /// Loads the translation for `lang`, e.g. `es` or `es-MX`.
+    #[cfg(feature = "i18n")]
+    pub fn new(lang: &str) -> Result<Self, CoordinatorError> {
+        let langid: LanguageIdentifier = lang
+            .parse()
+            .map_err(|e| CoordinatorError::Invalid(format!("language {}: {}", lang, e)))?;
+        let primary = langid.language.as_str().to_string();
+        if resource(&primary).is_none() {
+            return Err(CoordinatorError::Invalid(format!(
+                "no translation for {} (available: {})",
+                lang,
+                LANGUAGES.join(", ")
+            )));
+        }
+        let fallback = if primary == FALLBACK {
+            None
+        } else {
+            Some(bundle(FALLBACK)?)
+        };
+        Ok(Self {
+            rtl: langid.character_direction() == CharacterDirection::RTL,
+            bundle: bundle(&primary)?,
+            lang: primary,
+            fallback,
+        })
+    }
+
+    /// Accepts only English, e.g. `en` or `en-GB`, the one language
+    /// compiled in without the `i18n` feature.
+    #[cfg(not(feature = "i18n"))]
+    pub fn new(lang: &str) -> Result<Self, CoordinatorError> {
+        let primary = lang.split(['-', '_']).next().unwrap_or_default();
+        if !primary.eq_ignore_ascii_case(FALLBACK) {
+            return Err(CoordinatorError::Invalid(format!(
+                "no translation for {} (built without the i18n feature; available: {})",
+                lang,
+                LANGUAGES.join(", ")
+            )));
+        }
+        Ok(Self {
+            lang: FALLBACK.to_string(),
+            rtl: false,
+        })
+    }
+
+    /// The language named by `LC_ALL`, `LC_MESSAGES` or `LANG`, e.g.
+    /// `es_ES.UTF-8`, or English if it has no translation.
+    pub fn from_env() -> Result<Self, CoordinatorError> {
+        let from_env = ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| {
+                let lang = value.split(['.', '@']).next().unwrap_or_default();
+                Self::new(&lang.replace('_', "-")).ok()
+            });
+        match from_env {
+            Some(localizer) => Ok(localizer),
+            None => Self::new(FALLBACK),
+        }
+    }
+
+    pub fn language(&self) -> &str {
+        &self.lang
+    }
+
+    /// Formats message `id`. A message missing from both the translation
+    /// and English comes out as its ID, so a typo shows rather than
+    /// silently dropping a line.
+    #[cfg(feature = "i18n")]
+    pub fn tr(&self, id: &str, args: &[(&str, Arg)]) -> String {
+        let mut fluent_args = FluentArgs::new();
+        for (name, arg) in args {
+            let value = match arg {
+                Arg::Count(n) => FluentValue::from(*n),
+                Arg::Text(text) => FluentValue::from(strip_bidi_controls(text)),
+                Arg::Ltr(text) => FluentValue::from(self.ltr(text)),
+            };
+            fluent_args.set(*name, value);
+        }
+        std::iter::once(&self.bundle)
+            .chain(&self.fallback)
+            .find_map(|bundle| {
+                let pattern = bundle.get_message(id)?.value()?;
+                let mut errors = Vec::new();
+                Some(
+                    bundle
+                        .format_pattern(pattern, Some(&fluent_args), &mut errors)
+                        .into_owned(),
+                )
+            })
+            .unwrap_or_else(|| id.to_string())
+    }
+
+    /// Formats English message `id`, or returns the ID if there is none.
+    #[cfg(not(feature = "i18n"))]
+    pub fn tr(&self, id: &str, args: &[(&str, Arg)]) -> String {
+        match english_message(id) {
+            Some(pattern) => self.format(&pattern, args),
+            None => id.to_string(),
+        }
+    }
+
+    /// Substitutes each placeable of `pattern`; a selector's chosen
+    /// variant is formatted in turn.
+    #[cfg(not(feature = "i18n"))]
+    fn format(&self, pattern: &str, args: &[(&str, Arg)]) -> String {
+        let mut out = String::new();
+        let mut rest = pattern;
+        while let Some(start) = rest.find('{') {
+            let mut depth = 0;
+            let end = rest[start..].char_indices().find_map(|(i, c)| {
+                match c {
+                    '{' => depth += 1,
+                    '}' if depth == 1 => return Some(start + i),
+                    '}' => depth -= 1,
+                    _ => {}
+                }
+                None
+            });
+            let Some(end) = end else { break };
+            out.push_str(&rest[..start]);
+            out.push_str(&self.placeable(rest[start + 1..end].trim(), args));
+            rest = &rest[end + 1..];
+        }
+        out.push_str(rest);
+        out
+    }
+
+    /// A `$name` reference, or a selector on it with `[one]` and
+    /// `*[other]` variants, one per line.
+    #[cfg(not(feature = "i18n"))]
+    fn placeable(&self, expr: &str, args: &[(&str, Arg)]) -> String {
+        let (name, variants) = match expr.split_once("->") {
+            Some((name, variants)) => (name.trim(), Some(variants)),
+            None => (expr, None),
+        };
+        let name = name.trim_start_matches('$');
+        let arg = args.iter().find(|(n, _)| *n == name).map(|(_, arg)| arg);
+        let Some(variants) = variants else {
+            return match arg {
+                Some(Arg::Count(n)) => n.to_string(),
+                Some(Arg::Text(text)) => strip_bidi_controls(text),
+                Some(Arg::Ltr(text)) => self.ltr(text),
+                None => format!("{{${}}}", name),
+            };
+        };
+        let key = match arg {
+            Some(Arg::Count(1)) => "one",
+            _ => "other",
+        };
+        let mut default = "";
+        for line in variants.lines().map(str::trim) {
+            let is_default = line.starts_with('*');
+            let Some((variant, value)) = line
+                .trim_start_matches('*')
+                .strip_prefix('[')
+                .and_then(|line| line.split_once(']'))
+            else {
+                continue;
+            };
+            if variant.trim() == key {
+                return self.format(value.trim(), args);
+            }
+            if is_default {
+                default = value.trim();
+            }
+        }
+        self.format(default, args)
+    }
+
+    fn ltr(&self, text: &str) -> String {
+        let text = strip_bidi_controls(text);
+        if self.rtl {
+            format!("\u{2066}{}\u{2069}", text)
+        } else {
+            text
+        }
+    }
+}
+
+/// The pattern of message `id` in the English file: the text after `=`
+/// and any indented continuation lines.
+#[cfg(not(feature = "i18n"))]
+fn english_message(id: &str) -> Option<String> {
+    let mut lines = resource(FALLBACK)?.lines();
+    let first = lines.find_map(|line| line.strip_prefix(id)?.trim_start().strip_prefix('='))?;
+    let mut pattern = first.trim().to_string();
+    for line in lines.take_while(|line| line.starts_with(' ')) {
+        pattern.push('\n');
+        pattern.push_str(line.trim());
+    }
+    Some(pattern)
+}
+
+#[cfg(feature = "i18n")]
+fn bundle(lang: &str) -> Result<FluentBundle<FluentResource>, CoordinatorError> {
+    let source = resource(lang)
+        .ok_or_else(|| CoordinatorError::Invalid(format!("no translation for {}", lang)))?;
+    let resource = FluentResource::try_new(source.to_string())
+        .map_err(|(_, errors)| CoordinatorError::Invalid(format!("{}: {:?}", lang, errors)))?;
+    let langid: LanguageIdentifier = lang.parse().map_err(CoordinatorError::invalid)?;
+    let mut bundle = FluentBundle::new(vec![langid]);
+    // Isolation is applied per argument instead, and only where needed:
+    // Fluent's marks around every placeable show up as stray characters
+    // in many terminals.
+    bundle.set_use_isolating(false);
+    bundle
+        .add_resource(resource)
+        .map_err(|errors| CoordinatorError::Invalid(format!("{}: {:?}", lang, errors)))?;
+    Ok(bundle)
+}
+
+/// Removes the Unicode bidirectional embedding, override, isolate and
+/// mark characters, which could otherwise make text display in a
+/// different order than it is stored.
+pub fn strip_bidi_controls(text: &str) -> String {
+    text.chars()
+        .filter(|c| {
+            !matches!(
+                c,
+                '\u{061C}' | '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}'
+            )
+        })
+        .collect()
+}
+
+#[cfg(all(test, not(feature = "i18n")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn english_fallback_selects_plurals() {
+        let l10n = Localizer::new("en-GB").unwrap();
+        let summary = |inputs| {
+            l10n.tr(
+                "sign-summary",
+                &[
+                    ("inputs", Arg::Count(inputs)),
+                    ("signatures", Arg::Ltr("2".into())),
+                    ("threshold", Arg::Ltr("3".into())),
+                ],
+            )
+        };
+        assert_eq!(summary(1), "Signed 1 input, signatures: 2/3");
+        assert_eq!(summary(4), "Signed 4 inputs, signatures: 2/3");
+        assert_eq!(
+            l10n.tr(
+                "review-output-label",
+                &[
+                    ("output", Arg::Ltr("0".into())),
+                    ("label", Arg::Text("Rent\u{202E}".into()))
+                ]
+            ),
+            "Output 0 is labelled \"Rent\""
+        );
+        assert_eq!(l10n.tr("no-such-message", &[]), "no-such-message");
+        assert!(Localizer::new("es").is_err());
+    }
+}
@@ -1,12 +1,300 @@
-//! Shared types for 2-of-3 multisig PSBT coordinator.
+//! Shared types for M-of-N multisig PSBT coordinator.
 
-use bitcoin::bip32::{DerivationPath, Fingerprint, Xpub};
-use bitcoin::secp256k1::Secp256k1;
-use bitcoin::{Address, Network, ScriptBuf};
-use miniscript::descriptor::{Descriptor, DescriptorPublicKey};
+pub mod address_format;
+pub mod armor;
+pub mod attestation;
+pub mod auxiliary;
+pub mod batch;
+pub mod bundle;
+pub mod caravan;
+#[cfg(feature = "chain")]
+pub mod chain_source;
+#[cfg(feature = "cli")]
+pub mod cli;
+#[cfg(feature = "chain")]
+pub mod core_rpc;
+pub mod cosigner_file;
+pub mod destinations;
+#[cfg(feature = "chain")]
+pub mod electrum;
+pub mod entropy;
+pub mod error;
+pub mod escalation;
+#[cfg(feature = "chain")]
+pub mod esplora;
+pub mod export;
+pub mod fee_history;
+pub mod finalize;
+pub mod i18n;
+pub mod payment_proof;
+pub mod provenance;
+pub mod receipt;
+pub mod schema;
+#[cfg(feature = "cli")]
+pub mod shutdown;
+pub mod signer_policy;
+#[cfg(feature = "migration")]
+pub mod signer_state;
+pub mod signing;
+pub mod slip132;
+pub mod training;
+pub mod utxo_store;
+pub mod verify;
+pub mod wallet_file;
+
+use attestation::{AttestationStatus, TrustedDevices, XpubAttestation};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use bitcoin::bip32::{ChildNumber, DerivationPath, Fingerprint, Xpub};
+use bitcoin::hashes::{Hash, sha256};
+use bitcoin::opcodes::all::{OP_CHECKMULTISIG, OP_CHECKSIG, OP_CHECKSIGADD, OP_NUMEQUAL};
+use bitcoin::psbt::raw::ProprietaryKey;
+use bitcoin::psbt::{Input, Output, Psbt};
+use bitcoin::script::{Builder, Instruction};
+use bitcoin::secp256k1::{Secp256k1, Verification, VerifyOnly, XOnlyPublicKey};
+use bitcoin::taproot::{LeafVersion, TapLeafHash, TapTree, TaprootBuilder, TaprootSpendInfo};
+use bitcoin::{
+    Address, Amount, Denomination, Network, NetworkKind, Script, ScriptBuf, VarInt, Weight,
+    Witness, relative,
+};
+pub use error::CoordinatorError;
+use miniscript::descriptor::{
+    Descriptor, DescriptorPublicKey, ShInner, Tr, Wildcard, WshInner, checksum,
+};
+use miniscript::policy::{Concrete, Liftable};
+use miniscript::{Miniscript, Segwitv0, Terminal};
+use schema::SCHEMA_VERSION;
 use serde::{Deserialize, Serialize};
+use std::ops::Range;
 use std::str::FromStr;
 
+/// Prefix identifying this tool's PSBT proprietary fields (BIP 174 `0xFC`).
+pub const PROPRIETARY_PREFIX: &[u8] = b"psbtcoord";
+/// Global proprietary subtype carrying the wallet policy ID.
+pub const PROPRIETARY_POLICY_ID: u8 = 0x00;
+/// Global proprietary subtype marking a PSBT made in training mode.
+pub const PROPRIETARY_TRAINING: u8 = 0x01;
+/// Global proprietary subtype carrying the signature request's [`Expiry`].
+pub const PROPRIETARY_EXPIRY: u8 = 0x02;
+/// Global proprietary subtype carrying when the coordinator created the
+/// PSBT, as unix time.
+pub const PROPRIETARY_CREATED_AT: u8 = 0x03;
+/// Global proprietary subtype naming the network the PSBT was made for.
+pub const PROPRIETARY_NETWORK: u8 = 0x04;
+/// Output proprietary subtype recording the `descriptor:index` an output's
+/// script was derived from.
+pub const PROPRIETARY_OUTPUT_DESCRIPTOR: u8 = 0x00;
+/// Output proprietary subtype carrying a payout's label, such as an
+/// invoice number from a batch file.
+pub const PROPRIETARY_OUTPUT_LABEL: u8 = 0x01;
+
+/// Longest output label, in characters.
+pub const MAX_OUTPUT_LABEL_CHARS: usize = 100;
+
+/// Unit used when displaying amounts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AmountUnit {
+    Btc,
+    #[default]
+    Sat,
+}
+
+impl FromStr for AmountUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "btc" => Ok(AmountUnit::Btc),
+            "sat" | "sats" => Ok(AmountUnit::Sat),
+            other => Err(format!(
+                "unknown denomination: {} (expected btc or sat)",
+                other
+            )),
+        }
+    }
+}
+
+/// Parses an amount such as `1.5btc`, `150000sat` or a bare satoshi count.
+///
+/// Only `.` is accepted as the decimal separator, whatever the locale, and
+/// conversion is exact: amounts with sub-satoshi precision are rejected.
+pub fn parse_amount(s: &str) -> Result<Amount, CoordinatorError> {
+    let lower = s.trim().to_ascii_lowercase();
+    if lower.contains(',') {
+        return Err(CoordinatorError::Invalid(format!(
+            "invalid amount {:?}: use '.' as the decimal separator",
+            s
+        )));
+    }
+    let (number, denom) = if let Some(n) = lower.strip_suffix("btc") {
+        (n, Denomination::Bitcoin)
+    } else if let Some(n) = lower
+        .strip_suffix("sats")
+        .or_else(|| lower.strip_suffix("sat"))
+    {
+        (n, Denomination::Satoshi)
+    } else {
+        (lower.as_str(), Denomination::Satoshi)
+    };
+    Amount::from_str_in(number.trim(), denom)
+        .map_err(|e| CoordinatorError::Invalid(format!("invalid amount {:?}: {}", s, e)))
+}
+
+/// Formats an amount using integer arithmetic only.
+pub fn format_amount(amount: Amount, unit: AmountUnit) -> String {
+    let sat = amount.to_sat();
+    match unit {
+        AmountUnit::Btc => format!("{}.{:08} BTC", sat / 100_000_000, sat % 100_000_000),
+        AmountUnit::Sat => format!("{} sat", sat),
+    }
+}
+
+/// Splits `total` into the fewest near-equal parts that are each at most `cap`.
+///
+/// Used for destinations that reject single deposits above a size limit.
+pub fn split_amount(total: Amount, cap: Amount) -> Result<Vec<Amount>, CoordinatorError> {
+    if cap == Amount::ZERO {
+        return Err(CoordinatorError::invalid(
+            "split cap must be greater than zero",
+        ));
+    }
+    let total = total.to_sat();
+    let parts = total.div_ceil(cap.to_sat()).max(1);
+    let base = total / parts;
+    let remainder = total % parts;
+    Ok((0..parts)
+        .map(|i| Amount::from_sat(base + u64::from(i < remainder)))
+        .collect())
+}
+
+/// How an output's amount is determined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputShare {
+    Fixed(Amount),
+    /// Share of the amount being distributed, in basis points (1/100 %).
+    Percent(u32),
+    /// Whatever the other outputs leave, absorbing rounding.
+    Remainder,
+}
+
+impl FromStr for OutputShare {
+    type Err = CoordinatorError;
+
+    /// Parses `rest`, a percentage such as `70%` or `33.33%`, or an amount.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("rest") || s.eq_ignore_ascii_case("remainder") {
+            return Ok(OutputShare::Remainder);
+        }
+        let Some(pct) = s.strip_suffix('%') else {
+            return Ok(OutputShare::Fixed(parse_amount(s)?));
+        };
+        let (whole, frac) = pct.split_once('.').unwrap_or((pct, ""));
+        if frac.len() > 2 {
+            return Err(CoordinatorError::Invalid(format!(
+                "percentage {} has more than two decimals",
+                s
+            )));
+        }
+        let invalid = |e: std::num::ParseIntError| {
+            CoordinatorError::Invalid(format!("invalid percentage {}: {}", s, e))
+        };
+        let bps = u64::from(whole.parse::<u32>().map_err(invalid)?) * 100
+            + format!("{:0<2}", frac).parse::<u64>().map_err(invalid)?;
+        if bps == 0 || bps > 10_000 {
+            return Err(CoordinatorError::Invalid(format!(
+                "percentage {} must be above 0% and at most 100%",
+                s
+            )));
+        }
+        Ok(OutputShare::Percent(bps as u32))
+    }
+}
+
+/// Turns output shares into amounts that add up to exactly `available`
+/// (the spent total minus the fee).
+///
+/// Percentages are of `available` and round down; the rounding goes to the
+/// single `Remainder` output. Without one, the shares must add up exactly.
+pub fn allocate_shares(
+    available: Amount,
+    shares: &[OutputShare],
+) -> Result<Vec<Amount>, CoordinatorError> {
+    let remainders = shares
+        .iter()
+        .filter(|s| **s == OutputShare::Remainder)
+        .count();
+    if remainders > 1 {
+        return Err(CoordinatorError::invalid(
+            "only one output can take the remainder",
+        ));
+    }
+
+    let mut amounts = Vec::with_capacity(shares.len());
+    let mut allocated = Amount::ZERO;
+    for share in shares {
+        let amount = match share {
+            OutputShare::Fixed(amount) => *amount,
+            OutputShare::Percent(bps) => Amount::from_sat(
+                (u128::from(available.to_sat()) * u128::from(*bps) / 10_000) as u64,
+            ),
+            OutputShare::Remainder => Amount::ZERO,
+        };
+        allocated = allocated
+            .checked_add(amount)
+            .ok_or_else(|| CoordinatorError::invalid("output amounts overflow"))?;
+        amounts.push(amount);
+    }
+
+    let left = available.checked_sub(allocated).ok_or_else(|| {
+        CoordinatorError::Invalid(format!(
+            "outputs need {} sat but only {} sat is available after the fee",
+            allocated.to_sat(),
+            available.to_sat()
+        ))
+    })?;
+    match shares.iter().position(|s| *s == OutputShare::Remainder) {
+        Some(_) if left == Amount::ZERO => {
+            Err(CoordinatorError::invalid("remainder output would be empty"))
+        }
+        Some(pos) => {
+            amounts[pos] = left;
+            Ok(amounts)
+        }
+        None if left == Amount::ZERO => Ok(amounts),
+        None => Err(CoordinatorError::Invalid(format!(
+            "outputs leave {} sat unallocated; add a remainder output",
+            left.to_sat()
+        ))),
+    }
+}
+
+/// Who holds a cosigner key and how to reach them, so prompts and reports
+/// can name people and devices rather than fingerprints.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct CosignerMetadata {
+    /// Person or team holding the key, e.g. `Alice`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contact: Option<String>,
+    /// Signing device, e.g. `Coldcard`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    /// How PSBTs reach the cosigner, e.g. `sd-card`, `qr`, `usb`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transport: Option<String>,
+}
+
+impl CosignerMetadata {
+    pub fn is_empty(&self) -> bool {
+        self.display_name.is_none()
+            && self.contact.is_none()
+            && self.device.is_none()
+            && self.transport.is_none()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct KeyData {
     pub name: String,
@@ -14,124 +302,2485 @@ pub struct KeyData {
     pub xpub: String,
     pub fingerprint: String,
     pub derivation_path: String,
+    /// Network the key was generated for, e.g. `signet`; absent in files
+    /// from before it was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attestation: Option<XpubAttestation>,
+    #[serde(default, skip_serializing_if = "CosignerMetadata::is_empty")]
+    pub metadata: CosignerMetadata,
+}
+
+/// The public half of a key file: what a coordinator needs, without the
+/// `xprv`. Full key files read as this too.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublicKeyData {
+    pub name: String,
+    pub xpub: String,
+    /// Master fingerprint; absent for devices that do not export one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+    #[serde(default)]
+    pub derivation_path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attestation: Option<XpubAttestation>,
+    #[serde(default, skip_serializing_if = "CosignerMetadata::is_empty")]
+    pub metadata: CosignerMetadata,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct XpubOrigin {
+    /// Cosigner name from its key file, e.g. `key_a`.
+    pub name: String,
     pub xpub: Xpub,
     pub fingerprint: Fingerprint,
     pub derivation_path: DerivationPath,
+    pub attestation: AttestationStatus,
+    pub metadata: CosignerMetadata,
+}
+
+impl XpubOrigin {
+    /// Signs that the recorded master fingerprint is not the master key's,
+    /// which an xpub cannot prove either way: a fingerprint equal to the
+    /// xpub's own or its parent's, below depth 1.
+    pub fn origin_warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.derivation_path.is_empty() {
+            return warnings;
+        }
+        if self.fingerprint == self.xpub.fingerprint() {
+            warnings.push(format!(
+                "fingerprint {} is the xpub's own, not its master key's",
+                self.fingerprint
+            ));
+        } else if self.xpub.depth > 1 && self.fingerprint == self.xpub.parent_fingerprint {
+            warnings.push(format!(
+                "fingerprint {} is the xpub's parent's, not its master key's",
+                self.fingerprint
+            ));
+        }
+        warnings
+    }
+
+    /// Human-facing name, e.g. `Alice's Coldcard`, falling back to the key
+    /// file name when no metadata is configured.
+    pub fn display_name(&self) -> String {
+        match (&self.metadata.display_name, &self.metadata.device) {
+            (Some(name), Some(device)) => format!("{}'s {}", name, device),
+            (Some(name), None) => name.clone(),
+            (None, Some(device)) => format!("{} ({})", self.name, device),
+            (None, None) => self.name.clone(),
+        }
+    }
+}
+
+/// Output script type of a multisig wallet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScriptType {
+    /// `wsh(sortedmulti(...))`: P2WSH with `OP_CHECKMULTISIG`.
+    #[default]
+    Wsh,
+    /// `sh(wsh(sortedmulti(...)))`: the same P2WSH script wrapped in P2SH,
+    /// for services that only pay to legacy `3...`/`2...` addresses.
+    ShWsh,
+    /// `tr(NUMS, multi_a(...))`: P2TR with an unspendable internal key and
+    /// a single `OP_CHECKSIGADD` leaf, so every spend is a script path spend.
+    Tr,
+}
+
+impl FromStr for ScriptType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "wsh" | "p2wsh" => Ok(ScriptType::Wsh),
+            "sh-wsh" | "p2sh-p2wsh" | "nested" => Ok(ScriptType::ShWsh),
+            "tr" | "p2tr" | "taproot" => Ok(ScriptType::Tr),
+            other => Err(format!(
+                "unknown script type: {} (expected wsh, sh-wsh or tr)",
+                other
+            )),
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
+/// BIP 341's provably unspendable point H, used as the internal key of
+/// taproot multisig outputs so the key path cannot be used.
+pub const NUMS_INTERNAL_KEY: &str =
+    "50929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0";
+
+/// Length of the placeholder signatures in fee estimates: a DER signature
+/// and its sighash byte take 71 to 73 bytes, and 72 is the common case for
+/// signers that do not grind low-R signatures.
+pub const EXPECTED_SIG_LEN: usize = 72;
+
+/// Serializes as a [`WalletState`], so a wallet can be stored or sent to
+/// another process and read back with its cosigner names and metadata.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(into = "WalletState", try_from = "WalletState")]
 pub struct MultisigWallet {
     pub descriptor: Descriptor<DescriptorPublicKey>,
     pub network: Network,
     pub threshold: usize,
+    pub script_type: ScriptType,
+    pub xpub_origins: Vec<XpubOrigin>,
+}
+
+/// Serialized form of a [`MultisigWallet`]: the descriptor as a string with
+/// its checksum, and the fields derived from it, which are checked against
+/// it when read back.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletState {
+    pub schema_version: u32,
+    pub descriptor: String,
+    pub network: Network,
+    pub threshold: usize,
+    pub script_type: ScriptType,
     pub xpub_origins: Vec<XpubOrigin>,
 }
 
+impl From<MultisigWallet> for WalletState {
+    fn from(wallet: MultisigWallet) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            descriptor: wallet.descriptor_with_checksum(),
+            network: wallet.network,
+            threshold: wallet.threshold,
+            script_type: wallet.script_type,
+            xpub_origins: wallet.xpub_origins,
+        }
+    }
+}
+
+impl TryFrom<WalletState> for MultisigWallet {
+    type Error = CoordinatorError;
+
+    fn try_from(state: WalletState) -> Result<Self, Self::Error> {
+        if state.schema_version > SCHEMA_VERSION {
+            return Err(CoordinatorError::Invalid(format!(
+                "wallet has schema version {}, this build reads up to {}",
+                state.schema_version, SCHEMA_VERSION
+            )));
+        }
+        // Parsing checks the `#checksum`.
+        let descriptor = Descriptor::<DescriptorPublicKey>::from_str(&state.descriptor)
+            .map_err(CoordinatorError::descriptor)?;
+        let wallet = match &descriptor {
+            Descriptor::Wsh(wsh) if matches!(wsh.as_inner(), WshInner::Ms(ms) if !is_plain_multi(ms)) => {
+                Self::policy_from_descriptor(
+                    descriptor.clone(),
+                    &state.xpub_origins,
+                    state.network,
+                )?
+            }
+            _ => Self::from_descriptor(&state.descriptor, state.network)?,
+        };
+        if wallet.descriptor.to_string() != descriptor.to_string() {
+            return Err(CoordinatorError::descriptor(
+                "descriptor is not in the form wallets are written in",
+            ));
+        }
+        if wallet.script_type != state.script_type {
+            return Err(CoordinatorError::Descriptor(format!(
+                "script type {:?} does not match the descriptor ({:?})",
+                state.script_type, wallet.script_type
+            )));
+        }
+        if wallet.threshold != state.threshold {
+            return Err(CoordinatorError::Descriptor(format!(
+                "threshold {} does not match the descriptor ({})",
+                state.threshold, wallet.threshold
+            )));
+        }
+        if wallet.xpub_origins.len() != state.xpub_origins.len() {
+            return Err(CoordinatorError::Descriptor(format!(
+                "{} cosigners listed, the descriptor has {}",
+                state.xpub_origins.len(),
+                wallet.xpub_origins.len()
+            )));
+        }
+        for (i, (ours, theirs)) in wallet
+            .xpub_origins
+            .iter()
+            .zip(&state.xpub_origins)
+            .enumerate()
+        {
+            if ours.fingerprint != theirs.fingerprint
+                || ours.derivation_path != theirs.derivation_path
+                || ours.xpub != theirs.xpub
+            {
+                return Err(CoordinatorError::Descriptor(format!(
+                    "cosigner {} ({}) is not key {} of the descriptor",
+                    theirs.name,
+                    theirs.fingerprint,
+                    i + 1
+                )));
+            }
+        }
+        // Names, attestations and metadata are not in the descriptor.
+        Ok(Self {
+            xpub_origins: state.xpub_origins,
+            ..wallet
+        })
+    }
+}
+
 impl MultisigWallet {
+    /// Builds a `threshold`-of-N wallet, N being the number of key files.
     pub fn from_key_files(
         key_paths: &[&str],
+        threshold: usize,
+        script_type: ScriptType,
         network: Network,
-    ) -> Result<Self, Box<dyn std::error::Error>> {
-        if key_paths.len() != 5 {
-            return Err("expected 5 key files".into());
-        }
+    ) -> Result<Self, CoordinatorError> {
+        check_threshold(threshold, key_paths.len())?;
+        Self::from_origins(
+            load_key_files(key_paths, network)?,
+            threshold,
+            script_type,
+            network,
+        )
+    }
 
+    /// Builds a watch-only wallet from `(fingerprint, path, xpub)` triples,
+    /// for a coordinator that never sees key files or private keys.
+    /// Cosigners are named `cosigner_1`, `cosigner_2`, ... in the order given.
+    pub fn from_xpubs(
+        keys: &[(Fingerprint, DerivationPath, Xpub)],
+        threshold: usize,
+        script_type: ScriptType,
+        network: Network,
+    ) -> Result<Self, CoordinatorError> {
+        check_threshold(threshold, keys.len())?;
+        let xpub_origins = keys
+            .iter()
+            .enumerate()
+            .map(|(i, (fingerprint, derivation_path, xpub))| XpubOrigin {
+                name: format!("cosigner_{}", i + 1),
+                xpub: *xpub,
+                fingerprint: *fingerprint,
+                derivation_path: derivation_path.clone(),
+                attestation: AttestationStatus::Unattested,
+                metadata: CosignerMetadata::default(),
+            })
+            .collect();
+        Self::from_origins(xpub_origins, threshold, script_type, network)
+    }
+
+    fn from_origins(
+        xpub_origins: Vec<XpubOrigin>,
+        threshold: usize,
+        script_type: ScriptType,
+        network: Network,
+    ) -> Result<Self, CoordinatorError> {
+        check_key_networks(&xpub_origins, network)?;
+        let key_exprs: Vec<_> = xpub_origins
+            .iter()
+            .map(|o| key_expression(o.fingerprint, &o.derivation_path, &o.xpub))
+            .collect();
+        Ok(Self {
+            descriptor: multisig_descriptor(script_type, threshold, &key_exprs)?,
+            network,
+            threshold,
+            script_type,
+            xpub_origins,
+        })
+    }
+
+    /// Builds a P2WSH wallet from a miniscript policy that names keys by
+    /// their key file `name`, e.g. a 2-of-3 that falls back to a recovery
+    /// key after about a year:
+    ///
+    /// `or(99@thresh(2,pk(key_a),pk(key_b),pk(key_c)),and(pk(key_d),older(52560)))`
+    ///
+    /// The policy is compiled to the cheapest miniscript for its weights.
+    /// Key files the policy does not mention are left out of the wallet.
+    /// The returned wallet's `threshold` field holds the number of keys
+    /// needed before any timelock expires.
+    pub fn from_policy(
+        policy: &str,
+        key_paths: &[&str],
+        network: Network,
+    ) -> Result<Self, CoordinatorError> {
+        let origins = load_key_files(key_paths, network)?;
         let mut xpub_origins = Vec::new();
-        let mut descriptor_parts = Vec::new();
+        let mut expanded = String::new();
+        let mut rest = policy;
+        // Swap each key name for its descriptor key expression.
+        while let Some(start) = rest.find(|c: char| c.is_ascii_alphanumeric() || c == '_') {
+            let (before, tail) = rest.split_at(start);
+            let len = tail
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(tail.len());
+            let (word, after) = tail.split_at(len);
+            expanded.push_str(before);
+            match origins.iter().position(|o| o.name == word) {
+                Some(i) => {
+                    let o = &origins[i];
+                    expanded.push_str(&key_expression(o.fingerprint, &o.derivation_path, &o.xpub));
+                    if !xpub_origins.iter().any(|o: &XpubOrigin| o.name == word) {
+                        xpub_origins.push(origins[i].clone());
+                    }
+                }
+                None => expanded.push_str(word),
+            }
+            rest = after;
+        }
+        expanded.push_str(rest);
+        check_threshold(1, xpub_origins.len())?;
+        check_key_networks(&xpub_origins, network)?;
+
+        let compiled = Concrete::<DescriptorPublicKey>::from_str(&expanded)
+            .map_err(CoordinatorError::descriptor)?
+            .compile::<Segwitv0>()
+            .map_err(CoordinatorError::descriptor)?;
+        let descriptor = Descriptor::new_wsh(compiled).map_err(CoordinatorError::descriptor)?;
+        let threshold = descriptor
+            .lift()?
+            .at_age(relative::LockTime::ZERO)
+            .minimum_n_keys()
+            .ok_or_else(|| {
+                CoordinatorError::descriptor("policy cannot be spent before a timelock expires")
+            })?;
+
+        Ok(Self {
+            descriptor,
+            network,
+            threshold,
+            script_type: ScriptType::Wsh,
+            xpub_origins,
+        })
+    }
+
+    /// Reads back a wallet made by [`MultisigWallet::from_policy`]. The
+    /// compiler may reorder keys, so `xpub_origins` are kept in their own
+    /// order, but must be exactly the descriptor's keys.
+    fn policy_from_descriptor(
+        descriptor: Descriptor<DescriptorPublicKey>,
+        xpub_origins: &[XpubOrigin],
+        network: Network,
+    ) -> Result<Self, CoordinatorError> {
+        let mut keys: Vec<String> = Vec::new();
+        for key in descriptor.iter_pk() {
+            let key = key.to_string();
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+        let origin_keys: Vec<String> = xpub_origins
+            .iter()
+            .map(|o| key_expression(o.fingerprint, &o.derivation_path, &o.xpub))
+            .collect();
+        if let Some(i) = origin_keys.iter().position(|key| !keys.contains(key)) {
+            return Err(CoordinatorError::Descriptor(format!(
+                "cosigner {} ({}) is not in the descriptor",
+                xpub_origins[i].name, xpub_origins[i].fingerprint
+            )));
+        }
+        if keys.iter().any(|key| !origin_keys.contains(key)) || origin_keys.len() != keys.len() {
+            return Err(CoordinatorError::descriptor(
+                "cosigners do not match the descriptor's keys one for one",
+            ));
+        }
+        let xpub_origins = xpub_origins.to_vec();
+        check_key_networks(&xpub_origins, network)?;
+        let threshold = descriptor
+            .lift()?
+            .at_age(relative::LockTime::ZERO)
+            .minimum_n_keys()
+            .ok_or_else(|| {
+                CoordinatorError::descriptor("policy cannot be spent before a timelock expires")
+            })?;
+        Ok(Self {
+            descriptor,
+            network,
+            threshold,
+            script_type: ScriptType::Wsh,
+            xpub_origins,
+        })
+    }
+
+    /// Whether the wallet's script is a compiled miniscript policy rather
+    /// than a plain multisig.
+    pub fn is_policy(&self) -> bool {
+        match &self.descriptor {
+            Descriptor::Wsh(wsh) => {
+                matches!(wsh.as_inner(), WshInner::Ms(ms) if !is_plain_multi(ms))
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the script sorts its keys (`sortedmulti`, BIP 67). Unsorted
+    /// `multi()` wallets, and taproot `multi_a` ones, keep the cosigners'
+    /// order, so the key order is part of the wallet definition.
+    pub fn is_sorted(&self) -> bool {
+        let wsh = match &self.descriptor {
+            Descriptor::Wsh(wsh) => wsh,
+            Descriptor::Sh(sh) => match sh.as_inner() {
+                ShInner::Wsh(wsh) => wsh,
+                _ => return false,
+            },
+            _ => return false,
+        };
+        matches!(wsh.as_inner(), WshInner::SortedMulti(_))
+    }
+
+    /// Switches a `wsh` or `sh(wsh)` multisig to an unsorted `multi()`
+    /// script with the keys in cosigner order, for migrating wallets that
+    /// were created that way. The result has different addresses from the
+    /// sorted wallet.
+    pub fn preserve_key_order(mut self) -> Result<Self, CoordinatorError> {
+        if self.is_policy() || self.script_type == ScriptType::Tr {
+            return Err(CoordinatorError::descriptor(
+                "only wsh and sh(wsh) multisig wallets can be unsorted",
+            ));
+        }
+        let key_exprs: Vec<_> = self
+            .xpub_origins
+            .iter()
+            .map(|o| key_expression(o.fingerprint, &o.derivation_path, &o.xpub))
+            .collect();
+        self.descriptor =
+            unsorted_multisig_descriptor(self.script_type, self.threshold, &key_exprs)?;
+        Ok(self)
+    }
+
+    /// Rebuilds a wallet from a `wsh(sortedmulti(...))` or
+    /// `sh(wsh(sortedmulti(...)))` descriptor, such as one exported by a
+    /// Bitcoin Core watch-only wallet. `multi(...)` in place of
+    /// `sortedmulti(...)` is kept unsorted, in descriptor key order, as is
+    /// a taproot `tr(NUMS,multi_a(...))`.
+    ///
+    /// Each key must be an xpub, ending in `/<0;1>/*` or,
+    /// as in Core's receive descriptor, `/0/*`. Any fixed steps before the
+    /// chain step are folded into the origin, and the wallet is rebuilt as
+    /// a multipath descriptor so receive and change use chains 0 and 1, as
+    /// for key-file wallets. Cosigners are named `cosigner_1`,
+    /// `cosigner_2`, ... in descriptor order.
+    ///
+    /// A `#checksum` suffix is optional but, if given, must match.
+    pub fn from_descriptor(descriptor: &str, network: Network) -> Result<Self, CoordinatorError> {
+        if let Some((body, given)) = descriptor.trim().split_once('#') {
+            let mut engine = checksum::Engine::new();
+            engine.input(body).map_err(|_| {
+                CoordinatorError::descriptor("descriptor contains characters outside BIP 380")
+            })?;
+            let expected = engine.checksum();
+            if given != expected {
+                return Err(CoordinatorError::Descriptor(format!(
+                    "descriptor checksum mismatch: expected #{}, got #{}",
+                    expected, given
+                )));
+            }
+        }
+        let descriptor = Descriptor::<DescriptorPublicKey>::from_str(descriptor)
+            .map_err(CoordinatorError::descriptor)?;
+        let (wsh, script_type) = match &descriptor {
+            Descriptor::Wsh(wsh) => (wsh, ScriptType::Wsh),
+            Descriptor::Sh(sh) => match sh.as_inner() {
+                ShInner::Wsh(wsh) => (wsh, ScriptType::ShWsh),
+                _ => return Err(CoordinatorError::descriptor("expected sh(wsh(...))")),
+            },
+            Descriptor::Tr(tr) => return Self::from_tr_descriptor(tr, network),
+            _ => {
+                return Err(CoordinatorError::descriptor(
+                    "expected a wsh(), sh(wsh()) or tr() descriptor",
+                ));
+            }
+        };
+        let (threshold, pks, sorted) = match wsh.as_inner() {
+            WshInner::SortedMulti(multi) => (multi.k(), multi.pks().to_vec(), true),
+            WshInner::Ms(ms) => {
+                let Terminal::Multi(thresh) = &ms.node else {
+                    return Err(CoordinatorError::descriptor(
+                        "expected wsh(sortedmulti(...)) or wsh(multi(...))",
+                    ));
+                };
+                (thresh.k(), thresh.data().to_vec(), false)
+            }
+        };
+        Self::from_descriptor_keys(&pks, threshold, script_type, sorted, network)
+    }
 
-        for path in key_paths {
-            let data: KeyData = serde_json::from_str(&std::fs::read_to_string(path)?)?;
-            let xpub = Xpub::from_str(&data.xpub)?;
-            let fingerprint = Fingerprint::from_str(&data.fingerprint)?;
-            let derivation_path = DerivationPath::from_str(&data.derivation_path)?;
+    /// Rebuilds a taproot wallet from `tr(NUMS,multi_a(...))`, as
+    /// [`MultisigWallet::from_descriptor`] does for `wsh`.
+    fn from_tr_descriptor(
+        tr: &Tr<DescriptorPublicKey>,
+        network: Network,
+    ) -> Result<Self, CoordinatorError> {
+        if tr.internal_key().to_string() != NUMS_INTERNAL_KEY {
+            return Err(CoordinatorError::descriptor(
+                "taproot internal key must be the unspendable NUMS point",
+            ));
+        }
+        let mut leaves = tr.leaves();
+        let (Some(leaf), None) = (leaves.next(), leaves.next()) else {
+            return Err(CoordinatorError::descriptor(
+                "expected tr() with a single multi_a() leaf",
+            ));
+        };
+        let Terminal::MultiA(thresh) = &leaf.miniscript().node else {
+            return Err(CoordinatorError::descriptor(
+                "expected tr() with a single multi_a() leaf",
+            ));
+        };
+        Self::from_descriptor_keys(thresh.data(), thresh.k(), ScriptType::Tr, false, network)
+    }
 
+    /// The wallet of `threshold` of descriptor keys `pks`, each an xpub
+    /// ending in `/0/*` or `/<0;1>/*`.
+    fn from_descriptor_keys(
+        pks: &[DescriptorPublicKey],
+        threshold: usize,
+        script_type: ScriptType,
+        sorted: bool,
+        network: Network,
+    ) -> Result<Self, CoordinatorError> {
+        let secp = Secp256k1::verification_only();
+        let receive = ChildNumber::Normal { index: 0 };
+        let change = ChildNumber::Normal { index: 1 };
+        let mut xpub_origins = Vec::new();
+        let mut key_exprs = Vec::new();
+        for (i, key) in pks.iter().enumerate() {
+            let (origin, xkey, account_steps) = match key {
+                DescriptorPublicKey::XPub(xkey) if xkey.wildcard == Wildcard::Unhardened => {
+                    match xkey.derivation_path.as_ref().split_last() {
+                        Some((last, steps)) if *last == receive => {
+                            (&xkey.origin, &xkey.xkey, steps.to_vec())
+                        }
+                        _ => {
+                            return Err(CoordinatorError::Descriptor(format!(
+                                "key {} must end in /0/*",
+                                i + 1
+                            )));
+                        }
+                    }
+                }
+                DescriptorPublicKey::MultiXPub(xkey) if xkey.wildcard == Wildcard::Unhardened => {
+                    match xkey.derivation_paths.paths().as_slice() {
+                        [r, c] => match (r.as_ref().split_last(), c.as_ref().split_last()) {
+                            (Some((rl, rs)), Some((cl, cs)))
+                                if *rl == receive && *cl == change && rs == cs =>
+                            {
+                                (&xkey.origin, &xkey.xkey, rs.to_vec())
+                            }
+                            _ => {
+                                return Err(CoordinatorError::Descriptor(format!(
+                                    "key {} must end in /<0;1>/*",
+                                    i + 1
+                                )));
+                            }
+                        },
+                        _ => {
+                            return Err(CoordinatorError::Descriptor(format!(
+                                "key {} must end in /<0;1>/*",
+                                i + 1
+                            )));
+                        }
+                    }
+                }
+                _ => {
+                    return Err(CoordinatorError::Descriptor(format!(
+                        "key {} must be an xpub ending in an unhardened /*",
+                        i + 1
+                    )));
+                }
+            };
+            // Without origin info the xpub is its own origin, as descriptors
+            // define it: its own fingerprint and an empty path.
+            let (fingerprint, origin_path) = origin
+                .clone()
+                .unwrap_or_else(|| (xkey.fingerprint(), DerivationPath::master()));
+            let account_steps = DerivationPath::from(account_steps);
+            let xpub = xkey.derive_pub(&secp, &account_steps)?;
+            let derivation_path = origin_path.extend(&account_steps);
+            key_exprs.push(key_expression(fingerprint, &derivation_path, &xpub));
             xpub_origins.push(XpubOrigin {
+                name: format!("cosigner_{}", i + 1),
                 xpub,
                 fingerprint,
                 derivation_path,
+                attestation: AttestationStatus::Unattested,
+                metadata: CosignerMetadata::default(),
             });
-
-            let path_suffix = data
-                .derivation_path
-                .strip_prefix("m/")
-                .unwrap_or(&data.derivation_path);
-            descriptor_parts.push(format!(
-                "[{}/{}]{}/*",
-                data.fingerprint, path_suffix, data.xpub
-            ));
         }
+        check_key_networks(&xpub_origins, network)?;
 
-        let descriptor_str = format!(
-            "wsh(sortedmulti(3,{},{},{},{},{}))",
-            descriptor_parts[0],
-            descriptor_parts[1],
-            descriptor_parts[2],
-            descriptor_parts[3],
-            descriptor_parts[4]
-        );
-        let descriptor = Descriptor::<DescriptorPublicKey>::from_str(&descriptor_str)?;
-
+        let descriptor = if sorted {
+            multisig_descriptor(script_type, threshold, &key_exprs)?
+        } else {
+            unsorted_multisig_descriptor(script_type, threshold, &key_exprs)?
+        };
         Ok(Self {
+            threshold,
             descriptor,
             network,
-            threshold: 3,
+            script_type,
             xpub_origins,
         })
     }
 
-    pub fn derive_address(&self, index: u32) -> Result<Address, Box<dyn std::error::Error>> {
-        let derived = self.descriptor.at_derivation_index(index)?;
+    /// The descriptor with its BIP 380 `#checksum`, as Bitcoin Core and
+    /// Sparrow expect on import.
+    pub fn descriptor_with_checksum(&self) -> String {
+        // miniscript appends the checksum unless formatted with `{:#}`.
+        self.descriptor.to_string()
+    }
+
+    /// Stable identifier for this wallet definition.
+    ///
+    /// SHA256 of the descriptor string without its checksum, so every
+    /// artifact (PSBTs, exports) can be tied to exactly one wallet.
+    pub fn policy_id(&self) -> sha256::Hash {
+        let descriptor = self.descriptor.to_string();
+        let canonical = descriptor.split('#').next().unwrap_or(&descriptor);
+        sha256::Hash::hash(canonical.as_bytes())
+    }
+
+    /// Names a cosigner by its master fingerprint, e.g. `key_a (f00dbabe)` or
+    /// `Alice's Coldcard (f00dbabe)`,
+    /// falling back to the bare fingerprint for keys outside this wallet.
+    pub fn cosigner_label(&self, fingerprint: Fingerprint) -> String {
+        match self
+            .xpub_origins
+            .iter()
+            .find(|o| o.fingerprint == fingerprint)
+        {
+            Some(origin) => format!("{} ({})", origin.display_name(), fingerprint),
+            None => fingerprint.to_string(),
+        }
+    }
+
+    /// Address `index` on the receive chain, or the change chain when
+    /// `is_change` is set.
+    pub fn derive_address(&self, index: u32, is_change: bool) -> Result<Address, CoordinatorError> {
+        let derived = self
+            .chain_descriptor(is_change)?
+            .at_derivation_index(index)
+            .map_err(CoordinatorError::descriptor)?;
         let script_pubkey = derived.script_pubkey();
-        Ok(Address::from_script(&script_pubkey, self.network)?)
+        Address::from_script(&script_pubkey, self.network).map_err(CoordinatorError::descriptor)
     }
 
-    pub fn witness_script(&self, index: u32) -> Result<ScriptBuf, Box<dyn std::error::Error>> {
-        let derived = self.descriptor.at_derivation_index(index)?;
-        if let Descriptor::Wsh(wsh) = derived {
-            Ok(wsh.inner_script())
-        } else {
-            Err("expected WSH descriptor".into())
+    /// Upper bound on the weight a signed input adds to an unsigned one:
+    /// script sig and witness, taking the costliest spending path.
+    pub fn max_satisfaction_weight(&self) -> Result<Weight, CoordinatorError> {
+        Ok(self.descriptor.max_weight_to_satisfy()?)
+    }
+
+    /// Weight a signed input is expected to add to an unsigned one, so a
+    /// fee lands on the asked-for rate instead of above it: the script sig
+    /// and a witness of `threshold` placeholder signatures of
+    /// [`EXPECTED_SIG_LEN`] bytes and the real witness script. Taproot and
+    /// policy wallets fall back to [`Self::max_satisfaction_weight`].
+    pub fn expected_satisfaction_weight(&self) -> Result<Weight, CoordinatorError> {
+        if self.is_policy() || self.script_type == ScriptType::Tr {
+            return self.max_satisfaction_weight();
+        }
+        // Every address has a script of the same size.
+        let witness_script = self.witness_script(0, false)?;
+        let mut witness = Witness::new();
+        // OP_CHECKMULTISIG pops one element too many.
+        witness.push([]);
+        for _ in 0..self.threshold {
+            witness.push([0u8; EXPECTED_SIG_LEN]);
+        }
+        witness.push(witness_script.as_bytes());
+        // Like miniscript's bound, this leaves out the element count.
+        let witness_bytes: usize = witness
+            .iter()
+            .map(|element| VarInt::from(element.len()).size() + element.len())
+            .sum();
+        let script_sig_bytes = match self.script_type {
+            // One push of the 34-byte P2WSH script.
+            ScriptType::ShWsh => 1 + witness_script.to_p2wsh().len(),
+            ScriptType::Wsh | ScriptType::Tr => 0,
+        };
+        Ok(Weight::from_witness_data_size(witness_bytes as u64)
+            + Weight::from_non_witness_data_size(script_sig_bytes as u64))
+    }
+
+    /// The single-path descriptor for the receive or change chain.
+    pub fn chain_descriptor(
+        &self,
+        is_change: bool,
+    ) -> Result<Descriptor<DescriptorPublicKey>, CoordinatorError> {
+        self.descriptor
+            .clone()
+            .into_single_descriptors()?
+            .into_iter()
+            .nth(usize::from(is_change))
+            .ok_or_else(|| CoordinatorError::descriptor("descriptor has no change chain"))
+    }
+
+    /// The script an address commits to: the witness script for P2WSH
+    /// (wrapped or not), the `multi_a` leaf for P2TR.
+    pub fn witness_script(
+        &self,
+        index: u32,
+        is_change: bool,
+    ) -> Result<ScriptBuf, CoordinatorError> {
+        if self.is_policy() {
+            return Ok(self
+                .chain_descriptor(is_change)?
+                .at_derivation_index(index)
+                .map_err(CoordinatorError::descriptor)?
+                .explicit_script()?);
+        }
+        let mut keys = self.child_keys(index, is_change)?;
+        Ok(match self.script_type {
+            ScriptType::Wsh | ScriptType::ShWsh if !self.is_sorted() => {
+                multi_script(self.threshold, &keys)
+            }
+            ScriptType::Wsh | ScriptType::ShWsh => sortedmulti_script(self.threshold, &mut keys),
+            ScriptType::Tr => multi_a_script(self.threshold, &keys),
+        })
+    }
+
+    /// Each cosigner's public key at `index` on a chain, in key-file order.
+    fn child_keys(
+        &self,
+        index: u32,
+        is_change: bool,
+    ) -> Result<Vec<bitcoin::secp256k1::PublicKey>, CoordinatorError> {
+        let secp = Secp256k1::verification_only();
+        let sub_path = chain_path(index, is_change)?;
+        let mut keys = Vec::with_capacity(self.xpub_origins.len());
+        for origin in &self.xpub_origins {
+            keys.push(origin.xpub.derive_pub(&secp, &sub_path)?.public_key);
+        }
+        Ok(keys)
+    }
+
+    /// Taproot tree for a `tr` wallet at `index`: the NUMS internal key with
+    /// the `multi_a` leaf as its only script.
+    pub fn taproot_spend_info(
+        &self,
+        index: u32,
+        is_change: bool,
+    ) -> Result<(ScriptBuf, TaprootSpendInfo), CoordinatorError> {
+        if self.script_type != ScriptType::Tr {
+            return Err(CoordinatorError::descriptor("not a taproot wallet"));
+        }
+        let leaf = self.witness_script(index, is_change)?;
+        let spend_info = nums_spend_info(&Secp256k1::verification_only(), leaf.clone())?;
+        Ok((leaf, spend_info))
+    }
+
+    /// Fills in what signers and finalizers need to spend this wallet's
+    /// output at `index`: the witness script and BIP 32 derivations for
+    /// P2WSH (plus the redeem script when nested in P2SH), or the taproot
+    /// tree and key origins for P2TR.
+    pub fn update_psbt_input(
+        &self,
+        input: &mut Input,
+        index: u32,
+        is_change: bool,
+    ) -> Result<(), CoordinatorError> {
+        let sub_path = chain_path(index, is_change)?;
+        match self.script_type {
+            ScriptType::Wsh | ScriptType::ShWsh => {
+                let script = self.witness_script(index, is_change)?;
+                if self.script_type == ScriptType::ShWsh {
+                    input.redeem_script = Some(ScriptBuf::new_p2wsh(&script.wscript_hash()));
+                }
+                input.witness_script = Some(script);
+                for origin in &self.xpub_origins {
+                    let pubkey = self.derive_pubkey_at_path(origin, &sub_path)?;
+                    let full_path = origin.derivation_path.extend(&sub_path);
+                    input
+                        .bip32_derivation
+                        .insert(pubkey, (origin.fingerprint, full_path));
+                }
+            }
+            ScriptType::Tr => {
+                let (leaf, spend_info) = self.taproot_spend_info(index, is_change)?;
+                let leaf_hash = TapLeafHash::from_script(&leaf, LeafVersion::TapScript);
+                let control_block = spend_info
+                    .control_block(&(leaf.clone(), LeafVersion::TapScript))
+                    .ok_or_else(|| {
+                        CoordinatorError::descriptor("leaf missing from taproot tree")
+                    })?;
+                input.tap_internal_key = Some(spend_info.internal_key());
+                input.tap_merkle_root = spend_info.merkle_root();
+                input
+                    .tap_scripts
+                    .insert(control_block, (leaf, LeafVersion::TapScript));
+                for origin in &self.xpub_origins {
+                    let pubkey = self.derive_pubkey_at_path(origin, &sub_path)?;
+                    let full_path = origin.derivation_path.extend(&sub_path);
+                    input.tap_key_origins.insert(
+                        pubkey.x_only_public_key().0,
+                        (vec![leaf_hash], (origin.fingerprint, full_path)),
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Marks a transaction output as this wallet's, paid to `index`, so
+    /// signers can check a change output really comes back to the wallet:
+    /// the witness script and BIP 32 derivations for P2WSH, or the taproot
+    /// tree and key origins for P2TR.
+    pub fn update_psbt_output(
+        &self,
+        output: &mut Output,
+        index: u32,
+        is_change: bool,
+    ) -> Result<(), CoordinatorError> {
+        let sub_path = chain_path(index, is_change)?;
+        match self.script_type {
+            ScriptType::Wsh | ScriptType::ShWsh => {
+                let script = self.witness_script(index, is_change)?;
+                if self.script_type == ScriptType::ShWsh {
+                    output.redeem_script = Some(ScriptBuf::new_p2wsh(&script.wscript_hash()));
+                }
+                output.witness_script = Some(script);
+                for origin in &self.xpub_origins {
+                    let pubkey = self.derive_pubkey_at_path(origin, &sub_path)?;
+                    let full_path = origin.derivation_path.extend(&sub_path);
+                    output
+                        .bip32_derivation
+                        .insert(pubkey, (origin.fingerprint, full_path));
+                }
+            }
+            ScriptType::Tr => {
+                let (leaf, spend_info) = self.taproot_spend_info(index, is_change)?;
+                let leaf_hash = TapLeafHash::from_script(&leaf, LeafVersion::TapScript);
+                output.tap_internal_key = Some(spend_info.internal_key());
+                let builder = TaprootBuilder::new()
+                    .add_leaf(0, leaf)
+                    .map_err(CoordinatorError::descriptor)?;
+                output.tap_tree = Some(
+                    TapTree::try_from(builder)
+                        .map_err(|_| CoordinatorError::descriptor("incomplete taproot tree"))?,
+                );
+                for origin in &self.xpub_origins {
+                    let pubkey = self.derive_pubkey_at_path(origin, &sub_path)?;
+                    let full_path = origin.derivation_path.extend(&sub_path);
+                    output.tap_key_origins.insert(
+                        pubkey.x_only_public_key().0,
+                        (vec![leaf_hash], (origin.fingerprint, full_path)),
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Derives addresses and witness scripts for a range of indexes on the
+    /// receive or change chain.
+    ///
+    /// Going through the descriptor re-walks every key expression for each
+    /// index. For scans this builds the sortedmulti script directly from
+    /// public derivations below each cosigner's xpub instead.
+    pub fn derive_addresses(
+        &self,
+        range: Range<u32>,
+        is_change: bool,
+    ) -> Result<Vec<(u32, Address, ScriptBuf)>, CoordinatorError> {
+        let deriver = ChainDeriver::new(self, is_change)?;
+        range
+            .map(|index| {
+                let (address, script) = deriver.derive(index)?;
+                Ok((index, address, script))
+            })
+            .collect()
+    }
+
+    /// Lazily derives addresses for a range of indexes, for pre-generating
+    /// hundreds of deposit addresses without holding them all.
+    ///
+    /// Each cosigner's chain xpub and the secp context are set up once, so
+    /// every address costs one public derivation per cosigner.
+    pub fn iter_addresses(
+        &self,
+        range: Range<u32>,
+        is_change: bool,
+    ) -> Result<impl Iterator<Item = Result<(u32, Address), CoordinatorError>>, CoordinatorError>
+    {
+        let deriver = ChainDeriver::new(self, is_change)?;
+        Ok(range.map(move |index| Ok((index, deriver.derive(index)?.0))))
+    }
+
+    /// Like [`MultisigWallet::iter_addresses`], yielding each index's witness
+    /// script (the `multi_a` leaf for taproot) instead of its address.
+    pub fn iter_scripts(
+        &self,
+        range: Range<u32>,
+        is_change: bool,
+    ) -> Result<impl Iterator<Item = Result<(u32, ScriptBuf), CoordinatorError>>, CoordinatorError>
+    {
+        let deriver = ChainDeriver::new(self, is_change)?;
+        Ok(range.map(move |index| Ok((index, deriver.derive(index)?.1))))
+    }
+
+    /// Finds the address paying to `script_pubkey` among the first
+    /// `lookahead` indexes of the receive chain, then the change chain.
+    /// Returns its index and whether it is on the change chain.
+    pub fn is_mine(
+        &self,
+        script_pubkey: &Script,
+        lookahead: u32,
+    ) -> Result<Option<(u32, bool)>, CoordinatorError> {
+        for is_change in [false, true] {
+            for derived in self.iter_addresses(0..lookahead, is_change)? {
+                let (index, address) = derived?;
+                if address.script_pubkey().as_script() == script_pubkey {
+                    return Ok(Some((index, is_change)));
+                }
+            }
         }
+        Ok(None)
     }
 
     pub fn derive_child_pubkey(
         &self,
         origin: &XpubOrigin,
         index: u32,
-    ) -> Result<bitcoin::secp256k1::PublicKey, Box<dyn std::error::Error>> {
-        let secp = Secp256k1::new();
-        let child_path = DerivationPath::from_str(&format!("m/{}", index))?;
-        let child_xpub = origin.xpub.derive_pub(&secp, &child_path)?;
+        is_change: bool,
+    ) -> Result<bitcoin::secp256k1::PublicKey, CoordinatorError> {
+        self.derive_pubkey_at_path(origin, &chain_path(index, is_change)?)
+    }
+
+    /// Derives a cosigner's public key at an unhardened sub-path (e.g. `0/5`)
+    /// below its xpub.
+    pub fn derive_pubkey_at_path(
+        &self,
+        origin: &XpubOrigin,
+        sub_path: &DerivationPath,
+    ) -> Result<bitcoin::secp256k1::PublicKey, CoordinatorError> {
+        if sub_path.into_iter().any(ChildNumber::is_hardened) {
+            return Err(CoordinatorError::Derivation(format!(
+                "cannot derive hardened path {} from xpub {}",
+                sub_path, origin.fingerprint
+            )));
+        }
+        let secp = Secp256k1::verification_only();
+        let child_xpub = origin.xpub.derive_pub(&secp, sub_path)?;
         Ok(child_xpub.public_key)
     }
 }
 
+/// Derivation state shared across indexes of one chain: each cosigner's
+/// xpub one step down, at `<account>/<chain>`, and a single secp context.
+struct ChainDeriver<'a> {
+    wallet: &'a MultisigWallet,
+    is_change: bool,
+    secp: Secp256k1<VerifyOnly>,
+    chain_xpubs: Vec<Xpub>,
+}
+
+impl<'a> ChainDeriver<'a> {
+    fn new(wallet: &'a MultisigWallet, is_change: bool) -> Result<Self, CoordinatorError> {
+        let secp = Secp256k1::verification_only();
+        let chain = [ChildNumber::from_normal_idx(u32::from(is_change))?];
+        let chain_xpubs = wallet
+            .xpub_origins
+            .iter()
+            .map(|o| o.xpub.derive_pub(&secp, &chain))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            wallet,
+            is_change,
+            secp,
+            chain_xpubs,
+        })
+    }
+
+    /// Address and witness script (or tapscript leaf) at `index`.
+    fn derive(&self, index: u32) -> Result<(Address, ScriptBuf), CoordinatorError> {
+        let network = self.wallet.network;
+        if self.wallet.is_policy() {
+            let script = self.wallet.witness_script(index, self.is_change)?;
+            return Ok((Address::p2wsh(&script, network), script));
+        }
+        let child = [ChildNumber::from_normal_idx(index)?];
+        let mut keys = Vec::with_capacity(self.chain_xpubs.len());
+        for xpub in &self.chain_xpubs {
+            keys.push(xpub.derive_pub(&self.secp, &child)?.public_key);
+        }
+        let threshold = self.wallet.threshold;
+        let multisig = |keys: &mut [bitcoin::secp256k1::PublicKey]| {
+            if self.wallet.is_sorted() {
+                sortedmulti_script(threshold, keys)
+            } else {
+                multi_script(threshold, keys)
+            }
+        };
+        Ok(match self.wallet.script_type {
+            ScriptType::Wsh => {
+                let script = multisig(&mut keys);
+                (Address::p2wsh(&script, network), script)
+            }
+            ScriptType::ShWsh => {
+                let script = multisig(&mut keys);
+                (Address::p2shwsh(&script, network), script)
+            }
+            ScriptType::Tr => {
+                let leaf = multi_a_script(threshold, &keys);
+                let spend_info = nums_spend_info(&self.secp, leaf.clone())?;
+                (
+                    Address::p2tr_tweaked(spend_info.output_key(), network),
+                    leaf,
+                )
+            }
+        })
+    }
+}
+
+/// Taproot tree with the NUMS internal key and `leaf` as its only script.
+fn nums_spend_info<C: Verification>(
+    secp: &Secp256k1<C>,
+    leaf: ScriptBuf,
+) -> Result<TaprootSpendInfo, CoordinatorError> {
+    let internal_key =
+        XOnlyPublicKey::from_str(NUMS_INTERNAL_KEY).map_err(CoordinatorError::key)?;
+    TaprootBuilder::new()
+        .add_leaf(0, leaf)
+        .map_err(CoordinatorError::descriptor)?
+        .finalize(secp, internal_key)
+        .map_err(|_| CoordinatorError::descriptor("incomplete taproot tree"))
+}
+
+/// The wallet descriptor for a multisig over `[fp/path]xpub/<0;1>/*` key
+/// expressions. Taproot leaves take keys in the order given, so for `tr`
+/// the key order is part of the wallet definition.
+fn multisig_descriptor(
+    script_type: ScriptType,
+    threshold: usize,
+    key_exprs: &[String],
+) -> Result<Descriptor<DescriptorPublicKey>, CoordinatorError> {
+    let keys = key_exprs.join(",");
+    let descriptor = match script_type {
+        ScriptType::Wsh => format!("wsh(sortedmulti({},{}))", threshold, keys),
+        ScriptType::ShWsh => format!("sh(wsh(sortedmulti({},{})))", threshold, keys),
+        ScriptType::Tr => format!("tr({},multi_a({},{}))", NUMS_INTERNAL_KEY, threshold, keys),
+    };
+    Descriptor::<DescriptorPublicKey>::from_str(&descriptor).map_err(CoordinatorError::descriptor)
+}
+
+/// Like [`multisig_descriptor`] for `wsh` and `sh(wsh)`, with `multi()`
+/// instead of `sortedmulti()`, so the script keeps the keys' order.
+fn unsorted_multisig_descriptor(
+    script_type: ScriptType,
+    threshold: usize,
+    key_exprs: &[String],
+) -> Result<Descriptor<DescriptorPublicKey>, CoordinatorError> {
+    let keys = key_exprs.join(",");
+    let descriptor = match script_type {
+        ScriptType::Wsh => format!("wsh(multi({},{}))", threshold, keys),
+        ScriptType::ShWsh => format!("sh(wsh(multi({},{})))", threshold, keys),
+        ScriptType::Tr => return multisig_descriptor(script_type, threshold, key_exprs),
+    };
+    Descriptor::<DescriptorPublicKey>::from_str(&descriptor).map_err(CoordinatorError::descriptor)
+}
+
+/// Whether a miniscript is a bare `multi()`, as opposed to a policy.
+fn is_plain_multi(ms: &Miniscript<DescriptorPublicKey, Segwitv0>) -> bool {
+    matches!(ms.node, Terminal::Multi(_))
+}
+
+/// `[fp/path]xpub/<0;1>/*`: receive addresses on chain 0, change on 1.
+/// Origins of any depth work, down to `[fp]` for a master xpub.
+pub fn key_expression(fingerprint: Fingerprint, path: &DerivationPath, xpub: &Xpub) -> String {
+    if path.is_empty() {
+        format!("[{}]{}/<0;1>/*", fingerprint, xpub)
+    } else {
+        format!("[{}/{}]{}/<0;1>/*", fingerprint, path, xpub)
+    }
+}
+
+/// BIP 48 P2WSH account path, `m/48'/coin'/account'/2'`: coin type 0
+/// on mainnet, 1 elsewhere. One master key can back a separate wallet at
+/// each account.
+pub fn bip48_path(network: Network, account: u32) -> Result<DerivationPath, CoordinatorError> {
+    let coin = match network {
+        Network::Bitcoin => 0,
+        _ => 1,
+    };
+    Ok(DerivationPath::from(vec![
+        ChildNumber::from_hardened_idx(48)?,
+        ChildNumber::from_hardened_idx(coin)?,
+        ChildNumber::from_hardened_idx(account)?,
+        ChildNumber::from_hardened_idx(2)?,
+    ]))
+}
+
+/// Network names accepted by [`parse_network`].
+pub const NETWORK_NAMES: &str = "mainnet|testnet|signet|testnet4|regtest";
+
+/// Parses a network name: `mainnet` (or `bitcoin`), `testnet` (testnet3),
+/// `testnet4`, `signet` or `regtest`.
+pub fn parse_network(name: &str) -> Result<Network, CoordinatorError> {
+    match name {
+        "mainnet" => Ok(Network::Bitcoin),
+        "testnet3" => Ok(Network::Testnet),
+        other => Network::from_str(other).map_err(|_| {
+            CoordinatorError::Invalid(format!(
+                "unknown network {} (expected {})",
+                other, NETWORK_NAMES
+            ))
+        }),
+    }
+}
+
+/// Fails unless an artifact made for `found` may be used on `expected`.
+/// The test networks share address prefixes and key versions, so mixing
+/// them goes unnoticed until coins land where no one is watching.
+pub fn check_same_network(found: Network, expected: Network) -> Result<(), CoordinatorError> {
+    if found != expected {
+        return Err(CoordinatorError::Invalid(format!(
+            "made for {}, not {}",
+            found, expected
+        )));
+    }
+    Ok(())
+}
+
+/// Account index of a BIP 48 path (`m/48'/coin'/account'/script'/...`).
+pub fn bip48_account(path: &DerivationPath) -> Option<u32> {
+    match path.as_ref() {
+        [purpose, _, account, ..] if *purpose == ChildNumber::Hardened { index: 48 } => {
+            match account {
+                ChildNumber::Hardened { index } => Some(*index),
+                ChildNumber::Normal { .. } => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Name of a per-account file: `key_a` for account 0, as before accounts
+/// existed, and `key_a_account1` for account 1.
+pub fn account_name(base: &str, account: u32) -> String {
+    if account == 0 {
+        base.to_string()
+    } else {
+        format!("{}_account{}", base, account)
+    }
+}
+
+/// `m/48'/1'/0'/2'`, or `m` for the master key itself.
+pub fn format_path(path: &DerivationPath) -> String {
+    path.into_iter()
+        .fold(String::from("m"), |acc, step| format!("{}/{}", acc, step))
+}
+
+/// The derivation below an account xpub for address `index` on the
+/// receive (0) or change (1) chain.
+fn chain_path(index: u32, is_change: bool) -> Result<DerivationPath, CoordinatorError> {
+    Ok(DerivationPath::from(vec![
+        ChildNumber::from_normal_idx(u32::from(is_change))?,
+        ChildNumber::from_normal_idx(index)?,
+    ]))
+}
+
+/// Reads key files into their origins, in the order given. Only the public
+/// fields are read, so watch-only files without an `xprv` work too. A file
+/// that records another network than `network` is refused: xpubs only tell
+/// mainnet from test networks, so a signet key would otherwise pass for a
+/// testnet4 one. Xpub attestations are checked against the devices pinned
+/// in `trusted_devices.json`.
+fn load_key_files(
+    key_paths: &[&str],
+    network: Network,
+) -> Result<Vec<XpubOrigin>, CoordinatorError> {
+    let mut xpub_origins = Vec::new();
+    let trusted = TrustedDevices::load_if_present()?;
+
+    for path in key_paths {
+        let data: PublicKeyData = serde_json::from_str(&std::fs::read_to_string(path)?)
+            .map_err(|e| CoordinatorError::Key(format!("{}: {}", path, e)))?;
+        if let Some(name) = &data.network {
+            let key_network = parse_network(name)
+                .map_err(|e| CoordinatorError::Key(format!("{}: {}", path, e)))?;
+            check_same_network(key_network, network)
+                .map_err(|e| CoordinatorError::Key(format!("{}: {}", path, e)))?;
+        }
+        // Zpub/Vpub and other SLIP 132 forms are normalized to xpub/tpub.
+        let xpub = slip132::parse_xpub(&data.xpub)
+            .map_err(|e| CoordinatorError::Key(format!("{}: invalid xpub: {}", path, e)))?;
+        // Some devices export an xpub without its master fingerprint. The
+        // xpub then stands in for its own origin, as in a descriptor
+        // without origin info, and its path is unknown.
+        let (fingerprint, derivation_path) = match &data.fingerprint {
+            Some(fp) => (
+                Fingerprint::from_str(fp).map_err(|e| {
+                    CoordinatorError::Key(format!("{}: invalid fingerprint: {}", path, e))
+                })?,
+                DerivationPath::from_str(&data.derivation_path)?,
+            ),
+            None => (xpub.fingerprint(), DerivationPath::master()),
+        };
+        // Cosigners may use any depth, but the xpub must sit at its path.
+        if data.fingerprint.is_some() {
+            check_key_origin(fingerprint, &derivation_path, &xpub)
+                .map_err(|e| CoordinatorError::Derivation(format!("{}: {}", path, e)))?;
+        }
+        let attestation = match &data.attestation {
+            Some(att) => att.verify(&data.xpub, &trusted),
+            None => AttestationStatus::Unattested,
+        };
+
+        xpub_origins.push(XpubOrigin {
+            name: data.name.clone(),
+            xpub,
+            fingerprint,
+            derivation_path,
+            attestation,
+            metadata: data.metadata.clone(),
+        });
+    }
+    Ok(xpub_origins)
+}
+
+/// Checks what an xpub records about its own position against the origin
+/// claimed for it: its depth must be the path's length and its child
+/// number the path's last step, and the master fingerprint must be the
+/// key's own for a master key and its parent's at depth 1. A mismatch
+/// yields PSBTs that hardware wallets refuse to sign.
+pub fn check_key_origin(
+    fingerprint: Fingerprint,
+    path: &DerivationPath,
+    xpub: &Xpub,
+) -> Result<(), CoordinatorError> {
+    if usize::from(xpub.depth) != path.len() {
+        return Err(CoordinatorError::Derivation(format!(
+            "xpub is at depth {} but its path {} has {} steps",
+            xpub.depth,
+            format_path(path),
+            path.len()
+        )));
+    }
+    let last = path
+        .into_iter()
+        .last()
+        .copied()
+        .unwrap_or(ChildNumber::Normal { index: 0 });
+    if xpub.child_number != last {
+        return Err(CoordinatorError::Derivation(format!(
+            "xpub is child {} but its path {} ends in {}",
+            xpub.child_number,
+            format_path(path),
+            last
+        )));
+    }
+    // Deeper keys only record their parent's fingerprint, not the master's.
+    let master = match xpub.depth {
+        0 => Some(xpub.fingerprint()),
+        1 => Some(xpub.parent_fingerprint),
+        _ => None,
+    };
+    if let Some(master) = master.filter(|master| *master != fingerprint) {
+        return Err(CoordinatorError::Derivation(format!(
+            "xpub's master key is {} but its fingerprint is given as {}",
+            master, fingerprint
+        )));
+    }
+    Ok(())
+}
+
+/// Unused addresses to look past before assuming a chain has no more
+/// funds, as wallets commonly do (BIP 44's gap limit).
+pub const DEFAULT_GAP_LIMIT: u32 = 20;
+
+/// Largest N for `OP_CHECKMULTISIG`.
+pub const MAX_MULTISIG_KEYS: usize = 20;
+
+/// Checks that `threshold`-of-`keys` is a spendable multisig policy.
+pub fn check_threshold(threshold: usize, keys: usize) -> Result<(), CoordinatorError> {
+    if keys == 0 || keys > MAX_MULTISIG_KEYS {
+        return Err(CoordinatorError::Invalid(format!(
+            "a multisig wallet needs 1 to {} keys, got {}",
+            MAX_MULTISIG_KEYS, keys
+        )));
+    }
+    if threshold == 0 || threshold > keys {
+        return Err(CoordinatorError::Invalid(format!(
+            "threshold must be between 1 and {}, got {}",
+            keys, threshold
+        )));
+    }
+    Ok(())
+}
+
+/// Checks that every key belongs to `network`: the xpub's version (after
+/// any SLIP 132 form is normalized) and, for a BIP 48 path, its coin type,
+/// `0'` on mainnet and `1'` elsewhere. The error names every offending key.
+pub fn check_key_networks(
+    xpub_origins: &[XpubOrigin],
+    network: Network,
+) -> Result<(), CoordinatorError> {
+    let expected = NetworkKind::from(network);
+    let describe = |kind: NetworkKind| match kind {
+        NetworkKind::Main => "mainnet",
+        NetworkKind::Test => "testnet",
+    };
+    let mut problems = Vec::new();
+    for origin in xpub_origins {
+        if origin.xpub.network != expected {
+            problems.push(format!(
+                "{}: {} xpub",
+                origin.name,
+                describe(origin.xpub.network)
+            ));
+        }
+        let coin_type = match origin.derivation_path.as_ref() {
+            [purpose, ChildNumber::Hardened { index: 0 }, ..]
+                if *purpose == ChildNumber::Hardened { index: 48 } =>
+            {
+                Some(NetworkKind::Main)
+            }
+            [purpose, ChildNumber::Hardened { index: 1 }, ..]
+                if *purpose == ChildNumber::Hardened { index: 48 } =>
+            {
+                Some(NetworkKind::Test)
+            }
+            _ => None,
+        };
+        if let Some(kind) = coin_type.filter(|kind| *kind != expected) {
+            problems.push(format!(
+                "{}: path {} has the {} BIP 48 coin type",
+                origin.name,
+                format_path(&origin.derivation_path),
+                describe(kind)
+            ));
+        }
+    }
+    if !problems.is_empty() {
+        return Err(CoordinatorError::Key(format!(
+            "keys do not match network {}: {}",
+            network,
+            problems.join("; ")
+        )));
+    }
+    Ok(())
+}
+
+/// `<m> <keys sorted by serialization> <n> OP_CHECKMULTISIG`, as BIP 67 and
+/// the `sortedmulti` descriptor define it.
+fn sortedmulti_script(threshold: usize, keys: &mut [bitcoin::secp256k1::PublicKey]) -> ScriptBuf {
+    keys.sort_by_key(|k| k.serialize());
+    multi_script(threshold, keys)
+}
+
+/// `<m> <keys in the order given> <n> OP_CHECKMULTISIG`, the `multi`
+/// descriptor's script.
+fn multi_script(threshold: usize, keys: &[bitcoin::secp256k1::PublicKey]) -> ScriptBuf {
+    let mut builder = Builder::new().push_int(threshold as i64);
+    for key in keys.iter() {
+        builder = builder.push_key(&bitcoin::PublicKey::new(*key));
+    }
+    builder
+        .push_int(keys.len() as i64)
+        .push_opcode(OP_CHECKMULTISIG)
+        .into_script()
+}
+
+/// `<k1> OP_CHECKSIG <k2> OP_CHECKSIGADD ... <kn> OP_CHECKSIGADD <m> OP_NUMEQUAL`,
+/// the `multi_a` tapscript, with x-only keys in the order given.
+fn multi_a_script(threshold: usize, keys: &[bitcoin::secp256k1::PublicKey]) -> ScriptBuf {
+    let mut builder = Builder::new();
+    for (i, key) in keys.iter().enumerate() {
+        builder = builder.push_x_only_key(&key.x_only_public_key().0);
+        builder = builder.push_opcode(if i == 0 { OP_CHECKSIG } else { OP_CHECKSIGADD });
+    }
+    builder
+        .push_int(threshold as i64)
+        .push_opcode(OP_NUMEQUAL)
+        .into_script()
+}
+
+/// Labels a signing key for display: cosigner name and fingerprint when the
+/// wallet is known, else the fingerprint, else the full public key.
+pub fn signer_label(
+    wallet: Option<&MultisigWallet>,
+    fingerprint: Option<Fingerprint>,
+    pubkey: &bitcoin::PublicKey,
+) -> String {
+    match (wallet, fingerprint) {
+        (Some(wallet), Some(fp)) => wallet.cosigner_label(fp),
+        (None, Some(fp)) => fp.to_string(),
+        (_, None) => format!("pubkey {}", pubkey),
+    }
+}
+
 pub fn print_wallet_info(wallet: &MultisigWallet) {
     println!("Network: {:?}", wallet.network);
-    println!(
-        "Threshold: {}-of-{}",
-        wallet.threshold,
-        wallet.xpub_origins.len()
-    );
+    if wallet.is_policy() {
+        println!(
+            "Policy: miniscript, {}-of-{} before timelocks",
+            wallet.threshold,
+            wallet.xpub_origins.len()
+        );
+    } else {
+        println!(
+            "Threshold: {}-of-{}",
+            wallet.threshold,
+            wallet.xpub_origins.len()
+        );
+    }
     println!();
     for (i, origin) in wallet.xpub_origins.iter().enumerate() {
         println!(
-            "Signer {}: [{}] {}",
+            "Signer {}: {} [{}] {}",
             i + 1,
+            origin.name,
             origin.fingerprint,
             &origin.xpub.to_string()[..24]
         );
+        match &origin.attestation {
+            AttestationStatus::Unattested => {}
+            AttestationStatus::Verified { signer } => println!("  xpub attested by {}", signer),
+            AttestationStatus::Unverified { signer, reason } => {
+                println!(
+                    "  warning: xpub attestation by {} unverified: {}",
+                    signer, reason
+                )
+            }
+        }
+        for warning in origin.origin_warnings() {
+            println!(
+                "  warning: {}; hardware wallets may refuse to sign",
+                warning
+            );
+        }
+        if !origin.metadata.is_empty() {
+            println!("  held by {}", origin.display_name());
+        }
+        if let Some(contact) = &origin.metadata.contact {
+            println!("  contact: {}", contact);
+        }
+        if let Some(transport) = &origin.metadata.transport {
+            println!("  transport: {}", transport);
+        }
     }
     println!();
-    println!("Descriptor: {}", wallet.descriptor);
+    println!("Descriptor: {}", wallet.descriptor_with_checksum());
+    println!("Policy ID: {}", wallet.policy_id());
     println!();
     for i in 0..3 {
-        if let Ok(addr) = wallet.derive_address(i) {
+        if let Ok(addr) = wallet.derive_address(i, false) {
             println!("Address {}: {}", i, addr);
         }
     }
 }
+
+fn proprietary_key(subtype: u8) -> ProprietaryKey {
+    ProprietaryKey {
+        prefix: PROPRIETARY_PREFIX.to_vec(),
+        subtype,
+        key: Vec::new(),
+    }
+}
+
+/// Records the wallet policy ID in the PSBT's global proprietary fields.
+pub fn set_psbt_policy_id(psbt: &mut Psbt, policy_id: sha256::Hash) {
+    psbt.proprietary.insert(
+        proprietary_key(PROPRIETARY_POLICY_ID),
+        policy_id.to_byte_array().to_vec(),
+    );
+}
+
+/// Reads the wallet policy ID recorded by the coordinator, if any.
+pub fn psbt_policy_id(psbt: &Psbt) -> Option<sha256::Hash> {
+    psbt.proprietary
+        .get(&proprietary_key(PROPRIETARY_POLICY_ID))
+        .and_then(|v| sha256::Hash::from_slice(v).ok())
+}
+
+/// Records that `output` pays address `index` of `descriptor`, for audit.
+pub fn set_output_descriptor(output: &mut Output, descriptor: &str, index: u32) {
+    output.proprietary.insert(
+        proprietary_key(PROPRIETARY_OUTPUT_DESCRIPTOR),
+        format!("{}:{}", descriptor, index).into_bytes(),
+    );
+}
+
+/// The descriptor and index recorded for `output`, if any.
+pub fn output_descriptor(output: &Output) -> Option<(String, u32)> {
+    let value = output
+        .proprietary
+        .get(&proprietary_key(PROPRIETARY_OUTPUT_DESCRIPTOR))?;
+    let (descriptor, index) = std::str::from_utf8(value).ok()?.rsplit_once(':')?;
+    Some((descriptor.to_string(), index.parse().ok()?))
+}
+
+/// Checks an output label is short, single-line text, as signers print
+/// it.
+pub fn check_output_label(label: &str) -> Result<(), CoordinatorError> {
+    if label.chars().count() > MAX_OUTPUT_LABEL_CHARS {
+        return Err(CoordinatorError::Invalid(format!(
+            "label is longer than {} characters",
+            MAX_OUTPUT_LABEL_CHARS
+        )));
+    }
+    if label.chars().any(char::is_control) {
+        return Err(CoordinatorError::invalid(
+            "label contains control characters",
+        ));
+    }
+    Ok(())
+}
+
+/// Records a label for `output`.
+pub fn set_output_label(output: &mut Output, label: &str) {
+    output.proprietary.insert(
+        proprietary_key(PROPRIETARY_OUTPUT_LABEL),
+        label.as_bytes().to_vec(),
+    );
+}
+
+/// The label recorded for `output`, if any. It comes from whoever made
+/// the PSBT, and is checked like a label being set.
+pub fn output_label(output: &Output) -> Result<Option<String>, CoordinatorError> {
+    let Some(value) = output
+        .proprietary
+        .get(&proprietary_key(PROPRIETARY_OUTPUT_LABEL))
+    else {
+        return Ok(None);
+    };
+    let label = std::str::from_utf8(value)
+        .map_err(|_| CoordinatorError::invalid("output label is not UTF-8"))?;
+    check_output_label(label)?;
+    Ok(Some(label.to_string()))
+}
+
+/// Point after which a signature request should no longer be signed, so a
+/// leaked PSBT stops being actionable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expiry {
+    /// Unix time, in seconds.
+    Time(u64),
+    /// Block height.
+    Height(u32),
+}
+
+impl Expiry {
+    /// Whether the request has expired at unix time `now` and, if known,
+    /// chain height `height`. `None` when a height expiry meets an unknown
+    /// height, as on an offline signer.
+    pub fn is_expired(&self, now: u64, height: Option<u32>) -> Option<bool> {
+        match self {
+            Expiry::Time(t) => Some(now > *t),
+            Expiry::Height(h) => height.map(|height| height > *h),
+        }
+    }
+
+    // One tag byte, then the value little-endian.
+    fn to_bytes(self) -> Vec<u8> {
+        match self {
+            Expiry::Height(h) => [&[0u8][..], &h.to_le_bytes()].concat(),
+            Expiry::Time(t) => [&[1u8][..], &t.to_le_bytes()].concat(),
+        }
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [0, rest @ ..] => Some(Expiry::Height(u32::from_le_bytes(rest.try_into().ok()?))),
+            [1, rest @ ..] => Some(Expiry::Time(u64::from_le_bytes(rest.try_into().ok()?))),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Expiry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expiry::Time(t) => write!(f, "unix time {}", t),
+            Expiry::Height(h) => write!(f, "block height {}", h),
+        }
+    }
+}
+
+/// Records when the signature request expires.
+pub fn set_psbt_expiry(psbt: &mut Psbt, expiry: Expiry) {
+    psbt.proprietary
+        .insert(proprietary_key(PROPRIETARY_EXPIRY), expiry.to_bytes());
+}
+
+/// Reads the expiry recorded by the coordinator, if any.
+pub fn psbt_expiry(psbt: &Psbt) -> Result<Option<Expiry>, CoordinatorError> {
+    psbt.proprietary
+        .get(&proprietary_key(PROPRIETARY_EXPIRY))
+        .map(|v| {
+            Expiry::from_bytes(v).ok_or_else(|| CoordinatorError::psbt("malformed PSBT expiry"))
+        })
+        .transpose()
+}
+
+/// Records when the signature request was created, in unix seconds.
+pub fn set_psbt_created_at(psbt: &mut Psbt, unix_time: u64) {
+    psbt.proprietary.insert(
+        proprietary_key(PROPRIETARY_CREATED_AT),
+        unix_time.to_le_bytes().to_vec(),
+    );
+}
+
+/// Reads the creation time recorded by the coordinator, if any.
+pub fn psbt_created_at(psbt: &Psbt) -> Result<Option<u64>, CoordinatorError> {
+    psbt.proprietary
+        .get(&proprietary_key(PROPRIETARY_CREATED_AT))
+        .map(|v| {
+            let bytes = v
+                .as_slice()
+                .try_into()
+                .map_err(|_| CoordinatorError::psbt("malformed PSBT creation time"))?;
+            Ok(u64::from_le_bytes(bytes))
+        })
+        .transpose()
+}
+
+/// Records the network the PSBT spends on.
+pub fn set_psbt_network(psbt: &mut Psbt, network: Network) {
+    psbt.proprietary.insert(
+        proprietary_key(PROPRIETARY_NETWORK),
+        network.to_string().into_bytes(),
+    );
+}
+
+/// Reads the network recorded by the coordinator, if any.
+pub fn psbt_network(psbt: &Psbt) -> Result<Option<Network>, CoordinatorError> {
+    psbt.proprietary
+        .get(&proprietary_key(PROPRIETARY_NETWORK))
+        .map(|v| {
+            std::str::from_utf8(v)
+                .ok()
+                .and_then(|name| Network::from_str(name).ok())
+                .ok_or_else(|| CoordinatorError::psbt("malformed PSBT network"))
+        })
+        .transpose()
+}
+
+/// Which optional fields [`normalize_psbt`] removes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NormalizeOptions {
+    pub strip_unknown: bool,
+    pub strip_proprietary: bool,
+}
+
+/// Puts a PSBT into canonical form so equal content gives equal bytes.
+///
+/// Map entries are always re-serialized in key order; unknown and
+/// proprietary fields are removed when requested.
+pub fn normalize_psbt(psbt: &Psbt, opts: NormalizeOptions) -> Result<Vec<u8>, CoordinatorError> {
+    // A round trip through the typed representation drops duplicate and
+    // non-canonical encodings; BTreeMaps serialize in sorted key order.
+    let mut psbt = Psbt::deserialize(&psbt.serialize())?;
+    if opts.strip_unknown {
+        psbt.unknown.clear();
+        psbt.inputs.iter_mut().for_each(|i| i.unknown.clear());
+        psbt.outputs.iter_mut().for_each(|o| o.unknown.clear());
+    }
+    if opts.strip_proprietary {
+        psbt.proprietary.clear();
+        psbt.inputs.iter_mut().for_each(|i| i.proprietary.clear());
+        psbt.outputs.iter_mut().for_each(|o| o.proprietary.clear());
+    }
+    Ok(psbt.serialize())
+}
+
+/// Merges PSBTs for the same transaction into one (BIP 174 combiner).
+///
+/// PSBTs produced by other wallets (Electrum, hardware signers) are accepted
+/// as long as they describe the same unsigned transaction; their partial
+/// signatures and any redundant fields are merged in. Merging signatures
+/// already present changes nothing, but a key with two different signatures
+/// on one input is a [`CoordinatorError::Conflict`]: BIP 174 would silently
+/// keep one of them.
+pub fn combine_psbts(psbts: Vec<Psbt>) -> Result<Psbt, CoordinatorError> {
+    let mut iter = psbts.into_iter();
+    let mut combined = iter
+        .next()
+        .ok_or_else(|| CoordinatorError::psbt("no PSBTs to combine"))?;
+    for other in iter {
+        if other.unsigned_tx.compute_txid() != combined.unsigned_tx.compute_txid() {
+            return Err(CoordinatorError::Psbt(format!(
+                "PSBT spends a different transaction ({} vs {})",
+                other.unsigned_tx.compute_txid(),
+                combined.unsigned_tx.compute_txid()
+            )));
+        }
+        check_signature_conflicts(&combined, &other)?;
+        combined.combine(other)?;
+    }
+    Ok(combined)
+}
+
+/// Fails if a key signed an input of `theirs` differently than in `ours`.
+fn check_signature_conflicts(ours: &Psbt, theirs: &Psbt) -> Result<(), CoordinatorError> {
+    let conflict = |idx: usize, key: String| {
+        CoordinatorError::Conflict(format!(
+            "input {}: {} has two different signatures; keep one of the PSBTs",
+            idx, key
+        ))
+    };
+    for (idx, (a, b)) in ours.inputs.iter().zip(&theirs.inputs).enumerate() {
+        for (key, sig) in &b.partial_sigs {
+            if a.partial_sigs.get(key).is_some_and(|ours| ours != sig) {
+                return Err(conflict(idx, format!("key {}", key)));
+            }
+        }
+        for ((key, leaf), sig) in &b.tap_script_sigs {
+            if a.tap_script_sigs
+                .get(&(*key, *leaf))
+                .is_some_and(|ours| ours != sig)
+            {
+                return Err(conflict(idx, format!("key {}", key)));
+            }
+        }
+        if matches!((&a.tap_key_sig, &b.tap_key_sig), (Some(x), Some(y)) if x != y) {
+            return Err(conflict(idx, String::from("the key path")));
+        }
+    }
+    Ok(())
+}
+
+/// Reads the threshold and ordered pubkeys from a `multi`/`sortedmulti`
+/// witness script.
+pub fn parse_multisig_script(
+    script: &Script,
+) -> Result<(usize, Vec<bitcoin::PublicKey>), CoordinatorError> {
+    let instructions = script
+        .instructions()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(CoordinatorError::psbt)?;
+    let [first, keys @ .., count, last] = instructions.as_slice() else {
+        return Err(CoordinatorError::psbt(
+            "witness script is too short for multisig",
+        ));
+    };
+    if *last != Instruction::Op(OP_CHECKMULTISIG) {
+        return Err(CoordinatorError::psbt(
+            "witness script does not end in OP_CHECKMULTISIG",
+        ));
+    }
+
+    let threshold =
+        pushnum(first).ok_or_else(|| CoordinatorError::psbt("missing multisig threshold"))?;
+    let key_count =
+        pushnum(count).ok_or_else(|| CoordinatorError::psbt("missing multisig key count"))?;
+    let pubkeys = keys
+        .iter()
+        .map(|ins| match ins {
+            Instruction::PushBytes(bytes) => {
+                bitcoin::PublicKey::from_slice(bytes.as_bytes()).map_err(CoordinatorError::key)
+            }
+            _ => Err(CoordinatorError::psbt(
+                "unexpected opcode among multisig keys",
+            )),
+        })
+        .collect::<Result<Vec<_>, CoordinatorError>>()?;
+
+    if pubkeys.len() != key_count || threshold == 0 || threshold > key_count {
+        return Err(CoordinatorError::psbt("inconsistent multisig script"));
+    }
+    Ok((threshold, pubkeys))
+}
+
+/// Parses a `multi_a` tapscript into its threshold and x-only keys, in
+/// script order.
+pub fn parse_multi_a_script(
+    script: &Script,
+) -> Result<(usize, Vec<XOnlyPublicKey>), CoordinatorError> {
+    let instructions = script
+        .instructions()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(CoordinatorError::psbt)?;
+    let [body @ .., threshold, last] = instructions.as_slice() else {
+        return Err(CoordinatorError::psbt("tapscript is too short for multi_a"));
+    };
+    if *last != Instruction::Op(OP_NUMEQUAL) || body.is_empty() || body.len() % 2 != 0 {
+        return Err(CoordinatorError::psbt("tapscript is not a multi_a script"));
+    }
+
+    let mut keys = Vec::with_capacity(body.len() / 2);
+    for (i, pair) in body.chunks(2).enumerate() {
+        let expected = if i == 0 { OP_CHECKSIG } else { OP_CHECKSIGADD };
+        match pair {
+            [Instruction::PushBytes(key), Instruction::Op(op)] if *op == expected => {
+                keys.push(
+                    XOnlyPublicKey::from_slice(key.as_bytes()).map_err(CoordinatorError::key)?,
+                );
+            }
+            _ => {
+                return Err(CoordinatorError::psbt(
+                    "unexpected opcode among multi_a keys",
+                ));
+            }
+        }
+    }
+
+    let threshold =
+        pushnum(threshold).ok_or_else(|| CoordinatorError::psbt("missing multi_a threshold"))?;
+    if threshold == 0 || threshold > keys.len() {
+        return Err(CoordinatorError::psbt("inconsistent multi_a script"));
+    }
+    Ok((threshold, keys))
+}
+
+/// Threshold and keys of a wallet witness script: a plain multisig or,
+/// failing that, a miniscript policy.
+pub fn parse_witness_script(
+    script: &Script,
+) -> Result<(usize, Vec<bitcoin::PublicKey>), CoordinatorError> {
+    parse_multisig_script(script).or_else(|_| parse_policy_script(script))
+}
+
+/// Parses a miniscript witness script into the number of signatures it
+/// needs before any timelock expires, and its keys.
+pub fn parse_policy_script(
+    script: &Script,
+) -> Result<(usize, Vec<bitcoin::PublicKey>), CoordinatorError> {
+    let ms = Miniscript::<bitcoin::PublicKey, Segwitv0>::decode(script)
+        .map_err(CoordinatorError::descriptor)?;
+    let threshold = ms
+        .lift()?
+        .at_age(relative::LockTime::ZERO)
+        .minimum_n_keys()
+        .ok_or_else(|| {
+            CoordinatorError::descriptor("script cannot be spent before a timelock expires")
+        })?;
+    Ok((threshold, ms.iter_pk().collect()))
+}
+
+/// Reads a small positive number pushed by `OP_1`..`OP_16` or, above 16, as
+/// a minimal little-endian script number.
+fn pushnum(ins: &Instruction) -> Option<usize> {
+    match ins {
+        Instruction::Op(op) => {
+            let value = op.to_u8();
+            (0x51..=0x60)
+                .contains(&value)
+                .then_some((value - 0x50) as usize)
+        }
+        Instruction::PushBytes(bytes) => {
+            let bytes = bytes.as_bytes();
+            if bytes.is_empty() || bytes.len() > 2 || bytes[bytes.len() - 1] & 0x80 != 0 {
+                return None;
+            }
+            Some(
+                bytes
+                    .iter()
+                    .rev()
+                    .fold(0usize, |acc, b| (acc << 8) | usize::from(*b)),
+            )
+        }
+    }
+}
+
+/// Default upper bound on a decoded PSBT, enough for consolidations of
+/// several thousand inputs.
+pub const DEFAULT_MAX_PSBT_BYTES: usize = 16 * 1024 * 1024;
+
+/// The PSBT size limit: `PSBT_MAX_BYTES` from the environment if set,
+/// else [`DEFAULT_MAX_PSBT_BYTES`].
+pub fn max_psbt_bytes() -> usize {
+    std::env::var("PSBT_MAX_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_PSBT_BYTES)
+}
+
+/// Most inputs, or outputs, an untrusted PSBT may have.
+pub const MAX_PSBT_INPUTS_OUTPUTS: usize = 10_000;
+
+/// Most entries in any one key-value map of an untrusted PSBT: the global
+/// map or a single input or output.
+pub const MAX_PSBT_MAP_ENTRIES: usize = 1_000;
+
+/// Decodes PSBT bytes from an untrusted source, such as a PSBT handed to a
+/// signer. Beyond the checks of [`Psbt::deserialize`] it enforces
+/// [`max_psbt_bytes`], [`MAX_PSBT_INPUTS_OUTPUTS`] and
+/// [`MAX_PSBT_MAP_ENTRIES`], that every amount is at most 21M BTC with
+/// no overflow when summed, and that each full previous transaction is
+/// the one the input spends. Every tool reading a PSBT it did not just
+/// build goes through here.
+pub fn parse_untrusted_psbt(bytes: &[u8]) -> Result<Psbt, CoordinatorError> {
+    check_psbt_size(bytes.len(), max_psbt_bytes())?;
+    let psbt = Psbt::deserialize(bytes)?;
+    let tx = &psbt.unsigned_tx;
+
+    if tx.input.len() > MAX_PSBT_INPUTS_OUTPUTS || tx.output.len() > MAX_PSBT_INPUTS_OUTPUTS {
+        return Err(CoordinatorError::Psbt(format!(
+            "PSBT has {} inputs and {} outputs, over the limit of {}",
+            tx.input.len(),
+            tx.output.len(),
+            MAX_PSBT_INPUTS_OUTPUTS
+        )));
+    }
+    let check_map = |what: &str, entries: usize| {
+        if entries > MAX_PSBT_MAP_ENTRIES {
+            return Err(CoordinatorError::Psbt(format!(
+                "{} has {} entries, over the limit of {}",
+                what, entries, MAX_PSBT_MAP_ENTRIES
+            )));
+        }
+        Ok(())
+    };
+    check_map(
+        "global map",
+        psbt.xpub.len() + psbt.proprietary.len() + psbt.unknown.len(),
+    )?;
+    for (i, input) in psbt.inputs.iter().enumerate() {
+        check_map(
+            &format!("input {}", i),
+            input.partial_sigs.len()
+                + input.bip32_derivation.len()
+                + input.ripemd160_preimages.len()
+                + input.sha256_preimages.len()
+                + input.hash160_preimages.len()
+                + input.hash256_preimages.len()
+                + input.tap_script_sigs.len()
+                + input.tap_scripts.len()
+                + input.tap_key_origins.len()
+                + input.proprietary.len()
+                + input.unknown.len(),
+        )?;
+    }
+    for (i, output) in psbt.outputs.iter().enumerate() {
+        check_map(
+            &format!("output {}", i),
+            output.bip32_derivation.len()
+                + output.tap_key_origins.len()
+                + output.proprietary.len()
+                + output.unknown.len(),
+        )?;
+    }
+
+    // Amounts are checked one by one and as a sum so that later fee and
+    // total arithmetic cannot overflow.
+    let check_amounts = |what: String, values: &mut dyn Iterator<Item = Amount>| {
+        let mut total = Amount::ZERO;
+        for value in values {
+            total = total
+                .checked_add(value)
+                .filter(|total| value <= Amount::MAX_MONEY && *total <= Amount::MAX_MONEY)
+                .ok_or_else(|| CoordinatorError::Psbt(format!("{} exceed 21M BTC", what)))?;
+        }
+        Ok::<(), CoordinatorError>(())
+    };
+    check_amounts(
+        "outputs".to_string(),
+        &mut tx.output.iter().map(|o| o.value),
+    )?;
+    for (i, (input, txin)) in psbt.inputs.iter().zip(&tx.input).enumerate() {
+        if let Some(utxo) = &input.witness_utxo {
+            check_amounts(
+                format!("input {} amount", i),
+                &mut std::iter::once(utxo.value),
+            )?;
+        }
+        if let Some(prev_tx) = &input.non_witness_utxo {
+            if prev_tx.compute_txid() != txin.previous_output.txid {
+                return Err(CoordinatorError::Psbt(format!(
+                    "input {}: previous transaction is not the one it spends",
+                    i
+                )));
+            }
+            check_amounts(
+                format!("input {} previous transaction outputs", i),
+                &mut prev_tx.output.iter().map(|o| o.value),
+            )?;
+            // Signers read the amount from witness_utxo; it must be the
+            // output the previous transaction says is spent.
+            let spent = prev_tx
+                .output
+                .get(txin.previous_output.vout as usize)
+                .ok_or_else(|| {
+                    CoordinatorError::Psbt(format!(
+                        "input {}: previous transaction has no output {}",
+                        i, txin.previous_output.vout
+                    ))
+                })?;
+            if input
+                .witness_utxo
+                .as_ref()
+                .is_some_and(|utxo| utxo != spent)
+            {
+                return Err(CoordinatorError::Psbt(format!(
+                    "input {}: witness_utxo differs from the previous transaction",
+                    i
+                )));
+            }
+        }
+    }
+    Ok(psbt)
+}
+
+fn check_psbt_size(len: usize, limit: usize) -> Result<(), CoordinatorError> {
+    if len > limit {
+        return Err(CoordinatorError::Psbt(format!(
+            "PSBT is {} bytes, over the {} byte limit; split the transaction into \
+             smaller batches or raise PSBT_MAX_BYTES",
+            len, limit
+        )));
+    }
+    Ok(())
+}
+
+/// Writes a file so that it either has its old contents or all of the new
+/// ones, even if the process dies midway: the data goes to a temporary
+/// file next to it, is synced, and is renamed over the target.
+pub fn write_atomic(
+    path: impl AsRef<std::path::Path>,
+    contents: impl AsRef<[u8]>,
+) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let path = path.as_ref();
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let mut file = std::fs::File::create(&tmp)?;
+    file.write_all(contents.as_ref())?;
+    file.sync_all()?;
+    std::fs::rename(&tmp, path)
+}
+
+/// Loads PSBT bytes from a file path or an inline string.
+///
+/// Accepts raw binary files, base64 text, and armored text (see [`armor`]).
+/// Input over [`max_psbt_bytes`] is rejected before it is read; text
+/// encodings are allowed twice the limit for their expansion. A file
+/// without a `.base64` or `.asc` extension is binary if it starts with the
+/// PSBT magic bytes, and text otherwise.
+pub fn load_psbt(input: &str) -> Result<Vec<u8>, CoordinatorError> {
+    let limit = max_psbt_bytes();
+    let path = std::path::Path::new(input);
+    let is_text_file = input.ends_with(".base64") || input.ends_with(".asc");
+    let bytes = if is_text_file || path.exists() {
+        let file_len = usize::try_from(std::fs::metadata(path)?.len()).unwrap_or(usize::MAX);
+        let is_binary = !is_text_file && {
+            let mut magic = [0u8; 5];
+            let mut file = std::fs::File::open(path)?;
+            std::io::Read::read_exact(&mut file, &mut magic).is_ok() && magic == *b"psbt\xff"
+        };
+        check_psbt_size(if is_binary { file_len } else { file_len / 2 }, limit)?;
+        if is_text_file {
+            decode_psbt_text(&std::fs::read_to_string(input)?)?
+        } else {
+            let bytes = std::fs::read(input)?;
+            match std::str::from_utf8(&bytes) {
+                Ok(text) if armor::is_armored(text) => armor::dearmor(text)?,
+                _ => bytes,
+            }
+        }
+    } else {
+        check_psbt_size(input.len() / 2, limit)?;
+        decode_psbt_text(input)?
+    };
+    check_psbt_size(bytes.len(), limit)?;
+    Ok(bytes)
+}
+
+fn decode_psbt_text(text: &str) -> Result<Vec<u8>, CoordinatorError> {
+    if armor::is_armored(text) {
+        armor::dearmor(text)
+    } else {
+        STANDARD.decode(text.trim()).map_err(CoordinatorError::psbt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn read(state: &WalletState) -> Result<MultisigWallet, CoordinatorError> {
+        MultisigWallet::try_from(state.clone())
+    }
+
+    #[test]
+    fn wallet_state_round_trips() {
+        for script_type in [ScriptType::Wsh, ScriptType::ShWsh, ScriptType::Tr] {
+            let wallet = testing::wallet(2, 3, script_type);
+            let json = serde_json::to_string(&wallet).unwrap();
+            let back: MultisigWallet = serde_json::from_str(&json).unwrap();
+            assert_eq!(back.policy_id(), wallet.policy_id());
+            assert_eq!(back.threshold, 2);
+            assert_eq!(back.script_type, script_type);
+            let names = |w: &MultisigWallet| -> Vec<String> {
+                w.xpub_origins.iter().map(|o| o.name.clone()).collect()
+            };
+            assert_eq!(names(&back), names(&wallet));
+        }
+    }
+
+    #[test]
+    fn wallet_state_must_match_its_descriptor() {
+        let state = WalletState::from(testing::wallet(2, 3, ScriptType::Wsh));
+        assert!(read(&state).is_ok());
+
+        let mut threshold = state.clone();
+        threshold.threshold = 1;
+        assert!(read(&threshold).is_err());
+
+        let mut dropped = state.clone();
+        dropped.xpub_origins.pop();
+        assert!(read(&dropped).is_err());
+
+        let mut swapped = state.clone();
+        swapped.xpub_origins.swap(0, 1);
+        assert!(read(&swapped).is_err());
+
+        let mut script_type = state.clone();
+        script_type.script_type = ScriptType::ShWsh;
+        assert!(read(&script_type).is_err());
+    }
+
+    #[test]
+    fn descriptor_checksum_must_match() {
+        let wallet = testing::wallet(2, 3, ScriptType::Wsh);
+        let descriptor = wallet.descriptor_with_checksum();
+        let back = MultisigWallet::from_descriptor(&descriptor, wallet.network).unwrap();
+        assert_eq!(back.policy_id(), wallet.policy_id());
+
+        let (body, checksum) = descriptor.split_once('#').unwrap();
+        let last = if checksum.ends_with('q') { 'p' } else { 'q' };
+        let wrong = format!("{}#{}{}", body, &checksum[..7], last);
+        let err = MultisigWallet::from_descriptor(&wrong, wallet.network).unwrap_err();
+        assert!(err.to_string().contains(&format!("expected #{}", checksum)));
+    }
+
+    #[test]
+    fn from_descriptor_reads_multi_threshold_from_the_fragment() {
+        let wallet = testing::wallet(2, 3, ScriptType::Wsh);
+        let descriptor = format!("{:#}", wallet.descriptor);
+        let keys = descriptor
+            .strip_prefix("wsh(sortedmulti(2,")
+            .and_then(|rest| rest.strip_suffix("))"))
+            .unwrap();
+
+        let multi = format!("wsh(multi(2,{}))", keys);
+        let back = MultisigWallet::from_descriptor(&multi, wallet.network).unwrap();
+        assert_eq!(back.threshold, 2);
+        assert!(!back.is_sorted());
+
+        let keys: Vec<&str> = keys.split(',').collect();
+        let policy = format!(
+            "wsh(and_v(v:pk({}),multi(1,{},{})))",
+            keys[0], keys[1], keys[2]
+        );
+        assert!(MultisigWallet::from_descriptor(&policy, wallet.network).is_err());
+    }
+
+    #[test]
+    fn wallet_state_keeps_cosigner_details() {
+        let mut wallet = testing::wallet(2, 3, ScriptType::Wsh);
+        wallet.xpub_origins[0].name = "key_a".to_string();
+        wallet.xpub_origins[0].metadata.display_name = Some("Alice".to_string());
+        wallet.xpub_origins[1].attestation = AttestationStatus::Verified {
+            signer: "coldcard".to_string(),
+        };
+        let state = WalletState::from(wallet.clone());
+        let json = serde_json::to_string(&state).unwrap();
+        let back: WalletState = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.descriptor, wallet.descriptor_with_checksum());
+        let back = read(&back).unwrap();
+        assert_eq!(back.xpub_origins[0].name, "key_a");
+        assert_eq!(
+            back.xpub_origins[0].metadata.display_name.as_deref(),
+            Some("Alice")
+        );
+        assert_eq!(
+            back.xpub_origins[1].attestation,
+            wallet.xpub_origins[1].attestation
+        );
+    }
+
+    #[test]
+    fn wallet_state_from_a_newer_schema_is_refused() {
+        let mut state = WalletState::from(testing::wallet(2, 3, ScriptType::Wsh));
+        state.schema_version = SCHEMA_VERSION + 1;
+        assert!(read(&state).is_err());
+    }
+
+    fn psbt_with(outputs: &[Amount], input_value: Option<Amount>) -> Vec<u8> {
+        let tx = bitcoin::Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn::default()],
+            output: outputs
+                .iter()
+                .map(|value| bitcoin::TxOut {
+                    value: *value,
+                    script_pubkey: ScriptBuf::new(),
+                })
+                .collect(),
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo = input_value.map(|value| bitcoin::TxOut {
+            value,
+            script_pubkey: ScriptBuf::new(),
+        });
+        psbt.serialize()
+    }
+
+    #[test]
+    fn untrusted_psbt_amounts_stay_within_max_money() {
+        let one_sat = Amount::from_sat(1);
+        let over = Amount::MAX_MONEY + one_sat;
+        assert!(parse_untrusted_psbt(&psbt_with(&[Amount::MAX_MONEY], None)).is_ok());
+        assert!(parse_untrusted_psbt(&psbt_with(&[over], None)).is_err());
+        assert!(parse_untrusted_psbt(&psbt_with(&[one_sat], Some(over))).is_err());
+        // Each below the cap, together above it.
+        let half = Amount::from_sat(Amount::MAX_MONEY.to_sat() / 2 + 1);
+        assert!(parse_untrusted_psbt(&psbt_with(&[half, half], None)).is_err());
+        // Values whose sum does not fit in 64 bits.
+        let huge = Amount::from_sat(u64::MAX);
+        assert!(parse_untrusted_psbt(&psbt_with(&[huge, huge], None)).is_err());
+        assert!(parse_untrusted_psbt(&psbt_with(&[one_sat], Some(huge))).is_err());
+    }
+
+    #[test]
+    fn split_amount_makes_the_fewest_near_equal_parts() {
+        let sats = |total, cap| {
+            split_amount(Amount::from_sat(total), Amount::from_sat(cap))
+                .unwrap()
+                .iter()
+                .map(|part| part.to_sat())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sats(100_000_000, 30_000_000), [25_000_000; 4]);
+        assert_eq!(sats(10, 3), [3, 3, 2, 2]);
+        assert_eq!(sats(90, 30), [30, 30, 30]);
+        assert_eq!(sats(29, 30), [29]);
+        assert_eq!(sats(0, 30), [0]);
+        assert!(split_amount(Amount::from_sat(1), Amount::ZERO).is_err());
+    }
+
+    #[test]
+    fn output_shares_parse_percentages_to_basis_points() {
+        let parse = |s: &str| s.parse::<OutputShare>();
+        assert_eq!(parse("70%").unwrap(), OutputShare::Percent(7_000));
+        assert_eq!(parse("33.33%").unwrap(), OutputShare::Percent(3_333));
+        assert_eq!(parse("0.5%").unwrap(), OutputShare::Percent(50));
+        assert_eq!(parse("REST").unwrap(), OutputShare::Remainder);
+        assert_eq!(
+            parse("0.001btc").unwrap(),
+            OutputShare::Fixed(Amount::from_sat(100_000))
+        );
+        for invalid in ["0%", "100.01%", "1.234%", "-5%", "x%"] {
+            assert!(parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn allocate_shares_rounds_down_into_the_remainder() {
+        let allocate = |available, shares: &[&str]| {
+            let shares: Vec<OutputShare> = shares.iter().map(|s| s.parse().unwrap()).collect();
+            allocate_shares(Amount::from_sat(available), &shares)
+                .map(|amounts| amounts.iter().map(|a| a.to_sat()).collect::<Vec<_>>())
+        };
+        assert_eq!(allocate(1_000, &["70%", "30%"]).unwrap(), [700, 300]);
+        assert_eq!(allocate(1_001, &["70%", "rest"]).unwrap(), [700, 301]);
+        assert_eq!(
+            allocate(100_000, &["33.33%", "33.33%", "rest", "33.33%"]).unwrap(),
+            [33_330, 33_330, 10, 33_330]
+        );
+        assert_eq!(
+            allocate(100_000, &["10000sat", "50%", "rest"]).unwrap(),
+            [10_000, 50_000, 40_000]
+        );
+
+        // Rounding with nowhere to go, too much, or an empty remainder.
+        assert!(allocate(1_001, &["70%", "30%"]).is_err());
+        assert!(allocate(1_000, &["60%", "50%"]).is_err());
+        assert!(allocate(1_000, &["100%", "rest"]).is_err());
+        assert!(allocate(1_000, &["rest", "rest"]).is_err());
+    }
+
+    #[test]
+    fn tr_addresses_commit_to_the_multi_a_leaf_under_nums() {
+        // Computed separately from the BIP 341 construction: the multi_a
+        // leaf of the keys at each index as the only script under
+        // NUMS_INTERNAL_KEY.
+        let wallet = testing::wallet(2, 3, ScriptType::Tr);
+        assert_eq!(
+            wallet.derive_address(0, false).unwrap().to_string(),
+            "bcrt1pnsg0llhldsz9r67ayvaxsw4n62vmfu8pthr5qnh0sq93wullrfesvhtvcd"
+        );
+        assert_eq!(
+            wallet.derive_address(5, true).unwrap().to_string(),
+            "bcrt1ptelz8t7xxfj3utgeua7p93c5gzpe6xlwtrxd8xzazr98rrv0u6nqgzfukl"
+        );
+
+        let (leaf, info) = wallet.taproot_spend_info(0, false).unwrap();
+        assert_eq!(leaf, wallet.witness_script(0, false).unwrap());
+        assert_eq!(info.internal_key().to_string(), NUMS_INTERNAL_KEY);
+        assert_eq!(
+            ScriptBuf::new_p2tr_tweaked(info.output_key()),
+            wallet.derive_address(0, false).unwrap().script_pubkey()
+        );
+    }
+
+    #[test]
+    fn sh_wsh_addresses_wrap_the_p2wsh_script() {
+        // Computed separately: the sorted 2-of-3 script of the keys at each
+        // index, hashed into P2WSH and that program into P2SH.
+        let wallet = testing::wallet(2, 3, ScriptType::ShWsh);
+        assert_eq!(
+            wallet.derive_address(0, false).unwrap().to_string(),
+            "2MvFe56sSJ1MxM1cSELwts5mN2qdJnHth2U"
+        );
+        assert_eq!(
+            wallet.derive_address(5, true).unwrap().to_string(),
+            "2N2iMTjXu9cFYXt7uR9RLXwjpfc2Nk97ybo"
+        );
+        // The same script as the native wallet's.
+        let native = testing::wallet(2, 3, ScriptType::Wsh);
+        assert_eq!(
+            native.derive_address(0, false).unwrap().to_string(),
+            "bcrt1qkpad42v3wkkt9hfww7vs7udr72pm32vyz5kgphkjm5m3z57pgz0swjhzs8"
+        );
+        assert_eq!(
+            wallet.witness_script(5, true).unwrap(),
+            native.witness_script(5, true).unwrap()
+        );
+    }
+
+    /// Master key, and the xpub at `path` below it with its fingerprint.
+    fn origin_at(path: &str) -> (Fingerprint, DerivationPath, Xpub) {
+        let secp = Secp256k1::new();
+        let master = bitcoin::bip32::Xpriv::new_master(Network::Regtest, &[7; 32]).unwrap();
+        let path = DerivationPath::from_str(path).unwrap();
+        let xpub = Xpub::from_priv(&secp, &master.derive_priv(&secp, &path).unwrap());
+        (master.fingerprint(&secp), path, xpub)
+    }
+
+    #[test]
+    fn key_origins_of_any_depth() {
+        let (fp, master, xpub) = origin_at("m");
+        assert!(check_key_origin(fp, &master, &xpub).is_ok());
+        let wrong_fp = Fingerprint::from([0xde, 0xad, 0xbe, 0xef]);
+        assert!(check_key_origin(wrong_fp, &master, &xpub).is_err());
+        assert_eq!(
+            key_expression(fp, &master, &xpub),
+            format!("[{}]{}/<0;1>/*", fp, xpub)
+        );
+
+        let (fp, path, xpub) = origin_at("m/0'");
+        assert!(check_key_origin(fp, &path, &xpub).is_ok());
+        assert_eq!(
+            key_expression(fp, &path, &xpub),
+            format!("[{}/0']{}/<0;1>/*", fp, xpub)
+        );
+        assert!(check_key_origin(wrong_fp, &path, &xpub).is_err());
+
+        let (fp, path, xpub) = origin_at("m/48'/1'/0'/2'/7");
+        assert!(check_key_origin(fp, &path, &xpub).is_ok());
+        assert_eq!(
+            key_expression(fp, &path, &xpub),
+            format!("[{}/48'/1'/0'/2'/7]{}/<0;1>/*", fp, xpub)
+        );
+        // The path must be the xpub's: as long, and ending in its child.
+        let short = DerivationPath::from_str("m/48'/1'/0'/2'").unwrap();
+        assert!(check_key_origin(fp, &short, &xpub).is_err());
+        let other_child = DerivationPath::from_str("m/48'/1'/0'/2'/8").unwrap();
+        assert!(check_key_origin(fp, &other_child, &xpub).is_err());
+    }
+
+    #[test]
+    fn key_networks_check_the_xpub_and_bip48_coin_type() {
+        let wallet = testing::wallet(2, 3, ScriptType::Wsh);
+        let mut origins = wallet.xpub_origins.clone();
+        for network in [Network::Regtest, Network::Testnet, Network::Signet] {
+            assert!(check_key_networks(&origins, network).is_ok());
+        }
+        assert!(check_key_networks(&origins, Network::Bitcoin).is_err());
+
+        // A mainnet xpub, and a test key on the mainnet coin type path.
+        origins[0].xpub.network = NetworkKind::Main;
+        origins[2].derivation_path = DerivationPath::from_str("m/48'/0'/0'/2'").unwrap();
+        let err = check_key_networks(&origins, Network::Regtest)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains(&format!("{}: mainnet xpub", origins[0].name)),
+            "{}",
+            err
+        );
+        assert!(
+            err.contains(&format!("{}: path m/48'/0'/0'/2'", origins[2].name)),
+            "{}",
+            err
+        );
+        assert!(!err.contains(&origins[1].name), "{}", err);
+
+        // Only BIP 48 paths carry a coin type to check.
+        origins[0].xpub.network = NetworkKind::Test;
+        origins[2].derivation_path = DerivationPath::from_str("m/45'/0'").unwrap();
+        assert!(check_key_networks(&origins, Network::Regtest).is_ok());
+    }
+}
+
+/// Wallets of throwaway regtest keys for unit tests.
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::{MultisigWallet, ScriptType, bip48_path};
+    use bitcoin::bip32::{Xpriv, Xpub};
+    use bitcoin::psbt::Psbt;
+    use bitcoin::secp256k1::Secp256k1;
+    use bitcoin::{
+        Amount, Network, OutPoint, ScriptBuf, Transaction, TxIn, TxOut, absolute, transaction,
+    };
+
+    /// Account key of cosigner `seed`, whose master key is seeded with
+    /// its number.
+    pub(crate) fn account_xpriv(seed: u8) -> Xpriv {
+        let secp = Secp256k1::new();
+        let path = bip48_path(Network::Regtest, 0).unwrap();
+        let master = Xpriv::new_master(Network::Regtest, &[seed; 32]).unwrap();
+        master.derive_priv(&secp, &path).unwrap()
+    }
+
+    /// A `threshold`-of-`keys` wallet of cosigners 1 to `keys`.
+    pub(crate) fn wallet(threshold: usize, keys: u8, script_type: ScriptType) -> MultisigWallet {
+        let secp = Secp256k1::new();
+        let path = bip48_path(Network::Regtest, 0).unwrap();
+        let origins: Vec<_> = (1..=keys)
+            .map(|seed| {
+                let master = Xpriv::new_master(Network::Regtest, &[seed; 32]).unwrap();
+                (
+                    master.fingerprint(&secp),
+                    path.clone(),
+                    Xpub::from_priv(&secp, &account_xpriv(seed)),
+                )
+            })
+            .collect();
+        MultisigWallet::from_xpubs(&origins, threshold, script_type, Network::Regtest).unwrap()
+    }
+
+    /// A PSBT spending 50,000 sat from `wallet`'s receive address 0, with
+    /// the input filled in for signing and a 10,000 sat fee.
+    pub(crate) fn psbt(wallet: &MultisigWallet) -> Psbt {
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                ..TxIn::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(40_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: Amount::from_sat(50_000),
+            script_pubkey: wallet.derive_address(0, false).unwrap().script_pubkey(),
+        });
+        wallet
+            .update_psbt_input(&mut psbt.inputs[0], 0, false)
+            .unwrap();
+        psbt
+    }
+}
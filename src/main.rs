@@ -1,9 +1,88 @@
-fn main() {
-    println!("psbt-coordinator: 2-of-3 multisig PSBT toolkit");
-    println!();
-    println!("Available commands:");
-    println!("  cargo run --bin keygen       Generate 3 key pairs");
-    println!("  cargo run --bin coordinator  Create unsigned PSBT");
-    println!("  cargo run --bin signer       Sign PSBT with a key");
-    println!("  cargo run --bin finalizer    Finalize and extract TX");
+use clap::{Arg, Command, value_parser};
+use psbt_coordinator::cli::{
+    COMMANDS, Shell, completions, man_page, write_completions, write_man_pages,
+};
+use std::path::PathBuf;
+
+fn command() -> Command {
+    let dir = || {
+        Arg::new("dir")
+            .long("dir")
+            .value_name("DIR")
+            .value_parser(value_parser!(PathBuf))
+            .help("Write one file per tool into DIR")
+    };
+    let tools: Vec<&str> = COMMANDS.iter().map(|c| c.name).collect();
+    Command::new("psbt-coordinator")
+        .about("M-of-N multisig PSBT toolkit")
+        .disable_help_subcommand(true)
+        .subcommand(
+            Command::new("completions")
+                .about("Shell completions for the commands")
+                .arg(
+                    Arg::new("shell")
+                        .required(true)
+                        .value_parser(value_parser!(Shell)),
+                )
+                .arg(dir()),
+        )
+        .subcommand(
+            Command::new("man")
+                .about("Man pages for the commands")
+                .arg(
+                    Arg::new("tool")
+                        .required_unless_present("dir")
+                        .value_parser(tools),
+                )
+                .arg(dir()),
+        )
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let matches = command().get_matches();
+    match matches.subcommand() {
+        Some(("completions", args)) => {
+            let shell = *args.get_one::<Shell>("shell").ok_or("missing shell")?;
+            match args.get_one::<PathBuf>("dir") {
+                Some(dir) => {
+                    for path in write_completions(shell, dir)? {
+                        println!("Wrote {}", path.display());
+                    }
+                }
+                None => completions(shell, &mut std::io::stdout()),
+            }
+        }
+        Some(("man", args)) => match args.get_one::<PathBuf>("dir") {
+            Some(dir) => {
+                write_man_pages(dir)?;
+                println!("Wrote man pages to {}", dir.display());
+            }
+            None => {
+                let tool = args.get_one::<String>("tool").ok_or("missing tool")?;
+                man_page(tool, &mut std::io::stdout())?;
+            }
+        },
+        _ => {
+            println!("psbt-coordinator: M-of-N multisig PSBT toolkit");
+            println!();
+            println!("Available commands:");
+            let width = COMMANDS.iter().map(|c| c.name.len()).max().unwrap_or(0);
+            for command in COMMANDS {
+                println!(
+                    "  cargo run --features full --bin {:<width$} {}",
+                    command.name,
+                    command.about,
+                    width = width
+                );
+            }
+            println!();
+            println!(
+                "  cargo run --features full -- completions SHELL [--dir DIR]  Shell completions for the commands"
+            );
+            println!(
+                "  cargo run --features full -- man TOOL | --dir DIR          Man pages for the commands"
+            );
+        }
+    }
+    Ok(())
 }
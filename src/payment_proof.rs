@@ -0,0 +1,100 @@
+//! Payment proofs for descriptor destinations.
+//!
+//! When one treasury settles with another by paying into its published
+//! descriptor, the payee has to find the payment and match it to the
+//! settlement. The coordinator records the descriptor and index of each
+//! such output in the PSBT; once the transaction is final, a
+//! [`PaymentProof`] names the output, the derivation it pays and the amount,
+//! and carries the signed transaction. The payee checks it offline against
+//! its own copy of the descriptor, so nothing in the proof has to be
+//! trusted except the transaction, which the txid ties to the chain.
+//! Whether the transaction confirmed is for the payee to look up.
+
+use crate::destinations::descriptor_script_pubkey;
+use crate::schema::SCHEMA_VERSION;
+use crate::{CoordinatorError, output_descriptor};
+use bitcoin::consensus::encode::{deserialize_hex, serialize_hex};
+use bitcoin::psbt::Psbt;
+use bitcoin::{Amount, Transaction};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct PaymentProof {
+    pub schema_version: u32,
+    pub txid: String,
+    /// Output of the transaction that makes the payment.
+    pub vout: u32,
+    pub amount_sat: u64,
+    /// Destination descriptor as the payer used it.
+    pub descriptor: String,
+    /// Address index on the descriptor's receive chain.
+    pub index: u32,
+    /// The signed transaction, hex.
+    pub transaction: String,
+}
+
+impl PaymentProof {
+    /// Proofs for the outputs of `psbt` that record a destination
+    /// descriptor, `tx` being the transaction extracted from it.
+    pub fn for_transaction(psbt: &Psbt, tx: &Transaction) -> Vec<Self> {
+        let txid = tx.compute_txid().to_string();
+        let transaction = serialize_hex(tx);
+        psbt.outputs
+            .iter()
+            .zip(&tx.output)
+            .enumerate()
+            .filter_map(|(vout, (output, txout))| {
+                let (descriptor, index) = output_descriptor(output)?;
+                Some(Self {
+                    schema_version: SCHEMA_VERSION,
+                    txid: txid.clone(),
+                    vout: vout as u32,
+                    amount_sat: txout.value.to_sat(),
+                    descriptor,
+                    index,
+                    transaction: transaction.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// Checks the proof against the payee's `descriptor`: the transaction
+    /// must be the one the txid names, and its output `vout` must pay
+    /// `amount_sat` to address `index` of the descriptor's receive chain.
+    pub fn verify(&self, descriptor: &str) -> Result<Amount, CoordinatorError> {
+        if self.schema_version != SCHEMA_VERSION {
+            return Err(CoordinatorError::Invalid(format!(
+                "proof has schema version {}, expected {}",
+                self.schema_version, SCHEMA_VERSION
+            )));
+        }
+        let tx: Transaction = deserialize_hex(self.transaction.trim())
+            .map_err(|e| CoordinatorError::Invalid(format!("proof transaction: {}", e)))?;
+        if tx.compute_txid().to_string() != self.txid {
+            return Err(CoordinatorError::Invalid(format!(
+                "proof transaction is {}, not {}",
+                tx.compute_txid(),
+                self.txid
+            )));
+        }
+        let output = tx.output.get(self.vout as usize).ok_or_else(|| {
+            CoordinatorError::Invalid(format!("transaction has no output {}", self.vout))
+        })?;
+        if output.script_pubkey != descriptor_script_pubkey(descriptor, self.index)? {
+            return Err(CoordinatorError::Invalid(format!(
+                "output {} does not pay address {} of the descriptor",
+                self.vout, self.index
+            )));
+        }
+        if output.value.to_sat() != self.amount_sat {
+            return Err(CoordinatorError::Invalid(format!(
+                "output {} pays {} sat, the proof claims {}",
+                self.vout,
+                output.value.to_sat(),
+                self.amount_sat
+            )));
+        }
+        Ok(output.value)
+    }
+}
@@ -0,0 +1,90 @@
+//! Which build of this tool produced an artifact.
+//!
+//! Exports and signing receipts carry a [`SoftwareAttestation`] so a file
+//! can be tied to the exact software behavior behind it. Anyone holding
+//! the same build and wallet can rerun `version --attest` and compare the
+//! digest. The compiler version and git commit are captured by `build.rs`.
+
+use crate::MultisigWallet;
+use bitcoin::hashes::{Hash, sha256};
+use serde::{Deserialize, Serialize};
+
+/// rust-bitcoin version this crate is built against, as pinned in
+/// Cargo.toml. Bump together with the dependency.
+pub const BITCOIN_VERSION: &str = "0.32.8";
+/// rust-miniscript version this crate is built against, as pinned in
+/// Cargo.toml. Bump together with the dependency.
+pub const MINISCRIPT_VERSION: &str = "13.0.0";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct SoftwareAttestation {
+    pub name: String,
+    pub version: String,
+    /// Cargo features compiled in, e.g. `cli`, `parallel`.
+    pub features: Vec<String>,
+    pub bitcoin: String,
+    pub miniscript: String,
+    /// `rustc --version` of the compiler that built the tool.
+    #[serde(default)]
+    pub rustc: String,
+    /// Git commit the tool was built from, with `-dirty` for a build with
+    /// uncommitted changes, or `unknown`.
+    #[serde(default)]
+    pub git_commit: String,
+    /// Policy ID of the wallet the artifact is about, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy_id: Option<String>,
+    /// SHA256 over the fields above, see [`SoftwareAttestation::digest`].
+    pub digest: String,
+}
+
+impl SoftwareAttestation {
+    /// Describes this build, tied to `wallet` when there is one.
+    pub fn new(wallet: Option<&MultisigWallet>) -> Self {
+        let features = [
+            ("chain", cfg!(feature = "chain")),
+            ("cli", cfg!(feature = "cli")),
+            ("donation", cfg!(feature = "donation")),
+            ("i18n", cfg!(feature = "i18n")),
+            ("migration", cfg!(feature = "migration")),
+            ("mnemonic", cfg!(feature = "mnemonic")),
+            ("parallel", cfg!(feature = "parallel")),
+            ("schema", cfg!(feature = "schema")),
+        ]
+        .into_iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| name.to_string())
+        .collect();
+        let mut attestation = Self {
+            name: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            features,
+            bitcoin: BITCOIN_VERSION.to_string(),
+            miniscript: MINISCRIPT_VERSION.to_string(),
+            rustc: env!("PSBTCOORD_RUSTC_VERSION").to_string(),
+            git_commit: env!("PSBTCOORD_GIT_COMMIT").to_string(),
+            policy_id: wallet.map(|w| w.policy_id().to_string()),
+            digest: String::new(),
+        };
+        attestation.digest = attestation.digest().to_string();
+        attestation
+    }
+
+    /// SHA256 of one `field=value` line per field, in declaration order,
+    /// with features comma-separated and an empty policy ID when absent.
+    pub fn digest(&self) -> sha256::Hash {
+        let text = format!(
+            "name={}\nversion={}\nfeatures={}\nbitcoin={}\nminiscript={}\nrustc={}\ngit_commit={}\npolicy_id={}\n",
+            self.name,
+            self.version,
+            self.features.join(","),
+            self.bitcoin,
+            self.miniscript,
+            self.rustc,
+            self.git_commit,
+            self.policy_id.as_deref().unwrap_or("")
+        );
+        sha256::Hash::hash(text.as_bytes())
+    }
+}
@@ -0,0 +1,139 @@
+//! Signed receipts binding a signer's identity to what it approved.
+//!
+//! After signing, the signer signs a summary of the PSBT with its account
+//! key. The receipt is non-repudiable evidence of which key approved which
+//! transaction, and can be checked against the account xpubs the
+//! coordinator records in the PSBT's global xpub map. The signed summary
+//! includes the signer's build, so a receipt also shows which software
+//! approved the transaction.
+
+use crate::CoordinatorError;
+use crate::provenance::SoftwareAttestation;
+use crate::schema::SCHEMA_VERSION;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use bitcoin::hashes::{Hash, sha256};
+use bitcoin::psbt::Psbt;
+use bitcoin::secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+use bitcoin::sign_message::{MessageSignature, signed_msg_hash};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ReceiptOutput {
+    pub script_pubkey: String,
+    pub amount_sat: u64,
+}
+
+/// The signed part of a receipt.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ReceiptBody {
+    pub schema_version: u32,
+    pub signer: String,
+    pub fingerprint: String,
+    pub txid: String,
+    /// SHA256 of the PSBT exactly as the signer received it.
+    pub psbt_sha256: String,
+    pub outputs: Vec<ReceiptOutput>,
+    pub fee_sat: Option<u64>,
+    pub signed_inputs: Vec<usize>,
+    pub signed_at: u64,
+    /// Build of the signer; absent in receipts from older versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub software: Option<SoftwareAttestation>,
+}
+
+impl ReceiptBody {
+    pub fn new(
+        signer: &str,
+        fingerprint: &str,
+        received_psbt: &[u8],
+        psbt: &Psbt,
+        signed_inputs: Vec<usize>,
+        signed_at: u64,
+    ) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            signer: signer.to_string(),
+            fingerprint: fingerprint.to_string(),
+            txid: psbt.unsigned_tx.compute_txid().to_string(),
+            psbt_sha256: sha256::Hash::hash(received_psbt).to_string(),
+            outputs: psbt
+                .unsigned_tx
+                .output
+                .iter()
+                .map(|o| ReceiptOutput {
+                    script_pubkey: o.script_pubkey.to_hex_string(),
+                    amount_sat: o.value.to_sat(),
+                })
+                .collect(),
+            fee_sat: psbt.fee().ok().map(|f| f.to_sat()),
+            signed_inputs,
+            signed_at,
+            software: Some(SoftwareAttestation::new(None)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct SigningReceipt {
+    #[serde(flatten)]
+    pub body: ReceiptBody,
+    /// Public key of the signer's account xpub.
+    pub pubkey: String,
+    /// Base64 signed-message signature over the JSON-encoded body.
+    pub signature: String,
+}
+
+impl SigningReceipt {
+    pub fn sign(body: ReceiptBody, key: &SecretKey) -> Result<Self, CoordinatorError> {
+        let secp = Secp256k1::signing_only();
+        let msg_hash = signed_msg_hash(&serde_json::to_string(&body)?);
+        let msg = Message::from_digest(msg_hash.to_byte_array());
+        let sig = MessageSignature::new(secp.sign_ecdsa_recoverable(&msg, key), true);
+        Ok(Self {
+            body,
+            pubkey: PublicKey::from_secret_key(&secp, key).to_string(),
+            signature: STANDARD.encode(sig.serialize()),
+        })
+    }
+
+    /// Checks the signature and returns the signing public key.
+    pub fn verify(&self) -> Result<PublicKey, CoordinatorError> {
+        let bytes = STANDARD
+            .decode(self.signature.trim())
+            .map_err(CoordinatorError::key)?;
+        let sig = MessageSignature::from_slice(&bytes).map_err(CoordinatorError::key)?;
+        let msg_hash = signed_msg_hash(&serde_json::to_string(&self.body)?);
+        let recovered = sig
+            .recover_pubkey(&Secp256k1::verification_only(), msg_hash)
+            .map_err(CoordinatorError::key)?;
+        if recovered.inner.to_string() != self.pubkey {
+            return Err(CoordinatorError::key(
+                "receipt signature does not match its public key",
+            ));
+        }
+        Ok(recovered.inner)
+    }
+
+    /// Verifies the receipt and that it was made by one of the account keys
+    /// listed in `psbt`, for the same transaction.
+    pub fn verify_against(&self, psbt: &Psbt) -> Result<(), CoordinatorError> {
+        let pubkey = self.verify()?;
+        if self.body.txid != psbt.unsigned_tx.compute_txid().to_string() {
+            return Err(CoordinatorError::psbt(
+                "receipt is for a different transaction",
+            ));
+        }
+        let known = psbt.xpub.iter().any(|(xpub, (fp, _))| {
+            xpub.public_key == pubkey && fp.to_string() == self.body.fingerprint
+        });
+        if !known {
+            return Err(CoordinatorError::key(
+                "receipt key is not one of the wallet's account xpubs",
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+//! Versioned JSON structures for exported artifacts.
+//!
+//! Every JSON file this tool writes for other programs carries a
+//! `schema_version` and, with the `schema` feature, has a JSON Schema
+//! available via the `schema` command, so integrators can code against
+//! the structures directly rather than scraping text output. The version
+//! is bumped whenever a field is removed or changes meaning; new optional
+//! fields do not bump it.
+
+use crate::provenance::SoftwareAttestation;
+use crate::{CosignerMetadata, MultisigWallet, format_path};
+use serde::{Deserialize, Serialize};
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Artifacts with a published schema, as accepted by `json_schema` with
+/// the `schema` feature.
+pub const ARTIFACTS: &[&str] = &[
+    "wallet",
+    "receipt",
+    "audit",
+    "preimages",
+    "bundle",
+    "signer-policy",
+    "payment-proof",
+];
+
+/// Public wallet definition: everything needed to rebuild a watch-only
+/// copy of the wallet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct WalletDefinition {
+    pub schema_version: u32,
+    pub descriptor: String,
+    pub network: String,
+    pub threshold: usize,
+    pub policy_id: String,
+    pub cosigners: Vec<CosignerDefinition>,
+    /// Build that wrote the file; absent in files from older versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub software: Option<SoftwareAttestation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct CosignerDefinition {
+    pub name: String,
+    pub fingerprint: String,
+    pub derivation_path: String,
+    pub xpub: String,
+    #[serde(default, skip_serializing_if = "CosignerMetadata::is_empty")]
+    pub metadata: CosignerMetadata,
+}
+
+impl WalletDefinition {
+    pub fn from_wallet(wallet: &MultisigWallet) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            descriptor: wallet.descriptor_with_checksum(),
+            network: wallet.network.to_string(),
+            threshold: wallet.threshold,
+            policy_id: wallet.policy_id().to_string(),
+            cosigners: wallet
+                .xpub_origins
+                .iter()
+                .map(|o| CosignerDefinition {
+                    name: o.name.clone(),
+                    fingerprint: o.fingerprint.to_string(),
+                    derivation_path: format_path(&o.derivation_path),
+                    xpub: o.xpub.to_string(),
+                    metadata: o.metadata.clone(),
+                })
+                .collect(),
+            software: Some(SoftwareAttestation::new(Some(wallet))),
+        }
+    }
+}
+
+/// Returns the JSON Schema for one of [`ARTIFACTS`], pretty-printed.
+#[cfg(feature = "schema")]
+pub fn json_schema(artifact: &str) -> Result<String, crate::CoordinatorError> {
+    use crate::CoordinatorError;
+    use crate::bundle::OfflineBundle;
+    use crate::export::AuditExport;
+    use crate::payment_proof::PaymentProof;
+    use crate::receipt::SigningReceipt;
+    use crate::signer_policy::SignedSignerPolicy;
+    use crate::verify::SighashPreimage;
+    use schemars::schema_for;
+
+    let schema = match artifact {
+        "wallet" => schema_for!(WalletDefinition),
+        "receipt" => schema_for!(SigningReceipt),
+        "audit" => schema_for!(AuditExport),
+        "preimages" => schema_for!(Vec<SighashPreimage>),
+        "bundle" => schema_for!(OfflineBundle),
+        "signer-policy" => schema_for!(SignedSignerPolicy),
+        "payment-proof" => schema_for!(PaymentProof),
+        other => {
+            return Err(CoordinatorError::Invalid(format!(
+                "unknown artifact {} (expected one of: {})",
+                other,
+                ARTIFACTS.join(", ")
+            )));
+        }
+    };
+    Ok(serde_json::to_string_pretty(&schema)?)
+}
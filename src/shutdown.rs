@@ -0,0 +1,81 @@
+//! Graceful shutdown for the tools that run until stopped: the donation
+//! page, `signer_policy serve` and the broadcaster.
+//!
+//! SIGINT and SIGTERM (Ctrl-C on Windows) do not kill these on the spot.
+//! They set a flag that the tool checks between requests or polls, so the
+//! request in progress finishes writing `wallet.json` or
+//! `signer_policy.json`, and releases its file lock, before the process
+//! exits.
+
+use crate::CoordinatorError;
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// How often a waiting tool checks whether it was asked to stop.
+const CHECK_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Whether SIGINT or SIGTERM has arrived.
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    requested: Arc<AtomicBool>,
+}
+
+impl Shutdown {
+    /// Installs the signal handler. A process can only install one.
+    pub fn install() -> Result<Self, CoordinatorError> {
+        let requested = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&requested);
+        ctrlc::set_handler(move || {
+            eprintln!("Stopping after the current request");
+            flag.store(true, Ordering::SeqCst);
+        })
+        .map_err(|e| CoordinatorError::Invalid(format!("cannot handle signals: {}", e)))?;
+        Ok(Self { requested })
+    }
+
+    pub fn requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    /// Sleeps for `duration`, or less if asked to stop meanwhile. Returns
+    /// whether the tool should stop.
+    pub fn wait(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        while !self.requested() {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            std::thread::sleep(CHECK_INTERVAL.min(deadline - now));
+        }
+        true
+    }
+
+    /// The connections to `listener`, until asked to stop. An error
+    /// accepting one connection is passed on, not the end of the stream.
+    pub fn incoming<'a>(
+        &'a self,
+        listener: &'a TcpListener,
+    ) -> Result<impl Iterator<Item = io::Result<TcpStream>> + 'a, CoordinatorError> {
+        // A blocking accept() would not return until the next client.
+        listener.set_nonblocking(true)?;
+        Ok(std::iter::from_fn(move || {
+            loop {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        return Some(stream.set_nonblocking(false).map(|()| stream));
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        if self.wait(CHECK_INTERVAL) {
+                            return None;
+                        }
+                    }
+                    Err(e) => return Some(Err(e)),
+                }
+            }
+        }))
+    }
+}
@@ -0,0 +1,643 @@
+//! Signer policy documents that only a quorum of cosigners can change.
+//!
+//! The policy holds the signer's withdrawal allow-list (the internal
+//! wallets payments may go to without counting as external) and its
+//! external payment cap. Each cosigner signs the document with its account
+//! key, as for signing receipts, and a signer only accepts a document
+//! signed by at least the wallet's threshold of cosigners. Updates can
+//! then arrive over the network instead of by logging in to the signer.
+//!
+//! It can also hold approval tiers: larger withdrawals may need a given
+//! cosigner, such as the cold key, among the signers, more signatures
+//! than the script's threshold, or a waiting period. The waiting period is
+//! a time lock on the transaction, which consensus enforces; signers check
+//! it and leave room in the threshold for required cosigners, and the
+//! finalizer checks the whole tier before it builds the transaction.
+
+use crate::destinations::{InternalDestinations, InternalWallet};
+use crate::schema::SCHEMA_VERSION;
+use crate::verify::verify_partial_sigs;
+use crate::{CoordinatorError, MultisigWallet, write_atomic};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use bitcoin::bip32::Fingerprint;
+use bitcoin::hashes::Hash;
+use bitcoin::psbt::{Input, Psbt};
+use bitcoin::secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+use bitcoin::sign_message::{MessageSignature, signed_msg_hash};
+use bitcoin::{Amount, Script, absolute};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Where the signer keeps its current policy.
+pub const SIGNER_POLICY_FILE: &str = "signer_policy.json";
+
+/// Where the signer records the highest policy sequence it has installed
+/// or accepted for each wallet.
+pub const POLICY_SEQUENCE_FILE: &str = "signer_policy_sequence.json";
+
+/// The signed part of a signer policy.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct SignerPolicy {
+    pub schema_version: u32,
+    /// Policy ID of the wallet the document is for, so signatures cannot be
+    /// replayed on another wallet.
+    pub policy_id: String,
+    /// Must grow with every update; older documents are refused.
+    pub sequence: u64,
+    #[serde(default)]
+    pub internal_wallets: Vec<InternalWallet>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_external_sat: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub approval_tiers: Vec<ApprovalTier>,
+}
+
+/// Extra approval for withdrawals whose external payments total more than
+/// `above_sat`. Below the lowest tier, the wallet's threshold is enough.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ApprovalTier {
+    pub above_sat: u64,
+    /// Fingerprints of cosigners that must be among the signers.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub required: Vec<String>,
+    /// Signatures needed, when more than the wallet's threshold. The script
+    /// accepts the threshold, so only the finalizer can enforce this.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signatures: Option<usize>,
+    /// Seconds the transaction's time lock must lie beyond the moment each
+    /// cosigner signs, so it cannot be mined until then.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub delay_secs: u64,
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct PolicySignature {
+    pub fingerprint: String,
+    /// Public key of the cosigner's account xpub.
+    pub pubkey: String,
+    /// Base64 signed-message signature over the JSON-encoded policy.
+    pub signature: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct SignedSignerPolicy {
+    pub policy: SignerPolicy,
+    #[serde(default)]
+    pub signatures: Vec<PolicySignature>,
+}
+
+impl SignerPolicy {
+    pub fn new(wallet: &MultisigWallet, sequence: u64) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            policy_id: wallet.policy_id().to_string(),
+            sequence,
+            internal_wallets: Vec::new(),
+            max_external_sat: None,
+            approval_tiers: Vec::new(),
+        }
+    }
+
+    /// The tier for external payments totalling `external`, if any.
+    pub fn approval_tier(&self, external: Amount) -> Option<&ApprovalTier> {
+        self.approval_tiers
+            .iter()
+            .filter(|tier| external.to_sat() > tier.above_sat)
+            .max_by_key(|tier| tier.above_sat)
+    }
+
+    /// Total of `psbt`'s outputs that pay neither `wallet`, among its first
+    /// `lookahead` addresses on each chain, nor an internal wallet.
+    pub fn external_total(
+        &self,
+        psbt: &Psbt,
+        wallet: &MultisigWallet,
+        lookahead: u32,
+    ) -> Result<Amount, CoordinatorError> {
+        let internal = InternalDestinations::new(&self.internal_wallets, lookahead)?;
+        let mut total = Amount::ZERO;
+        for txout in &psbt.unsigned_tx.output {
+            let script: &Script = &txout.script_pubkey;
+            if internal.label(script).is_some() || wallet.is_mine(script, lookahead)?.is_some() {
+                continue;
+            }
+            total = total
+                .checked_add(txout.value)
+                .ok_or_else(|| CoordinatorError::invalid("external payment amounts overflow"))?;
+        }
+        Ok(total)
+    }
+}
+
+impl ApprovalTier {
+    /// Signatures the tier needs on each input.
+    pub fn signatures_needed(&self, wallet: &MultisigWallet) -> usize {
+        self.signatures
+            .map_or(wallet.threshold, |n| n.max(wallet.threshold))
+    }
+
+    pub fn required_cosigners(&self) -> Result<Vec<Fingerprint>, CoordinatorError> {
+        self.required
+            .iter()
+            .map(|fp| {
+                Fingerprint::from_str(fp).map_err(|e| {
+                    CoordinatorError::Key(format!("approval tier cosigner {}: {}", fp, e))
+                })
+            })
+            .collect()
+    }
+
+    /// Checks that `wallet`'s cosigners can meet the tier.
+    pub fn check(&self, wallet: &MultisigWallet) -> Result<(), CoordinatorError> {
+        let required = self.required_cosigners()?;
+        if let Some(fp) = required
+            .iter()
+            .find(|fp| !wallet.xpub_origins.iter().any(|o| o.fingerprint == **fp))
+        {
+            return Err(CoordinatorError::Invalid(format!(
+                "approval tier requires {}, which is not a cosigner",
+                fp
+            )));
+        }
+        if required.len() > wallet.threshold {
+            return Err(CoordinatorError::Invalid(format!(
+                "approval tier requires {} cosigners, more than the threshold of {}",
+                required.len(),
+                wallet.threshold
+            )));
+        }
+        if self.signatures_needed(wallet) > wallet.xpub_origins.len() {
+            return Err(CoordinatorError::Invalid(format!(
+                "approval tier needs {} signatures from {} cosigners",
+                self.signatures_needed(wallet),
+                wallet.xpub_origins.len()
+            )));
+        }
+        Ok(())
+    }
+
+    /// For a tier with a waiting period, checks that `psbt`'s transaction
+    /// is time-locked to unix time `not_before` or later, with every input
+    /// leaving the lock in force. The request's creation time is the
+    /// coordinator's word; the lock is the network's.
+    fn check_time_lock(&self, psbt: &Psbt, not_before: u64) -> Result<(), CoordinatorError> {
+        if self.delay_secs == 0 {
+            return Ok(());
+        }
+        let absolute::LockTime::Seconds(time) = psbt.unsigned_tx.lock_time else {
+            return Err(CoordinatorError::Psbt(format!(
+                "approval tier has a {}h waiting period, but the transaction is not time-locked; \
+                 create it with the coordinator's --lock-hours",
+                self.delay_secs / 3600
+            )));
+        };
+        if psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .any(|input| !input.sequence.enables_absolute_lock_time())
+        {
+            return Err(CoordinatorError::psbt(
+                "an input's sequence disables the transaction's time lock",
+            ));
+        }
+        if u64::from(time.to_consensus_u32()) < not_before {
+            return Err(CoordinatorError::Psbt(format!(
+                "approval tier needs the transaction time-locked until unix time {} or later, \
+                 it is locked until {}",
+                not_before,
+                time.to_consensus_u32()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks that cosigner `signer` may sign `psbt` at unix time `now`:
+    /// the transaction cannot be mined until the waiting period from now is
+    /// over and, unless `signer` is a required
+    /// cosigner, the other cosigners' signatures leave room in the
+    /// threshold for every required one. Required cosigners therefore sign
+    /// before the threshold fills up, and the claimed signatures need not
+    /// be verified: a forged one only makes other signers refuse.
+    pub fn check_signer(
+        &self,
+        wallet: &MultisigWallet,
+        psbt: &Psbt,
+        signer: Fingerprint,
+        now: u64,
+    ) -> Result<(), CoordinatorError> {
+        self.check_time_lock(psbt, now.saturating_add(self.delay_secs))?;
+        let required = self.required_cosigners()?;
+        if required.contains(&signer) {
+            return Ok(());
+        }
+        let room = wallet
+            .threshold
+            .checked_sub(required.len())
+            .ok_or_else(|| {
+                CoordinatorError::Invalid(format!(
+                    "approval tier requires {} cosigners, more than the threshold of {}",
+                    required.len(),
+                    wallet.threshold
+                ))
+            })?;
+        for (idx, input) in psbt
+            .inputs
+            .iter()
+            .enumerate()
+            .filter(|(_, i)| is_wallet_input(i))
+        {
+            let others = input_signers(input)
+                .into_iter()
+                .filter(|fp| *fp != Some(signer) && !fp.is_some_and(|fp| required.contains(&fp)))
+                .count();
+            if others + 1 > room {
+                let names: Vec<_> = required
+                    .iter()
+                    .map(|fp| wallet.cosigner_label(*fp))
+                    .collect();
+                return Err(CoordinatorError::Psbt(format!(
+                    "input {}: approval tier needs {} among the signers; they must sign first",
+                    idx,
+                    names.join(", ")
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that `psbt` may be finalized: it carries the waiting period's
+    /// time lock, which signers checked against the time they signed, and
+    /// every wallet input carries valid signatures from each required
+    /// cosigner and from as many cosigners as the tier needs.
+    pub fn check_approved(
+        &self,
+        wallet: &MultisigWallet,
+        psbt: &Psbt,
+    ) -> Result<(), CoordinatorError> {
+        self.check_time_lock(psbt, 0)?;
+        let required = self.required_cosigners()?;
+        let invalid: Vec<_> = verify_partial_sigs(psbt)
+            .into_iter()
+            .filter(|check| !check.is_valid())
+            .collect();
+        for (idx, input) in psbt
+            .inputs
+            .iter()
+            .enumerate()
+            .filter(|(_, i)| is_wallet_input(i))
+        {
+            let mut signers: BTreeSet<Fingerprint> =
+                input_signers(input).into_iter().flatten().collect();
+            for check in invalid.iter().filter(|check| check.input == idx) {
+                if let Some(fp) = check.fingerprint {
+                    signers.remove(&fp);
+                }
+            }
+            if let Some(fp) = required.iter().find(|fp| !signers.contains(*fp)) {
+                return Err(CoordinatorError::Psbt(format!(
+                    "input {}: approval tier needs a signature from {}",
+                    idx,
+                    wallet.cosigner_label(*fp)
+                )));
+            }
+            let needed = self.signatures_needed(wallet);
+            if signers.len() < needed {
+                return Err(CoordinatorError::Psbt(format!(
+                    "input {}: approval tier needs {} signatures, has {}",
+                    idx,
+                    needed,
+                    signers.len()
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Inputs spending the multisig, as opposed to single-sig ones mixed in.
+fn is_wallet_input(input: &Input) -> bool {
+    input.witness_script.is_some() || !input.tap_scripts.is_empty()
+}
+
+/// Master fingerprint of each key with a signature on `input`, `None` when
+/// the key's origin is not listed.
+fn input_signers(input: &Input) -> Vec<Option<Fingerprint>> {
+    let ecdsa = input
+        .partial_sigs
+        .keys()
+        .map(|pk| input.bip32_derivation.get(&pk.inner).map(|(fp, _)| *fp));
+    let schnorr = input
+        .tap_script_sigs
+        .keys()
+        .map(|(key, _)| input.tap_key_origins.get(key).map(|(_, (fp, _))| *fp));
+    ecdsa.chain(schnorr).collect()
+}
+
+impl SignedSignerPolicy {
+    pub fn new(policy: SignerPolicy) -> Self {
+        Self {
+            policy,
+            signatures: Vec::new(),
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, CoordinatorError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        serde_json::from_str(&text)
+            .map_err(|e| CoordinatorError::Invalid(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), CoordinatorError> {
+        Ok(write_atomic(path, serde_json::to_string_pretty(self)?)?)
+    }
+
+    /// Adds this cosigner's signature, replacing any earlier one.
+    pub fn sign(&mut self, fingerprint: &str, key: &SecretKey) -> Result<(), CoordinatorError> {
+        let secp = Secp256k1::signing_only();
+        let msg_hash = signed_msg_hash(&serde_json::to_string(&self.policy)?);
+        let msg = Message::from_digest(msg_hash.to_byte_array());
+        let sig = MessageSignature::new(secp.sign_ecdsa_recoverable(&msg, key), true);
+        self.signatures.retain(|s| s.fingerprint != fingerprint);
+        self.signatures.push(PolicySignature {
+            fingerprint: fingerprint.to_string(),
+            pubkey: PublicKey::from_secret_key(&secp, key).to_string(),
+            signature: STANDARD.encode(sig.serialize()),
+        });
+        Ok(())
+    }
+
+    /// Checks that the document is for `wallet` and that at least its
+    /// threshold of cosigners signed it. Any signature that is invalid or
+    /// not from a cosigner is an error, not just left uncounted. Returns
+    /// the names of the signing cosigners.
+    pub fn verify(&self, wallet: &MultisigWallet) -> Result<Vec<String>, CoordinatorError> {
+        if self.policy.policy_id != wallet.policy_id().to_string() {
+            return Err(CoordinatorError::Invalid(format!(
+                "signer policy is for wallet {}, not {}",
+                self.policy.policy_id,
+                wallet.policy_id()
+            )));
+        }
+        let secp = Secp256k1::verification_only();
+        let msg_hash = signed_msg_hash(&serde_json::to_string(&self.policy)?);
+        let mut signers = BTreeSet::new();
+        for signature in &self.signatures {
+            let position = wallet
+                .xpub_origins
+                .iter()
+                .position(|o| {
+                    o.fingerprint.to_string() == signature.fingerprint
+                        && o.xpub.public_key.to_string() == signature.pubkey
+                })
+                .ok_or_else(|| {
+                    CoordinatorError::Key(format!(
+                        "signature by {} is not from a cosigner",
+                        signature.fingerprint
+                    ))
+                })?;
+            let origin = &wallet.xpub_origins[position];
+            let bytes = STANDARD
+                .decode(signature.signature.trim())
+                .map_err(CoordinatorError::key)?;
+            let sig = MessageSignature::from_slice(&bytes).map_err(CoordinatorError::key)?;
+            let recovered = sig
+                .recover_pubkey(&secp, msg_hash)
+                .map_err(CoordinatorError::key)?;
+            if recovered.inner != origin.xpub.public_key {
+                return Err(CoordinatorError::Key(format!(
+                    "invalid signature by {}",
+                    origin.name
+                )));
+            }
+            signers.insert(position);
+        }
+        if signers.len() < wallet.threshold {
+            return Err(CoordinatorError::Key(format!(
+                "signer policy has {} of the {} cosigner signatures it needs",
+                signers.len(),
+                wallet.threshold
+            )));
+        }
+        let mut tier_floors = BTreeSet::new();
+        for tier in &self.policy.approval_tiers {
+            if !tier_floors.insert(tier.above_sat) {
+                return Err(CoordinatorError::Invalid(format!(
+                    "two approval tiers start above {} sat",
+                    tier.above_sat
+                )));
+            }
+            tier.check(wallet)?;
+        }
+        Ok(signers
+            .into_iter()
+            .map(|i| wallet.xpub_origins[i].name.clone())
+            .collect())
+    }
+
+    /// Checks that the document may replace `current`: it verifies, and
+    /// its sequence number is higher, so an old document cannot be replayed.
+    pub fn check_update(
+        &self,
+        current: Option<&SignedSignerPolicy>,
+        wallet: &MultisigWallet,
+    ) -> Result<Vec<String>, CoordinatorError> {
+        let signers = self.verify(wallet)?;
+        if let Some(current) = current.filter(|c| self.policy.sequence <= c.policy.sequence) {
+            return Err(CoordinatorError::Conflict(format!(
+                "signer policy sequence {} is not newer than the current {}",
+                self.policy.sequence, current.policy.sequence
+            )));
+        }
+        Ok(signers)
+    }
+}
+
+/// Highest signer policy sequence installed for each wallet, by policy ID.
+/// Deleting `signer_policy.json` or putting back an older one would
+/// otherwise lift the guardrails without a quorum.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PolicySequences {
+    pub schema_version: u32,
+    #[serde(default)]
+    pub sequences: BTreeMap<String, u64>,
+}
+
+impl PolicySequences {
+    /// Reads the record at `path`, normally [`POLICY_SEQUENCE_FILE`]; empty
+    /// when no policy was installed.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, CoordinatorError> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self {
+                schema_version: SCHEMA_VERSION,
+                sequences: BTreeMap::new(),
+            });
+        }
+        let text = std::fs::read_to_string(path)?;
+        serde_json::from_str(&text)
+            .map_err(|e| CoordinatorError::Invalid(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Raises the recorded sequence for `policy`'s wallet, never lowering
+    /// it, and saves the record to `path` if it changed.
+    pub fn record(
+        &mut self,
+        path: impl AsRef<Path>,
+        policy: &SignerPolicy,
+    ) -> Result<(), CoordinatorError> {
+        let recorded = self.sequences.get(&policy.policy_id).copied();
+        if recorded.is_some_and(|sequence| sequence >= policy.sequence) {
+            return Ok(());
+        }
+        self.sequences
+            .insert(policy.policy_id.clone(), policy.sequence);
+        self.schema_version = SCHEMA_VERSION;
+        Ok(write_atomic(path, serde_json::to_string_pretty(self)?)?)
+    }
+
+    /// Refuses `policy` if it is older than the one recorded for its wallet.
+    pub fn check_not_older(&self, policy: &SignerPolicy) -> Result<(), CoordinatorError> {
+        match self.sequences.get(&policy.policy_id) {
+            Some(&recorded) if policy.sequence < recorded => {
+                Err(CoordinatorError::Conflict(format!(
+                    "signer policy sequence {} is older than the installed {}",
+                    policy.sequence, recorded
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks the policy found next to `wallet` against the record: one
+    /// must be there if one was ever installed, and it must not be older.
+    pub fn check_installed(
+        &self,
+        installed: Option<&SignerPolicy>,
+        wallet: &MultisigWallet,
+    ) -> Result<(), CoordinatorError> {
+        let policy_id = wallet.policy_id().to_string();
+        match (installed, self.sequences.get(&policy_id)) {
+            (Some(policy), _) => self.check_not_older(policy),
+            (None, Some(recorded)) => Err(CoordinatorError::Conflict(format!(
+                "signer policy {} was installed for this wallet, but {} is missing",
+                recorded, SIGNER_POLICY_FILE
+            ))),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ScriptType;
+    use crate::testing;
+    use bitcoin::{ScriptBuf, Sequence, Transaction, TxIn, TxOut, transaction};
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn psbt(lock_time: absolute::LockTime, sequence: Sequence) -> Psbt {
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time,
+            input: vec![TxIn {
+                sequence,
+                ..TxIn::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        Psbt::from_unsigned_tx(tx).unwrap()
+    }
+
+    fn locked_until(time: u64) -> absolute::LockTime {
+        absolute::LockTime::from_time(u32::try_from(time).unwrap()).unwrap()
+    }
+
+    fn tier(required: &[Fingerprint], delay_secs: u64) -> ApprovalTier {
+        ApprovalTier {
+            above_sat: 0,
+            required: required.iter().map(ToString::to_string).collect(),
+            signatures: None,
+            delay_secs,
+        }
+    }
+
+    #[test]
+    fn check_signer_refuses_more_required_cosigners_than_the_threshold() {
+        let wallet = testing::wallet(2, 4, ScriptType::Wsh);
+        let fps: Vec<_> = wallet.xpub_origins.iter().map(|o| o.fingerprint).collect();
+        let tier = tier(&fps[..3], 0);
+        assert!(tier.check(&wallet).is_err());
+        let psbt = psbt(absolute::LockTime::ZERO, Sequence::ENABLE_RBF_NO_LOCKTIME);
+        assert!(tier.check_signer(&wallet, &psbt, fps[3], NOW).is_err());
+    }
+
+    #[test]
+    fn check_signer_lets_cosigners_sign_with_room_left() {
+        let wallet = testing::wallet(2, 3, ScriptType::Wsh);
+        let fps: Vec<_> = wallet.xpub_origins.iter().map(|o| o.fingerprint).collect();
+        let tier = tier(&fps[..1], 0);
+        let psbt = psbt(absolute::LockTime::ZERO, Sequence::ENABLE_RBF_NO_LOCKTIME);
+        assert!(tier.check_signer(&wallet, &psbt, fps[1], NOW).is_ok());
+        assert!(tier.check_signer(&wallet, &psbt, fps[0], NOW).is_ok());
+    }
+
+    #[test]
+    fn delay_is_a_time_lock_beyond_signing() {
+        let wallet = testing::wallet(2, 3, ScriptType::Wsh);
+        let signer = wallet.xpub_origins[0].fingerprint;
+        let tier = tier(&[], 3600);
+
+        let unlocked = psbt(absolute::LockTime::ZERO, Sequence::ENABLE_RBF_NO_LOCKTIME);
+        assert!(tier.check_signer(&wallet, &unlocked, signer, NOW).is_err());
+        assert!(tier.check_approved(&wallet, &unlocked).is_err());
+
+        let locked = psbt(locked_until(NOW + 7200), Sequence::ENABLE_RBF_NO_LOCKTIME);
+        assert!(tier.check_signer(&wallet, &locked, signer, NOW).is_ok());
+        // Signing later leaves less than the delay before the lock.
+        assert!(
+            tier.check_signer(&wallet, &locked, signer, NOW + 3601)
+                .is_err()
+        );
+
+        let final_sequence = psbt(locked_until(NOW + 7200), Sequence::MAX);
+        assert!(
+            tier.check_signer(&wallet, &final_sequence, signer, NOW)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn installed_policy_cannot_be_removed_or_rolled_back() {
+        let wallet = testing::wallet(2, 3, ScriptType::Wsh);
+        let mut sequences = PolicySequences::default();
+        assert!(sequences.check_installed(None, &wallet).is_ok());
+
+        sequences
+            .sequences
+            .insert(wallet.policy_id().to_string(), 3);
+        assert!(sequences.check_installed(None, &wallet).is_err());
+        let older = SignerPolicy::new(&wallet, 2);
+        assert!(sequences.check_installed(Some(&older), &wallet).is_err());
+        let current = SignerPolicy::new(&wallet, 3);
+        assert!(sequences.check_installed(Some(&current), &wallet).is_ok());
+
+        let other = testing::wallet(2, 2, ScriptType::Wsh);
+        assert!(sequences.check_installed(None, &other).is_ok());
+    }
+}
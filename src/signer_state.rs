@@ -0,0 +1,385 @@
+//! Moving a signer's guardrails to a new machine.
+//!
+//! A signer's policy state is the files next to its key: the wallet files
+//! it signs for (`wallet.json`, `wallet_account1.json`, ...), the
+//! quorum-signed `signer_policy.json` with its allow-list, cap and
+//! sequence number, and its latest signing receipt. `export` packs them
+//! into one file, encrypted and signed with the signer's account key;
+//! `import` on the replacement machine, holding the same key restored from
+//! its mnemonic, checks the signature, decrypts, and installs them. Without
+//! this a new machine starts with no wallet and no policy, and signs
+//! whatever it is given.
+//!
+//! The encryption key is derived from the account private key, so only the
+//! signer itself can read the package, and no passphrase is needed.
+
+use crate::CoordinatorError;
+use crate::schema::SCHEMA_VERSION;
+use crate::signer_policy::{
+    POLICY_SEQUENCE_FILE, PolicySequences, SIGNER_POLICY_FILE, SignedSignerPolicy,
+};
+use crate::wallet_file::{WALLET_FILE, WalletFile};
+use crate::write_atomic;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use bitcoin::hashes::{Hash, HashEngine, Hmac, HmacEngine, sha256};
+use bitcoin::secp256k1::rand::{RngCore, thread_rng};
+use bitcoin::secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+use bitcoin::sign_message::{MessageSignature, signed_msg_hash};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Default name of an exported package.
+pub const STATE_FILE: &str = "signer_state.json";
+
+/// One file of the signer's state, by name in the signer's directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateFile {
+    pub name: String,
+    pub contents: String,
+}
+
+/// The files a package carries, before encryption.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignerState {
+    pub schema_version: u32,
+    pub files: Vec<StateFile>,
+}
+
+/// Whether `name` is a file a package may carry: a plain file name, so an
+/// import cannot write outside the signer's directory.
+fn is_state_file(name: &str, signer: &str) -> bool {
+    let is_wallet = name == WALLET_FILE
+        || name
+            .strip_prefix("wallet_account")
+            .and_then(|rest| rest.strip_suffix(".json"))
+            .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()));
+    let is_plain = !name.contains(['/', '\\']);
+    is_plain && (is_wallet || name == SIGNER_POLICY_FILE || name == receipt_file(signer))
+}
+
+fn receipt_file(signer: &str) -> String {
+    format!("receipt_{}.json", signer)
+}
+
+impl SignerState {
+    /// Reads the state of signer `signer` (its key file name) from the
+    /// current directory.
+    pub fn collect(signer: &str) -> Result<Self, CoordinatorError> {
+        let mut names: Vec<String> = std::fs::read_dir(".")?
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|name| is_state_file(name, signer))
+            .collect();
+        names.sort();
+        let files = names
+            .into_iter()
+            .map(|name| {
+                let contents = std::fs::read_to_string(&name)?;
+                Ok(StateFile { name, contents })
+            })
+            .collect::<Result<_, std::io::Error>>()?;
+        Ok(Self {
+            schema_version: SCHEMA_VERSION,
+            files,
+        })
+    }
+
+    fn file(&self, name: &str) -> Option<&StateFile> {
+        self.files.iter().find(|f| f.name == name)
+    }
+
+    /// Checks what the package would install: only state files, wallet
+    /// files that load, and a signer policy that still verifies against the
+    /// packaged wallet.
+    pub fn check(&self, signer: &str) -> Result<(), CoordinatorError> {
+        for file in &self.files {
+            if !is_state_file(&file.name, signer) {
+                return Err(CoordinatorError::Invalid(format!(
+                    "package holds unexpected file {}",
+                    file.name
+                )));
+            }
+            if file.name.starts_with("wallet") {
+                serde_json::from_str::<WalletFile>(&file.contents)
+                    .map_err(|e| CoordinatorError::Invalid(format!("{}: {}", file.name, e)))?
+                    .wallet()?;
+            }
+        }
+        if let Some(file) = self.file(SIGNER_POLICY_FILE) {
+            let document: SignedSignerPolicy = serde_json::from_str(&file.contents)?;
+            let wallet = self.file(WALLET_FILE).ok_or_else(|| {
+                CoordinatorError::Invalid(format!("{} without {}", SIGNER_POLICY_FILE, WALLET_FILE))
+            })?;
+            let wallet = serde_json::from_str::<WalletFile>(&wallet.contents)?.wallet()?;
+            document.verify(&wallet)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the files into `dir`, the signer's working directory.
+    /// Existing files are only replaced with `force`, and a signer policy
+    /// never by one with a lower sequence number than the installed or
+    /// recorded one, so an old package cannot roll guardrails back.
+    /// Returns the names written.
+    pub fn install(&self, dir: &Path, force: bool) -> Result<Vec<String>, CoordinatorError> {
+        let sequence_file = dir.join(POLICY_SEQUENCE_FILE);
+        let mut sequences = PolicySequences::load(&sequence_file)?;
+        let imported = match self.file(SIGNER_POLICY_FILE) {
+            Some(file) => Some(serde_json::from_str::<SignedSignerPolicy>(&file.contents)?),
+            None => None,
+        };
+        if let Some(imported) = &imported {
+            sequences.check_not_older(&imported.policy)?;
+        }
+        for file in &self.files {
+            let path = dir.join(&file.name);
+            if !path.exists() {
+                continue;
+            }
+            if !force {
+                return Err(CoordinatorError::Conflict(format!(
+                    "{} already exists; pass --force to replace it",
+                    file.name
+                )));
+            }
+            if file.name == SIGNER_POLICY_FILE {
+                let current = SignedSignerPolicy::load(&path)?;
+                let imported: SignedSignerPolicy = serde_json::from_str(&file.contents)?;
+                if imported.policy.sequence < current.policy.sequence {
+                    return Err(CoordinatorError::Conflict(format!(
+                        "packaged signer policy {} is older than the installed {}",
+                        imported.policy.sequence, current.policy.sequence
+                    )));
+                }
+            }
+        }
+        for file in &self.files {
+            write_atomic(dir.join(&file.name), &file.contents)?;
+        }
+        if let Some(imported) = &imported {
+            sequences.record(&sequence_file, &imported.policy)?;
+        }
+        Ok(self.files.iter().map(|f| f.name.clone()).collect())
+    }
+}
+
+/// The encrypted part of a package, as signed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SealedBody {
+    pub schema_version: u32,
+    pub signer: String,
+    pub fingerprint: String,
+    pub exported_at: u64,
+    /// ChaCha20-Poly1305 nonce, base64.
+    pub nonce: String,
+    /// The JSON [`SignerState`], encrypted, base64.
+    pub ciphertext: String,
+}
+
+/// An exported package: the sealed state, signed with the account key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SealedSignerState {
+    #[serde(flatten)]
+    pub body: SealedBody,
+    /// Public key of the signer's account xpub.
+    pub pubkey: String,
+    /// Base64 signed-message signature over the JSON-encoded body.
+    pub signature: String,
+}
+
+/// Encryption key for the packages of the signer holding `key`.
+fn cipher(key: &SecretKey) -> ChaCha20Poly1305 {
+    let mut engine = HmacEngine::<sha256::Hash>::new(&key.secret_bytes());
+    engine.input(b"psbtcoord signer state");
+    let derived = Hmac::<sha256::Hash>::from_engine(engine).to_byte_array();
+    ChaCha20Poly1305::new(Key::from_slice(&derived))
+}
+
+impl SealedSignerState {
+    pub fn seal(
+        state: &SignerState,
+        signer: &str,
+        fingerprint: &str,
+        key: &SecretKey,
+        exported_at: u64,
+    ) -> Result<Self, CoordinatorError> {
+        let mut nonce = [0u8; 12];
+        thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = cipher(key)
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                serde_json::to_vec(state)?.as_slice(),
+            )
+            .map_err(|e| CoordinatorError::Key(format!("encryption failed: {}", e)))?;
+        let body = SealedBody {
+            schema_version: SCHEMA_VERSION,
+            signer: signer.to_string(),
+            fingerprint: fingerprint.to_string(),
+            exported_at,
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(ciphertext),
+        };
+        Self::sign(body, key)
+    }
+
+    /// Signs `body` with `key`.
+    fn sign(body: SealedBody, key: &SecretKey) -> Result<Self, CoordinatorError> {
+        let secp = Secp256k1::signing_only();
+        let msg_hash = signed_msg_hash(&serde_json::to_string(&body)?);
+        let msg = Message::from_digest(msg_hash.to_byte_array());
+        let sig = MessageSignature::new(secp.sign_ecdsa_recoverable(&msg, key), true);
+        Ok(Self {
+            body,
+            pubkey: PublicKey::from_secret_key(&secp, key).to_string(),
+            signature: STANDARD.encode(sig.serialize()),
+        })
+    }
+
+    pub fn load(path: &str) -> Result<Self, CoordinatorError> {
+        let text = std::fs::read_to_string(path)?;
+        serde_json::from_str(&text)
+            .map_err(|e| CoordinatorError::Invalid(format!("{}: {}", path, e)))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), CoordinatorError> {
+        Ok(write_atomic(path, serde_json::to_string_pretty(self)?)?)
+    }
+
+    /// Checks that the package was signed by the signer holding `key` and
+    /// decrypts it.
+    pub fn open(&self, key: &SecretKey) -> Result<SignerState, CoordinatorError> {
+        let secp = Secp256k1::signing_only();
+        let pubkey = PublicKey::from_secret_key(&secp, key);
+        let bytes = STANDARD
+            .decode(self.signature.trim())
+            .map_err(CoordinatorError::key)?;
+        let sig = MessageSignature::from_slice(&bytes).map_err(CoordinatorError::key)?;
+        let msg_hash = signed_msg_hash(&serde_json::to_string(&self.body)?);
+        let recovered = sig
+            .recover_pubkey(&Secp256k1::verification_only(), msg_hash)
+            .map_err(CoordinatorError::key)?;
+        if recovered.inner != pubkey || self.pubkey != pubkey.to_string() {
+            return Err(CoordinatorError::key("package was not signed by this key"));
+        }
+        let nonce: [u8; 12] = STANDARD
+            .decode(&self.body.nonce)
+            .map_err(CoordinatorError::invalid)?
+            .try_into()
+            .map_err(|_| CoordinatorError::invalid("malformed nonce"))?;
+        let ciphertext = STANDARD
+            .decode(&self.body.ciphertext)
+            .map_err(CoordinatorError::invalid)?;
+        let plaintext = cipher(key)
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|_| CoordinatorError::key("package does not decrypt with this key"))?;
+        let state: SignerState = serde_json::from_slice(&plaintext)?;
+        if state.schema_version != SCHEMA_VERSION {
+            return Err(CoordinatorError::Invalid(format!(
+                "package has schema version {}, expected {}",
+                state.schema_version, SCHEMA_VERSION
+            )));
+        }
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signer_policy::SignerPolicy;
+    use crate::{ScriptType, testing};
+
+    fn key(seed: u8) -> SecretKey {
+        SecretKey::from_slice(&[seed; 32]).unwrap()
+    }
+
+    fn state() -> SignerState {
+        SignerState {
+            schema_version: SCHEMA_VERSION,
+            files: vec![StateFile {
+                name: WALLET_FILE.to_string(),
+                contents: "{}".to_string(),
+            }],
+        }
+    }
+
+    fn sealed() -> SealedSignerState {
+        SealedSignerState::seal(&state(), "alice", "f00dbabe", &key(1), 1_700_000_000).unwrap()
+    }
+
+    #[test]
+    fn opens_what_it_sealed() {
+        let opened = sealed().open(&key(1)).unwrap();
+        assert_eq!(opened.files.len(), 1);
+        assert_eq!(opened.files[0].name, WALLET_FILE);
+        assert_eq!(opened.files[0].contents, "{}");
+    }
+
+    #[test]
+    fn refuses_another_key() {
+        assert!(sealed().open(&key(2)).is_err());
+    }
+
+    #[test]
+    fn refuses_a_tampered_body_or_ciphertext() {
+        let mut tampered = sealed();
+        tampered.body.exported_at += 1;
+        assert!(tampered.open(&key(1)).is_err());
+
+        // Even signed again with the right key, a modified ciphertext
+        // fails authentication.
+        let mut body = sealed().body;
+        let mut ciphertext = STANDARD.decode(&body.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        body.ciphertext = STANDARD.encode(ciphertext);
+        let resigned = SealedSignerState::sign(body, &key(1)).unwrap();
+        assert!(resigned.open(&key(1)).is_err());
+    }
+
+    #[test]
+    fn carries_only_plain_state_file_names() {
+        assert!(is_state_file("wallet.json", "alice"));
+        assert!(is_state_file("wallet_account1.json", "alice"));
+        assert!(is_state_file("signer_policy.json", "alice"));
+        assert!(is_state_file("receipt_alice.json", "alice"));
+        assert!(!is_state_file("receipt_bob.json", "alice"));
+        assert!(!is_state_file("../wallet.json", "alice"));
+        assert!(!is_state_file("..\\wallet.json", "alice"));
+        assert!(!is_state_file("wallet_accountX.json", "alice"));
+        assert!(!is_state_file("wallet_account.json", "alice"));
+    }
+
+    #[test]
+    fn install_refuses_an_older_signer_policy() {
+        let dir = std::env::temp_dir().join(format!("psbtcoord-state-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let policy_file = dir.join(SIGNER_POLICY_FILE);
+
+        let wallet = testing::wallet(2, 3, ScriptType::Wsh);
+        SignedSignerPolicy::new(SignerPolicy::new(&wallet, 5))
+            .save(&policy_file)
+            .unwrap();
+        let package = |sequence| SignerState {
+            schema_version: SCHEMA_VERSION,
+            files: vec![StateFile {
+                name: SIGNER_POLICY_FILE.to_string(),
+                contents: serde_json::to_string(&SignedSignerPolicy::new(SignerPolicy::new(
+                    &wallet, sequence,
+                )))
+                .unwrap(),
+            }],
+        };
+
+        let result = package(4).install(&dir, true);
+        let installed = SignedSignerPolicy::load(&policy_file).unwrap();
+        assert!(matches!(result, Err(CoordinatorError::Conflict(_))));
+        assert_eq!(installed.policy.sequence, 5);
+
+        package(6).install(&dir, true).unwrap();
+        let installed = SignedSignerPolicy::load(&policy_file).unwrap();
+        assert_eq!(installed.policy.sequence, 6);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,186 @@
+//! Sighash computation and signing, one [`SignatureScheme`] per script
+//! version.
+//!
+//! Segwit v0 inputs take an ECDSA signature over the BIP 143 sighash;
+//! taproot inputs take Schnorr signatures over BIP 341 sighashes, by the
+//! tweaked key for a key path spend or per leaf for a script path spend. A
+//! scheme holds the key a PSBT input lists for us and produces the
+//! [`PartialSig`] to add to the input, so the signer only picks a scheme
+//! per input and merges what it returns. A new script version is a new
+//! scheme here, not a new branch in the signer.
+
+use crate::CoordinatorError;
+use bitcoin::ecdsa;
+use bitcoin::hashes::Hash;
+use bitcoin::key::TapTweak;
+use bitcoin::psbt::Input;
+use bitcoin::secp256k1::{All, Keypair, Message, PublicKey, Secp256k1, SecretKey, XOnlyPublicKey};
+use bitcoin::sighash::{EcdsaSighashType, Prevouts, SighashCache, TapSighashType};
+use bitcoin::taproot::{self, TapLeafHash};
+use bitcoin::{CompressedPublicKey, ScriptBuf, Transaction, TxOut};
+
+/// Signatures one scheme made for one input.
+pub enum PartialSig {
+    Ecdsa(bitcoin::PublicKey, ecdsa::Signature),
+    Schnorr(Vec<((XOnlyPublicKey, TapLeafHash), taproot::Signature)>),
+    TaprootKey(taproot::Signature),
+}
+
+impl PartialSig {
+    /// Adds the signatures to the PSBT input they were made for.
+    pub fn add_to(self, input: &mut Input) {
+        match self {
+            PartialSig::Ecdsa(pubkey, sig) => {
+                input.partial_sigs.insert(pubkey, sig);
+            }
+            PartialSig::Schnorr(sigs) => input.tap_script_sigs.extend(sigs),
+            PartialSig::TaprootKey(sig) => input.tap_key_sig = Some(sig),
+        }
+    }
+}
+
+/// `None` when our derived key is not the one the input lists for us.
+pub type SignResult = Result<Option<PartialSig>, CoordinatorError>;
+
+pub trait SignatureScheme: Send + Sync {
+    /// Signs input `index` with `privkey`. `prevouts` holds every input's
+    /// previous output, when the PSBT has them all, for sighashes that
+    /// commit to them.
+    fn sign(
+        &self,
+        secp: &Secp256k1<All>,
+        cache: &mut SighashCache<&Transaction>,
+        input: &Input,
+        index: usize,
+        privkey: &SecretKey,
+        prevouts: Option<&[TxOut]>,
+    ) -> SignResult;
+}
+
+/// P2WSH, or P2WPKH for an input without a witness script.
+pub struct EcdsaSegwitV0 {
+    pub pubkey: PublicKey,
+}
+
+impl SignatureScheme for EcdsaSegwitV0 {
+    fn sign(
+        &self,
+        secp: &Secp256k1<All>,
+        cache: &mut SighashCache<&Transaction>,
+        input: &Input,
+        index: usize,
+        privkey: &SecretKey,
+        _prevouts: Option<&[TxOut]>,
+    ) -> SignResult {
+        let derived = PublicKey::from_secret_key(secp, privkey);
+        if derived != self.pubkey {
+            return Ok(None);
+        }
+        let utxo = input
+            .witness_utxo
+            .as_ref()
+            .ok_or_else(|| CoordinatorError::psbt("no witness utxo"))?;
+        let sighash = match &input.witness_script {
+            Some(script) => cache
+                .p2wsh_signature_hash(index, script, utxo.value, EcdsaSighashType::All)
+                .map_err(CoordinatorError::psbt)?,
+            // A single-sig UTXO mixed in with the multisig ones.
+            None => {
+                let ours = ScriptBuf::new_p2wpkh(&CompressedPublicKey(derived).wpubkey_hash());
+                if utxo.script_pubkey != ours {
+                    return Err(CoordinatorError::psbt(
+                        "input has no witness script and is not our P2WPKH",
+                    ));
+                }
+                cache
+                    .p2wpkh_signature_hash(
+                        index,
+                        &utxo.script_pubkey,
+                        utxo.value,
+                        EcdsaSighashType::All,
+                    )
+                    .map_err(CoordinatorError::psbt)?
+            }
+        };
+
+        let msg = Message::from_digest(*sighash.as_byte_array());
+        // Grind for a low-R signature: at most 71 bytes in the witness.
+        let sig = secp.sign_ecdsa_low_r(&msg, privkey);
+        Ok(Some(PartialSig::Ecdsa(
+            bitcoin::PublicKey::new(derived),
+            ecdsa::Signature::sighash_all(sig),
+        )))
+    }
+}
+
+/// How a taproot input is spent with our key.
+pub enum TaprootSpend {
+    /// Our key is the internal key: one signature by the tweaked key.
+    KeyPath,
+    /// One signature per leaf our key appears in.
+    ScriptPath(Vec<TapLeafHash>),
+}
+
+pub struct SchnorrTaproot {
+    pub key: XOnlyPublicKey,
+    pub spend: TaprootSpend,
+}
+
+impl SignatureScheme for SchnorrTaproot {
+    fn sign(
+        &self,
+        secp: &Secp256k1<All>,
+        cache: &mut SighashCache<&Transaction>,
+        input: &Input,
+        index: usize,
+        privkey: &SecretKey,
+        prevouts: Option<&[TxOut]>,
+    ) -> SignResult {
+        if PublicKey::from_secret_key(secp, privkey)
+            .x_only_public_key()
+            .0
+            != self.key
+        {
+            return Ok(None);
+        }
+        // Taproot sighashes commit to every input's previous output.
+        let prevouts =
+            Prevouts::All(prevouts.ok_or_else(|| {
+                CoordinatorError::psbt("taproot signing needs every witness utxo")
+            })?);
+        let keypair = Keypair::from_secret_key(secp, privkey);
+        match &self.spend {
+            TaprootSpend::KeyPath => {
+                let keypair = keypair.tap_tweak(secp, input.tap_merkle_root).to_keypair();
+                let sighash = cache
+                    .taproot_key_spend_signature_hash(index, &prevouts, TapSighashType::Default)
+                    .map_err(CoordinatorError::psbt)?;
+                let msg = Message::from_digest(sighash.to_byte_array());
+                Ok(Some(PartialSig::TaprootKey(taproot::Signature {
+                    signature: secp.sign_schnorr(&msg, &keypair),
+                    sighash_type: TapSighashType::Default,
+                })))
+            }
+            TaprootSpend::ScriptPath(leaf_hashes) => {
+                let mut sigs = Vec::new();
+                for leaf_hash in leaf_hashes {
+                    let sighash = cache
+                        .taproot_script_spend_signature_hash(
+                            index,
+                            &prevouts,
+                            *leaf_hash,
+                            TapSighashType::Default,
+                        )
+                        .map_err(CoordinatorError::psbt)?;
+                    let msg = Message::from_digest(sighash.to_byte_array());
+                    let sig = taproot::Signature {
+                        signature: secp.sign_schnorr(&msg, &keypair),
+                        sighash_type: TapSighashType::Default,
+                    };
+                    sigs.push(((self.key, *leaf_hash), sig));
+                }
+                Ok(Some(PartialSig::Schnorr(sigs)))
+            }
+        }
+    }
+}
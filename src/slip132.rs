@@ -0,0 +1,144 @@
+//! SLIP 132 extended public keys (`ypub`, `Zpub`, `Vpub`, ...).
+//!
+//! Electrum, older Coldcard firmware and mobile wallets encode the script
+//! type in the version bytes. Descriptors carry it in the script instead,
+//! so such keys are read as plain `xpub`/`tpub`.
+
+use crate::CoordinatorError;
+use bitcoin::NetworkKind;
+use bitcoin::base58;
+use bitcoin::bip32::Xpub;
+
+const XPUB_VERSION: [u8; 4] = [0x04, 0x88, 0xb2, 0x1e];
+const TPUB_VERSION: [u8; 4] = [0x04, 0x35, 0x87, 0xcf];
+/// SLIP 132 multisig P2WSH, mainnet.
+const ZPUB_VERSION: [u8; 4] = [0x02, 0xaa, 0x7e, 0xd3];
+/// SLIP 132 multisig P2WSH, testnet.
+const VPUB_VERSION: [u8; 4] = [0x02, 0x57, 0x54, 0x83];
+
+/// SLIP 132 multisig P2SH-P2WSH, mainnet.
+const CAPITAL_YPUB_VERSION: [u8; 4] = [0x02, 0x95, 0xb4, 0x3f];
+/// SLIP 132 multisig P2SH-P2WSH, testnet.
+const CAPITAL_UPUB_VERSION: [u8; 4] = [0x02, 0x42, 0x89, 0xef];
+
+/// `xpub`, `ypub`, `zpub`, `Ypub` and `Zpub`.
+const MAINNET_VERSIONS: [[u8; 4]; 5] = [
+    XPUB_VERSION,
+    [0x04, 0x9d, 0x7c, 0xb2],
+    [0x04, 0xb2, 0x47, 0x46],
+    CAPITAL_YPUB_VERSION,
+    ZPUB_VERSION,
+];
+/// `tpub`, `upub`, `vpub`, `Upub` and `Vpub`.
+const TESTNET_VERSIONS: [[u8; 4]; 5] = [
+    TPUB_VERSION,
+    [0x04, 0x4a, 0x52, 0x62],
+    [0x04, 0x5f, 0x1c, 0xf6],
+    CAPITAL_UPUB_VERSION,
+    VPUB_VERSION,
+];
+
+/// Parses an extended public key in standard or any SLIP 132 encoding,
+/// normalized to `xpub`/`tpub`.
+pub fn parse_xpub(s: &str) -> Result<Xpub, CoordinatorError> {
+    let s = s.trim();
+    let mut data = base58::decode_check(s)
+        .map_err(|e| CoordinatorError::Key(format!("invalid extended key {}: {}", s, e)))?;
+    if data.len() != 78 {
+        return Err(CoordinatorError::Key(format!(
+            "invalid extended key length: {}",
+            s
+        )));
+    }
+    let version = [data[0], data[1], data[2], data[3]];
+    let standard = if MAINNET_VERSIONS.contains(&version) {
+        XPUB_VERSION
+    } else if TESTNET_VERSIONS.contains(&version) {
+        TPUB_VERSION
+    } else {
+        return Err(CoordinatorError::Key(format!(
+            "unsupported extended key version: {}",
+            s
+        )));
+    };
+    data[..4].copy_from_slice(&standard);
+    Xpub::decode(&data).map_err(CoordinatorError::key)
+}
+
+/// Encodes `xpub` as `Zpub` (mainnet) or `Vpub` (other networks), the
+/// multisig P2WSH form mobile wallets expect.
+pub fn to_multisig_p2wsh(xpub: &Xpub) -> String {
+    encode_as(xpub, ZPUB_VERSION, VPUB_VERSION)
+}
+
+/// Encodes `xpub` as `Ypub` (mainnet) or `Upub` (other networks), the
+/// multisig P2SH-P2WSH form.
+pub fn to_multisig_p2sh_p2wsh(xpub: &Xpub) -> String {
+    encode_as(xpub, CAPITAL_YPUB_VERSION, CAPITAL_UPUB_VERSION)
+}
+
+fn encode_as(xpub: &Xpub, mainnet: [u8; 4], testnet: [u8; 4]) -> String {
+    let mut data = xpub.encode();
+    let version = match xpub.network {
+        NetworkKind::Main => mainnet,
+        NetworkKind::Test => testnet,
+    };
+    data[..4].copy_from_slice(&version);
+    base58::encode_check(&data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::bip32::Xpriv;
+    use bitcoin::secp256k1::Secp256k1;
+
+    fn xpub(network: NetworkKind) -> Xpub {
+        let master = Xpriv::new_master(network, &[3; 32]).unwrap();
+        Xpub::from_priv(&Secp256k1::new(), &master)
+    }
+
+    fn with_version(xpub: &Xpub, version: [u8; 4]) -> String {
+        let mut data = xpub.encode();
+        data[..4].copy_from_slice(&version);
+        base58::encode_check(&data)
+    }
+
+    #[test]
+    fn multisig_encodings_normalize_to_xpub_and_tpub() {
+        let main = xpub(NetworkKind::Main);
+        let zpub = to_multisig_p2wsh(&main);
+        assert!(zpub.starts_with("Zpub"), "{}", zpub);
+        assert_eq!(parse_xpub(&zpub).unwrap(), main);
+        let ypub = to_multisig_p2sh_p2wsh(&main);
+        assert!(ypub.starts_with("Ypub"), "{}", ypub);
+        assert_eq!(parse_xpub(&ypub).unwrap(), main);
+
+        let test = xpub(NetworkKind::Test);
+        let vpub = to_multisig_p2wsh(&test);
+        assert!(vpub.starts_with("Vpub"), "{}", vpub);
+        let parsed = parse_xpub(&format!("  {}\n", vpub)).unwrap();
+        assert_eq!(parsed, test);
+        assert!(parsed.to_string().starts_with("tpub"));
+        assert!(to_multisig_p2sh_p2wsh(&test).starts_with("Upub"));
+
+        // Single-sig forms are read as the same key too.
+        for version in MAINNET_VERSIONS {
+            assert_eq!(parse_xpub(&with_version(&main, version)).unwrap(), main);
+        }
+        for version in TESTNET_VERSIONS {
+            assert_eq!(parse_xpub(&with_version(&test, version)).unwrap(), test);
+        }
+    }
+
+    #[test]
+    fn private_and_damaged_keys_are_refused() {
+        let main = xpub(NetworkKind::Main);
+        // xprv version bytes.
+        assert!(parse_xpub(&with_version(&main, [0x04, 0x88, 0xad, 0xe4])).is_err());
+        let mut zpub = to_multisig_p2wsh(&main);
+        let last = zpub.pop().unwrap();
+        zpub.push(if last == '1' { '2' } else { '1' });
+        assert!(parse_xpub(&zpub).is_err());
+    }
+}
@@ -0,0 +1,62 @@
+//! Training mode for signer onboarding drills.
+//!
+//! With `--training`, a tool works in its own `training/` directory on
+//! regtest, prints a watermark around its output, marks every PSBT it
+//! writes, and refuses mainnet keys and PSBTs without the mark. A real
+//! PSBT pasted into a drill terminal is rejected rather than signed.
+
+use crate::{CoordinatorError, PROPRIETARY_TRAINING, proprietary_key};
+use bitcoin::NetworkKind;
+use bitcoin::psbt::Psbt;
+
+/// Data directory for training runs, relative to where the tool starts.
+/// Key files, PSBTs and transactions all live here.
+pub const TRAINING_DIR: &str = "training";
+
+pub const WATERMARK: &str = "TRAINING - NOT REAL FUNDS";
+
+/// Switches into [`TRAINING_DIR`], creating it if needed, and prints the
+/// watermark. Relative paths given afterwards resolve inside it.
+pub fn enter() -> Result<(), CoordinatorError> {
+    std::fs::create_dir_all(TRAINING_DIR)?;
+    std::env::set_current_dir(TRAINING_DIR)?;
+    print_watermark();
+    Ok(())
+}
+
+pub fn print_watermark() {
+    println!("==== {} ====\n", WATERMARK);
+}
+
+/// Marks a PSBT as created in training mode.
+pub fn mark_psbt(psbt: &mut Psbt) {
+    psbt.proprietary.insert(
+        proprietary_key(PROPRIETARY_TRAINING),
+        WATERMARK.as_bytes().to_vec(),
+    );
+}
+
+pub fn is_marked(psbt: &Psbt) -> bool {
+    psbt.proprietary
+        .contains_key(&proprietary_key(PROPRIETARY_TRAINING))
+}
+
+/// Refuses a PSBT that was not created in training mode.
+pub fn check_psbt(psbt: &Psbt) -> Result<(), CoordinatorError> {
+    if !is_marked(psbt) {
+        return Err(CoordinatorError::psbt(
+            "training mode: PSBT is not marked TRAINING; refusing a possibly real PSBT",
+        ));
+    }
+    Ok(())
+}
+
+/// Refuses mainnet keys.
+pub fn check_key_network(network: NetworkKind) -> Result<(), CoordinatorError> {
+    if network == NetworkKind::Main {
+        return Err(CoordinatorError::key(
+            "training mode: refusing a mainnet key",
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,147 @@
+//! UTXOs entered by hand, for when no backend is available to look them
+//! up.
+//!
+//! `utxo add` parses the raw funding transaction, checks that the chosen
+//! output pays the wallet at the given index, and records it in
+//! `utxos.json`. The coordinator then spends the recorded UTXOs, attaching
+//! the funding transaction to each input.
+
+use crate::{CoordinatorError, MultisigWallet, write_atomic};
+use bitcoin::consensus::encode::{deserialize_hex, serialize_hex};
+use bitcoin::{Amount, Network, OutPoint, ScriptBuf, Transaction, TxOut};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Where manually entered UTXOs are kept.
+pub const UTXO_FILE: &str = "utxos.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManualUtxo {
+    pub outpoint: OutPoint,
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub value: Amount,
+    pub script_pubkey: ScriptBuf,
+    /// Wallet address index the output pays.
+    pub index: u32,
+    #[serde(default)]
+    pub is_change: bool,
+    /// The funding transaction as hex, for the inputs' `non_witness_utxo`.
+    pub funding_tx: String,
+    /// Network of the wallet it was recorded for. The test networks share
+    /// scripts, so the script alone cannot tell a signet coin from a
+    /// testnet4 one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<Network>,
+}
+
+impl ManualUtxo {
+    /// Reads output `vout` of the raw transaction `raw_tx` (hex) and checks
+    /// that it pays the wallet's address `index` on the receive or change
+    /// chain.
+    pub fn from_funding_tx(
+        wallet: &MultisigWallet,
+        raw_tx: &str,
+        vout: u32,
+        index: u32,
+        is_change: bool,
+    ) -> Result<Self, CoordinatorError> {
+        let tx: Transaction = deserialize_hex(raw_tx.trim())
+            .map_err(|e| CoordinatorError::Invalid(format!("raw transaction: {}", e)))?;
+        let output = tx.output.get(vout as usize).ok_or_else(|| {
+            CoordinatorError::Invalid(format!(
+                "transaction {} has {} outputs, no output {}",
+                tx.compute_txid(),
+                tx.output.len(),
+                vout
+            ))
+        })?;
+        let address = wallet.derive_address(index, is_change)?;
+        if output.script_pubkey != address.script_pubkey() {
+            let chain = if is_change { "change" } else { "receive" };
+            return Err(CoordinatorError::Invalid(format!(
+                "output {} does not pay {} address {} ({})",
+                vout, chain, index, address
+            )));
+        }
+        Ok(Self {
+            outpoint: OutPoint {
+                txid: tx.compute_txid(),
+                vout,
+            },
+            value: output.value,
+            script_pubkey: output.script_pubkey.clone(),
+            index,
+            is_change,
+            funding_tx: serialize_hex(&tx),
+            network: Some(wallet.network),
+        })
+    }
+
+    pub fn txout(&self) -> TxOut {
+        TxOut {
+            value: self.value,
+            script_pubkey: self.script_pubkey.clone(),
+        }
+    }
+
+    pub fn funding_tx(&self) -> Result<Transaction, CoordinatorError> {
+        deserialize_hex(&self.funding_tx)
+            .map_err(|e| CoordinatorError::Invalid(format!("{}: {}", self.outpoint, e)))
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UtxoStore {
+    pub utxos: Vec<ManualUtxo>,
+}
+
+impl UtxoStore {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, CoordinatorError> {
+        let path = path.as_ref();
+        serde_json::from_str(&std::fs::read_to_string(path)?)
+            .map_err(|e| CoordinatorError::Invalid(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Reads [`UTXO_FILE`] from the current directory, or an empty store
+    /// if there is none.
+    pub fn load_or_default() -> Result<Self, CoordinatorError> {
+        if !Path::new(UTXO_FILE).exists() {
+            return Ok(Self::default());
+        }
+        Self::load(UTXO_FILE)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), CoordinatorError> {
+        let json = serde_json::to_string_pretty(self).map_err(CoordinatorError::invalid)?;
+        Ok(write_atomic(path, json)?)
+    }
+
+    pub fn add(&mut self, utxo: ManualUtxo) -> Result<(), CoordinatorError> {
+        if self.utxos.iter().any(|u| u.outpoint == utxo.outpoint) {
+            return Err(CoordinatorError::Invalid(format!(
+                "{} is already in {}",
+                utxo.outpoint, UTXO_FILE
+            )));
+        }
+        self.utxos.push(utxo);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, outpoint: OutPoint) -> Result<ManualUtxo, CoordinatorError> {
+        let position = self
+            .utxos
+            .iter()
+            .position(|u| u.outpoint == outpoint)
+            .ok_or_else(|| {
+                CoordinatorError::Invalid(format!("{} is not in {}", outpoint, UTXO_FILE))
+            })?;
+        Ok(self.utxos.remove(position))
+    }
+
+    /// Sum of all recorded UTXOs.
+    pub fn total(&self) -> Option<Amount> {
+        self.utxos
+            .iter()
+            .try_fold(Amount::ZERO, |total, u| total.checked_add(u.value))
+    }
+}
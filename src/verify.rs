@@ -0,0 +1,436 @@
+//! Verification of partial signatures already present in a PSBT.
+
+use crate::{NUMS_INTERNAL_KEY, parse_witness_script};
+use bitcoin::bip32::Fingerprint;
+use bitcoin::ecdsa::Signature as EcdsaSignature;
+use bitcoin::hashes::{Hash, HashEngine, sha256d};
+use bitcoin::hex::DisplayHex;
+use bitcoin::psbt::{Input, Psbt};
+use bitcoin::secp256k1::{Message, Secp256k1, VerifyOnly, XOnlyPublicKey};
+use bitcoin::sighash::{EcdsaSighashType, Prevouts, SighashCache, TapSighash, TapSighashType};
+use bitcoin::taproot::{Signature as TaprootSignature, TapLeafHash};
+use bitcoin::{ScriptBuf, Transaction, TxOut};
+use serde::Serialize;
+
+/// Outcome of checking one partial signature.
+#[derive(Debug, Clone)]
+pub struct SignatureCheck {
+    pub input: usize,
+    pub pubkey: bitcoin::PublicKey,
+    /// Master fingerprint from the input's BIP 32 derivations, if listed.
+    pub fingerprint: Option<Fingerprint>,
+    pub result: Result<(), String>,
+}
+
+impl SignatureCheck {
+    pub fn is_valid(&self) -> bool {
+        self.result.is_ok()
+    }
+}
+
+/// Checks every partial signature in the PSBT against its BIP 143 sighash.
+///
+/// A signature is only valid if its key appears in the input's witness
+/// script, or for an input without one, if the input pays that key as
+/// P2WPKH, and it verifies against the sighash for its own sighash type.
+pub fn verify_partial_sigs(psbt: &Psbt) -> Vec<SignatureCheck> {
+    let secp = Secp256k1::verification_only();
+    let mut cache = SighashCache::new(&psbt.unsigned_tx);
+    (0..psbt.inputs.len())
+        .flat_map(|idx| input_checks(&secp, &mut cache, psbt, idx))
+        .collect()
+}
+
+/// Like [`verify_partial_sigs`], for input `idx` alone.
+pub fn verify_input_sigs(psbt: &Psbt, idx: usize) -> Vec<SignatureCheck> {
+    let secp = Secp256k1::verification_only();
+    let mut cache = SighashCache::new(&psbt.unsigned_tx);
+    input_checks(&secp, &mut cache, psbt, idx)
+}
+
+fn input_checks(
+    secp: &Secp256k1<VerifyOnly>,
+    cache: &mut SighashCache<&Transaction>,
+    psbt: &Psbt,
+    idx: usize,
+) -> Vec<SignatureCheck> {
+    let input = &psbt.inputs[idx];
+    input
+        .partial_sigs
+        .iter()
+        .map(|(pubkey, sig)| SignatureCheck {
+            input: idx,
+            pubkey: *pubkey,
+            fingerprint: input.bip32_derivation.get(&pubkey.inner).map(|(fp, _)| *fp),
+            result: check_signature(secp, cache, idx, input, pubkey, sig),
+        })
+        .collect()
+}
+
+fn check_signature(
+    secp: &Secp256k1<VerifyOnly>,
+    cache: &mut SighashCache<&Transaction>,
+    idx: usize,
+    input: &Input,
+    pubkey: &bitcoin::PublicKey,
+    sig: &EcdsaSignature,
+) -> Result<(), String> {
+    let utxo = input.witness_utxo.as_ref().ok_or("missing witness utxo")?;
+    let value = utxo.value;
+
+    let sighash = match &input.witness_script {
+        Some(script) => {
+            let (_, script_keys) = parse_witness_script(script).map_err(|e| e.to_string())?;
+            if !script_keys.contains(pubkey) {
+                return Err("key is not in the witness script".into());
+            }
+            cache
+                .p2wsh_signature_hash(idx, script, value, sig.sighash_type)
+                .map_err(|e| e.to_string())?
+        }
+        // A single-key input spent alongside the wallet's.
+        None => {
+            let wpubkey_hash = pubkey.wpubkey_hash().map_err(|e| e.to_string())?;
+            if utxo.script_pubkey != ScriptBuf::new_p2wpkh(&wpubkey_hash) {
+                return Err("input has no witness script and does not pay this key".into());
+            }
+            cache
+                .p2wpkh_signature_hash(idx, &utxo.script_pubkey, value, sig.sighash_type)
+                .map_err(|e| e.to_string())?
+        }
+    };
+    let msg = Message::from_digest(*sighash.as_byte_array());
+    secp.verify_ecdsa(&msg, &sig.signature, &pubkey.inner)
+        .map_err(|_| "signature does not match sighash".into())
+}
+
+/// Checks the key path signature of taproot input `idx`, if it has one,
+/// against its BIP 341 sighash and the output key the input spends.
+pub fn verify_tap_key_sig(psbt: &Psbt, idx: usize) -> Option<Result<(), String>> {
+    let sig = psbt.inputs[idx].tap_key_sig?;
+    let prevouts: Option<Vec<TxOut>> = psbt
+        .inputs
+        .iter()
+        .map(|input| input.witness_utxo.clone())
+        .collect();
+    let result = match prevouts {
+        Some(prevouts) => check_tap_key_signature(psbt, idx, &prevouts, &sig),
+        None => Err("missing witness utxo".to_string()),
+    };
+    Some(result)
+}
+
+fn check_tap_key_signature(
+    psbt: &Psbt,
+    idx: usize,
+    prevouts: &[TxOut],
+    sig: &TaprootSignature,
+) -> Result<(), String> {
+    // The output key is what the key path signature is checked against,
+    // whatever the internal key and tweak.
+    let script_pubkey = &prevouts[idx].script_pubkey;
+    if !script_pubkey.is_p2tr() {
+        return Err("key path signature on an input that is not P2TR".into());
+    }
+    let output_key =
+        XOnlyPublicKey::from_slice(&script_pubkey.as_bytes()[2..]).map_err(|e| e.to_string())?;
+    let sighash = SighashCache::new(&psbt.unsigned_tx)
+        .taproot_key_spend_signature_hash(idx, &Prevouts::All(prevouts), sig.sighash_type)
+        .map_err(|e| e.to_string())?;
+    let msg = Message::from_digest(sighash.to_byte_array());
+    Secp256k1::verification_only()
+        .verify_schnorr(&sig.signature, &msg, &output_key)
+        .map_err(|_| "signature does not match sighash".into())
+}
+
+/// Outcome of checking one taproot script path signature.
+#[derive(Debug, Clone)]
+pub struct TapSignatureCheck {
+    pub input: usize,
+    pub key: XOnlyPublicKey,
+    pub leaf_hash: TapLeafHash,
+    pub result: Result<(), String>,
+}
+
+/// Checks the script path signatures of input `idx` against their BIP 341
+/// sighash. Each must be for a leaf the input lists, and the sighash needs
+/// the amount of every input, not just this one.
+pub fn verify_tap_script_sigs(psbt: &Psbt, idx: usize) -> Vec<TapSignatureCheck> {
+    let secp = Secp256k1::verification_only();
+    let mut cache = SighashCache::new(&psbt.unsigned_tx);
+    let prevouts: Option<Vec<TxOut>> = psbt
+        .inputs
+        .iter()
+        .map(|input| input.witness_utxo.clone())
+        .collect();
+    let input = &psbt.inputs[idx];
+    input
+        .tap_script_sigs
+        .iter()
+        .map(|(&(key, leaf_hash), sig)| {
+            let result = match &prevouts {
+                Some(prevouts) => check_tap_signature(
+                    &secp,
+                    &mut cache,
+                    idx,
+                    input,
+                    prevouts,
+                    (key, leaf_hash),
+                    sig,
+                ),
+                None => Err("missing witness utxo".to_string()),
+            };
+            TapSignatureCheck {
+                input: idx,
+                key,
+                leaf_hash,
+                result,
+            }
+        })
+        .collect()
+}
+
+fn check_tap_signature(
+    secp: &Secp256k1<VerifyOnly>,
+    cache: &mut SighashCache<&Transaction>,
+    idx: usize,
+    input: &Input,
+    prevouts: &[TxOut],
+    (key, leaf_hash): (XOnlyPublicKey, TapLeafHash),
+    sig: &TaprootSignature,
+) -> Result<(), String> {
+    let listed = input
+        .tap_scripts
+        .values()
+        .any(|(script, version)| TapLeafHash::from_script(script, *version) == leaf_hash);
+    if !listed {
+        return Err("leaf is not one of the input's scripts".into());
+    }
+    let sighash = cache
+        .taproot_script_spend_signature_hash(
+            idx,
+            &Prevouts::All(prevouts),
+            leaf_hash,
+            sig.sighash_type,
+        )
+        .map_err(|e| e.to_string())?;
+    let msg = Message::from_digest(sighash.to_byte_array());
+    secp.verify_schnorr(&sig.signature, &msg, &key)
+        .map_err(|_| "signature does not match sighash".into())
+}
+
+/// Signing data for one input, or one taproot leaf, for checking with an
+/// independent tool.
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct SighashPreimage {
+    pub input: usize,
+    /// `bip143` for segwit v0 inputs, `bip341` for taproot ones.
+    pub algorithm: String,
+    pub sighash_type: String,
+    /// Leaf of a taproot script path spend, hex; absent for a key path
+    /// spend and for segwit v0.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub leaf_hash: Option<String>,
+    /// Serialized preimage, hex: the BIP 143 preimage, or the BIP 341
+    /// signature message with its leading epoch byte.
+    pub preimage: String,
+    /// Digest of the preimage in the byte order that is signed, hex:
+    /// double-SHA256 for BIP 143, the `TapSighash` tagged hash for BIP 341.
+    pub digest: String,
+}
+
+/// The preimages [`sighash_preimages`] could compute, and for each input
+/// it could not, why.
+#[derive(Debug, Clone, Default)]
+pub struct SighashPreimages {
+    pub preimages: Vec<SighashPreimage>,
+    pub skipped: Vec<(usize, String)>,
+}
+
+/// Computes the preimage and digest the signer signs for each input:
+/// SIGHASH_ALL over BIP 143 for P2WSH and P2WPKH inputs, nested or not,
+/// and SIGHASH_DEFAULT over BIP 341 for taproot inputs, once per leaf for
+/// a script path spend. Inputs that lack the data are skipped with a
+/// reason rather than failing the rest.
+pub fn sighash_preimages(psbt: &Psbt) -> SighashPreimages {
+    let mut cache = SighashCache::new(&psbt.unsigned_tx);
+    let prevouts: Option<Vec<TxOut>> = psbt
+        .inputs
+        .iter()
+        .map(|input| input.witness_utxo.clone())
+        .collect();
+    let mut result = SighashPreimages::default();
+
+    for (idx, input) in psbt.inputs.iter().enumerate() {
+        let preimages = if input.tap_internal_key.is_some() || !input.tap_scripts.is_empty() {
+            match &prevouts {
+                Some(prevouts) => taproot_preimages(&mut cache, idx, input, prevouts),
+                None => Err("taproot sighashes need every input's witness utxo".to_string()),
+            }
+        } else {
+            segwit_v0_preimage(&mut cache, idx, input).map(|preimage| vec![preimage])
+        };
+        match preimages {
+            Ok(preimages) => result.preimages.extend(preimages),
+            Err(reason) => result.skipped.push((idx, reason)),
+        }
+    }
+    result
+}
+
+fn segwit_v0_preimage(
+    cache: &mut SighashCache<&Transaction>,
+    idx: usize,
+    input: &Input,
+) -> Result<SighashPreimage, String> {
+    let utxo = input.witness_utxo.as_ref().ok_or("missing witness utxo")?;
+    // The witness script, or for P2WPKH the script code of the key hash
+    // in the output or, when nested, in the redeem script.
+    let script_code = match &input.witness_script {
+        Some(script) => script.clone(),
+        None => input
+            .redeem_script
+            .as_ref()
+            .unwrap_or(&utxo.script_pubkey)
+            .p2wpkh_script_code()
+            .ok_or("not a P2WSH, P2WPKH or taproot input")?,
+    };
+
+    let mut preimage = Vec::new();
+    cache
+        .segwit_v0_encode_signing_data_to(
+            &mut preimage,
+            idx,
+            &script_code,
+            utxo.value,
+            EcdsaSighashType::All,
+        )
+        .map_err(|e| e.to_string())?;
+    let digest = sha256d::Hash::hash(&preimage);
+    Ok(SighashPreimage {
+        input: idx,
+        algorithm: "bip143".to_string(),
+        sighash_type: EcdsaSighashType::All.to_string(),
+        leaf_hash: None,
+        preimage: preimage.to_lower_hex_string(),
+        digest: digest.as_byte_array()[..].to_lower_hex_string(),
+    })
+}
+
+/// One preimage for the key path when the input names an internal key
+/// other than the unspendable NUMS point, and one per leaf of its script
+/// paths.
+fn taproot_preimages(
+    cache: &mut SighashCache<&Transaction>,
+    idx: usize,
+    input: &Input,
+    prevouts: &[TxOut],
+) -> Result<Vec<SighashPreimage>, String> {
+    let mut leaves: Vec<Option<TapLeafHash>> = Vec::new();
+    if input
+        .tap_internal_key
+        .is_some_and(|key| key.to_string() != NUMS_INTERNAL_KEY)
+    {
+        leaves.push(None);
+    }
+    for (script, version) in input.tap_scripts.values() {
+        leaves.push(Some(TapLeafHash::from_script(script, *version)));
+    }
+
+    let mut preimages = Vec::new();
+    for leaf_hash in leaves {
+        let mut preimage = Vec::new();
+        cache
+            .taproot_encode_signing_data_to(
+                &mut preimage,
+                idx,
+                &Prevouts::All(prevouts),
+                None,
+                leaf_hash.map(|hash| (hash, 0xFFFFFFFF)),
+                TapSighashType::Default,
+            )
+            .map_err(|e| e.to_string())?;
+        let mut engine = TapSighash::engine();
+        engine.input(&preimage);
+        let digest = TapSighash::from_engine(engine);
+        preimages.push(SighashPreimage {
+            input: idx,
+            algorithm: "bip341".to_string(),
+            sighash_type: TapSighashType::Default.to_string(),
+            leaf_hash: leaf_hash.map(|hash| hash.to_string()),
+            preimage: preimage.to_lower_hex_string(),
+            digest: digest.as_byte_array()[..].to_lower_hex_string(),
+        });
+    }
+    Ok(preimages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ScriptType, testing};
+
+    #[test]
+    fn bip143_preimages_hash_to_the_sighash_signed() {
+        for script_type in [ScriptType::Wsh, ScriptType::ShWsh] {
+            let psbt = testing::psbt(&testing::wallet(2, 3, script_type));
+            let result = sighash_preimages(&psbt);
+            assert!(result.skipped.is_empty());
+            let [preimage] = result.preimages.as_slice() else {
+                panic!("expected one preimage, got {:?}", result.preimages);
+            };
+            let input = &psbt.inputs[0];
+            let sighash = SighashCache::new(&psbt.unsigned_tx)
+                .p2wsh_signature_hash(
+                    0,
+                    input.witness_script.as_ref().unwrap(),
+                    input.witness_utxo.as_ref().unwrap().value,
+                    EcdsaSighashType::All,
+                )
+                .unwrap();
+            assert_eq!(preimage.algorithm, "bip143");
+            assert_eq!(
+                preimage.digest,
+                sighash.as_byte_array()[..].to_lower_hex_string()
+            );
+        }
+    }
+
+    #[test]
+    fn bip341_preimages_cover_the_leaf_but_not_the_nums_key_path() {
+        let psbt = testing::psbt(&testing::wallet(2, 3, ScriptType::Tr));
+        let result = sighash_preimages(&psbt);
+        assert!(result.skipped.is_empty());
+        let [preimage] = result.preimages.as_slice() else {
+            panic!("expected one preimage, got {:?}", result.preimages);
+        };
+        let (script, version) = psbt.inputs[0].tap_scripts.values().next().unwrap();
+        let leaf_hash = TapLeafHash::from_script(script, *version);
+        let prevouts = [psbt.inputs[0].witness_utxo.clone().unwrap()];
+        let sighash = SighashCache::new(&psbt.unsigned_tx)
+            .taproot_script_spend_signature_hash(
+                0,
+                &Prevouts::All(&prevouts[..]),
+                leaf_hash,
+                TapSighashType::Default,
+            )
+            .unwrap();
+        assert_eq!(preimage.algorithm, "bip341");
+        assert_eq!(preimage.leaf_hash, Some(leaf_hash.to_string()));
+        assert!(preimage.preimage.starts_with("00"));
+        assert_eq!(
+            preimage.digest,
+            sighash.as_byte_array()[..].to_lower_hex_string()
+        );
+    }
+
+    #[test]
+    fn inputs_without_signing_data_are_skipped_with_a_reason() {
+        let mut psbt = testing::psbt(&testing::wallet(2, 3, ScriptType::Wsh));
+        psbt.inputs[0].witness_utxo = None;
+        let result = sighash_preimages(&psbt);
+        assert!(result.preimages.is_empty());
+        assert_eq!(result.skipped, [(0, "missing witness utxo".to_string())]);
+    }
+}
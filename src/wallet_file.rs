@@ -0,0 +1,239 @@
+//! The coordinator's persistent wallet configuration, `wallet.json`.
+//!
+//! Written once by `wallet init`, it holds the wallet definition (the
+//! descriptor, network and cosigner metadata) and the next unused index
+//! on each chain. With it, every tool loads the same canonical wallet
+//! instead of rebuilding it from key files and built-in defaults. It can
+//! also hold auxiliary single-sig accounts next to the multisig.
+//!
+//! Wallets at other BIP 48 accounts of the same keys keep their own state
+//! in `wallet_account1.json`, `wallet_account2.json`, ...
+
+use crate::auxiliary::AuxiliaryAccount;
+use crate::schema::WalletDefinition;
+use crate::{CoordinatorError, MultisigWallet, account_name, bip48_account, write_atomic};
+use bitcoin::Network;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Where tools look for the wallet configuration.
+pub const WALLET_FILE: &str = "wallet.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletFile {
+    #[serde(flatten)]
+    pub definition: WalletDefinition,
+    /// First receive index not yet handed out.
+    #[serde(default)]
+    pub next_receive_index: u32,
+    /// First change index not yet used.
+    #[serde(default)]
+    pub next_change_index: u32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub auxiliary: Vec<AuxiliaryAccount>,
+}
+
+impl WalletFile {
+    pub fn new(wallet: &MultisigWallet) -> Self {
+        Self {
+            definition: WalletDefinition::from_wallet(wallet),
+            next_receive_index: 0,
+            next_change_index: 0,
+            auxiliary: Vec::new(),
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, CoordinatorError> {
+        let path = path.as_ref();
+        serde_json::from_str(&std::fs::read_to_string(path)?)
+            .map_err(|e| CoordinatorError::Descriptor(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Reads [`WALLET_FILE`] from the current directory, if there is one.
+    pub fn load_if_present() -> Result<Option<Self>, CoordinatorError> {
+        Self::load_account_if_present(0)
+    }
+
+    /// Where the wallet at BIP 48 `account` is kept: [`WALLET_FILE`] for
+    /// account 0.
+    pub fn account_path(account: u32) -> String {
+        format!("{}.json", account_name("wallet", account))
+    }
+
+    /// Reads the file of the wallet at `account`, if there is one.
+    pub fn load_account_if_present(account: u32) -> Result<Option<Self>, CoordinatorError> {
+        let path = Self::account_path(account);
+        if !Path::new(&path).exists() {
+            return Ok(None);
+        }
+        Self::load(&path).map(Some)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), CoordinatorError> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| CoordinatorError::Descriptor(e.to_string()))?;
+        Ok(write_atomic(path, json)?)
+    }
+
+    pub fn add_auxiliary(&mut self, account: AuxiliaryAccount) -> Result<(), CoordinatorError> {
+        if self.auxiliary.iter().any(|a| a.label == account.label) {
+            return Err(CoordinatorError::Invalid(format!(
+                "{} already has an account named {}",
+                WALLET_FILE, account.label
+            )));
+        }
+        self.auxiliary.push(account);
+        Ok(())
+    }
+
+    pub fn auxiliary(&self, label: &str) -> Result<&AuxiliaryAccount, CoordinatorError> {
+        self.auxiliary
+            .iter()
+            .find(|a| a.label == label)
+            .ok_or_else(|| no_account(label))
+    }
+
+    pub fn auxiliary_mut(
+        &mut self,
+        label: &str,
+    ) -> Result<&mut AuxiliaryAccount, CoordinatorError> {
+        self.auxiliary
+            .iter_mut()
+            .find(|a| a.label == label)
+            .ok_or_else(|| no_account(label))
+    }
+
+    /// Moves the receive index of the file at `path` past `used`, unless
+    /// it already is. The file is read again under its lock, so a change
+    /// another tool saved in the meantime is kept. Returns the new index.
+    pub fn advance_receive_index(
+        path: impl AsRef<Path>,
+        used: u32,
+    ) -> Result<u32, CoordinatorError> {
+        let path = path.as_ref();
+        let _lock = FileLock::acquire(path)?;
+        let mut file = Self::load(path)?;
+        let next = used
+            .checked_add(1)
+            .ok_or_else(|| CoordinatorError::invalid("receive chain exhausted"))?;
+        if file.next_receive_index < next {
+            file.next_receive_index = next;
+            file.save(path)?;
+        }
+        Ok(file.next_receive_index)
+    }
+
+    /// Reads the file at `path` under its lock, applies `change` and saves
+    /// the result, as [`advance_receive_index`](Self::advance_receive_index)
+    /// does for the receive index. Nothing is saved if `change` fails.
+    pub fn update<T>(
+        path: impl AsRef<Path>,
+        change: impl FnOnce(&mut Self) -> Result<T, CoordinatorError>,
+    ) -> Result<T, CoordinatorError> {
+        let path = path.as_ref();
+        let _lock = FileLock::acquire(path)?;
+        let mut file = Self::load(path)?;
+        let result = change(&mut file)?;
+        file.save(path)?;
+        Ok(result)
+    }
+
+    /// Rebuilds the wallet from its descriptor, checks it against the
+    /// recorded policy ID, and restores cosigner names and metadata.
+    pub fn wallet(&self) -> Result<MultisigWallet, CoordinatorError> {
+        let definition = &self.definition;
+        let network = Network::from_str(&definition.network).map_err(CoordinatorError::invalid)?;
+        let mut wallet = MultisigWallet::from_descriptor(&definition.descriptor, network)?;
+        if wallet.policy_id().to_string() != definition.policy_id {
+            return Err(CoordinatorError::Descriptor(format!(
+                "{}: policy ID {} does not match the descriptor ({})",
+                WALLET_FILE,
+                definition.policy_id,
+                wallet.policy_id()
+            )));
+        }
+        for origin in &mut wallet.xpub_origins {
+            let xpub = origin.xpub.to_string();
+            if let Some(cosigner) = definition.cosigners.iter().find(|c| c.xpub == xpub) {
+                origin.name = cosigner.name.clone();
+                origin.metadata = cosigner.metadata.clone();
+            }
+        }
+        Ok(wallet)
+    }
+
+    /// Like [`wallet`](Self::wallet), and also checks that the wallet is
+    /// at BIP 48 `account`, so one account's file cannot stand in for
+    /// another's.
+    pub fn account_wallet(&self, account: u32) -> Result<MultisigWallet, CoordinatorError> {
+        let wallet = self.wallet()?;
+        check_account(&wallet, account)?;
+        Ok(wallet)
+    }
+}
+
+/// Fails if a cosigner key sits at another BIP 48 account than `account`.
+/// Keys outside BIP 48 are not checked.
+pub fn check_account(wallet: &MultisigWallet, account: u32) -> Result<(), CoordinatorError> {
+    for origin in &wallet.xpub_origins {
+        let other = bip48_account(&origin.derivation_path).filter(|a| *a != account);
+        if let Some(other) = other {
+            return Err(CoordinatorError::Derivation(format!(
+                "{} is at account {}, not account {}",
+                origin.name, other, account
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// How long to wait for another process to release a lock.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// `<path>.lock`, held while a read-modify-write of `path` is in progress
+/// and removed on drop. A process that dies holding it leaves it behind,
+/// and it must then be deleted by hand.
+struct FileLock {
+    path: PathBuf,
+}
+
+impl FileLock {
+    fn acquire(target: &Path) -> Result<Self, CoordinatorError> {
+        let mut path = target.as_os_str().to_owned();
+        path.push(".lock");
+        let path = PathBuf::from(path);
+        let deadline = Instant::now() + LOCK_TIMEOUT;
+        loop {
+            match std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(_) => return Ok(Self { path }),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    if Instant::now() >= deadline {
+                        return Err(CoordinatorError::Conflict(format!(
+                            "{} is locked; remove {} if no other tool is running",
+                            target.display(),
+                            path.display()
+                        )));
+                    }
+                    std::thread::sleep(Duration::from_millis(100));
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+fn no_account(label: &str) -> CoordinatorError {
+    CoordinatorError::Invalid(format!("no auxiliary account {} in {}", label, WALLET_FILE))
+}